@@ -1,5 +1,8 @@
 precision mediump float;
 
+// Range of depth values in pixels.  Keep in sync with terrain2.vert.
+const float DEPTH_RANGE = 544.0;
+
 varying vec2 texCoord;
 
 uniform sampler2D image0Tex;
@@ -20,7 +23,7 @@ float check_horiz(vec2 off, float centerDepth) {
     vec2 pos = texCoord + off / screenSize;
     float depth = texture2D(depthTex, pos).r;
     // #2
-    if (depth < centerDepth + 8.0 / 512.0) {
+    if (depth < centerDepth + 8.0 / DEPTH_RANGE) {
         return 0.0;
     }
 
@@ -31,7 +34,7 @@ float check_horiz(vec2 off, float centerDepth) {
     }
 
     float baseZ = color1.r * (255.0 / 8.0 * 32.0);
-    float pixelZ = depth * 512.0;
+    float pixelZ = depth * DEPTH_RANGE;
 
     if (pixelZ - baseZ > 0.75) {
         // #3
@@ -39,14 +42,14 @@ float check_horiz(vec2 off, float centerDepth) {
     }
 
     float neighborDepth = texture2D(depthTex, pos + vec2(0.0, -1.0) / screenSize).r;
-    float neighborDelta = (depth - neighborDepth) * 512.0;
+    float neighborDelta = (depth - neighborDepth) * DEPTH_RANGE;
     if (0.5 < neighborDelta && neighborDelta < 1.5) {
         // #4
         return 0.0;
     }
 
-    float delta = depth - (centerDepth + 8.0 / 512.0);
-    return clamp(delta * 512.0 / 16.0, 0.0, 1.0);
+    float delta = depth - (centerDepth + 8.0 / DEPTH_RANGE);
+    return clamp(delta * DEPTH_RANGE / 16.0, 0.0, 1.0);
 }
 
 // Highlight if the pixel above
@@ -57,7 +60,7 @@ float check_vert(vec2 off, float centerDepth) {
     vec2 pos = texCoord + off / screenSize;
     float depth = texture2D(depthTex, pos).r;
     // #2
-    if (depth < centerDepth + 8.0 / 512.0) {
+    if (depth < centerDepth + 8.0 / DEPTH_RANGE) {
         return 0.0;
     }
 
@@ -68,15 +71,15 @@ float check_vert(vec2 off, float centerDepth) {
     }
 
     float baseZ = color1.r * (255.0 / 8.0 * 32.0);
-    float pixelZ = depth * 512.0;
+    float pixelZ = depth * DEPTH_RANGE;
 
     if (pixelZ - baseZ > 0.75) {
         // #3
         return 0.0;
     }
 
-    float delta = depth - (centerDepth + 8.0 / 512.0);
-    return clamp(delta * 512.0 / 16.0, 0.0, 1.0);
+    float delta = depth - (centerDepth + 8.0 / DEPTH_RANGE);
+    return clamp(delta * DEPTH_RANGE / 16.0, 0.0, 1.0);
 }
 
 float get_highlight() {
@@ -1,5 +1,8 @@
 precision mediump float;
 
+// Range of depth values in pixels.  Keep in sync with terrain2.vert.
+const float DEPTH_RANGE = 544.0;
+
 varying vec3 localCenter;
 varying float radius;
 varying vec3 color;
@@ -13,7 +16,7 @@ void main(void) {
     texCoord.y = 1.0 - texCoord.y;
 
     float depth = texture2D(depthTex, texCoord).r;
-    float z = depth * 512.0;
+    float z = depth * DEPTH_RANGE;
     vec3 localPos3 = vec3(localPos.x, localPos.y + z, z);
     vec3 off = localPos3 - localCenter;
     float dist = length(off);
@@ -14,6 +14,9 @@ const mat4 scaling =
           0.0,  0.0,  2.0,  0.0,
          -1.0,  1.0, -1.0,  1.0);
 
+// Range of depth values in pixels.  Keep in sync with terrain2.vert.
+const float DEPTH_RANGE = 544.0;
+
 uniform vec2 cameraPos;
 uniform vec2 cameraSize;
 uniform vec2 sheetSize;
@@ -36,7 +39,7 @@ void main(void) {
             pos.z + anchor.y - abs(size.y) * posOffset.y,
             1.0);
 
-    vec4 pos = (worldPos4 - vec4(cameraPos, 0.0, 0.0)) / vec4(cameraSize, 512.0, 1.0);
+    vec4 pos = (worldPos4 - vec4(cameraPos, 0.0, 0.0)) / vec4(cameraSize, DEPTH_RANGE, 1.0);
 
     gl_Position = scaling * pos;
 }
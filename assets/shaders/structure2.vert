@@ -3,6 +3,11 @@ const float TILE_SIZE = 32.0;
 const float CHUNK_SIZE = 16.0;
 const float LOCAL_SIZE = 8.0;
 const float ATLAS_SIZE = 32.0;
+// Range of `depth` values (in pixels) that maps onto the clip volume: all
+// CHUNK_SIZE levels of the chunk, plus one level of headroom so the top plane
+// of the highest layer stays inside once the tiebreaker is added.  Every
+// shader that writes or reads the depth buffer must use the same range.
+const float DEPTH_RANGE = (CHUNK_SIZE + 1.0) * TILE_SIZE;
 const float ANIM_MODULUS_MS = 55440.0;
 
 uniform vec2 cameraPos;
@@ -39,7 +44,7 @@ void main(void) {
     float depth = pos.z + adjZ;
 
     vec2 normPos = (pixelPos - cameraPos) / cameraSize;
-    float normDepth = depth / (CHUNK_SIZE * TILE_SIZE);
+    float normDepth = depth / DEPTH_RANGE;
     vec3 glPos = vec3(normPos, normDepth) * 2.0 - 1.0;
     glPos.y = -glPos.y;
     gl_Position = vec4(glPos, 1.0);
@@ -65,3 +65,19 @@ def init():
             .light((255, 100, 0), 50)
 
     mk_floor_blocks(tiles('lpc-base-tiles/holemid.png'), 'cave_pit', base_img=cave_floor)
+
+    mk_vault('shrine', [
+            '  ...  ',
+            ' ..... ',
+            '.......',
+            '.......',
+            '.......',
+            ' ..... ',
+            '  ...  ',
+            ], weight=2, min_level=1) \
+            .structure((3, 2, 0), 'fountain') \
+            .structure((1, 4, 0), 'chest', loot='cave/chest') \
+            .connection((3, 0)) \
+            .connection((0, 3)) \
+            .connection((6, 3)) \
+            .connection((3, 6))
@@ -32,8 +32,13 @@ def init():
     tiles = loader('tiles', unit=TILE_SIZE)
 
     terrain2.terrain_blocks('grass', tiles('lpc-base-tiles/grass.png'), shape='floor')
-    terrain2.terrain_blocks('water_grass', tiles('lpc-base-tiles/watergrass.png'))
-    terrain2.terrain_cross_blocks('water_grass', tiles('lpc-watergrass-cross.png'))
+    # Tiles along the shore are shallow enough to wade through.  Only tiles that are entirely
+    # water are impassable.
+    water = terrain2.terrain_blocks('water_grass', tiles('lpc-base-tiles/watergrass.png'),
+            shape='floor')
+    water.shape({'center/v%d' % i: 'empty' for i in range(4)})
+    terrain2.terrain_cross_blocks('water_grass', tiles('lpc-watergrass-cross.png'),
+            shape='floor')
 
     terrain2.interior_blocks('farmland', tiles('farmland-interior-parts.png'), shape='floor')
 
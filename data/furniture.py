@@ -76,11 +76,20 @@ def init():
     mk_structure_item(s['statue/e'], 'statue', 'Statue') \
             .recipe('anvil', {'stone': 50})
 
-    stair_verts_tile = models.quad((0, 1, 0), (1, 1, 0), (1, 0, 1), (0, 0, 1))
-    stair_model = Model([tuple(x * TILE_SIZE for x in vert) for vert in stair_verts_tile])
+    def stair_model(*verts):
+        return Model([tuple(x * TILE_SIZE for x in vert) for vert in models.quad(*verts)])
 
     stair_img = structures('stair.png')
-    stair_shape = Shape(1, 1, 1, ['ramp_n'])
-    stair = mk_structure('stair/n', stair_img, stair_model, stair_shape, 1)
+    stair = mk_structure('stair/n', stair_img,
+            stair_model((0, 1, 0), (1, 1, 0), (1, 0, 1), (0, 0, 1)),
+            Shape(1, 1, 1, ['ramp_n']), 1)
+    # East and west stairs reuse the north-facing image.  A south-facing ramp
+    # is edge-on to the camera, so there is no `stair/s`.
+    stair.merge(mk_structure('stair/e', stair_img,
+            stair_model((0, 1, 0), (1, 1, 1), (1, 0, 1), (0, 0, 0)),
+            Shape(1, 1, 1, ['ramp_e']), 1))
+    stair.merge(mk_structure('stair/w', stair_img.transpose(Image.FLIP_LEFT_RIGHT),
+            stair_model((0, 1, 1), (1, 1, 0), (1, 0, 0), (0, 0, 1)),
+            Shape(1, 1, 1, ['ramp_w']), 1))
     mk_structure_item(stair['stair/n'], 'stair', 'Stairs') \
             .recipe('anvil', {'wood': 10})
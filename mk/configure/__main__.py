@@ -212,6 +212,9 @@ if __name__ == '__main__':
             native.rust('backend', 'bin',
                 ('physics', 'terrain_gen', 'server_config', 'server_types', 'server_util'),
                 '$root/src/server/main.rs'),
+            native.rust('save_tool', 'bin',
+                ('physics', 'server_config', 'server_types', 'server_util'),
+                '$root/src/server/save_tool.rs'),
             native.cxx('wrapper', 'bin',
                 ('$root/src/wrapper/%s' % f
                     for f in os.listdir(os.path.join(i.root_dir, 'src', 'wrapper'))
@@ -55,6 +55,7 @@ def process():
             for s in ('server', 'client')
             for f in ('structures', 'blocks', 'items', 'recipes', 'animations', 'attach_slots')]
     data_files.append('loot_tables_server.json')
+    data_files.append('vaults_server.json')
     data_files.append('extras_client.json')
     return template('''
         rule process_data
@@ -52,6 +52,8 @@ local action = require('core.action')
 local command = require('core.command')
 local util = require('core.util')
 
+-- Everything loaded after this point runs in the sandbox environment.
+require('core.sandbox')
 require('loader')
 
 
@@ -303,6 +305,26 @@ function command.su_handler.tribe(client, args)
 end
 command.help.tribe = '/tribe [E|P|U|A]: Change the tribe of your character'
 
+function command.su_handler.save(client, args)
+    if args == '' then
+        client:world():save()
+        client:send_message('Saving world...')
+        return
+    end
+
+    local secs = args:match('^interval (%d+)$')
+    if secs == nil then
+        client:send_message('usage: /save [interval <seconds>]')
+        return
+    end
+    client:world():set_autosave_interval(secs + 0)
+    client:send_message('Autosave interval set to ' .. secs .. ' seconds')
+end
+command.help.save = {
+    '/save: Save the world now',
+    '/save interval <seconds>: Change the time between autosaves (0 to disable)',
+}
+
 
 function outpost_ffi.callbacks.login(c)
     c:set_main_inventories(c:pawn():inventory('main'),
@@ -1,21 +1,27 @@
 local outpost_ffi = require('outpost_ffi')
 
+-- Code typed at the REPL runs with full access to the real globals.  Capture
+-- them now, while this module is running in the real environment, instead of
+-- looking them up from whatever environment the caller has.
+local real_env = getfenv(1)
+local real_loadstring = loadstring
+local real_setfenv = setfenv
+
 
 function outpost_ffi.callbacks.eval(w, code)
-    func, err = loadstring(code, '<repl>')
+    local func, err = real_loadstring(code, '<repl>')
 
     if not func then
         return 'error parsing code: ' .. err .. '\n >>> '
     end
 
     local out_buf = ''
-    local real_env = getfenv(0)
     local eval_env = {
         _real_env = real_env,
         print = function(...)
-            s = ''
+            local s = ''
             for i = 1, select('#', ...) do
-                x = select(i, ...)
+                local x = select(i, ...)
                 s = s .. tostring(x) .. '\t'
             end
             out_buf = out_buf .. s .. '\n'
@@ -23,9 +29,9 @@ function outpost_ffi.callbacks.eval(w, code)
         w = w,
     }
     setmetatable(eval_env, { __index = real_env })
-    setfenv(func, eval_env)
+    real_setfenv(func, eval_env)
 
-    ok, msg = pcall(func)
+    local ok, msg = pcall(func)
 
     if ok then
         if msg ~= nil then
@@ -66,7 +66,10 @@ function outpost_ffi.callbacks.set_structure_extra(id, extra)
 end
 
 
--- Extension methods for accessing the extra data.
+-- Extension methods for accessing the extra data.  Scripts may modify the
+-- returned table, so getting the extra of an entity, inventory, or structure
+-- marks the chunk that saves it as changed.  Clients, planes, and the world
+-- are written by every autosave.
 
 local function get_or_create(t, k)
     local result = t[k]
@@ -86,10 +89,12 @@ function outpost_ffi.types.Client.table.extra(self)
 end
 
 function outpost_ffi.types.Entity.table.extra(self)
+    self:mark_dirty()
     return get_or_create(entity_extra, self:id())
 end
 
 function outpost_ffi.types.Inventory.table.extra(self)
+    self:mark_dirty()
     return get_or_create(inventory_extra, self:id())
 end
 
@@ -98,6 +103,7 @@ function outpost_ffi.types.Plane.table.extra(self)
 end
 
 function outpost_ffi.types.Structure.table.extra(self)
+    self:mark_dirty()
     return get_or_create(structure_extra, self:id())
 end
 
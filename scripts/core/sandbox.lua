@@ -0,0 +1,148 @@
+-- Restricted global environment for game and mod scripts.
+--
+-- Modules outside of `core` are loaded with a shared environment that can
+-- read ordinary globals but can't reach anything that touches the filesystem,
+-- loads arbitrary code, or escapes back to the real global table.
+
+local real_require = require
+local real_loadfile = loadfile
+local real_setfenv = setfenv
+local io_open = io.open
+local outpost_ffi = require('outpost_ffi')
+
+
+-- Globals that sandboxed code can't see.
+local blocked_globals = {
+    io = true,
+    os = true,
+    debug = true,
+    package = true,
+    module = true,
+    require = true,
+    load = true,
+    loadfile = true,
+    loadstring = true,
+    dofile = true,
+    getfenv = true,
+    setfenv = true,
+    newproxy = true,
+    collectgarbage = true,
+    jit = true,
+    ffi = true,
+    _G = true,
+}
+
+-- Modules that sandboxed code can't `require`.
+local blocked_modules = {
+    io = true,
+    os = true,
+    debug = true,
+    package = true,
+    jit = true,
+    ffi = true,
+    _G = true,
+}
+
+
+-- Callbacks that sandboxed code can neither read nor replace.  `eval` runs
+-- arbitrary code in the real global environment.
+local blocked_callbacks = {
+    eval = true,
+}
+
+-- Sandboxed code sees this in place of the real `outpost_ffi`.  Mods can add
+-- methods to the types and define callbacks, but can't get at the blocked
+-- callbacks.
+local ffi_proxy = {
+    types = outpost_ffi.types,
+    callbacks = setmetatable({}, {
+        __index = function(t, k)
+            if blocked_callbacks[k] then
+                return nil
+            end
+            return outpost_ffi.callbacks[k]
+        end,
+        __newindex = function(t, k, v)
+            if blocked_callbacks[k] then
+                error('callback ' .. tostring(k) .. ' can\'t be replaced by scripts', 2)
+            end
+            outpost_ffi.callbacks[k] = v
+        end,
+        __metatable = false,
+    }),
+}
+
+
+local env = {}
+
+env.outpost_ffi = ffi_proxy
+
+env.os = {
+    clock = os.clock,
+    date = os.date,
+    difftime = os.difftime,
+    time = os.time,
+}
+
+function env.require(name)
+    if blocked_modules[name] then
+        error('module ' .. tostring(name) .. ' is not available to scripts', 2)
+    end
+    if name == 'outpost_ffi' then
+        return ffi_proxy
+    end
+    return real_require(name)
+end
+
+env._G = env
+
+setmetatable(env, {
+    __index = function(t, k)
+        if blocked_globals[k] then
+            return nil
+        end
+        return _G[k]
+    end,
+    __metatable = false,
+})
+
+
+local function find_file(name)
+    local file_name = name:gsub('%.', '/')
+    for pattern in package.path:gmatch('[^;]+') do
+        local path = pattern:gsub('%?', file_name)
+        local f = io_open(path, 'r')
+        if f ~= nil then
+            f:close()
+            return path
+        end
+    end
+    return nil
+end
+
+local function sandbox_loader(name)
+    if name:sub(1, 5) == 'core.' then
+        return nil
+    end
+
+    local path = find_file(name)
+    if path == nil then
+        return nil
+    end
+
+    local chunk, err = real_loadfile(path)
+    if chunk == nil then
+        error(err, 0)
+    end
+    real_setfenv(chunk, env)
+    return chunk
+end
+
+-- Run before the standard Lua file loader, so that every non-core script file
+-- gets the sandbox environment.
+table.insert(package.loaders, 2, sandbox_loader)
+
+
+return {
+    env = env,
+}
@@ -64,6 +64,14 @@ function outpost_ffi.callbacks.timeout(slot)
     t:callback()
 end
 
+-- Called before the scripts are reloaded.  The new scripts won't have the
+-- callbacks for any of the current timers, so they must not fire.
+function outpost_ffi.callbacks.cancel_timers()
+    for _, t in pairs(timers) do
+        t:cancel()
+    end
+end
+
 
 return {
     set_timer = set_timer,
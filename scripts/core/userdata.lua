@@ -85,6 +85,40 @@ function outpost_ffi.types.Client.table.send_message(c, msg)
     c:send_message_raw('***\t' .. msg)
 end
 
+function outpost_ffi.types.Entity.table.set_behavior(e, name, radius)
+    e:set_behavior_raw(name, radius or 0)
+end
+
+-- Create a new plane.  `kind` names the terrain generator to use for the
+-- plane ('forest', 'dungeon', 'flat', or 'void'; defaults to 'dungeon'), and
+-- `params` is an optional table of parameters for the generator, whose keys
+-- and values are converted to strings.  Keys that convert to the same string,
+-- like `1` and `'1'`, are an error.  The dungeon generator accepts `levels`,
+-- the number of levels (1-4, default 3).
+function outpost_ffi.types.World.table.create_plane(w, name, kind, params)
+    local args = ExtraArg.map()
+    for k, v in pairs(params or {}) do
+        args:set(k, ExtraArg.str(tostring(v)))
+    end
+    return w:create_plane_raw(name, kind or 'dungeon', args)
+end
+
+-- Returns a table with the plane's day/night settings: `cycle_ms` (length of a
+-- full day in milliseconds), `time` (fixed time of day in ticks, 0-23999), and
+-- `ambient` (fixed ambient light color as 0xRRGGBB).  `time` and `ambient`
+-- are nil when not set.
+function outpost_ffi.types.Plane.table.env(p)
+    local cycle_ms, time, ambient = p:env_raw()
+    return { cycle_ms = cycle_ms, time = time, ambient = ambient }
+end
+
+-- Replace the plane's day/night settings.  Fields missing from `env` are
+-- unset, except `cycle_ms`, which keeps its current value.
+function outpost_ffi.types.Plane.table.set_env(p, env)
+    local cycle_ms = env.cycle_ms or p:env().cycle_ms
+    return p:set_env_raw(cycle_ms, env.time or -1, env.ambient or -1)
+end
+
 function outpost_ffi.types.Inventory.table.update(i, item, amount)
     if amount > 0 then
         i:bulk_add(item, amount)
@@ -106,6 +106,7 @@ add_structure_item('trophy')
 add_structure_item('fountain')
 add_structure_item('torch')
 add_structure_item('stair', 'stair/n')
+mallet_cycle('stair/', { 'n', 'e', 'w' })
 
 
 
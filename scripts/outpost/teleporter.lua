@@ -117,7 +117,8 @@ end
 
 function action.use.dungeon_entrance(c, s)
     if s:extra().target_plane == nil then
-        local p = s:world():create_plane('Dungeon')
+        local p = s:world():create_plane('Dungeon', 'dungeon')
+        p:set_env({ ambient = 0x000000 })
         p:extra().exit_pos = c:pawn():pos()
         s:extra().target_plane = p:stable_id()
     end
@@ -0,0 +1,91 @@
+-- Check that sandboxed scripts can't reach `io`, `os`, or the real global
+-- table through any module they can `require`.
+--
+-- Run from the `scripts` directory with `luajit test/sandbox.lua`.  The script
+-- raises an error if the sandbox leaks, and prints 'ok' otherwise.
+
+-- Stand-in for the library the server provides.
+outpost_ffi = {
+    types = {},
+    callbacks = {},
+}
+package.loaded.outpost_ffi = outpost_ffi
+
+require('core.eval')
+local sandbox = require('core.sandbox')
+local env = sandbox.env
+
+
+-- Values that sandboxed code must never get hold of.
+local forbidden = {
+    [io] = 'io',
+    [io.open] = 'io.open',
+    [os] = 'os',
+    [os.execute] = 'os.execute',
+    [_G] = '_G',
+    [loadstring] = 'loadstring',
+    [getfenv] = 'getfenv',
+    [setfenv] = 'setfenv',
+    [package] = 'package',
+    [outpost_ffi] = 'outpost_ffi',
+    [outpost_ffi.callbacks] = 'outpost_ffi.callbacks',
+    [outpost_ffi.callbacks.eval] = 'outpost_ffi.callbacks.eval',
+}
+
+local function scan(x, path, seen)
+    if forbidden[x] then
+        error('sandbox leak: ' .. path .. ' is ' .. forbidden[x], 0)
+    end
+    if type(x) ~= 'table' or seen[x] then
+        return
+    end
+    seen[x] = true
+    for k, v in pairs(x) do
+        scan(k, path .. '[key]', seen)
+        scan(v, path .. '.' .. tostring(k), seen)
+    end
+end
+
+
+-- Run `code` in the sandbox and return its results.
+local function run(code)
+    local chunk = assert(loadstring(code, '<sandbox test>'))
+    setfenv(chunk, env)
+    return chunk()
+end
+
+
+-- Direct access to the dangerous globals.
+assert(run('return io') == nil, 'io is visible')
+assert(run('return os.execute') == nil, 'os.execute is visible')
+assert(run('return getfenv') == nil, 'getfenv is visible')
+assert(run('return loadstring') == nil, 'loadstring is visible')
+assert(run('return _G') == env, '_G is not the sandbox')
+
+-- The eval callback, which runs code against the real globals.
+assert(run('return outpost_ffi.callbacks.eval') == nil, 'eval is visible')
+assert(run("return require('outpost_ffi').callbacks.eval") == nil, 'eval is visible')
+assert(not pcall(run, "require('outpost_ffi').callbacks.eval = function() end"),
+       'eval can be replaced')
+assert(not pcall(run, "return require('outpost_ffi').callbacks.eval(nil, 'return io')"),
+       'eval can be called')
+
+-- Every module that has been loaded so far, plus the usual standard ones.
+local names = { 'io', 'os', 'debug', 'package', 'jit', 'ffi', '_G', 'outpost_ffi' }
+for name, _ in pairs(package.loaded) do
+    names[#names + 1] = name
+end
+
+scan(env, 'env', {})
+for _, name in ipairs(names) do
+    local ok, m = pcall(function()
+        local chunk = assert(loadstring('return require(...)', '<sandbox test>'))
+        setfenv(chunk, env)
+        return chunk(name)
+    end)
+    if ok then
+        scan(m, "require('" .. name .. "')", {})
+    end
+end
+
+print('ok')
@@ -76,6 +76,8 @@ fn shape_overrides(old: Shape, new: Shape) -> bool {
 
         (Shape::Solid, _) => false,
 
+        // Everything else, including ramps (in any direction), acts like `Solid`: it replaces
+        // floors, but nothing replaces it.
         _ => false,
     }
 }
@@ -16,6 +16,13 @@ function DayNight(assets) {
     this.active = true;
     this.base_time = 0;
     this.cycle_ms = 24000;
+
+    // Plane settings.  `ambient` is used instead of the day/night cycle when
+    // `active` is false, and `fixed_time` replaces the current time of day
+    // when `time_fixed` is true.
+    this.ambient = [0, 0, 0];
+    this.time_fixed = false;
+    this.fixed_time = 0;
 }
 exports.DayNight = DayNight;
 
@@ -35,10 +42,16 @@ DayNight.prototype._phaseTime = function(time) {
 
 DayNight.prototype.getAmbientColor = function(now) {
     if (!this.active) {
-        return [0, 0, 0];
+        return this.ambient;
     }
 
-    var pt = this._phaseTime((now - this.base_time) * CYCLE_LENGTH / this.cycle_ms);
+    var cycle_time;
+    if (this.time_fixed) {
+        cycle_time = this.fixed_time;
+    } else {
+        cycle_time = (now - this.base_time) * CYCLE_LENGTH / this.cycle_ms;
+    }
+    var pt = this._phaseTime(cycle_time);
     var phase = pt[0];
     var time = pt[1];
     if (phase == 0) {
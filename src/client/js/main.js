@@ -342,6 +342,7 @@ function openConn(info, next) {
     conn.onMainInventory = handleMainInventory;
     conn.onAbilityInventory = handleAbilityInventory;
     conn.onPlaneFlags = handlePlaneFlags;
+    conn.onPlaneEnv = handlePlaneEnv;
     conn.onGetInteractArgs = handleGetInteractArgs;
     conn.onGetUseItemArgs = handleGetUseItemArgs;
     conn.onGetUseAbilityArgs = handleGetUseAbilityArgs;
@@ -930,7 +931,16 @@ function handleAbilityInventory(iid) {
 }
 
 function handlePlaneFlags(flags) {
-    day_night.active = (flags == 0);
+    day_night.active = (flags & 1) == 0;
+    day_night.time_fixed = (flags & 2) != 0;
+}
+
+function handlePlaneEnv(now, cycle_base, cycle_ms, fixed_time, ambient) {
+    var pst_now = timing.decodeRecv(now);
+    day_night.base_time = pst_now - cycle_base;
+    day_night.cycle_ms = cycle_ms;
+    day_night.fixed_time = fixed_time;
+    day_night.ambient = [(ambient >> 16) & 0xff, (ambient >> 8) & 0xff, ambient & 0xff];
 }
 
 function handleGetInteractArgs(dialog_id, parts) {
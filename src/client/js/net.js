@@ -48,6 +48,7 @@ var OP_STRUCTURE_REPLACE =      0x8018;
 var OP_INVENTORY_UPDATE =       0x8019;
 var OP_INVENTORY_APPEAR =       0x801a;
 var OP_INVENTORY_GONE =         0x801b;
+var OP_PLANE_ENV =              0x801c;
 
 exports.SYNC_LOADING = 0;
 exports.SYNC_OK = 1;
@@ -92,6 +93,7 @@ function Connection(url) {
     this.onInventoryUpdate = null;
     this.onInventoryAppear = null;
     this.onInventoryGone = null;
+    this.onPlaneEnv = null;
 }
 exports.Connection = Connection;
 
@@ -404,6 +406,17 @@ Connection.prototype._handleMessage = function(evt) {
             };
             break;
 
+        case OP_PLANE_ENV:
+            if (this.onPlaneEnv != null) {
+                var now = get16();
+                var cycle_base = get32();
+                var cycle_ms = get32();
+                var fixed_time = get32();
+                var ambient = get32();
+                this.onPlaneEnv(now, cycle_base, cycle_ms, fixed_time, ambient);
+            };
+            break;
+
         default:
             console.assert(false, 'received invalid opcode:', opcode.toString(16));
             break;
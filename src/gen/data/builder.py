@@ -1,4 +1,4 @@
-from . import structure, block, item, recipe, animation, attachment, loot_table, extra
+from . import structure, block, item, recipe, animation, attachment, loot_table, extra, vault
 
 
 class Objects(object):
@@ -129,6 +129,25 @@ class Extras(Objects):
         self.owner.extras.append(e)
         return self
 
+class Vaults(Objects):
+    def create(self, name, grid, weight=1, min_level=0):
+        v = vault.VaultDef(name, grid, weight, min_level)
+        self._add(v)
+        self.owner.vaults.append(v)
+        return self
+
+    def block(self, pos, block):
+        self._foreach(lambda v: v.add_block(pos, block))
+        return self
+
+    def structure(self, pos, template, loot=None):
+        self._foreach(lambda v: v.add_structure(pos, template, loot))
+        return self
+
+    def connection(self, pos):
+        self._foreach(lambda v: v.add_connection(pos))
+        return self
+
 
 class Builder(object):
     def __init__(self):
@@ -142,6 +161,7 @@ class Builder(object):
         self.attach_slots = []
         self.loot_tables = []
         self.extras = []
+        self.vaults = []
 
 
     def block_builder(self):
@@ -200,6 +220,13 @@ class Builder(object):
         return self.extra_builder().create(*args, **kwargs)
 
 
+    def vault_builder(self):
+        return Vaults(self)
+
+    def mk_vault(self, *args, **kwargs):
+        return self.vault_builder().create(*args, **kwargs)
+
+
 INSTANCE = Builder()
 mk_block = INSTANCE.mk_block
 mk_structure = INSTANCE.mk_structure
@@ -209,6 +236,7 @@ mk_anim_group = INSTANCE.mk_anim_group
 mk_sprite = INSTANCE.mk_sprite
 mk_attach_slot = INSTANCE.mk_attach_slot
 mk_extra = INSTANCE.mk_extra
+mk_vault = INSTANCE.mk_vault
 
 block_builder = INSTANCE.block_builder
 structure_builder = INSTANCE.structure_builder
@@ -218,3 +246,4 @@ anim_group_builder = INSTANCE.anim_group_builder
 sprite_builder = INSTANCE.sprite_builder
 attach_slot_builder = INSTANCE.attach_slot_builder
 extra_builder = INSTANCE.extra_builder
+vault_builder = INSTANCE.vault_builder
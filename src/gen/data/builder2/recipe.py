@@ -6,7 +6,7 @@ from outpost_data.core.recipe import RecipeDef
 
 class RecipePrototype(PrototypeBase):
     KIND = 'recipe'
-    FIELDS = ('display_name', 'station', 'inputs', 'outputs')
+    FIELDS = ('display_name', 'station', 'inputs', 'outputs', 'time')
     def __init__(self):
         super(RecipePrototype, self).__init__()
         self.inputs = {}
@@ -22,13 +22,15 @@ class RecipePrototype(PrototypeBase):
         self.name = self.require('name') or '_%x' % id(self)
         display_name = self.require('display_name', default=self.name)
         station = self.require('station', default='anvil')
-        return RecipeDef(self.name, display_name, station, self.inputs, self.outputs)
+        time = self.time or 0
+        return RecipeDef(self.name, display_name, station, self.inputs, self.outputs, time)
 
 class RecipeBuilder(BuilderBase):
     PROTO_CLASS = RecipePrototype
 
     display_name = dict_modifier('display_name')
     station = dict_modifier('station')
+    time = dict_modifier('time')
     # `inputs` and `outputs` are already dicts, so there's no way for `_dict_modifier`
     # to distinguish the "set all" and "set named" cases.
     inputs = modifier('inputs')
@@ -4,7 +4,7 @@ import os
 
 
 from . import builder, builder2, files, loader, util
-from . import structure, block, item, recipe, animation, attachment, loot_table, extra
+from . import structure, block, item, recipe, animation, attachment, loot_table, extra, vault
 from outpost_data.core.loader import TimeIt
 
 
@@ -43,6 +43,7 @@ def postprocess(b):
     recipe.resolve_structure_ids(b.recipes, id_maps.structures)
     loot_table.resolve_object_ids(b.loot_tables, id_maps)
     extra.resolve_all(b.extras, b, id_maps)
+    vault.check_names(b.vaults, id_maps, b.loot_tables)
 
 def write_json(output_dir, basename, j):
     with open(os.path.join(output_dir, basename), 'w') as f:
@@ -140,6 +141,10 @@ def emit_loot_tables(output_dir, loot_tables):
     write_json(output_dir, 'loot_tables_server.json',
             loot_table.build_server_json(loot_tables))
 
+def emit_vaults(output_dir, vaults):
+    write_json(output_dir, 'vaults_server.json',
+            vault.build_server_json(vaults))
+
 def emit_extras(output_dir, extras):
     write_json(output_dir, 'extras_client.json',
             extra.build_client_json(extras))
@@ -162,13 +167,14 @@ def generate(output_dir):
     time('sprites', emit_sprites, output_dir, b.sprites)
     time('attach_slots', emit_attach_slots, output_dir, b.attach_slots)
     time('loot_tables', emit_loot_tables, output_dir, b.loot_tables)
+    time('vaults', emit_vaults, output_dir, b.vaults)
     time('extras', emit_extras, output_dir, b.extras)
 
     print('%d structures, %d blocks, %d items, %d recipes' %
             (len(b.structures), len(b.blocks), len(b.items), len(b.recipes)))
-    print('%d animations, %d sprites, %d attach_slots, %d loot tables, %d extras' %
+    print('%d animations, %d sprites, %d attach_slots, %d loot tables, %d vaults, %d extras' %
             (len(b.animations), len(b.sprites), len(b.attach_slots),
-                len(b.loot_tables), len(b.extras)))
+                len(b.loot_tables), len(b.vaults), len(b.extras)))
 
     with open(os.path.join(output_dir, 'stamp'), 'w') as f:
         pass
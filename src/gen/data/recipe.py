@@ -3,12 +3,13 @@ from .util import err
 
 
 class RecipeDef(object):
-    def __init__(self, name, ui_name, station, inputs, outputs):
+    def __init__(self, name, ui_name, station, inputs, outputs, time=0):
         self.name = name
         self.ui_name = ui_name
         self.station_name = station
         self.input_names = tuple(inputs.items())
         self.output_names = tuple(outputs.items())
+        self.time = time
 
         self.id = None
         self.station_id = None
@@ -50,5 +51,6 @@ def build_server_json(recipes):
                 'station': r.station_id,
                 'inputs': r.input_ids,
                 'outputs': r.output_ids,
+                'time': r.time,
                 }
     return list(convert(r) for r in recipes)
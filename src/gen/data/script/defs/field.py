@@ -130,7 +130,8 @@ def _build_field_map():
             display_name = StringField,
             station = NameField,
             input = ItemCountField,
-            output = ItemCountField
+            output = ItemCountField,
+            time = IntField,
             )
 
     return fm
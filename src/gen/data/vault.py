@@ -0,0 +1,77 @@
+from .util import err
+
+
+class VaultDef(object):
+    '''A prefab dungeon room.  `grid` is a list of strings, one per row of
+    tiles: `#` for cave wall, `.` for open floor, and space to let the cave
+    generator decide.  Blocks and structures are placed relative to the
+    top-left corner of the grid.'''
+    def __init__(self, name, grid, weight=1, min_level=0):
+        self.name = name
+        self.grid = list(grid)
+        self.weight = weight
+        self.min_level = min_level
+
+        self.blocks = []
+        self.structures = []
+        self.connections = []
+
+        self.size = (len(self.grid[0]) if len(self.grid) > 0 else 0, len(self.grid))
+
+    def add_block(self, pos, block):
+        self.blocks.append((pos, block))
+
+    def add_structure(self, pos, template, loot=None):
+        self.structures.append((pos, template, loot))
+
+    def add_connection(self, pos):
+        self.connections.append(pos)
+
+def check_names(vaults, id_maps, loot_tables):
+    item_tables = set(t.name for t in loot_tables if t.object_kind() == 'item')
+    seen = set()
+    for v in vaults:
+        if v.name in seen:
+            err('vault %r: duplicate name' % v.name)
+        seen.add(v.name)
+
+        w, h = v.size
+        if w == 0 or h == 0:
+            err('vault %r: grid is empty' % v.name)
+        if not 0 <= v.weight < 0x10000:
+            err('vault %r: weight %d is out of range' % (v.name, v.weight))
+        if not 0 <= v.min_level < 0x100:
+            err('vault %r: min_level %d is out of range' % (v.name, v.min_level))
+        for i, row in enumerate(v.grid):
+            if len(row) != w:
+                err('vault %r: row %d has length %d (expected %d)' % (v.name, i, len(row), w))
+            bad = set(row) - set('#. ')
+            if len(bad) > 0:
+                err('vault %r: bad characters in row %d: %r' % (v.name, i, ''.join(sorted(bad))))
+
+        for pos, block in v.blocks:
+            if block not in id_maps.blocks:
+                err('vault %r: no such block: %r' % (v.name, block))
+        for pos, template, loot in v.structures:
+            if template not in id_maps.structures:
+                err('vault %r: no such structure: %r' % (v.name, template))
+            if loot is not None and loot not in item_tables:
+                err('vault %r: no such item loot table: %r' % (v.name, loot))
+        for x, y in v.connections:
+            if not (0 <= x < w and 0 <= y < h):
+                err('vault %r: connection %r is outside the room' % (v.name, (x, y)))
+
+def build_server_json(vaults):
+    def convert(v):
+        return {
+                'name': v.name,
+                'size': v.size,
+                'grid': v.grid,
+                'blocks': [{'pos': pos, 'block': block} for pos, block in v.blocks],
+                'structures': [{'pos': pos, 'template': template, 'loot': loot}
+                    for pos, template, loot in v.structures],
+                'connections': v.connections,
+                'weight': v.weight,
+                'min_level': v.min_level,
+                }
+    return list(convert(v) for v in vaults)
@@ -16,6 +16,7 @@ use v3::{Vn, V3, scalar};
 
 
 pub mod v3;
+pub mod path;
 mod walk;
 
 
@@ -54,8 +55,10 @@ impl Shape {
             0 => Empty,
             1 => Floor,
             2 => Solid,
+            3 => RampE,
+            4 => RampW,
+            5 => RampS,
             6 => RampN,
-            // TODO: add ramp variants once they are actually supported
             _ => return None,
         };
         Some(s)
@@ -146,3 +149,54 @@ fn walk_path<S, CB>(chunk: &S, start_pos: V3, _size: V3, velocity: V3,
 
     pos
 }
+
+
+#[cfg(test)]
+mod tests {
+    use core::prelude::*;
+
+    use v3::V3;
+
+    use super::{Shape, ShapeSource, TILE_SIZE, collide};
+
+    /// Three rows of terrain: floor for `x < 2`, a ramp climbing one tile over `x == 2`, and a
+    /// raised floor (the top of a solid block) for `2 < x < 5`.
+    struct Ramp(Shape);
+
+    impl ShapeSource for Ramp {
+        fn get_shape(&self, pos: V3) -> Shape {
+            if pos.y < 0 || pos.y >= 3 || pos.z != 0 {
+                return Shape::Empty;
+            }
+            match pos.x {
+                0 | 1 => Shape::Floor,
+                2 => self.0,
+                3 | 4 => Shape::Solid,
+                _ => Shape::Empty,
+            }
+        }
+    }
+
+    /// Call `collide` repeatedly, the way the movement code does when `collide` stops early
+    /// because the walking direction changed.
+    fn walk<S: ShapeSource>(chunk: &S, mut pos: V3, size: V3, velocity: V3) -> V3 {
+        for _ in 0..10 {
+            let (next, _) = collide(chunk, pos, size, velocity);
+            if next == pos {
+                break;
+            }
+            pos = next;
+        }
+        pos
+    }
+
+    #[test]
+    fn walk_up_east_ramp() {
+        let size = V3::new(16, 16, 32);
+        let start = V3::new(8, 40, 0);
+        let end = walk(&Ramp(Shape::RampE), start, size, V3::new(100, 0, 0));
+        assert_eq!(end.z, TILE_SIZE);
+        assert!(end.x > 3 * TILE_SIZE);
+        assert_eq!(end.y, start.y);
+    }
+}
@@ -0,0 +1,496 @@
+//! Pathfinding over the terrain described by a `ShapeSource`.
+//!
+//! This is an A* search over the tile grid.  Each node of the search is a tile where an entity can
+//! stand: either a `Floor` tile, or a ramp tile partway between two levels.  Entities can move
+//! between neighboring floor tiles on the same level (diagonally only if both tiles beside the
+//! diagonal are also open), and can move onto or off of a ramp only along the ramp's slope.
+//!
+//! This library can't allocate memory (it also runs in asm.js on the client), so the caller
+//! provides the storage for the search.  The size of that storage is also the search budget: once
+//! it is mostly full, the search gives up.
+use core::prelude::*;
+use core::cmp;
+
+use v3::{V3, Vn, scalar};
+
+use super::{Shape, ShapeSource};
+use super::TILE_SIZE;
+
+
+const COST_STRAIGHT: i32 = 10;
+const COST_DIAGONAL: i32 = 14;
+
+const NO_NODE: u32 = !0;
+
+/// Offsets to the eight horizontal neighbors of a tile.  The four orthogonal directions come first.
+const DIRS: [(i32, i32); 8] = [
+    ( 1,  0),
+    ( 0,  1),
+    (-1,  0),
+    ( 0, -1),
+    ( 1,  1),
+    (-1,  1),
+    (-1, -1),
+    ( 1, -1),
+];
+
+
+/// A node of the search.  Callers only need this to allocate storage for a `Pathfinder`.
+#[derive(Clone, Copy)]
+pub struct Node {
+    pos: V3,
+    /// Cost of the best known path from the start to this node.
+    cost: i32,
+    /// Index of the previous node on the best known path.  Once a path has been found, this is
+    /// reversed to point to the next node instead.
+    link: u32,
+    used: bool,
+    closed: bool,
+}
+
+impl Node {
+    pub fn new() -> Node {
+        Node {
+            pos: scalar(0),
+            cost: 0,
+            link: NO_NODE,
+            used: false,
+            closed: false,
+        }
+    }
+}
+
+/// An entry in the open set.  Callers only need this to allocate storage for a `Pathfinder`.
+#[derive(Clone, Copy)]
+pub struct HeapEntry {
+    /// Estimated cost of the full path through the node.
+    score: i32,
+    idx: u32,
+}
+
+impl HeapEntry {
+    pub fn new() -> HeapEntry {
+        HeapEntry {
+            score: 0,
+            idx: NO_NODE,
+        }
+    }
+}
+
+
+pub struct Pathfinder<'a> {
+    /// Open-addressed hash table of all nodes seen so far, keyed on position.
+    nodes: &'a mut [Node],
+    node_count: usize,
+    /// Binary heap of open nodes, ordered by `score`.
+    heap: &'a mut [HeapEntry],
+    heap_len: usize,
+}
+
+impl<'a> Pathfinder<'a> {
+    /// Create a pathfinder using the provided storage.  The search fails if it visits more than
+    /// three quarters of `nodes.len()` tiles, or if the open set grows beyond `heap.len()`.
+    pub fn new(nodes: &'a mut [Node], heap: &'a mut [HeapEntry]) -> Pathfinder<'a> {
+        Pathfinder {
+            nodes: nodes,
+            node_count: 0,
+            heap: heap,
+            heap_len: 0,
+        }
+    }
+
+    /// Find a path from tile `start` to tile `goal` for an entity of `size` (in pixels).  On
+    /// success, returns the tiles along the path, starting with `start` and ending with `goal`.
+    /// Returns `None` if there is no path, or if the search runs out of space before finding one.
+    pub fn find_path<'b, S: ShapeSource>(&'b mut self,
+                                         chunk: &S,
+                                         start: V3,
+                                         goal: V3,
+                                         size: V3) -> Option<Path<'b>> {
+        self.clear();
+        if self.nodes.len() == 0 {
+            return None;
+        }
+
+        let height = (size.z + TILE_SIZE - 1) / TILE_SIZE;
+        if !standable(chunk, start, height) || !standable(chunk, goal, height) {
+            return None;
+        }
+
+        let start_idx = self.slot(start);
+        self.nodes[start_idx] = Node {
+            pos: start,
+            cost: 0,
+            link: NO_NODE,
+            used: true,
+            closed: false,
+        };
+        self.node_count = 1;
+        if !self.push(estimate(start, goal), start_idx) {
+            return None;
+        }
+
+        let mut neighbors = [(scalar(0), 0); 8];
+        while let Some(idx) = self.pop() {
+            let node = self.nodes[idx];
+            if node.closed {
+                // Stale heap entry, left over from before a cheaper path was found.
+                continue;
+            }
+            if node.pos == goal {
+                return Some(self.finish(idx));
+            }
+            self.nodes[idx].closed = true;
+
+            let count = find_neighbors(chunk, node.pos, height, &mut neighbors);
+            for &(pos, step_cost) in &neighbors[.. count] {
+                let cost = node.cost + step_cost;
+                let n_idx = self.slot(pos);
+                let n = self.nodes[n_idx];
+                if n.used && (n.closed || n.cost <= cost) {
+                    continue;
+                }
+
+                if !n.used {
+                    // Keep the hash table at most 3/4 full.
+                    if (self.node_count + 1) * 4 > self.nodes.len() * 3 {
+                        return None;
+                    }
+                    self.node_count += 1;
+                }
+                self.nodes[n_idx] = Node {
+                    pos: pos,
+                    cost: cost,
+                    link: idx as u32,
+                    used: true,
+                    closed: false,
+                };
+                if !self.push(cost + estimate(pos, goal), n_idx) {
+                    return None;
+                }
+            }
+        }
+
+        None
+    }
+
+    fn clear(&mut self) {
+        for n in self.nodes.iter_mut() {
+            *n = Node::new();
+        }
+        self.node_count = 0;
+        self.heap_len = 0;
+    }
+
+    /// Find the slot in `nodes` for `pos`.  The slot is either the existing node for `pos`, or the
+    /// unused slot where a new one should go.
+    fn slot(&self, pos: V3) -> usize {
+        let len = self.nodes.len();
+        let mut idx = hash(pos) as usize % len;
+        loop {
+            let n = &self.nodes[idx];
+            if !n.used || n.pos == pos {
+                return idx;
+            }
+            idx = (idx + 1) % len;
+        }
+    }
+
+    fn push(&mut self, score: i32, idx: usize) -> bool {
+        if self.heap_len == self.heap.len() {
+            return false;
+        }
+
+        let mut i = self.heap_len;
+        self.heap[i] = HeapEntry {
+            score: score,
+            idx: idx as u32,
+        };
+        self.heap_len += 1;
+
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.heap[parent].score <= self.heap[i].score {
+                break;
+            }
+            self.heap.swap(parent, i);
+            i = parent;
+        }
+        true
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.heap_len == 0 {
+            return None;
+        }
+
+        let result = self.heap[0].idx as usize;
+        self.heap_len -= 1;
+        self.heap.swap(0, self.heap_len);
+
+        let len = self.heap_len;
+        let mut i = 0;
+        loop {
+            let left = i * 2 + 1;
+            let right = left + 1;
+            let mut best = i;
+            if left < len && self.heap[left].score < self.heap[best].score {
+                best = left;
+            }
+            if right < len && self.heap[right].score < self.heap[best].score {
+                best = right;
+            }
+            if best == i {
+                break;
+            }
+            self.heap.swap(i, best);
+            i = best;
+        }
+
+        Some(result)
+    }
+
+    /// Reverse the links along the path ending at `goal_idx`, so the path can be read out
+    /// starting from the beginning.
+    fn finish<'b>(&'b mut self, goal_idx: usize) -> Path<'b> {
+        let mut prev = NO_NODE;
+        let mut cur = goal_idx as u32;
+        while cur != NO_NODE {
+            let next = self.nodes[cur as usize].link;
+            self.nodes[cur as usize].link = prev;
+            prev = cur;
+            cur = next;
+        }
+
+        Path {
+            nodes: self.nodes,
+            next: prev,
+        }
+    }
+}
+
+
+/// Iterator over the tiles of a path found by `Pathfinder::find_path`.  For ramp tiles, the `z`
+/// coordinate is that of the bottom of the ramp.
+pub struct Path<'a> {
+    nodes: &'a [Node],
+    next: u32,
+}
+
+impl<'a> Iterator for Path<'a> {
+    type Item = V3;
+
+    fn next(&mut self) -> Option<V3> {
+        if self.next == NO_NODE {
+            return None;
+        }
+        let n = &self.nodes[self.next as usize];
+        self.next = n.link;
+        Some(n.pos)
+    }
+}
+
+
+fn hash(pos: V3) -> u32 {
+    (pos.x as u32).wrapping_mul(73856093) ^
+    (pos.y as u32).wrapping_mul(19349663) ^
+    (pos.z as u32).wrapping_mul(83492791)
+}
+
+/// Estimate the cost of moving from `a` to `b`.  This never overestimates, since every step moves
+/// at most one tile along each axis.
+fn estimate(a: V3, b: V3) -> i32 {
+    let d = (b - a).abs();
+    let min = cmp::min(d.x, d.y);
+    let max = cmp::max(d.x, d.y);
+    let planar = COST_STRAIGHT * (max - min) + COST_DIAGONAL * min;
+    cmp::max(planar, COST_STRAIGHT * d.z)
+}
+
+/// Get the direction in which `shape` slopes upward, or `None` if it's not a ramp.
+fn ramp_dir(shape: Shape) -> Option<V3> {
+    match shape {
+        Shape::RampE => Some(V3::new( 1,  0, 0)),
+        Shape::RampW => Some(V3::new(-1,  0, 0)),
+        Shape::RampS => Some(V3::new( 0,  1, 0)),
+        Shape::RampN => Some(V3::new( 0, -1, 0)),
+        _ => None,
+    }
+}
+
+fn blocks_headroom(shape: Shape) -> bool {
+    shape == Shape::Solid || shape.is_ramp()
+}
+
+/// Check if an entity `height` tiles tall can stand at tile `pos`.
+fn standable<S: ShapeSource>(chunk: &S, pos: V3, height: i32) -> bool {
+    let shape = chunk.get_shape(pos);
+    // An entity partway up a ramp sticks up into one more tile than one standing on a floor.
+    let top =
+        if shape == Shape::Floor {
+            height
+        } else if shape.is_ramp() {
+            height + 1
+        } else {
+            return false;
+        };
+    (1 .. top).all(|dz| !blocks_headroom(chunk.get_shape(pos + V3::new(0, 0, dz))))
+}
+
+/// Find the node reached by walking from level `pos.z` in tile `pos` one tile in the horizontal
+/// direction `dir`.  This may land on a floor at the same level, on the bottom of a ramp going up,
+/// or on the top of a ramp going down.
+fn enter<S: ShapeSource>(chunk: &S, pos: V3, dir: V3, height: i32) -> Option<V3> {
+    let target = pos + dir;
+    let shape = chunk.get_shape(target);
+    let result =
+        if shape == Shape::Floor || ramp_dir(shape) == Some(dir) {
+            target
+        } else if shape == Shape::Empty &&
+                  ramp_dir(chunk.get_shape(target - V3::new(0, 0, 1))) == Some(-dir) {
+            target - V3::new(0, 0, 1)
+        } else {
+            return None;
+        };
+
+    if standable(chunk, result, height) {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Find all nodes reachable in one step from `pos`, along with the cost of each step.  Returns
+/// the number of entries of `out` that were filled in.
+fn find_neighbors<S: ShapeSource>(chunk: &S,
+                                  pos: V3,
+                                  height: i32,
+                                  out: &mut [(V3, i32); 8]) -> usize {
+    let mut count = 0;
+
+    if let Some(dir) = ramp_dir(chunk.get_shape(pos)) {
+        // The only ways off a ramp are up or down the slope.
+        if let Some(p) = enter(chunk, pos + V3::new(0, 0, 1), dir, height) {
+            out[count] = (p, COST_STRAIGHT);
+            count += 1;
+        }
+        if let Some(p) = enter(chunk, pos, -dir, height) {
+            out[count] = (p, COST_STRAIGHT);
+            count += 1;
+        }
+        return count;
+    }
+
+    for &(dx, dy) in &DIRS[.. 4] {
+        if let Some(p) = enter(chunk, pos, V3::new(dx, dy, 0), height) {
+            out[count] = (p, COST_STRAIGHT);
+            count += 1;
+        }
+    }
+
+    // Diagonal moves are allowed only between floors on the same level, and only when the entity
+    // won't clip the corner of either tile beside the diagonal.
+    let open = |p: V3| chunk.get_shape(p) == Shape::Floor && standable(chunk, p, height);
+    for &(dx, dy) in &DIRS[4 ..] {
+        let target = pos + V3::new(dx, dy, 0);
+        if open(target) && open(pos + V3::new(dx, 0, 0)) && open(pos + V3::new(0, dy, 0)) {
+            out[count] = (target, COST_DIAGONAL);
+            count += 1;
+        }
+    }
+
+    count
+}
+
+
+#[cfg(test)]
+mod tests {
+    use core::prelude::*;
+    use std::vec::Vec;
+
+    use v3::{V3, scalar};
+
+    use super::{Pathfinder, Node, HeapEntry};
+    use super::super::{Shape, ShapeSource};
+
+    /// A single level of terrain, drawn as rows of `.` for floor and `#` for wall.  Everything
+    /// outside the rows is empty.
+    struct Map(&'static [&'static str]);
+
+    impl ShapeSource for Map {
+        fn get_shape(&self, pos: V3) -> Shape {
+            if pos.x < 0 || pos.y < 0 || pos.y as usize >= self.0.len() {
+                return Shape::Empty;
+            }
+            let row = self.0[pos.y as usize].as_bytes();
+            if pos.x as usize >= row.len() {
+                return Shape::Empty;
+            }
+            match (row[pos.x as usize], pos.z) {
+                (b'.', 0) => Shape::Floor,
+                (b'#', 0) | (b'#', 1) => Shape::Solid,
+                _ => Shape::Empty,
+            }
+        }
+    }
+
+    /// Floor everywhere on level 0.
+    struct Plain;
+
+    impl ShapeSource for Plain {
+        fn get_shape(&self, pos: V3) -> Shape {
+            if pos.z == 0 { Shape::Floor } else { Shape::Empty }
+        }
+    }
+
+    fn find<S: ShapeSource>(source: &S, start: V3, goal: V3, storage: usize) -> Option<Vec<V3>> {
+        let mut nodes = vec![Node::new(); storage];
+        let mut heap = vec![HeapEntry::new(); storage];
+        let mut pathfinder = Pathfinder::new(&mut nodes, &mut heap);
+        pathfinder.find_path(source, start, goal, V3::new(32, 32, 64))
+                  .map(|path| path.collect())
+    }
+
+    #[test]
+    fn walk_around_wall() {
+        let map = Map(&["..#..",
+                        "..#..",
+                        "....."]);
+        let start = V3::new(0, 0, 0);
+        let goal = V3::new(4, 0, 0);
+        let path = find(&map, start, goal, 256).unwrap();
+
+        assert_eq!(path[0], start);
+        assert_eq!(path[path.len() - 1], goal);
+        for i in 1 .. path.len() {
+            let step = (path[i] - path[i - 1]).abs();
+            assert!(step.x <= 1 && step.y <= 1 && step.z == 0);
+            assert_eq!(map.get_shape(path[i]), Shape::Floor);
+        }
+    }
+
+    #[test]
+    fn unreachable_goal() {
+        let map = Map(&["..#..",
+                        "..#..",
+                        "..#.."]);
+        assert!(find(&map, V3::new(0, 0, 0), V3::new(4, 0, 0), 256).is_none());
+    }
+
+    #[test]
+    fn blocked_start() {
+        let map = Map(&[".....",
+                        "..#..",
+                        "....."]);
+        assert!(find(&map, V3::new(2, 1, 0), V3::new(4, 0, 0), 256).is_none());
+        assert!(find(&map, V3::new(4, 0, 0), V3::new(2, 1, 0), 256).is_none());
+    }
+
+    #[test]
+    fn search_runs_out_of_storage() {
+        let start = scalar(0);
+        let goal = V3::new(40, 30, 0);
+        assert!(find(&Plain, start, goal, 64).is_none());
+        assert!(find(&Plain, start, goal, 4096).is_some());
+    }
+}
@@ -1,6 +1,7 @@
 use std::borrow::ToOwned;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::iter::repeat;
+use std::{u8, u16, u32};
 use rand::Rng;
 use rustc_serialize::json::Json;
 
@@ -20,6 +21,8 @@ pub struct Data {
     pub structure_templates: StructureTemplates,
     pub animations: AnimationData,
     pub loot_tables: LootTables,
+    pub vault_prefabs: VaultPrefabs,
+    pub migrations: Migrations,
 }
 
 impl Data {
@@ -28,13 +31,20 @@ impl Data {
                      recipe_json: Json,
                      structure_template_json: Json,
                      animation_json: Json,
-                     loot_table_json: Json) -> Result<Data, ParseError> {
+                     loot_table_json: Json,
+                     vault_json: Json,
+                     migration_json: Json) -> Result<Data, ParseError> {
         let block_data = try!(BlockData::from_json(block_json));
         let item_data = try!(ItemData::from_json(item_json));
         let recipes = try!(RecipeData::from_json(recipe_json));
         let structure_templates = try!(StructureTemplates::from_json(structure_template_json));
         let animations = try!(AnimationData::from_json(animation_json));
         let loot_tables = try!(LootTables::from_json(loot_table_json));
+        let vault_prefabs = try!(VaultPrefabs::from_json(vault_json,
+                                                         &block_data,
+                                                         &structure_templates,
+                                                         &loot_tables));
+        let migrations = try!(Migrations::from_json(migration_json));
         Ok(Data {
             block_data: block_data,
             item_data: item_data,
@@ -42,6 +52,8 @@ impl Data {
             structure_templates: structure_templates,
             animations: animations,
             loot_tables: loot_tables,
+            vault_prefabs: vault_prefabs,
+            migrations: migrations,
         })
     }
 }
@@ -135,6 +147,9 @@ impl BlockData {
                 "empty" => Shape::Empty,
                 "floor" => Shape::Floor,
                 "solid" => Shape::Solid,
+                "ramp_e" => Shape::RampE,
+                "ramp_w" => Shape::RampW,
+                "ramp_s" => Shape::RampS,
                 "ramp_n" => Shape::RampN,
                 _ => return fail!("invalid shape \"{}\" for block {} ({})",
                                   shape_str, i, name),
@@ -219,6 +234,9 @@ pub struct Recipe {
     pub inputs: HashMap<ItemId, u8>,
     pub outputs: HashMap<ItemId, u8>,
     pub station: Option<TemplateId>,
+    /// Time (in milliseconds) to craft the recipe once.  Recipes with a nonzero time run as jobs
+    /// on the station structure instead of completing immediately.
+    pub time: u32,
 }
 
 pub struct RecipeData {
@@ -242,6 +260,18 @@ impl RecipeData {
                 Ok(station) => Some(station as TemplateId),
                 Err(_) => None,
             };
+            // Recipes without a time are crafted instantly.
+            let time = match recipe.find("time") {
+                Some(_) => {
+                    let time = get_convert!(recipe, "time", as_u64,
+                                            "for recipe {}", i);
+                    if time > u32::MAX as u64 {
+                        return fail!("time out of range for recipe {}", i);
+                    }
+                    time as u32
+                },
+                None => 0,
+            };
 
             fn build_map(list: &[Json], what: &str, i: usize) -> Result<HashMap<ItemId, u8>, ParseError> {
                 let mut map = HashMap::new();
@@ -273,6 +303,7 @@ impl RecipeData {
                 inputs: inputs,
                 outputs: outputs,
                 station: station,
+                time: time,
             });
             name_to_id.insert(name.to_owned(), i as RecipeId); 
         }
@@ -589,3 +620,371 @@ impl LootTables {
         result
     }
 }
+
+
+/// A structure placed as part of a `VaultPrefab`.
+pub struct PrefabStructure {
+    /// Position relative to the prefab's origin.  `z` is relative to the floor of the dungeon
+    /// layer.
+    pub pos: V3,
+    pub template: TemplateId,
+    /// Name of an item loot table used to fill the structure's `contents` inventory.
+    pub loot: Option<String>,
+}
+
+/// A pre-designed dungeon room, loaded from the data directory.  The dungeon generator places
+/// these like its built-in vaults.
+pub struct VaultPrefab {
+    pub name: String,
+    /// Size of the room in tiles.
+    pub size: V2,
+    /// Cave wall settings for the corners of the room's tiles, in the region from `0` to `size +
+    /// 1`.  `Some(true)` forces a wall, `Some(false)` forces open floor, and `None` leaves the
+    /// corner up to the cave generator.
+    pub walls: Box<[Option<bool>]>,
+    /// Blocks to place over the generated terrain.  Positions are relative to the prefab's origin
+    /// and to the floor of the dungeon layer.
+    pub blocks: Vec<(V3, BlockId)>,
+    pub structures: Vec<PrefabStructure>,
+    /// Tiles inside the room where tunnels can connect to it, relative to its origin.
+    pub connections: Vec<V2>,
+    /// Relative likelihood of choosing this prefab for a treasure spot.
+    pub weight: u16,
+    /// Minimum dungeon level (number of doors and puzzles between the spot and the entrance)
+    /// where this prefab can appear.
+    pub min_level: u8,
+}
+
+impl VaultPrefab {
+    pub fn bounds(&self) -> Region<V2> {
+        Region::new(scalar(0), self.size)
+    }
+}
+
+/// Dungeon room prefabs.  The JSON form is an array of objects, each with keys `name`, `size`
+/// (`[w, h]`), `grid` (one string per row of tiles: `#` for wall, `.` for floor, and space for
+/// either), `blocks` and `structures` (lists of objects with a `pos` and a block or `template`
+/// name, plus an optional `loot` table for structures), `connections` (list of `[x, y]`),
+/// `weight`, and `min_level`.
+pub struct VaultPrefabs {
+    prefabs: Vec<VaultPrefab>,
+    name_to_id: HashMap<String, usize>,
+}
+
+impl VaultPrefabs {
+    pub fn from_json(json: Json,
+                     block_data: &BlockData,
+                     templates: &StructureTemplates,
+                     loot_tables: &LootTables) -> Result<VaultPrefabs, ParseError> {
+        let vaults = expect!(json.as_array(),
+                             "found non-array at top level");
+
+        fn get_coords(json: &Json, len: usize) -> Option<Vec<i32>> {
+            let arr = match json.as_array() {
+                Some(x) => x,
+                None => return None,
+            };
+            if arr.len() != len {
+                return None;
+            }
+            let mut result = Vec::with_capacity(len);
+            for x in arr {
+                match x.as_i64() {
+                    Some(x) => result.push(x as i32),
+                    None => return None,
+                }
+            }
+            Some(result)
+        }
+
+        let mut prefabs = Vec::with_capacity(vaults.len());
+        let mut name_to_id = HashMap::new();
+
+        for (i, vault) in vaults.iter().enumerate() {
+            let name = get_convert!(vault, "name", as_string,
+                                    "for vault {}", i);
+            if name_to_id.contains_key(name) {
+                return fail!("duplicate vault prefab name {:?} in vaults[{}]", name, i);
+            }
+            let size_json = expect!(vault.find("size"),
+                                    "missing key \"size\" for vault {} ({})", i, name);
+            let size = expect!(get_coords(size_json, 2),
+                               "expected [w, h] in vaults[{}].size ({})", i, name);
+            let size = V2::new(size[0], size[1]);
+            if size.x <= 0 || size.y <= 0 {
+                return fail!("empty size in vaults[{}].size ({})", i, name);
+            }
+
+            // Convert the tile grid to wall settings for the tile corners.  Floor tiles take
+            // priority over walls, so a wall tile next to a floor tile keeps its shared corners
+            // open.
+            let rows = get_convert!(vault, "grid", as_array,
+                                    "for vault {} ({})", i, name);
+            if rows.len() != size.y as usize {
+                return fail!("wrong number of rows in vaults[{}].grid ({})", i, name);
+            }
+            let corner_bounds = Region::new(scalar(0), size + scalar(1));
+            let mut walls = repeat(None).take(corner_bounds.volume() as usize)
+                                        .collect::<Vec<_>>().into_boxed_slice();
+            for &(ch, val) in &[('#', true), ('.', false)] {
+                for (y, row) in rows.iter().enumerate() {
+                    let row = expect!(row.as_string(),
+                                      "non-string in vaults[{}].grid ({})", i, name);
+                    if row.chars().count() != size.x as usize {
+                        return fail!("wrong length for vaults[{}].grid[{}] ({})", i, y, name);
+                    }
+                    for (x, c) in row.chars().enumerate() {
+                        if c != ch {
+                            if c != '#' && c != '.' && c != ' ' {
+                                return fail!("bad character {:?} in vaults[{}].grid[{}] ({})",
+                                             c, i, y, name);
+                            }
+                            continue;
+                        }
+                        let pos = V2::new(x as i32, y as i32);
+                        for corner in Region::new(pos, pos + scalar(2)).points() {
+                            walls[corner_bounds.index(corner)] = Some(val);
+                        }
+                    }
+                }
+            }
+
+            let mut blocks = Vec::new();
+            if let Some(blocks_json) = vault.find("blocks") {
+                let blocks_arr = expect!(blocks_json.as_array(),
+                                         "non-array in vaults[{}].blocks ({})", i, name);
+                for (j, b) in blocks_arr.iter().enumerate() {
+                    let pos = expect!(b.find("pos").and_then(|p| get_coords(p, 3)),
+                                      "expected [x, y, z] in vaults[{}].blocks[{}] ({})",
+                                      i, j, name);
+                    let block_name = get_convert!(b, "block", as_string,
+                                                  "for vaults[{}].blocks[{}] ({})", i, j, name);
+                    let block_id = expect!(block_data.find_id(block_name),
+                                           "unknown block {:?} in vaults[{}].blocks[{}] ({})",
+                                           block_name, i, j, name);
+                    blocks.push((V3::new(pos[0], pos[1], pos[2]), block_id));
+                }
+            }
+
+            let mut structures = Vec::new();
+            if let Some(structures_json) = vault.find("structures") {
+                let structures_arr = expect!(structures_json.as_array(),
+                                             "non-array in vaults[{}].structures ({})", i, name);
+                for (j, s) in structures_arr.iter().enumerate() {
+                    let pos = expect!(s.find("pos").and_then(|p| get_coords(p, 3)),
+                                      "expected [x, y, z] in vaults[{}].structures[{}] ({})",
+                                      i, j, name);
+                    let template_name = get_convert!(s, "template", as_string,
+                                                     "for vaults[{}].structures[{}] ({})",
+                                                     i, j, name);
+                    let template_id = expect!(templates.find_id(template_name),
+                                              "unknown template {:?} in \
+                                               vaults[{}].structures[{}] ({})",
+                                              template_name, i, j, name);
+                    let loot = match s.find("loot") {
+                        None | Some(&Json::Null) => None,
+                        Some(j_loot) => {
+                            let table = expect!(j_loot.as_string(),
+                                                "non-string loot in vaults[{}].structures[{}] \
+                                                 ({})", i, j, name);
+                            if !loot_tables.item_by_name.contains_key(table) {
+                                return fail!("unknown loot table {:?} in \
+                                              vaults[{}].structures[{}] ({})",
+                                             table, i, j, name);
+                            }
+                            Some(table.to_owned())
+                        },
+                    };
+                    structures.push(PrefabStructure {
+                        pos: V3::new(pos[0], pos[1], pos[2]),
+                        template: template_id,
+                        loot: loot,
+                    });
+                }
+            }
+
+            let mut connections = Vec::new();
+            if let Some(connections_json) = vault.find("connections") {
+                let connections_arr = expect!(connections_json.as_array(),
+                                              "non-array in vaults[{}].connections ({})",
+                                              i, name);
+                for (j, c) in connections_arr.iter().enumerate() {
+                    let pos = expect!(get_coords(c, 2),
+                                      "expected [x, y] in vaults[{}].connections[{}] ({})",
+                                      i, j, name);
+                    let pos = V2::new(pos[0], pos[1]);
+                    if !Region::new(scalar(0), size).contains(pos) {
+                        return fail!("vaults[{}].connections[{}] is outside the room ({})",
+                                     i, j, name);
+                    }
+                    connections.push(pos);
+                }
+            }
+
+            let weight = get_convert!(vault, "weight", as_i64,
+                                      "for vault {} ({})", i, name);
+            if weight < 0 || weight > u16::MAX as i64 {
+                return fail!("vaults[{}].weight is out of range: {} ({})", i, weight, name);
+            }
+            let min_level = get_convert!(vault, "min_level", as_i64,
+                                         "for vault {} ({})", i, name);
+            if min_level < 0 || min_level > u8::MAX as i64 {
+                return fail!("vaults[{}].min_level is out of range: {} ({})",
+                             i, min_level, name);
+            }
+
+            info!("parsed vault prefab: {}", name);
+            prefabs.push(VaultPrefab {
+                name: name.to_owned(),
+                size: size,
+                walls: walls,
+                blocks: blocks,
+                structures: structures,
+                connections: connections,
+                weight: weight as u16,
+                min_level: min_level as u8,
+            });
+            name_to_id.insert(name.to_owned(), i);
+        }
+
+        Ok(VaultPrefabs {
+            prefabs: prefabs,
+            name_to_id: name_to_id,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefabs.len()
+    }
+
+    pub fn prefab(&self, idx: usize) -> &VaultPrefab {
+        &self.prefabs[idx]
+    }
+
+    pub fn find(&self, name: &str) -> Option<&VaultPrefab> {
+        self.name_to_id.get(name).map(|&idx| &self.prefabs[idx])
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<VaultPrefab> {
+        self.prefabs.iter()
+    }
+}
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Migration<'a> {
+    /// Load objects saved with the old name as if they had this name instead.
+    Replace(&'a str),
+    /// Discard objects saved with the old name.
+    Drop,
+}
+
+/// Table of block, item, structure, and recipe names to change when loading a save.  This lets
+/// definitions be renamed or removed without breaking saves that still refer to them.
+///
+/// The JSON form is an object with optional keys `blocks`, `items`, `structures`, and `recipes`.
+/// Each maps old names to either a new name or `null` (to drop the object).  An optional
+/// `resized_structures` key lists old structure names that may be loaded even though their
+/// template now has a different size.
+pub struct Migrations {
+    blocks: HashMap<String, Option<String>>,
+    items: HashMap<String, Option<String>>,
+    structures: HashMap<String, Option<String>>,
+    recipes: HashMap<String, Option<String>>,
+    resized_structures: HashSet<String>,
+}
+
+impl Migrations {
+    pub fn new() -> Migrations {
+        Migrations {
+            blocks: HashMap::new(),
+            items: HashMap::new(),
+            structures: HashMap::new(),
+            recipes: HashMap::new(),
+            resized_structures: HashSet::new(),
+        }
+    }
+
+    pub fn from_json(json: Json) -> Result<Migrations, ParseError> {
+        if json.is_null() {
+            return Ok(Migrations::new());
+        }
+
+        fn build_map(json: &Json,
+                     key: &str) -> Result<HashMap<String, Option<String>>, ParseError> {
+            let mut map = HashMap::new();
+            let obj = match json.find(key) {
+                Some(j) => expect!(j.as_object(),
+                                   "found non-object for migration key \"{}\"", key),
+                None => return Ok(map),
+            };
+            for (old, new) in obj.iter() {
+                let new =
+                    if new.is_null() {
+                        None
+                    } else {
+                        Some(expect!(new.as_string(),
+                                     "found non-string for migration of {} \"{}\"", key, old)
+                             .to_owned())
+                    };
+                map.insert(old.clone(), new);
+            }
+            Ok(map)
+        }
+
+        fn build_set(json: &Json, key: &str) -> Result<HashSet<String>, ParseError> {
+            let mut set = HashSet::new();
+            let arr = match json.find(key) {
+                Some(j) => expect!(j.as_array(),
+                                   "found non-array for migration key \"{}\"", key),
+                None => return Ok(set),
+            };
+            for name in arr.iter() {
+                let name = expect!(name.as_string(),
+                                   "found non-string in migration key \"{}\"", key);
+                set.insert(name.to_owned());
+            }
+            Ok(set)
+        }
+
+        expect!(json.as_object(), "found non-object at top level");
+        Ok(Migrations {
+            blocks: try!(build_map(&json, "blocks")),
+            items: try!(build_map(&json, "items")),
+            structures: try!(build_map(&json, "structures")),
+            recipes: try!(build_map(&json, "recipes")),
+            resized_structures: try!(build_set(&json, "resized_structures")),
+        })
+    }
+
+    pub fn block(&self, name: &str) -> Option<Migration> {
+        lookup_migration(&self.blocks, name)
+    }
+
+    pub fn item(&self, name: &str) -> Option<Migration> {
+        lookup_migration(&self.items, name)
+    }
+
+    pub fn structure(&self, name: &str) -> Option<Migration> {
+        lookup_migration(&self.structures, name)
+    }
+
+    pub fn recipe(&self, name: &str) -> Option<Migration> {
+        lookup_migration(&self.recipes, name)
+    }
+
+    /// Check if structures saved with this name may be loaded with a template of a different
+    /// size.  By default this is refused, since the resized structure could overlap its
+    /// neighbors.
+    pub fn structure_resize_allowed(&self, name: &str) -> bool {
+        self.resized_structures.contains(name)
+    }
+}
+
+fn lookup_migration<'a>(map: &'a HashMap<String, Option<String>>,
+                        name: &str) -> Option<Migration<'a>> {
+    map.get(name).map(|new| match *new {
+        Some(ref new) => Migration::Replace(new),
+        None => Migration::Drop,
+    })
+}
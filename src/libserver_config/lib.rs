@@ -13,3 +13,4 @@ pub use storage::Storage;
 pub mod data;
 pub mod storage;
 pub mod loot;
+pub mod region;
@@ -0,0 +1,278 @@
+//! Region files, which pack up to `REGION_SIZE` small save files into a single file on disk.
+//!
+//! A region file starts with a header and a table of `REGION_SIZE` entries, followed by the data
+//! for each entry.  New data is always appended to the end of the file, and the table entry is
+//! updated only after the data is safely on disk, so a crash in the middle of a write leaves the
+//! previous contents intact.  Each table entry also remembers the location of the previous
+//! contents, which serves the same purpose as the `.bak` files kept by `SaveFile`.
+//!
+//! Replaced data is left in place until the wasted space grows large enough, at which point the
+//! whole region is rewritten.
+//!
+//! None of these functions do any locking.  The caller must make sure that only one thread at a
+//! time accesses a given region file.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use storage::sync_parent_dir;
+
+
+/// Number of entries in each region file.
+pub const REGION_SIZE: usize = 256;
+
+const MAGIC: &'static [u8] = b"OREG";
+const VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = 8;
+/// Each table entry holds the offset and length of the current and previous contents.
+const ENTRY_SIZE: usize = 16;
+const TABLE_SIZE: usize = ENTRY_SIZE * REGION_SIZE;
+const DATA_START: u64 = (HEADER_SIZE + TABLE_SIZE) as u64;
+
+/// Don't bother compacting a region until it wastes at least this many bytes.
+const COMPACT_MIN_WASTE: u64 = 256 * 1024;
+
+
+#[derive(Clone, Copy)]
+struct Slot {
+    /// Offset of the data within the file.  Zero means the slot is empty.
+    offset: u32,
+    len: u32,
+}
+
+impl Slot {
+    fn empty() -> Slot {
+        Slot {
+            offset: 0,
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset == 0
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    cur: Slot,
+    old: Slot,
+}
+
+struct Table {
+    entries: Vec<Entry>,
+}
+
+impl Table {
+    fn new() -> Table {
+        Table {
+            entries: vec![Entry { cur: Slot::empty(), old: Slot::empty() }; REGION_SIZE],
+        }
+    }
+
+    fn read_from(f: &mut File) -> io::Result<Table> {
+        let mut header = [0; HEADER_SIZE];
+        try!(read_full(f, &mut header));
+        if &header[0 .. 4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a region file"));
+        }
+        if get_u32(&header[4 .. 8]) != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "unsupported region file version"));
+        }
+
+        let mut buf = vec![0; TABLE_SIZE];
+        try!(read_full(f, &mut buf));
+        let mut table = Table::new();
+        for (i, e) in table.entries.iter_mut().enumerate() {
+            e.cur = get_slot(&buf[i * ENTRY_SIZE ..]);
+            e.old = get_slot(&buf[i * ENTRY_SIZE + 8 ..]);
+        }
+        Ok(table)
+    }
+
+    fn write_to(&self, f: &mut File) -> io::Result<()> {
+        let mut buf = vec![0; HEADER_SIZE + TABLE_SIZE];
+        for (dest, &b) in buf.iter_mut().zip(MAGIC.iter()) {
+            *dest = b;
+        }
+        put_u32(&mut buf[4 .. 8], VERSION);
+        for (i, e) in self.entries.iter().enumerate() {
+            let base = HEADER_SIZE + i * ENTRY_SIZE;
+            put_slot(&mut buf[base ..], e.cur);
+            put_slot(&mut buf[base + 8 ..], e.old);
+        }
+        f.write_all(&buf)
+    }
+
+    fn write_entry(&self, f: &mut File, idx: usize) -> io::Result<()> {
+        let e = self.entries[idx];
+        let mut buf = [0; ENTRY_SIZE];
+        put_slot(&mut buf[0 ..], e.cur);
+        put_slot(&mut buf[8 ..], e.old);
+        try!(f.seek(SeekFrom::Start((HEADER_SIZE + idx * ENTRY_SIZE) as u64)));
+        f.write_all(&buf)
+    }
+
+    /// Total size of all data still referenced by the table.
+    fn live_bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.cur.len as u64 + e.old.len as u64).fold(0, |a, b| a + b)
+    }
+}
+
+
+/// Read entry `idx` of the region file at `path`.  If `backup` is set, read the previous contents
+/// of the entry instead of the current ones.  Returns `None` if the region file or the entry does
+/// not exist.
+pub fn read_entry(path: &Path, idx: usize, backup: bool) -> io::Result<Option<Vec<u8>>> {
+    let mut f = match File::open(path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let table = try!(Table::read_from(&mut f));
+    let e = table.entries[idx];
+    let slot = if backup { e.old } else { e.cur };
+    if slot.is_empty() {
+        return Ok(None);
+    }
+    read_slot(&mut f, slot).map(Some)
+}
+
+/// Replace the contents of entry `idx` of the region file at `path`, creating the file if it
+/// doesn't exist.  If `keep_backup` is set, the old contents remain available through
+/// `read_entry` until the next write.
+///
+/// Writes without `keep_backup` are not synced to disk, so a crash may leave the entry empty or
+/// damaged.  This is meant for data like summaries, which can be regenerated.
+pub fn write_entry(path: &Path, idx: usize, data: &[u8], keep_backup: bool) -> io::Result<()> {
+    try!(fs::create_dir_all(path.parent().unwrap()));
+    let mut f = try!(OpenOptions::new().read(true).write(true).create(true).open(path));
+
+    let mut table =
+        if try!(f.metadata()).len() == 0 {
+            let table = Table::new();
+            try!(table.write_to(&mut f));
+            table
+        } else {
+            try!(Table::read_from(&mut f))
+        };
+
+    let end = try!(f.seek(SeekFrom::End(0)));
+    let slot = try!(append(&mut f, end, data));
+    if keep_backup {
+        try!(f.sync_data());
+    }
+
+    {
+        let e = &mut table.entries[idx];
+        e.old = if keep_backup { e.cur } else { Slot::empty() };
+        e.cur = slot;
+    }
+    try!(table.write_entry(&mut f, idx));
+    if keep_backup {
+        try!(f.sync_data());
+    }
+
+    let size = end + data.len() as u64;
+    let live = table.live_bytes();
+    let waste = size - DATA_START - live;
+    if waste >= COMPACT_MIN_WASTE && waste > live {
+        try!(compact(path, &mut f, &table));
+    }
+    Ok(())
+}
+
+/// Rewrite the region file at `path`, dropping all data that is no longer referenced.
+fn compact(path: &Path, f: &mut File, table: &Table) -> io::Result<()> {
+    let tmp_path = tmp_path(path);
+    let mut tmp = try!(File::create(&tmp_path));
+
+    let mut new_table = Table::new();
+    try!(new_table.write_to(&mut tmp));
+    let mut end = DATA_START;
+    for (old_e, new_e) in table.entries.iter().zip(new_table.entries.iter_mut()) {
+        if !old_e.cur.is_empty() {
+            let data = try!(read_slot(f, old_e.cur));
+            new_e.cur = try!(append(&mut tmp, end, &data));
+            end += data.len() as u64;
+        }
+        if !old_e.old.is_empty() {
+            let data = try!(read_slot(f, old_e.old));
+            new_e.old = try!(append(&mut tmp, end, &data));
+            end += data.len() as u64;
+        }
+    }
+
+    try!(tmp.seek(SeekFrom::Start(0)));
+    try!(new_table.write_to(&mut tmp));
+    try!(tmp.sync_all());
+    try!(fs::rename(&tmp_path, path));
+    sync_parent_dir(path)
+}
+
+
+fn append(f: &mut File, end: u64, data: &[u8]) -> io::Result<Slot> {
+    if end + data.len() as u64 > u32::max_value() as u64 {
+        return Err(io::Error::new(io::ErrorKind::Other, "region file is too large"));
+    }
+    try!(f.seek(SeekFrom::Start(end)));
+    try!(f.write_all(data));
+    Ok(Slot {
+        offset: end as u32,
+        len: data.len() as u32,
+    })
+}
+
+fn read_slot(f: &mut File, slot: Slot) -> io::Result<Vec<u8>> {
+    try!(f.seek(SeekFrom::Start(slot.offset as u64)));
+    let mut buf = vec![0; slot.len as usize];
+    try!(read_full(f, &mut buf));
+    Ok(buf)
+}
+
+fn read_full(f: &mut File, mut buf: &mut [u8]) -> io::Result<()> {
+    while buf.len() > 0 {
+        let n = try!(f.read(buf));
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "unexpected end of region file"));
+        }
+        let tmp = buf;
+        buf = &mut tmp[n ..];
+    }
+    Ok(())
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".tmp");
+    PathBuf::from(s)
+}
+
+
+fn get_u32(buf: &[u8]) -> u32 {
+    (buf[0] as u32) |
+    (buf[1] as u32) << 8 |
+    (buf[2] as u32) << 16 |
+    (buf[3] as u32) << 24
+}
+
+fn put_u32(buf: &mut [u8], x: u32) {
+    buf[0] = x as u8;
+    buf[1] = (x >> 8) as u8;
+    buf[2] = (x >> 16) as u8;
+    buf[3] = (x >> 24) as u8;
+}
+
+fn get_slot(buf: &[u8]) -> Slot {
+    Slot {
+        offset: get_u32(&buf[0 .. 4]),
+        len: get_u32(&buf[4 .. 8]),
+    }
+}
+
+fn put_slot(buf: &mut [u8], slot: Slot) {
+    put_u32(&mut buf[0 .. 4], slot.offset);
+    put_u32(&mut buf[4 .. 8], slot.len);
+}
@@ -1,12 +1,16 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::fs::{self, File};
-use std::io;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use libphysics::v3::V2;
 use libserver_types::{Stable, PlaneId, TerrainChunkId};
 
+use region::{self, REGION_SIZE};
+
 
 const DATA_DIR: &'static str = "data";
 const BLOCK_DATA_FILE: &'static str = "blocks.json";
@@ -16,6 +20,9 @@ const OLD_TEMPLATE_DATA_FILE: &'static str = "objects.json";
 const TEMPLATE_DATA_FILE: &'static str = "structures.json";
 const ANIMATION_DATA_FILE: &'static str = "animations.json";
 const LOOT_TABLE_DATA_FILE: &'static str = "loot_tables.json";
+const VAULT_DATA_FILE: &'static str = "vaults.json";
+const MIGRATION_DATA_FILE: &'static str = "migrations.json";
+const RATE_LIMIT_DATA_FILE: &'static str = "rate_limits.json";
 
 const SCRIPT_DIR: &'static str = "scripts";
 
@@ -28,9 +35,20 @@ const WORLD_FILE_NAME: &'static str = "world.dat";
 const MISC_FILE_NAME: &'static str = "misc.dat";
 const AUTH_DB_FILE_NAME: &'static str = "auth.sqlite";
 const RESTART_FILE_NAME: &'static str = "restart.dat";
+const RATE_LIMIT_LOG_FILE_NAME: &'static str = "rate_limit.log";
+
+/// Summary region files cover a square of `1 << SUMMARY_REGION_BITS` chunks on each side.
+const SUMMARY_REGION_BITS: usize = 4;
+const SUMMARY_REGION_MASK: i32 = (1 << SUMMARY_REGION_BITS) - 1;
 
 pub struct Storage {
     base: PathBuf,
+    /// One lock for each region file, held while accessing that file.  Summaries are read and
+    /// written by the terrain generation threads, so several threads may touch the same region at
+    /// once.  Locks are never removed, but there are few enough region files that this is fine.
+    region_locks: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+    /// The rate limit log, opened on first use and then kept open.
+    rate_limit_log: Mutex<Option<File>>,
 }
 
 impl Storage {
@@ -42,6 +60,8 @@ impl Storage {
 
         Storage {
             base: base,
+            region_locks: Mutex::new(HashMap::new()),
+            rate_limit_log: Mutex::new(None),
         }
     }
 
@@ -78,6 +98,21 @@ impl Storage {
         File::open(self.data_path(LOOT_TABLE_DATA_FILE)).unwrap()
     }
 
+    pub fn open_vault_data(&self) -> File {
+        File::open(self.data_path(VAULT_DATA_FILE)).unwrap()
+    }
+
+    /// Open the save migration table.  Unlike the other data files, this one is optional.
+    pub fn open_migration_data(&self) -> Option<File> {
+        try_open_file(self.data_path(MIGRATION_DATA_FILE))
+    }
+
+    /// Open the request rate limit settings.  This file is optional; any limits it doesn't set
+    /// keep their built-in defaults.
+    pub fn open_rate_limit_data(&self) -> Option<File> {
+        try_open_file(self.data_path(RATE_LIMIT_DATA_FILE))
+    }
+
 
     pub fn script_dir(&self) -> PathBuf {
         self.base.join(SCRIPT_DIR)
@@ -107,24 +142,43 @@ impl Storage {
             .with_extension("plane")
     }
 
-    pub fn terrain_chunk_path(&self, stable_tcid: Stable<TerrainChunkId>) -> PathBuf {
-        self.base.join(SAVE_DIR).join(TERRAIN_CHUNK_DIR)
-            .join(format!("{:x}", stable_tcid.unwrap()))
-            .with_extension("terrain_chunk")
+    /// Get the region file containing the terrain chunk, and the chunk's index within it.
+    /// Terrain chunks are grouped by stable ID, since those are mostly assigned to neighboring
+    /// chunks in sequence.
+    pub fn terrain_chunk_region(&self, stable_tcid: Stable<TerrainChunkId>) -> (PathBuf, usize) {
+        let id = stable_tcid.unwrap();
+        let path = self.base.join(SAVE_DIR).join(TERRAIN_CHUNK_DIR)
+            .join(format!("{:x}", id / REGION_SIZE as u64))
+            .with_extension("region");
+        (path, (id % REGION_SIZE as u64) as usize)
     }
 
     pub fn restart_file_path(&self) -> PathBuf {
         self.base.join(SAVE_DIR).join(RESTART_FILE_NAME)
     }
 
-    pub fn summary_file_path(&self,
-                             name: &str,
-                             stable_pid: Stable<PlaneId>,
-                             cpos: V2) -> PathBuf {
+    pub fn rate_limit_log_path(&self) -> PathBuf {
+        self.base.join(SAVE_DIR).join(RATE_LIMIT_LOG_FILE_NAME)
+    }
+
+    /// Get the region file containing the summary, and the summary's index within it.
+    pub fn summary_region(&self,
+                          name: &str,
+                          stable_pid: Stable<PlaneId>,
+                          cpos: V2) -> (PathBuf, usize) {
+        let path = self.summary_dir(name, stable_pid)
+            .join(format!("{},{}.region",
+                          cpos.x >> SUMMARY_REGION_BITS,
+                          cpos.y >> SUMMARY_REGION_BITS));
+        let idx = ((cpos.y & SUMMARY_REGION_MASK) << SUMMARY_REGION_BITS) |
+                  (cpos.x & SUMMARY_REGION_MASK);
+        (path, idx as usize)
+    }
+
+    fn summary_dir(&self, name: &str, stable_pid: Stable<PlaneId>) -> PathBuf {
         self.base.join(SAVE_DIR).join(SUMMARY_DIR)
             .join(format!("{:x}", stable_pid.unwrap()))
             .join(name)
-            .join(format!("{},{}.dat", cpos.x, cpos.y))
     }
 
 
@@ -144,60 +198,405 @@ impl Storage {
         try_open_file(self.plane_path(stable_pid))
     }
 
-    pub fn open_terrain_chunk_file(&self, stable_tcid: Stable<TerrainChunkId>) -> Option<File> {
-        try_open_file(self.terrain_chunk_path(stable_tcid))
+    pub fn open_terrain_chunk_file(&self,
+                                   stable_tcid: Stable<TerrainChunkId>) -> Option<RegionEntry> {
+        let (path, idx) = self.terrain_chunk_region(stable_tcid);
+        self.try_read_region(&path, idx, false)
+    }
+
+    /// Open the previous version of a terrain chunk.  This is the region file equivalent of
+    /// `open_backup_file`.
+    pub fn open_terrain_chunk_backup(&self,
+                                     stable_tcid: Stable<TerrainChunkId>) -> Option<RegionEntry> {
+        let (path, idx) = self.terrain_chunk_region(stable_tcid);
+        self.try_read_region(&path, idx, true)
     }
 
     pub fn open_restart_file(&self) -> Option<File> {
         try_open_file(self.restart_file_path())
     }
 
+    /// Open the previous generation of the save file at `path`.  Every `create_*_file` method
+    /// except `create_summary_file` keeps the old contents as a backup when the new file is
+    /// committed.  For terrain chunks, which are stored in region files, use
+    /// `open_terrain_chunk_backup` instead.
+    pub fn open_backup_file<P: AsRef<Path>>(&self, path: P) -> Option<File> {
+        try_open_file(backup_path(path.as_ref()))
+    }
+
     pub fn open_summary_file(&self,
                              name: &str,
                              stable_pid: Stable<PlaneId>,
-                             cpos: V2) -> Option<File> {
-        try_open_file(self.summary_file_path(name, stable_pid, cpos))
+                             cpos: V2) -> Option<RegionEntry> {
+        let (path, idx) = self.summary_region(name, stable_pid, cpos);
+        self.try_read_region(&path, idx, false)
+    }
+
+    /// Read an entry from a region file.  Errors are logged and treated as a missing entry, so
+    /// one damaged region file doesn't take down the whole server, and callers can fall back on
+    /// the backup.
+    fn try_read_region(&self, path: &Path, idx: usize, backup: bool) -> Option<RegionEntry> {
+        let lock = self.region_lock(path);
+        let _guard = lock.lock().unwrap();
+        match region::read_entry(path, idx, backup) {
+            Ok(opt_data) => opt_data.map(io::Cursor::new),
+            Err(e) => {
+                warn!("error reading entry {} of region file {:?}: {}", idx, path, e);
+                None
+            },
+        }
+    }
+
+    /// Get the lock for the region file at `path`.
+    fn region_lock(&self, path: &Path) -> Arc<Mutex<()>> {
+        let mut locks = self.region_locks.lock().unwrap();
+        let lock = locks.entry(path.to_owned()).or_insert_with(|| Arc::new(Mutex::new(())));
+        lock.clone()
+    }
+
+    fn write_region(&self,
+                    path: &Path,
+                    idx: usize,
+                    data: &[u8],
+                    keep_backup: bool) -> io::Result<()> {
+        let lock = self.region_lock(path);
+        let _guard = lock.lock().unwrap();
+        region::write_entry(path, idx, data, keep_backup)
     }
 
 
-    pub fn create_world_file(&self) -> File {
-        File::create(self.world_path()).unwrap()
+    pub fn create_world_file(&self) -> io::Result<SaveFile> {
+        SaveFile::create(self.world_path())
     }
 
-    pub fn create_misc_file(&self) -> File {
-        File::create(self.misc_path()).unwrap()
+    pub fn create_misc_file(&self) -> io::Result<SaveFile> {
+        SaveFile::create(self.misc_path())
     }
 
-    pub fn create_client_file(&self, name: &str) -> File {
-        File::create(self.client_path(name)).unwrap()
+    pub fn create_client_file(&self, name: &str) -> io::Result<SaveFile> {
+        SaveFile::create(self.client_path(name))
     }
 
-    pub fn create_plane_file(&self, stable_pid: Stable<PlaneId>) -> File {
-        File::create(self.plane_path(stable_pid)).unwrap()
+    pub fn create_plane_file(&self, stable_pid: Stable<PlaneId>) -> io::Result<SaveFile> {
+        SaveFile::create(self.plane_path(stable_pid))
     }
 
-    pub fn create_terrain_chunk_file(&self, stable_tcid: Stable<TerrainChunkId>) -> File {
-        File::create(self.terrain_chunk_path(stable_tcid)).unwrap()
+    pub fn create_terrain_chunk_file(&self,
+                                     stable_tcid: Stable<TerrainChunkId>) -> RegionSaveFile {
+        let (path, idx) = self.terrain_chunk_region(stable_tcid);
+        RegionSaveFile::new(self, path, idx, true)
     }
 
     pub fn create_restart_file(&self) -> File {
         File::create(self.restart_file_path()).unwrap()
     }
 
+    /// Append `line` to the rate limit log, creating the log if it doesn't exist.
+    pub fn write_rate_limit_log(&self, line: &str) -> io::Result<()> {
+        let mut log = self.rate_limit_log.lock().unwrap();
+        if log.is_none() {
+            *log = Some(try!(OpenOptions::new().append(true).create(true)
+                                 .open(self.rate_limit_log_path())));
+        }
+        let result = log.as_mut().unwrap().write_all(line.as_bytes());
+        result
+    }
+
     pub fn remove_restart_file(&self) {
         fs::remove_file(self.restart_file_path()).unwrap()
     }
 
+    /// Delete a client's save file, along with its backup.  Files that don't exist are skipped.
+    pub fn remove_client_file(&self, name: &str) -> io::Result<()> {
+        let path = self.client_path(name);
+        try!(remove_if_exists(&path));
+        try!(remove_if_exists(&backup_path(&path)));
+        Ok(())
+    }
+
     pub fn create_summary_file(&self,
                                name: &str,
                                stable_pid: Stable<PlaneId>,
-                               cpos: V2) -> File {
-        let path = self.summary_file_path(name, stable_pid, cpos);
-        fs::create_dir_all(path.parent().unwrap()).unwrap();
-        File::create(path).unwrap()
+                               cpos: V2) -> RegionSaveFile {
+        let (path, idx) = self.summary_region(name, stable_pid, cpos);
+        RegionSaveFile::new(self, path, idx, false)
+    }
+
+
+    /// Check for terrain chunks saved in the old one-file-per-chunk layout.  These must be
+    /// converted with `convert_legacy_files` before the server can load them.  A missing terrain
+    /// chunk directory has no legacy files.
+    pub fn has_legacy_files(&self) -> io::Result<bool> {
+        let dir = self.base.join(SAVE_DIR).join(TERRAIN_CHUNK_DIR);
+        let entries = match fs::read_dir(dir) {
+            Ok(x) => x,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            if has_extension(&try!(entry).path(), "terrain_chunk") {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Move terrain chunks and summaries saved in the old one-file-per-chunk layout into region
+    /// files, deleting the old files.  Returns the number of files converted.  This must not be
+    /// run while the server is using the save directory.
+    pub fn convert_legacy_files(&self) -> io::Result<usize> {
+        let mut count = 0;
+
+        let tc_dir = self.base.join(SAVE_DIR).join(TERRAIN_CHUNK_DIR);
+        let tc_entries = match fs::read_dir(&tc_dir) {
+            Ok(x) => x.collect::<Vec<_>>(),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        for entry in tc_entries {
+            let path = try!(entry).path();
+            if !has_extension(&path, "terrain_chunk") {
+                continue;
+            }
+            let id = match file_stem(&path).and_then(|s| u64::from_str_radix(s, 16).ok()) {
+                Some(x) => x,
+                None => {
+                    warn!("skipping unrecognized file {:?}", path);
+                    continue;
+                },
+            };
+            let (region_path, idx) = self.terrain_chunk_region(Stable::new(id));
+
+            // Write the backup first, so it becomes the previous version of the entry.
+            let backup = backup_path(&path);
+            if fs::metadata(&backup).is_ok() {
+                let data = try!(read_file(&backup));
+                try!(self.write_region(&region_path, idx, &data, true));
+            }
+            let data = try!(read_file(&path));
+            try!(self.write_region(&region_path, idx, &data, true));
+
+            try!(fs::remove_file(&path));
+            if fs::metadata(&backup).is_ok() {
+                try!(fs::remove_file(&backup));
+            }
+            count += 1;
+        }
+
+        let summary_dir = self.base.join(SAVE_DIR).join(SUMMARY_DIR);
+        if fs::metadata(&summary_dir).is_err() {
+            return Ok(count);
+        }
+        for pid_entry in try!(fs::read_dir(&summary_dir)) {
+            let pid_dir = try!(pid_entry).path();
+            let pid = match file_stem(&pid_dir).and_then(|s| u64::from_str_radix(s, 16).ok()) {
+                Some(x) => Stable::new(x),
+                None => continue,
+            };
+            for name_entry in try!(fs::read_dir(&pid_dir)) {
+                let name_dir = try!(name_entry).path();
+                let name = match name_dir.file_name().and_then(|s| s.to_str()) {
+                    Some(x) => x.to_owned(),
+                    None => continue,
+                };
+                for entry in try!(fs::read_dir(&name_dir)) {
+                    let path = try!(entry).path();
+                    if !has_extension(&path, "dat") {
+                        continue;
+                    }
+                    let cpos = match file_stem(&path).and_then(parse_coords) {
+                        Some(x) => x,
+                        None => {
+                            warn!("skipping unrecognized file {:?}", path);
+                            continue;
+                        },
+                    };
+                    let (region_path, idx) = self.summary_region(&name, pid, cpos);
+                    let data = try!(read_file(&path));
+                    try!(self.write_region(&region_path, idx, &data, false));
+                    try!(fs::remove_file(&path));
+                    count += 1;
+                }
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+/// A save file that is being rewritten.  Data is written to a temporary file, which replaces the
+/// real file only when `commit` is called.  If the `SaveFile` is dropped without being committed,
+/// the temporary file is deleted and the real file is left untouched.
+///
+/// On commit, the old contents of the file are kept as a backup (see `open_backup_file`), in case
+/// the new contents turn out to be unreadable.
+pub struct SaveFile {
+    file: File,
+    path: PathBuf,
+    tmp_path: PathBuf,
+    committed: bool,
+}
+
+impl SaveFile {
+    fn create(path: PathBuf) -> io::Result<SaveFile> {
+        SaveFile::create_at(path)
+    }
+
+    /// Start writing a new version of the file at `path`, which need not be inside the save
+    /// directory.  Like the files from `Storage::create_*_file`, the old contents are replaced
+    /// only on `commit`, and are kept as a backup.
+    pub fn create_at<P: AsRef<Path>>(path: P) -> io::Result<SaveFile> {
+        let path = path.as_ref().to_owned();
+        let tmp_path = append_extension(&path, "tmp");
+        let file = try!(File::create(&tmp_path));
+        Ok(SaveFile {
+            file: file,
+            path: path,
+            tmp_path: tmp_path,
+            committed: false,
+        })
+    }
+
+    /// Flush the new contents to disk and move them into place.
+    pub fn commit(mut self) -> io::Result<()> {
+        try!(self.file.sync_all());
+        if fs::metadata(&self.path).is_ok() {
+            try!(fs::rename(&self.path, backup_path(&self.path)));
+        }
+        try!(fs::rename(&self.tmp_path, &self.path));
+        self.committed = true;
+        sync_parent_dir(&self.path)
+    }
+}
+
+impl Write for SaveFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for SaveFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+/// The contents of a single entry of a region file.
+pub type RegionEntry = io::Cursor<Vec<u8>>;
+
+/// A region file entry that is being rewritten.  Data is buffered in memory and written to the
+/// region file only when `commit` is called.  If the `RegionSaveFile` is dropped without being
+/// committed, the region file is left untouched.
+pub struct RegionSaveFile<'a> {
+    storage: &'a Storage,
+    path: PathBuf,
+    idx: usize,
+    keep_backup: bool,
+    buf: Vec<u8>,
+}
+
+impl<'a> RegionSaveFile<'a> {
+    fn new(storage: &'a Storage,
+           path: PathBuf,
+           idx: usize,
+           keep_backup: bool) -> RegionSaveFile<'a> {
+        RegionSaveFile {
+            storage: storage,
+            path: path,
+            idx: idx,
+            keep_backup: keep_backup,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Write the new contents into the region file.
+    pub fn commit(self) -> io::Result<()> {
+        self.storage.write_region(&self.path, self.idx, &self.buf, self.keep_backup)
+    }
+}
+
+impl<'a> Write for RegionSaveFile<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.write(buf)
     }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Flush the directory containing `path` to disk, so that a rename into that directory survives
+/// a crash.  Directories can't be opened as files on Windows, so this does nothing there.
+#[cfg(unix)]
+pub fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    try!(File::open(dir)).sync_all()
+}
+
+#[cfg(not(unix))]
+pub fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn append_extension(path: &Path, ext: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".");
+    s.push(ext);
+    PathBuf::from(s)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    append_extension(path, "bak")
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension().and_then(|e| e.to_str()) == Some(ext)
+}
+
+fn file_stem(path: &Path) -> Option<&str> {
+    path.file_stem().and_then(|s| s.to_str())
 }
 
+/// Parse the `x,y` coordinates used in the names of old summary files.
+fn parse_coords(s: &str) -> Option<V2> {
+    let mut parts = s.split(',');
+    let x = match parts.next().and_then(|p| p.parse().ok()) {
+        Some(x) => x,
+        None => return None,
+    };
+    let y = match parts.next().and_then(|p| p.parse().ok()) {
+        Some(y) => y,
+        None => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(V2::new(x, y))
+}
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    try!(try!(File::open(path)).read_to_end(&mut buf));
+    Ok(buf)
+}
+
+
 fn char_legal(c: char) -> bool {
     (c >= 'a' && c <= 'z') ||
     (c >= 'A' && c <= 'Z') ||
@@ -0,0 +1,16 @@
+//! CRC-32 (the IEEE 802.3 polynomial, as used by zlib and PNG), for detecting corrupted files.
+
+const POLY: u32 = 0xedb88320;
+
+/// Compute the CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0 .. 8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (POLY & mask);
+        }
+    }
+    !crc
+}
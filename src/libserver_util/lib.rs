@@ -17,6 +17,7 @@ use libserver_types::Time;
 pub use self::bit_slice::BitSlice;
 pub use self::bytes::Bytes;
 pub use self::convert::Convert;
+pub use self::crc32::crc32;
 pub use self::small_vec::SmallVec;
 pub use self::small_set::SmallSet;
 pub use self::str_error::{StrError, StrResult};
@@ -26,6 +27,7 @@ pub use self::str_error::{StringError, StringResult};
 pub mod bit_slice;
 pub mod bytes;
 pub mod convert;
+pub mod crc32;
 pub mod small_set;
 pub mod small_vec;
 
@@ -1,5 +1,4 @@
 use std::error::Error;
-use std::fs::File;
 use std::io;
 use linked_hash_map::LinkedHashMap;
 
@@ -12,10 +11,10 @@ pub trait Summary {
     fn alloc() -> Box<Self>;
 
     /// Write the summary data to a file.
-    fn write_to(&self, f: File) -> io::Result<()>;
+    fn write_to<W: io::Write>(&self, f: W) -> io::Result<()>;
 
     /// Create a new summary from the contents of a file.
-    fn read_from(f: File) -> io::Result<Box<Self>>;
+    fn read_from<R: io::Read>(f: R) -> io::Result<Box<Self>>;
 }
 
 
@@ -55,8 +54,8 @@ impl<'d, T: Summary> Cache<'d, T> {
         while self.cache.len() + extra > CACHE_LIMIT {
             let ((pid, cpos), entry) = self.cache.pop_front().unwrap();
             if entry.dirty {
-                let file = self.storage.create_summary_file(self.name, pid, cpos);
-                match entry.data.write_to(file) {
+                let mut file = self.storage.create_summary_file(self.name, pid, cpos);
+                match entry.data.write_to(&mut file).and_then(|_| file.commit()) {
                     Ok(_) => {},
                     Err(e) => {
                         warn!("error writing cache entry to disk: {}",
@@ -79,8 +78,9 @@ impl<'d, T: Summary> Cache<'d, T> {
             Ok(())
         } else {
             self.make_space(1);
-            let path = self.storage.summary_file_path(self.name, pid, cpos);
-            let file = try!(File::open(path));
+            let file = try!(self.storage.open_summary_file(self.name, pid, cpos).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "summary not found")
+            }));
             let summary = try!(T::read_from(file));
             self.cache.insert((pid, cpos), CacheEntry::new(summary));
             Ok(())
@@ -93,6 +93,14 @@ impl<'d, T: Summary> Cache<'d, T> {
         &self.cache[&(pid, cpos)].data
     }
 
+    /// Get a copy of a loaded summary.  This lets callers keep using the summary after releasing
+    /// the lock on a shared cache.
+    pub fn get_copy(&self, pid: Stable<PlaneId>, cpos: V2) -> Box<T> {
+        let mut buf = Vec::new();
+        self.get(pid, cpos).write_to(&mut buf).unwrap();
+        T::read_from(&buf[..]).unwrap()
+    }
+
     pub fn get_mut(&mut self, pid: Stable<PlaneId>, cpos: V2) -> &mut T {
         let entry = &mut self.cache[&(pid, cpos)];
         entry.dirty = true;
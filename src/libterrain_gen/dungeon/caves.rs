@@ -1,10 +1,9 @@
 use std::cmp;
-use rand::Rng;
 
 use libphysics::CHUNK_SIZE;
 use libserver_types::*;
 
-use StdRng;
+use derive_seed;
 use algo;
 use algo::blob::BlobGrid;
 use algo::cellular::CellularGrid;
@@ -18,20 +17,23 @@ use super::vault::Vault;
 
 
 pub struct Caves<'a> {
-    rng: StdRng,
+    seed: u64,
     cpos: V2,
+    depth: u8,
     plane_summ: &'a PlaneSummary,
     vaults: &'a [&'a Vault],
 }
 
 impl<'a> Caves<'a> {
-    pub fn new(rng: StdRng,
+    pub fn new(seed: u64,
                cpos: V2,
+               depth: u8,
                plane_summ: &'a PlaneSummary,
                vaults: &'a [&'a Vault]) -> Caves<'a> {
         Caves {
-            rng: rng,
+            seed: seed,
             cpos: cpos,
+            depth: depth,
             plane_summ: plane_summ,
             vaults: vaults,
         }
@@ -60,7 +62,11 @@ impl<'a> LocalProperty for Caves<'a> {
             }
             for pos in tri.bounds.points() {
                 if tri.contains(pos) && bounds.contains(pos) {
-                    if self.rng.gen_range(0, 10) < 5 {
+                    // Use the position instead of an RNG, so that neighboring chunks start with
+                    // the same cells in the area they share.  Then `generate` gives the same
+                    // result along the shared edge, since it doesn't run enough steps for the
+                    // outer parts of the grid (which differ between the two) to reach it.
+                    if derive_seed(self.seed, (pos.x, pos.y)) % 10 < 5 {
                     //if (pos.x + pos.y) % 2 == 0 {
                         grid.set(pos - base, false);
                     }
@@ -69,7 +75,7 @@ impl<'a> LocalProperty for Caves<'a> {
         }
 
         for &(a, b) in &self.plane_summ.neg_edges {
-            if !bounds.contains(a) && !bounds.contains(b) {
+            if !line_bounds(a, b).overlaps(bounds) {
                 continue;
             }
             algo::line_points(a, b, |pos, big| {
@@ -81,7 +87,7 @@ impl<'a> LocalProperty for Caves<'a> {
 
         // Let positive edges override negative ones.
         for &(a, b) in &self.plane_summ.edges {
-            if !bounds.contains(a) && !bounds.contains(b) {
+            if !line_bounds(a, b).overlaps(bounds) {
                 continue;
             }
             algo::line_points(a, b, |pos, big| {
@@ -93,7 +99,7 @@ impl<'a> LocalProperty for Caves<'a> {
             });
         }
 
-        if bounds.contains(ENTRANCE_POS) {
+        if self.depth == 0 && bounds.contains(ENTRANCE_POS) {
             for offset in Region::new(scalar(-1), scalar(5)).points() {
                 let p = ENTRANCE_POS + offset - base;
                 if grid.bounds().contains(p) {
@@ -109,16 +115,6 @@ impl<'a> LocalProperty for Caves<'a> {
         grid
     }
 
-    fn load(&mut self, grid: &mut CellularGrid, dir: V2, summ: &ChunkSummary) {
-        let base = (dir + scalar(1)) * scalar(CHUNK_SIZE);
-        let bounds = Region::new(base, base + scalar(CHUNK_SIZE + 1));
-        let cave_walls = summ.cave_walls();
-
-        for pos in bounds.points() {
-            grid.set_fixed(pos, cave_walls.get(bounds.index(pos)));
-        }
-    }
-
     fn generate(&mut self, grid: &mut CellularGrid) {
         for _ in 0 .. 5 {
             grid.step(|here, active, total| 2 * (here as u8 + active) > total);
@@ -135,3 +131,12 @@ impl<'a> LocalProperty for Caves<'a> {
         }
     }
 }
+
+/// Get a region containing every cell that `algo::line_points(a, b, ...)` touches, including the
+/// extra width added to positive edges.  Edges that pass through the grid without ending inside
+/// it still need to be drawn, or chunks would disagree about the area they share.
+fn line_bounds(a: V2, b: V2) -> Region<V2> {
+    let min = V2::new(cmp::min(a.x, b.x), cmp::min(a.y, b.y));
+    let max = V2::new(cmp::max(a.x, b.x), cmp::max(a.y, b.y));
+    Region::new(min, max + scalar(2))
+}
@@ -12,3 +12,13 @@ mod vault;
 
 const DUNGEON_SIZE: i32 = 256;
 const ENTRANCE_POS: V2 = V2 { x: DUNGEON_SIZE / 2, y: DUNGEON_SIZE / 2 };
+
+/// Layer containing the top level of the dungeon.  Deeper levels are stacked below it, one layer
+/// each.  Teleporters into the dungeon expect the entrance to be on this layer, so it stays where
+/// single-level dungeons were placed, one below the highest layer.
+const TOP_LAYER: u8 = 6;
+/// Maximum number of levels in a single dungeon.  Every level must fit between `TOP_LAYER` and
+/// layer 0.
+const MAX_LEVELS: u8 = 4;
+/// Number of levels to generate when the plane doesn't set the `levels` parameter.
+const DEFAULT_LEVELS: u8 = 3;
@@ -16,7 +16,7 @@ use algo::triangulate;
 use algo::union_find::UnionFind;
 use prop::GlobalProperty;
 
-use super::{DUNGEON_SIZE, ENTRANCE_POS};
+use super::DUNGEON_SIZE;
 use super::summary::PlaneSummary;
 use super::vault::{self, Vault};
 use super::types::Triangle;
@@ -25,13 +25,22 @@ use super::types::Triangle;
 pub struct Plan<'d> {
     rng: StdRng,
     data: &'d Data,
+    depth: u8,
+    origin: V2,
+    last: bool,
 }
 
 impl<'d> Plan<'d> {
-    pub fn new(rng: StdRng, data: &'d Data) -> Plan<'d> {
+    /// Plan one level of the dungeon.  `depth` is 0 for the top level, and `origin` is the
+    /// position of the entrance (for the top level) or the stairs from the level above.  Every
+    /// level except the `last` gets a `Stairwell` leading to the next one.
+    pub fn new(rng: StdRng, data: &'d Data, depth: u8, origin: V2, last: bool) -> Plan<'d> {
         Plan {
             rng: rng,
             data: data,
+            depth: depth,
+            origin: origin,
+            last: last,
         }
     }
 }
@@ -444,6 +453,10 @@ impl BaseVert {
 }
 
 pub struct Temporary {
+    depth: u8,
+    origin: V2,
+    last: bool,
+
     base: Graph<BaseVert>,
     tunnels: Graph<()>,
     next_area: u32,
@@ -462,16 +475,22 @@ const AREA_FIRST_PUZZLE: u32 = 2;
 
 /// Minimum vertex spacing in the base graph.
 const BASE_SPACING: i32 = 12;
+/// Largest prefab room that fits around a vertex without overlapping its neighbors.
+const MAX_PREFAB_SIZE: i32 = BASE_SPACING - 1;
+/// Percent chance to place a prefab room at an ordinary treasure spot.
+const PREFAB_CHANCE: u32 = 30;
 /// Amount of extra space to add around the border, to avoid artifacts near the boundaries of the
 /// generated graph.
 const BASE_PADDING: i32 = 2 * BASE_SPACING;
+/// Amount added to the loot level of treasure spots for each level of depth.
+const DEPTH_LOOT_LEVEL: u8 = 2;
 
 impl Temporary {
     fn gen_base(&mut self, rng: &mut StdRng) {
         let mut samp = DiskSampler::new(scalar(DUNGEON_SIZE + 2 * BASE_PADDING),
                                         BASE_SPACING,
                                         2 * BASE_SPACING);
-        samp.add_init_point(ENTRANCE_POS + scalar(BASE_PADDING));
+        samp.add_init_point(self.origin + scalar(BASE_PADDING));
         samp.generate(rng, 30);
 
         let bounds = Region::new(scalar(0), scalar(DUNGEON_SIZE)) + scalar(BASE_PADDING);
@@ -511,8 +530,9 @@ impl Temporary {
 
         let mut queue = Vec::new();
 
-        let origin = self.base.verts.iter().position(|v| v.pos == ENTRANCE_POS)
-                         .expect("ENTRANCE_POS should always be in self.base.verts") as u16;
+        let origin_pos = self.origin;
+        let origin = self.base.verts.iter().position(|v| v.pos == origin_pos)
+                         .expect("origin should always be in self.base.verts") as u16;
 
         for &v in &self.gen_entrance(rng, origin) {
             queue.push(Path { cur: v, level: 0 });
@@ -551,7 +571,8 @@ impl Temporary {
 
                 // Place something at the end of the tunnel.
                 (|| {
-                    if rng.gen_range(0, 100) < 40 {
+                    // Deeper levels have more puzzles.
+                    if rng.gen_range(0, 100) < 40 + 10 * self.depth as u32 {
                         if let Some((above, below)) =
                                 self.gen_gem_puzzle(rng, tunnel_end, p.level) {
                             queue.push(Path { cur: above, level: p.level + 2 });
@@ -624,7 +645,11 @@ impl Temporary {
         }
 
         // Place vault
-        self.vaults.push(Box::new(vault::Entrance::new(ENTRANCE_POS)));
+        if self.depth == 0 {
+            self.vaults.push(Box::new(vault::Entrance::new(self.origin)));
+        } else {
+            self.vaults.push(Box::new(vault::Stairs::new(self.origin)));
+        }
 
         // Choose outgoing vertexes
         let mut choose = || {
@@ -988,7 +1013,7 @@ impl Temporary {
         info!("generated {} edges", self.edges.len());
     }
 
-    fn assign_treasure(&mut self, rng: &mut StdRng) {
+    fn assign_treasure(&mut self, rng: &mut StdRng, data: &Data) {
         let spots = mem::replace(&mut self.treasure_spots, Vec::new());
         info!("assigning treasure to spots: {:?}", spots);
         let num_spots = spots.len();
@@ -1021,13 +1046,19 @@ impl Temporary {
             contents[idx].push((item, 1));
         }
 
-        // Assign loot to remaining spots.  The max-level spot gets special loot.
+        // Assign loot to remaining spots.  The max-level spot gets special loot on the bottom level
+        // of the dungeon, and the stairs down on every other level.
         let max_level = spots.iter().map(|s| s.1).max().unwrap();
         let max_idx = reservoir_sample(rng, (0 .. num_spots).filter(
                 |&i| spots[i].1 == max_level)).unwrap();
+        let depth_level = self.depth * DEPTH_LOOT_LEVEL;
 
         for (i, s) in spots.iter().enumerate() {
-            if i == max_idx {
+            if i == max_idx && !self.last {
+                info!("stairs down at {:?}", self.base.vert(s.0).pos);
+                let pos = self.base.vert(s.0).pos;
+                self.vaults.push(Box::new(vault::Stairwell::new(pos)));
+            } else if i == max_idx {
                 info!("big loot at {:?}", self.base.vert(s.0).pos);
                 self.gen_big_loot(rng, s.0);
             } else if contents[i].len() > 0 {
@@ -1037,6 +1068,9 @@ impl Temporary {
                 let v = s.0;
                 let pos = self.base.vert(v).pos;
                 self.vaults.push(Box::new(vault::Chest::new(pos, contents[i].to_owned())));
+            } else if rng.gen_range(0, 100) < PREFAB_CHANCE &&
+                      self.gen_prefab(rng, data, s.0, s.1 + depth_level) {
+                info!("prefab at {:?}", self.base.vert(s.0).pos);
             } else {
                 info!("small loot at {:?}", self.base.vert(s.0).pos);
                 self.gen_loot(rng, s.0);
@@ -1048,7 +1082,7 @@ impl Temporary {
         let pos = self.base.vert(v).pos;
         let n = rng.gen_range(0, 100);
         if n < 30 {
-            let count = rng.gen_range(6, 13);
+            let count = rng.gen_range(6, 13) + 4 * self.depth;
             let contents = vec![(vault::ChestItem::Book, count)];
             self.vaults.push(Box::new(vault::Chest::new(pos, contents)));
         } else {
@@ -1056,13 +1090,49 @@ impl Temporary {
         }
     }
 
+    /// Place a prefab room from the data files, centered on `v`.  Returns `false` if no prefab is
+    /// allowed at this level.
+    ///
+    /// The room's walls override the tunnels that lead to `v`, so each tunnel gets an extra edge
+    /// from the neighboring vertex to the nearest of the room's connection points.  Prefabs
+    /// without any connection points are never chosen.
+    fn gen_prefab(&mut self, rng: &mut StdRng, data: &Data, v: u16, level: u8) -> bool {
+        let opt_idx = {
+            let iter = data.vault_prefabs.iter().enumerate().map(|(i, p)| {
+                let fits = p.size.x <= MAX_PREFAB_SIZE && p.size.y <= MAX_PREFAB_SIZE;
+                let ok = fits && p.min_level <= level && p.connections.len() > 0;
+                (i, if ok { p.weight } else { 0 })
+            });
+            reservoir_sample_weighted(rng, iter)
+        };
+        let prefab = match opt_idx {
+            Some(i) => data.vault_prefabs.prefab(i),
+            None => return false,
+        };
+
+        let pos = self.base.vert(v).pos - prefab.size.div_floor(scalar(2));
+        for &n in self.tunnels.neighbors(v) {
+            let n_pos = self.base.vert(n).pos;
+            let conn = prefab.connections.iter()
+                             .map(|&c| pos + c)
+                             .min_by(|&c| (c - n_pos).abs().max())
+                             .unwrap();
+            self.edges.push((n_pos, conn));
+        }
+
+        let seed = rng.gen();
+        self.vaults.push(Box::new(vault::Prefab::new(pos, prefab, seed)));
+        true
+    }
+
     fn gen_big_loot(&mut self, rng: &mut StdRng, v: u16) {
         let pos = self.base.vert(v).pos;
         let n = rng.gen_range(0, 100);
-        if n < 16 {
+        // The bottom of a deeper dungeon is more likely to have a hat.
+        if n < 16 + 8 * self.depth as u32 {
             let contents = vec![(vault::ChestItem::Hat, 1)];
             self.vaults.push(Box::new(vault::Chest::new(pos, contents)));
-        } else if n < 50 {
+        } else if n < 50 + 4 * self.depth as u32 {
             let count = rng.gen_range(25, 40);
             let contents = vec![(vault::ChestItem::Book, count)];
             self.vaults.push(Box::new(vault::Chest::new(pos, contents)));
@@ -1168,6 +1238,10 @@ impl<'d> GlobalProperty for Plan<'d> {
 
     fn init(&mut self, _: &PlaneSummary) -> Temporary {
         Temporary {
+            depth: self.depth,
+            origin: self.origin,
+            last: self.last,
+
             base: Graph::new(),
             tunnels: Graph::new(),
             next_area: AREA_FIRST_PUZZLE,
@@ -1187,7 +1261,7 @@ impl<'d> GlobalProperty for Plan<'d> {
 
         tmp.make_edges();
         tmp.make_tris();
-        tmp.assign_treasure(&mut self.rng);
+        tmp.assign_treasure(&mut self.rng, self.data);
     }
 
     fn save(&mut self, tmp: Temporary, summ: &mut PlaneSummary) {
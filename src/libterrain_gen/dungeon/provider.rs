@@ -1,3 +1,4 @@
+use std::sync::Mutex;
 use rand::Rng;
 
 use libserver_types::*;
@@ -8,79 +9,167 @@ use libserver_config::Storage;
 
 use {GenChunk, GenStructure};
 use StdRng;
+use {derive_seed, seeded_rng};
 use cache::Cache;
 use prop::{LocalProperty, GlobalProperty};
+use registry::GenConfig;
 
 use super::summary::ChunkSummary;
 use super::summary::PlaneSummary;
 
-use super::ENTRANCE_POS;
+use super::{ENTRANCE_POS, TOP_LAYER, MAX_LEVELS, DEFAULT_LEVELS};
 use super::plan::Plan;
 use super::vault::Vault;
 use super::caves::Caves;
 
 
+/// Names of the chunk summary caches for each level.  The top level uses the same name as
+/// dungeons from before multi-level support, so their existing summaries remain valid.
+static CHUNK_CACHE_NAMES: [&'static str; MAX_LEVELS as usize] =
+    ["chunk", "chunk_1", "chunk_2", "chunk_3"];
+
+/// Dungeon terrain generator.  Like `forest::Provider`, this can be shared by several worker
+/// threads.
+///
+/// Each dungeon has one or more levels, set by the plane's `levels` parameter.  Level `n` is
+/// placed on layer `TOP_LAYER - n`, and its `PlaneSummary` is stored in the plane cache under
+/// `cpos = (n, 0)`.
 pub struct Provider<'d> {
     data: &'d Data,
-    rng: StdRng,
-    cache: Cache<'d, ChunkSummary>,
-    plane_cache: Cache<'d, PlaneSummary>,
+    seed: u64,
+    caches: Vec<Mutex<Cache<'d, ChunkSummary>>>,
+    plane_cache: Mutex<Cache<'d, PlaneSummary>>,
 }
 
 impl<'d> Provider<'d> {
-    pub fn new(data: &'d Data, storage: &'d Storage, rng: StdRng) -> Provider<'d> {
+    pub fn new(data: &'d Data, storage: &'d Storage, seed: u64) -> Provider<'d> {
         Provider {
             data: data,
-            rng: rng,
-            cache: Cache::new(storage, "chunk"),
-            plane_cache: Cache::new(storage, "plane"),
+            seed: seed,
+            caches: CHUNK_CACHE_NAMES.iter()
+                        .map(|&name| Mutex::new(Cache::new(storage, name)))
+                        .collect(),
+            plane_cache: Mutex::new(Cache::new(storage, "plane")),
         }
     }
 
-    fn load_plane_summary(&mut self,
-                          pid: Stable<PlaneId>) {
-        if let Err(_) = self.plane_cache.load(pid, scalar(0)) {
-            Plan::new(self.rng.gen(), self.data)
-                .generate_into(&mut self.plane_cache, pid, scalar(0));
+    /// Get an RNG for one part of the generation process.  Each dungeon plane gets its own seed,
+    /// and each `(plane, purpose, cpos)` gets its own RNG, so the result doesn't depend on the
+    /// order in which chunks are generated.
+    fn rng(&self, pid: Stable<PlaneId>, purpose: &str, cpos: V2) -> StdRng {
+        let plane_seed = derive_seed(self.seed, pid.unwrap());
+        seeded_rng(derive_seed(plane_seed, (purpose, cpos.x, cpos.y)))
+    }
+
+    /// Like `rng`, but for one level of the dungeon.  The top level uses the same RNGs as
+    /// single-level dungeons did.
+    fn level_rng(&self, pid: Stable<PlaneId>, purpose: &str, depth: u8, cpos: V2) -> StdRng {
+        if depth == 0 {
+            return self.rng(pid, purpose, cpos);
         }
+        let plane_seed = derive_seed(self.seed, pid.unwrap());
+        seeded_rng(derive_seed(plane_seed, (purpose, depth, cpos.x, cpos.y)))
+    }
+
+    /// Get a seed for one part of the generation process on one level of the dungeon, for values
+    /// that are shared between neighboring chunks.
+    fn level_seed(&self, pid: Stable<PlaneId>, purpose: &str, depth: u8) -> u64 {
+        let plane_seed = derive_seed(self.seed, pid.unwrap());
+        derive_seed(plane_seed, (purpose, depth))
     }
 
-    fn generate_summary(&mut self,
+    /// Load (or generate) the plane summary for each level.  Returns the number of levels
+    /// actually available, which may be less than `levels` if some level had nowhere to put the
+    /// stairs down.
+    fn load_plane_summaries(&self,
+                            plane_cache: &mut Cache<'d, PlaneSummary>,
+                            pid: Stable<PlaneId>,
+                            levels: u8) -> u8 {
+        let mut origin = ENTRANCE_POS;
+        for depth in 0 .. levels {
+            let key = level_key(depth);
+            if let Err(_) = plane_cache.load(pid, key) {
+                let last = depth + 1 == levels;
+                Plan::new(self.level_rng(pid, "plan", depth, scalar(0)),
+                          self.data, depth, origin, last)
+                    .generate_into(plane_cache, pid, key);
+            }
+
+            if depth + 1 < levels {
+                let plane_summ = plane_cache.get(pid, key);
+                origin = match plane_summ.vaults.iter().filter_map(|v| v.stairs_down()).next() {
+                    Some(pos) => pos,
+                    None => {
+                        warn!("dungeon level {} has no stairs down", depth);
+                        return depth + 1;
+                    },
+                };
+            }
+        }
+        levels
+    }
+
+    fn generate_summary(&self,
+                        plane_summ: &PlaneSummary,
                         pid: Stable<PlaneId>,
+                        depth: u8,
                         cpos: V2) {
-        self.load_plane_summary(pid);
-        let plane_summ = self.plane_cache.get(pid, scalar(0));
+        let cache = &self.caches[depth as usize];
+        // Reuse the existing summary, if there is one.  See `forest::Provider::generate_summary`.
+        if let Ok(_) = cache.lock().unwrap().load(pid, cpos) {
+            return;
+        }
 
         let base = cpos * scalar(CHUNK_SIZE) - scalar(CHUNK_SIZE);
         let bounds = Region::new(scalar(0), scalar(3 * CHUNK_SIZE)) + base;
         let local_vaults = vaults_in_bounds(&plane_summ.vaults, bounds);
 
-        Caves::new(self.rng.gen(), cpos, plane_summ, &local_vaults)
-            .generate_into(&mut self.cache, pid, cpos);
+        Caves::new(self.level_seed(pid, "caves", depth),
+                   cpos,
+                   depth,
+                   plane_summ,
+                   &local_vaults)
+            .generate_into(cache, pid, cpos);
     }
 
 
-    pub fn generate(&mut self,
+    pub fn generate(&self,
                     pid: Stable<PlaneId>,
-                    cpos: V2) -> GenChunk {
-        self.generate_summary(pid, cpos);
-
+                    cpos: V2,
+                    config: &GenConfig) -> GenChunk {
+        let levels = match config.param("levels").and_then(|s| s.parse::<u8>().ok()) {
+            Some(n) if n >= 1 && n <= MAX_LEVELS => n,
+            _ => DEFAULT_LEVELS,
+        };
+
+        // Every chunk in the dungeon depends on the plane summaries, so keep them locked for the
+        // whole process.  This means only one dungeon chunk is generated at a time, but dungeons
+        // are cheap to generate compared to the forest.
+        let mut plane_cache = self.plane_cache.lock().unwrap();
+        let levels = self.load_plane_summaries(&mut plane_cache, pid, levels);
 
         let mut gc = GenChunk::new();
+        for depth in 0 .. levels {
+            // Loading the other levels may have evicted this one.
+            plane_cache.load(pid, level_key(depth)).unwrap();
+            let plane_summ = plane_cache.get(pid, level_key(depth));
+
+            self.generate_summary(plane_summ, pid, depth, cpos);
+
+            let mut rng = self.level_rng(pid, "structures", depth, cpos);
+            let mut cache = self.caches[depth as usize].lock().unwrap();
+            cache.load(pid, cpos).unwrap();
 
-        {
             let mut ctx = Context {
-                rng: &mut self.rng,
+                rng: &mut rng,
                 gc: &mut gc,
-                summ: self.cache.get(pid, cpos),
-                plane_summ: self.plane_cache.get(pid, scalar(0)),
+                summ: cache.get(pid, cpos),
+                plane_summ: plane_summ,
                 cpos: cpos,
                 data: &self.data,
                 block_data: &self.data.block_data,
                 structure_templates: &self.data.structure_templates,
-                // Don't use layer 7.  Anything 2 blocks tall on that layer will have its top plane
-                // fail to render.  TODO: fix rendering so this doesn't happen
-                layer: 6,
+                layer: TOP_LAYER - depth,
                 vaults: Vec::new(),
             };
             ctx.gen();
@@ -295,6 +384,11 @@ impl<'a> Context<'a> {
     }
 }
 
+/// Key for the `PlaneSummary` of the level at `depth`.
+fn level_key(depth: u8) -> V2 {
+    V2::new(depth as i32, 0)
+}
+
 fn vaults_in_bounds<'a>(vaults: &'a [Box<Vault>],
                         bounds: Region<V2>) -> Vec<&'a Vault> {
     let mut chunk_vaults = Vec::new();
@@ -307,3 +401,57 @@ fn vaults_in_bounds<'a>(vaults: &'a [Box<Vault>],
     //info!("{} vaults in region {:?}", chunk_vaults.len(), bounds);
     chunk_vaults
 }
+
+
+#[cfg(test)]
+mod tests {
+    use libphysics::CHUNK_SIZE;
+    use libserver_types::*;
+
+    use test_util::{empty_data, temp_storage};
+
+    use super::{Provider, level_key};
+    use super::super::{TOP_LAYER, MAX_LEVELS, DEFAULT_LEVELS};
+
+    const SEED: u64 = 0x1234_5678;
+
+    fn pid() -> Stable<PlaneId> {
+        Stable::new(3)
+    }
+
+    #[test]
+    fn levels_fit_below_top_layer() {
+        assert!(DEFAULT_LEVELS >= 1 && DEFAULT_LEVELS <= MAX_LEVELS);
+        assert!(MAX_LEVELS <= TOP_LAYER + 1);
+    }
+
+    #[test]
+    fn second_level_starts_at_stairs_down() {
+        let data = empty_data();
+        let storage = temp_storage("dungeon-second-level");
+        let provider = Provider::new(&data, &storage, SEED);
+
+        let mut plane_cache = provider.plane_cache.lock().unwrap();
+        assert_eq!(provider.load_plane_summaries(&mut plane_cache, pid(), 2), 2);
+
+        let stairs_down = {
+            let top = plane_cache.get(pid(), level_key(0));
+            top.vaults.iter().filter_map(|v| v.stairs_down()).next().unwrap()
+        };
+
+        // The second level is the last one, so it has stairs up where the first level's stairs
+        // come down, and no stairs leading further down.
+        let second = plane_cache.get(pid(), level_key(1));
+        assert!(second.vaults.iter().any(|v| v.bounds().contains(stairs_down)));
+        assert!(second.vaults.iter().all(|v| v.stairs_down().is_none()));
+
+        // The caves of the second level leave the bottom of the stairs open.
+        let cpos = stairs_down.div_floor(scalar(CHUNK_SIZE));
+        provider.generate_summary(second, pid(), 1, cpos);
+        let mut cache = provider.caches[1].lock().unwrap();
+        cache.load(pid(), cpos).unwrap();
+        let grid_bounds = Region::new(scalar(0), scalar(CHUNK_SIZE + 1));
+        let corner = stairs_down - cpos * scalar(CHUNK_SIZE);
+        assert!(!cache.get(pid(), cpos).cave_walls().get(grid_bounds.index(corner)));
+    }
+}
@@ -1,4 +1,3 @@
-use std::fs::File;
 use std::io::{self, Write};
 use std::mem;
 use std::ptr;
@@ -39,13 +38,13 @@ impl Summary for ChunkSummary {
         })
     }
 
-    fn write_to(&self, mut f: File) -> io::Result<()> {
+    fn write_to<W: Write>(&self, mut f: W) -> io::Result<()> {
         try!(f.write_all(&self.cave_walls));
 
         Ok(())
     }
 
-    fn read_from(mut f: File) -> io::Result<Box<ChunkSummary>> {
+    fn read_from<R: io::Read>(mut f: R) -> io::Result<Box<ChunkSummary>> {
         let mut summary = ChunkSummary::alloc();
 
         try!(f.read_exact(&mut summary.cave_walls));
@@ -82,20 +81,24 @@ impl Summary for PlaneSummary {
         })
     }
 
-    fn write_to(&self, mut f: File) -> io::Result<()> {
+    fn write_to<W: Write>(&self, mut f: W) -> io::Result<()> {
         try!(unsafe { write_vec(&mut f, &self.edges) });
         try!(unsafe { write_vec(&mut f, &self.neg_edges) });
         try!(unsafe { write_vec(&mut f, &self.tris) });
 
         try!(f.write_bytes(self.vaults.len().to_u32().unwrap()));
+        // Vaults are trait objects, so they can't take a generic writer.  Buffer their data
+        // instead.
+        let mut buf = Vec::new();
         for v in &self.vaults {
-            v.write_to(&mut f);
+            try!(v.write_to(&mut buf));
         }
+        try!(f.write_all(&buf));
 
         Ok(())
     }
 
-    fn read_from(mut f: File) -> io::Result<Box<PlaneSummary>> {
+    fn read_from<R: io::Read>(mut f: R) -> io::Result<Box<PlaneSummary>> {
         let mut summary = PlaneSummary::alloc();
 
         summary.edges = try!(unsafe { read_vec(&mut f) });
@@ -103,9 +106,12 @@ impl Summary for PlaneSummary {
         summary.tris = try!(unsafe { read_vec(&mut f) });
 
         let vaults_count = try!(f.read_bytes::<u32>()) as usize;
+        let mut buf = Vec::new();
+        try!(io::Read::read_to_end(&mut f, &mut buf));
+        let mut r = &buf[..];
         summary.vaults = Vec::with_capacity(vaults_count);
         for _ in 0 .. vaults_count {
-            summary.vaults.push(try!(read_vault(&mut f)));
+            summary.vaults.push(try!(read_vault(&mut r)));
         }
 
         Ok(summary)
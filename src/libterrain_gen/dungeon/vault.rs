@@ -1,9 +1,9 @@
-use std::fs::File;
 use std::io::{self, Write};
-use rand::{self, Rng};
+use rand::Rng;
 
 use libserver_types::*;
 use libserver_config::Data;
+use libserver_config::data::VaultPrefab;
 use libphysics::CHUNK_SIZE;
 use libserver_util::{Convert, ReadExact};
 use libserver_util::{transmute_slice, transmute_slice_mut};
@@ -14,8 +14,11 @@ use libserver_util::bytes::*;
 use GenStructure;
 use algo::cellular::CellularGrid;
 use StdRng;
+use {derive_seed, seeded_rng};
 
-pub trait Vault {
+/// A pre-designed feature placed somewhere in the dungeon.  Vaults are stored in the
+/// `PlaneSummary`, which is shared between terrain generation threads, so they must be `Send`.
+pub trait Vault: Send {
     fn pos(&self) -> V2;
     fn size(&self) -> V2;
 
@@ -41,11 +44,15 @@ pub trait Vault {
                       bounds: Region<V2>,
                       layer: u8) {}
 
-    fn write_to(&self, f: &mut File) -> io::Result<()>;
+    /// If this vault leads down to the next level of the dungeon, get the position where the
+    /// next level's stairs should be placed.
+    fn stairs_down(&self) -> Option<V2> { None }
+
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()>;
 }
 
 pub trait VaultRead: Vault {
-    fn read_from(f: &mut File) -> io::Result<Box<Self>>;
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Self>>;
 }
 
 
@@ -112,7 +119,7 @@ impl Vault for Structure {
         }
     }
 
-    fn write_to(&self, f: &mut File) -> io::Result<()> {
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
         try!(f.write_bytes(1_u8));
         try!(f.write_bytes(self.pos));
         try!(f.write_bytes(self.kind));
@@ -121,7 +128,7 @@ impl Vault for Structure {
 }
 
 impl VaultRead for Structure {
-    fn read_from(f: &mut File) -> io::Result<Box<Structure>> {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Structure>> {
         let pos = try!(f.read_bytes());
         let kind = try!(f.read_bytes());
         Ok(Box::new(Structure {
@@ -249,7 +256,7 @@ impl Vault for Door {
         }
     }
 
-    fn write_to(&self, f: &mut File) -> io::Result<()> {
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
         try!(f.write_bytes(2_u8));
         try!(f.write_bytes(self.center));
         try!(f.write_bytes(self.area));
@@ -259,7 +266,7 @@ impl Vault for Door {
 }
 
 impl VaultRead for Door {
-    fn read_from(f: &mut File) -> io::Result<Box<Door>> {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Door>> {
         let center = try!(f.read_bytes());
         let area = try!(f.read_bytes());
         let kind = try!(f.read_bytes());
@@ -314,7 +321,7 @@ impl Vault for Entrance {
         }
     }
 
-    fn write_to(&self, f: &mut File) -> io::Result<()> {
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
         try!(f.write_bytes(3_u8));
         try!(f.write_bytes(self.center));
         Ok(())
@@ -322,7 +329,7 @@ impl Vault for Entrance {
 }
 
 impl VaultRead for Entrance {
-    fn read_from(f: &mut File) -> io::Result<Box<Entrance>> {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Entrance>> {
         let center = try!(f.read_bytes());
         Ok(Box::new(Entrance {
             center: center,
@@ -331,6 +338,188 @@ impl VaultRead for Entrance {
 }
 
 
+/// Apply a table of cave grid settings (0 = no change, 1 = open, 2 = wall) to the region of
+/// `grid` starting at `pos`.
+fn apply_grid_layout(layout: &[[u8; 8]; 8],
+                     pos: V2,
+                     grid: &mut CellularGrid,
+                     grid_bounds: Region<V2>) {
+    let layout_bounds = Region::new(pos, pos + scalar(8));
+    for p in layout_bounds.intersect(grid_bounds).points() {
+        let offset = p - pos;
+        let setting =
+            match layout[offset.y as usize][offset.x as usize] {
+                1 => Some(false),
+                2 => Some(true),
+                _ => None,
+            };
+        if let Some(val) = setting {
+            grid.set_fixed(p - grid_bounds.min, val);
+        }
+    }
+}
+
+/// Ramp leading up from the origin of a lower dungeon level to the `Stairwell` on the level
+/// above.  The ramp runs north from `center`, with its top edge two tiles north of `center`.
+pub struct Stairs {
+    center: V2,
+}
+
+impl Stairs {
+    pub fn new(center: V2) -> Stairs {
+        Stairs {
+            center: center,
+        }
+    }
+}
+
+impl Vault for Stairs {
+    fn pos(&self) -> V2 { self.center - V2::new(3, 3) }
+    fn size(&self) -> V2 { V2::new(7, 7) }
+
+    fn connection_points(&self) -> &[V2] { &[] }
+
+    fn gen_cave_grid(&self,
+                     grid: &mut CellularGrid,
+                     grid_bounds: Region<V2>) {
+        static GRID: [[u8; 8]; 8] = [
+            [1, 1, 1, 1, 1, 1, 1, 1],
+            [1, 1, 2, 2, 2, 2, 1, 1],
+            [1, 1, 2, 1, 1, 2, 1, 1],
+            [1, 1, 2, 1, 1, 2, 1, 1],
+            [1, 1, 1, 1, 1, 1, 1, 1],
+            [1, 1, 1, 1, 1, 1, 1, 1],
+            [1, 1, 1, 1, 1, 1, 1, 1],
+            [1, 1, 1, 1, 1, 1, 1, 1],
+        ];
+        apply_grid_layout(&GRID, self.pos(), grid, grid_bounds);
+    }
+
+    fn gen_terrain(&self,
+                   data: &Data,
+                   terrain: &mut [BlockId],
+                   bounds: Region<V2>,
+                   layer: u8) {
+        let layer_z = layer as i32 * 2;
+        let tile_bounds = bounds.extend(0, CHUNK_SIZE);
+        let mut set = |pos: V2, z: i32, name: &str| {
+            if bounds.contains(pos) {
+                terrain[tile_bounds.index(pos.extend(layer_z + z))] =
+                    data.block_data.get_id(name);
+            }
+        };
+
+        // These are the same blocks used for natural ramps in the forest.  The keys for the back
+        // and sides match the corner settings in `GRID` above.
+        set(self.center, 0, "natural_ramp/ramp/z0/dirt");
+        set(self.center - V2::new(0, 1), 1, "natural_ramp/ramp/z1");
+        set(self.center - V2::new(0, 2), 1, "natural_ramp/back/36");
+
+        set(self.center - V2::new(1, 1), 0, "cave/36/z0/dirt");
+        set(self.center - V2::new(1, 1), 1, "natural_ramp/left/39/z1");
+        set(self.center + V2::new(1, -1), 0, "cave/36/z0/dirt");
+        set(self.center + V2::new(1, -1), 1, "natural_ramp/right/37/z1");
+    }
+
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
+        try!(f.write_bytes(8_u8));
+        try!(f.write_bytes(self.center));
+        Ok(())
+    }
+}
+
+impl VaultRead for Stairs {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Stairs>> {
+        let center = try!(f.read_bytes());
+        Ok(Box::new(Stairs {
+            center: center,
+        }))
+    }
+}
+
+
+/// Opening in the floor above a `Stairs` vault with the same `center`.  The two tiles over the
+/// ramp are left empty, and the tile north of them is the landing at the top of the ramp.
+pub struct Stairwell {
+    center: V2,
+}
+
+impl Stairwell {
+    pub fn new(center: V2) -> Stairwell {
+        Stairwell {
+            center: center,
+        }
+    }
+}
+
+impl Vault for Stairwell {
+    fn pos(&self) -> V2 { self.center - V2::new(3, 3) }
+    fn size(&self) -> V2 { V2::new(7, 7) }
+
+    fn connection_points(&self) -> &[V2] { &[] }
+
+    fn gen_cave_grid(&self,
+                     grid: &mut CellularGrid,
+                     grid_bounds: Region<V2>) {
+        // Walls on three sides of the opening, so players can only step down from the landing.
+        static GRID: [[u8; 8]; 8] = [
+            [1, 1, 1, 1, 1, 1, 1, 1],
+            [1, 1, 1, 1, 1, 1, 1, 1],
+            [1, 1, 2, 1, 1, 2, 1, 1],
+            [1, 1, 2, 1, 1, 2, 1, 1],
+            [1, 1, 2, 2, 2, 2, 1, 1],
+            [1, 1, 1, 1, 1, 1, 1, 1],
+            [1, 1, 1, 1, 1, 1, 1, 1],
+            [1, 1, 1, 1, 1, 1, 1, 1],
+        ];
+        apply_grid_layout(&GRID, self.pos(), grid, grid_bounds);
+    }
+
+    fn gen_terrain(&self,
+                   data: &Data,
+                   terrain: &mut [BlockId],
+                   bounds: Region<V2>,
+                   layer: u8) {
+        let layer_z = layer as i32 * 2;
+        let tile_bounds = bounds.extend(0, CHUNK_SIZE);
+        let empty = data.block_data.get_id("empty");
+
+        for &dy in &[0, 1] {
+            let pos = self.center - V2::new(0, dy);
+            if bounds.contains(pos) {
+                terrain[tile_bounds.index(pos.extend(layer_z))] = empty;
+                terrain[tile_bounds.index(pos.extend(layer_z + 1))] = empty;
+            }
+        }
+
+        let landing = self.center - V2::new(0, 2);
+        if bounds.contains(landing) {
+            terrain[tile_bounds.index(landing.extend(layer_z))] =
+                data.block_data.get_id("natural_ramp/top");
+        }
+    }
+
+    fn stairs_down(&self) -> Option<V2> {
+        Some(self.center)
+    }
+
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
+        try!(f.write_bytes(9_u8));
+        try!(f.write_bytes(self.center));
+        Ok(())
+    }
+}
+
+impl VaultRead for Stairwell {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Stairwell>> {
+        let center = try!(f.read_bytes());
+        Ok(Box::new(Stairwell {
+            center: center,
+        }))
+    }
+}
+
+
 #[derive(Clone, Copy, Debug)]
 pub enum ChestItem {
     Hat,
@@ -400,7 +589,7 @@ impl Vault for Chest {
         }
     }
 
-    fn write_to(&self, f: &mut File) -> io::Result<()> {
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
         try!(f.write_bytes(4_u8));
         try!(f.write_bytes(self.center));
         try!(unsafe { write_vec(f, &self.contents) });
@@ -409,7 +598,7 @@ impl Vault for Chest {
 }
 
 impl VaultRead for Chest {
-    fn read_from(f: &mut File) -> io::Result<Box<Chest>> {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Chest>> {
         let center = try!(f.read_bytes());
         let contents = try!(unsafe { read_vec(f) });
         Ok(Box::new(Chest {
@@ -477,7 +666,7 @@ impl Vault for Library {
         }
     }
 
-    fn write_to(&self, f: &mut File) -> io::Result<()> {
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
         try!(f.write_bytes(5_u8));
         try!(f.write_bytes(self.center));
         try!(f.write_bytes(self.size));
@@ -486,10 +675,12 @@ impl Vault for Library {
 }
 
 impl VaultRead for Library {
-    fn read_from(f: &mut File) -> io::Result<Box<Library>> {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Library>> {
         let center = try!(f.read_bytes());
-        let size = try!(f.read_bytes());
-        let rng = rand::random();
+        let size: i32 = try!(f.read_bytes());
+        // The RNG state isn't saved, so derive one from the vault's position.  This keeps the
+        // shelves the same each time the summary is loaded.
+        let rng = seeded_rng(derive_seed(0, (center.x, center.y, size)));
         Ok(Box::new(Library {
             center: center,
             size: size,
@@ -662,7 +853,7 @@ impl Vault for GemPuzzle {
         }
     }
 
-    fn write_to(&self, f: &mut File) -> io::Result<()> {
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
         try!(f.write_bytes(6_u8));
         try!(f.write_bytes(self.center));
         try!(f.write_bytes(self.area));
@@ -673,7 +864,7 @@ impl Vault for GemPuzzle {
 }
 
 impl VaultRead for GemPuzzle {
-    fn read_from(f: &mut File) -> io::Result<Box<GemPuzzle>> {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<GemPuzzle>> {
         let center = try!(f.read_bytes());
         let area = try!(f.read_bytes());
         let colors = try!(unsafe { read_array(f) });
@@ -686,8 +877,167 @@ impl VaultRead for GemPuzzle {
 }
 
 
+/// A room built from a `VaultPrefab` in the data files.  The room's layout and connection points
+/// are copied into the summary, since the cave grid is generated before `Data` is available.  The
+/// blocks and structures are looked up by name when each chunk is generated.
+pub struct Prefab {
+    pos: V2,
+    size: V2,
+    name: String,
+    /// Seed for the loot tables, taken from the dungeon plan's RNG.
+    seed: u64,
+    /// Cave wall settings for the corners of the room's tiles: 0 to leave the corner alone, 1 for
+    /// open floor, and 2 for a wall.
+    walls: Vec<u8>,
+    connections: Vec<V2>,
+}
+
+impl Prefab {
+    pub fn new(pos: V2, prefab: &VaultPrefab, seed: u64) -> Prefab {
+        let walls = prefab.walls.iter().map(|&w| match w {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        }).collect();
+        Prefab {
+            pos: pos,
+            size: prefab.size,
+            name: prefab.name.clone(),
+            seed: seed,
+            walls: walls,
+            connections: prefab.connections.clone(),
+        }
+    }
+
+    fn prefab<'d>(&self, data: &'d Data) -> Option<&'d VaultPrefab> {
+        let opt_prefab = data.vault_prefabs.find(&self.name);
+        if opt_prefab.is_none() {
+            warn!("vault prefab {:?} is no longer defined", self.name);
+        }
+        opt_prefab
+    }
+}
+
+impl Vault for Prefab {
+    fn pos(&self) -> V2 { self.pos }
+    fn size(&self) -> V2 { self.size }
+
+    fn connection_points(&self) -> &[V2] { &self.connections }
+
+    fn gen_cave_grid(&self,
+                     grid: &mut CellularGrid,
+                     grid_bounds: Region<V2>) {
+        let vault_bounds = Region::new(self.pos, self.pos + self.size + scalar(1));
+        for pos in vault_bounds.intersect(grid_bounds).points() {
+            let setting =
+                match self.walls[vault_bounds.index(pos)] {
+                    1 => Some(false),
+                    2 => Some(true),
+                    _ => None,
+                };
+            if let Some(val) = setting {
+                grid.set_fixed(pos - grid_bounds.min, val);
+            }
+        }
+    }
+
+    fn gen_terrain(&self,
+                   data: &Data,
+                   terrain: &mut [BlockId],
+                   bounds: Region<V2>,
+                   layer: u8) {
+        let prefab = match self.prefab(data) {
+            Some(x) => x,
+            None => return,
+        };
+        let layer_z = layer as i32 * 2;
+        let tile_bounds = bounds.extend(0, CHUNK_SIZE);
+
+        for &(offset, block) in &prefab.blocks {
+            let pos = self.pos + offset.reduce();
+            let z = layer_z + offset.z;
+            if !bounds.contains(pos) || z < 0 || z >= CHUNK_SIZE {
+                continue;
+            }
+            terrain[tile_bounds.index(pos.extend(z))] = block;
+        }
+    }
+
+    fn gen_structures(&self,
+                      data: &Data,
+                      structures: &mut Vec<GenStructure>,
+                      bounds: Region<V2>,
+                      layer: u8) {
+        let prefab = match self.prefab(data) {
+            Some(x) => x,
+            None => return,
+        };
+        let layer_z = layer as i32 * 2;
+        // Every chunk must see the same loot, so evaluate the loot tables for all structures
+        // in order, even the ones outside `bounds`.
+        let mut rng = seeded_rng(self.seed);
+
+        for s in &prefab.structures {
+            let contents = s.loot.as_ref().map(|table| {
+                data.loot_tables.eval_item_table(&mut rng, table)
+            });
+
+            let pos = self.pos + s.pos.reduce();
+            if !bounds.contains(pos) {
+                continue;
+            }
+            let mut gs = GenStructure::new((pos - bounds.min).extend(layer_z + s.pos.z),
+                                           s.template);
+            if let Some(contents) = contents {
+                let mut loot_str = String::new();
+                for &(item, count) in &contents {
+                    loot_str.push_str(&format!("{}:{},", data.item_data.name(item), count));
+                }
+                gs.extra.insert("loot".to_owned(), loot_str);
+            }
+            structures.push(gs);
+        }
+    }
+
+    fn write_to(&self, f: &mut Vec<u8>) -> io::Result<()> {
+        try!(f.write_bytes(7_u8));
+        try!(f.write_bytes(self.pos));
+        try!(f.write_bytes(self.size));
+        try!(unsafe { write_vec(f, &self.name.as_bytes().to_owned()) });
+        try!(f.write_bytes(self.seed));
+        try!(unsafe { write_vec(f, &self.walls) });
+        try!(unsafe { write_vec(f, &self.connections) });
+        Ok(())
+    }
+}
+
+impl VaultRead for Prefab {
+    fn read_from(f: &mut &[u8]) -> io::Result<Box<Prefab>> {
+        let pos = try!(f.read_bytes());
+        let size = try!(f.read_bytes());
+        let name_bytes = try!(unsafe { read_vec(f) });
+        let name = match String::from_utf8(name_bytes) {
+            Ok(s) => s,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                "bad vault prefab name in summary")),
+        };
+        let seed = try!(f.read_bytes());
+        let walls = try!(unsafe { read_vec(f) });
+        let connections = try!(unsafe { read_vec(f) });
+        Ok(Box::new(Prefab {
+            pos: pos,
+            size: size,
+            name: name,
+            seed: seed,
+            walls: walls,
+            connections: connections,
+        }))
+    }
+}
+
+
 
-pub fn read_vault(f: &mut File) -> io::Result<Box<Vault>> {
+pub fn read_vault(f: &mut &[u8]) -> io::Result<Box<Vault>> {
     match try!(f.read_bytes::<u8>()) {
         1 => Ok(try!(Structure::read_from(f))),
         2 => Ok(try!(Door::read_from(f))),
@@ -695,6 +1045,9 @@ pub fn read_vault(f: &mut File) -> io::Result<Box<Vault>> {
         4 => Ok(try!(Chest::read_from(f))),
         5 => Ok(try!(Library::read_from(f))),
         6 => Ok(try!(GemPuzzle::read_from(f))),
+        7 => Ok(try!(Prefab::read_from(f))),
+        8 => Ok(try!(Stairs::read_from(f))),
+        9 => Ok(try!(Stairwell::read_from(f))),
         _ => panic!("bad vault tag in summary"),
     }
 }
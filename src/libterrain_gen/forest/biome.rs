@@ -0,0 +1,184 @@
+use libphysics::CHUNK_SIZE;
+use libserver_types::*;
+
+use derive_seed;
+
+
+/// Terrain and content settings for one kind of area in the forest.
+pub struct Biome {
+    pub name: &'static str,
+    /// Blocks for the ground layer.  Each tile gets one chosen at random.
+    pub floor_blocks: &'static [&'static str],
+    /// Floor type for hill and cave blocks on layer 0 (`grass` or `dirt`).
+    pub floor_type: &'static str,
+    /// Percent chance to keep each tree/rock position.
+    pub tree_density: u32,
+    /// Structure loot table for trees and rocks on the ground.
+    pub floor_table: &'static str,
+    /// Structure loot table for trees and rocks on top of hills.
+    pub hill_table: &'static str,
+    /// Percent chance for each hill layer in a chunk to get a cave entrance, if there's room.
+    pub cave_chance: u32,
+    /// Structure loot table for cave floors.
+    pub cave_table: &'static str,
+    /// Item loot table for chests in caves.
+    pub chest_table: &'static str,
+}
+
+pub type BiomeId = u8;
+
+pub const FOREST: BiomeId = 0;
+pub const MEADOW: BiomeId = 1;
+pub const SWAMP: BiomeId = 2;
+pub const HIGHLAND: BiomeId = 3;
+
+pub static BIOMES: [Biome; 4] = [
+    Biome {
+        name: "forest",
+        floor_blocks: &["grass/center/v0", "grass/center/v1",
+                        "grass/center/v2", "grass/center/v3"],
+        floor_type: "grass",
+        tree_density: 100,
+        floor_table: "forest/floor",
+        hill_table: "forest/hill",
+        cave_chance: 100,
+        cave_table: "cave/floor",
+        chest_table: "cave/chest",
+    },
+    Biome {
+        name: "meadow",
+        floor_blocks: &["grass/center/v0", "grass/center/v1"],
+        floor_type: "grass",
+        tree_density: 20,
+        floor_table: "meadow/floor",
+        hill_table: "meadow/hill",
+        cave_chance: 50,
+        cave_table: "cave/floor",
+        chest_table: "cave/chest",
+    },
+    Biome {
+        name: "swamp",
+        floor_blocks: &["grass/center/v2", "grass/center/v3", "grass/center/v3",
+                        "cave/40/z0/dirt"],
+        floor_type: "grass",
+        tree_density: 70,
+        floor_table: "swamp/floor",
+        hill_table: "forest/hill",
+        cave_chance: 25,
+        cave_table: "cave/floor",
+        chest_table: "swamp/chest",
+    },
+    Biome {
+        name: "highland",
+        floor_blocks: &["cave/40/z0/dirt", "cave/40/z0/dirt", "grass/center/v0"],
+        floor_type: "dirt",
+        tree_density: 60,
+        floor_table: "highland/floor",
+        hill_table: "highland/hill",
+        cave_chance: 100,
+        cave_table: "highland/cave/floor",
+        chest_table: "highland/chest",
+    },
+];
+
+/// Moisture level used for the area around spawn.  This is in the forest range.
+pub const SPAWN_MOISTURE: u8 = 128;
+
+/// Superchunk heights at or above this level are highlands.
+const HIGHLAND_HEIGHT: i32 = 102;
+/// Moisture at or above this level is swamp.
+const SWAMP_MOISTURE: i32 = 180;
+/// Moisture below this level is meadow.
+const MEADOW_MOISTURE: i32 = 72;
+
+/// Maximum random adjustment to the height and moisture of each tile, in 1/16ths.  Near a
+/// border, this mixes tiles of both biomes instead of drawing a straight line between them.
+const HEIGHT_DITHER: i32 = 16;
+const MOISTURE_DITHER: i32 = 24 * 16;
+
+fn classify(height: i32, moisture: i32) -> BiomeId {
+    if height >= HIGHLAND_HEIGHT * 16 && moisture < SWAMP_MOISTURE * 16 {
+        HIGHLAND
+    } else if moisture >= SWAMP_MOISTURE * 16 {
+        SWAMP
+    } else if moisture < MEADOW_MOISTURE * 16 {
+        MEADOW
+    } else {
+        FOREST
+    }
+}
+
+
+/// Biome lookup for the 3x3 chunk area around a chunk, matching the grids used by the
+/// `LocalProperty` steps.  Height and moisture are interpolated between chunk corners, so biomes
+/// blend smoothly into each other instead of changing at chunk boundaries.
+pub struct BiomeMap {
+    seed: u64,
+    /// Tile position of grid point (0, 0).
+    base: V2,
+    /// Height and moisture at each chunk corner, or `None` for corners in superchunks generated
+    /// before biomes were added.
+    corners: [Option<(u8, u8)>; 4 * 4],
+}
+
+impl BiomeMap {
+    pub fn new<F>(seed: u64, cpos: V2, mut f: F) -> BiomeMap
+            where F: FnMut(V2) -> Option<(u8, u8)> {
+        let mut corners = [None; 4 * 4];
+        let corner_bounds = Region::<V2>::new(scalar(0), scalar(4));
+        for offset in corner_bounds.points() {
+            corners[corner_bounds.index(offset)] = f(cpos - scalar(1) + offset);
+        }
+
+        BiomeMap {
+            seed: seed,
+            base: (cpos - scalar(1)) * scalar(CHUNK_SIZE),
+            corners: corners,
+        }
+    }
+
+    fn corner(&self, pos: V2) -> (i32, i32) {
+        let corner_bounds = Region::<V2>::new(scalar(0), scalar(4));
+        match self.corners[corner_bounds.index(pos)] {
+            Some((height, moisture)) => (height as i32, moisture as i32),
+            // Plain forest.
+            None => (0, SPAWN_MOISTURE as i32),
+        }
+    }
+
+    /// Get the biome at a grid position, in the range `0 .. 3 * CHUNK_SIZE + 1`.
+    pub fn get(&self, pos: V2) -> BiomeId {
+        let mut chunk = pos.div_floor(scalar(CHUNK_SIZE));
+        if chunk.x > 2 { chunk.x = 2; }
+        if chunk.y > 2 { chunk.y = 2; }
+        let frac = pos - chunk * scalar(CHUNK_SIZE);
+
+        // Bilinear interpolation, giving values in 1/16ths.
+        let mut height = 0;
+        let mut moisture = 0;
+        for &(dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let wx = if dx == 0 { CHUNK_SIZE - frac.x } else { frac.x };
+            let wy = if dy == 0 { CHUNK_SIZE - frac.y } else { frac.y };
+            let (h, m) = self.corner(chunk + V2::new(dx, dy));
+            height += h * wx * wy;
+            moisture += m * wx * wy;
+        }
+        height /= CHUNK_SIZE;
+        moisture /= CHUNK_SIZE;
+
+        // Use a hash of the position rather than an RNG, so that every chunk that looks at a
+        // particular tile sees the same biome there.
+        let world_pos = self.base + pos;
+        let h = derive_seed(self.seed, ("biome", world_pos.x, world_pos.y));
+        let dither = |bits: u64, range: i32| (bits % (2 * range as u64 + 1)) as i32 - range;
+        height += dither(h & 0xffff_ffff, HEIGHT_DITHER);
+        moisture += dither(h >> 32, MOISTURE_DITHER);
+
+        classify(height, moisture)
+    }
+
+    /// Get the biome at a position within the center chunk.
+    pub fn get_local(&self, pos: V2) -> BiomeId {
+        self.get(pos + scalar(CHUNK_SIZE))
+    }
+}
@@ -5,6 +5,7 @@ use libphysics::CHUNK_SIZE;
 use libserver_types::*;
 
 use StdRng;
+use derive_seed;
 use algo::cellular::CellularGrid;
 use algo::dsc::DscGrid;
 use prop::LocalProperty;
@@ -13,6 +14,8 @@ use super::summary::ChunkSummary;
 
 
 pub struct Caves<'a> {
+    seed: u64,
+    cpos: V2,
     rng: StdRng,
     layer: u8,
     level_cutoff: u8,
@@ -20,11 +23,15 @@ pub struct Caves<'a> {
 }
 
 impl<'a> Caves<'a> {
-    pub fn new(rng: StdRng,
+    pub fn new(seed: u64,
+               cpos: V2,
+               rng: StdRng,
                layer: u8,
                level_cutoff: u8,
                heightmap: &'a DscGrid) -> Caves<'a> {
         Caves {
+            seed: seed,
+            cpos: cpos,
             rng: rng,
             layer: layer,
             level_cutoff: level_cutoff,
@@ -70,26 +77,27 @@ impl<'a> LocalProperty for Caves<'a> {
             }
         }
 
-        grid
-    }
-
-    fn load(&mut self, grid: &mut CellularGrid, dir: V2, summ: &ChunkSummary) {
-        let base = (dir + scalar(1)) * scalar(CHUNK_SIZE);
-        let bounds = Region::new(base, base + scalar(CHUNK_SIZE + 1));
-        let layer = summ.cave_wall_layer(self.layer);
-
-        for pos in bounds.points() {
-            grid.set_fixed(pos, layer.get(bounds.index(pos)));
-        }
-
-        // Apply additional constraints.
-        for &(old_pos, is_wall) in &summ.cave_wall_constraints[self.layer as usize] {
-            let pos = old_pos + base - scalar(CHUNK_SIZE);
-            if !grid.bounds().contains(pos) {
+        // Fix the cells on the edges of the center chunk, which are shared with the neighbors.
+        // Their values are based only on the position, so both sides agree.  `CliffVaults` keeps
+        // entrances far enough from the edge that the open area around them doesn't reach it.
+        let base = (self.cpos - scalar(1)) * scalar(CHUNK_SIZE);
+        let center_bounds = Region::new(scalar(CHUNK_SIZE), scalar(CHUNK_SIZE * 2 + 1));
+        for pos in center_bounds.points() {
+            if pos.x != center_bounds.min.x && pos.x != center_bounds.max.x - 1 &&
+               pos.y != center_bounds.min.y && pos.y != center_bounds.max.y - 1 {
                 continue;
             }
+            let is_wall = if self.heightmap.get_value(pos).unwrap() < self.level_cutoff {
+                true
+            } else {
+                let abs_pos = base + pos;
+                let h = derive_seed(self.seed, ("edge", self.layer, abs_pos.x, abs_pos.y));
+                h % 10 < 5
+            };
             grid.set_fixed(pos, is_wall);
         }
+
+        grid
     }
 
     fn generate(&mut self, grid: &mut CellularGrid) {
@@ -11,24 +11,42 @@ use prop::LocalProperty;
 
 use super::provider;
 use super::summary::ChunkSummary;
+use super::biome::{BiomeMap, BIOMES};
+use super::water::WaterMap;
 
 
 pub struct CliffVaults<'a> {
     rng: StdRng,
     height_grid: &'a DscGrid,
+    biomes: &'a BiomeMap,
+    water: &'a WaterMap,
 }
 
 impl<'a> CliffVaults<'a> {
-    pub fn new(rng: StdRng, height_grid: &'a DscGrid) -> CliffVaults<'a> {
+    pub fn new(rng: StdRng,
+               height_grid: &'a DscGrid,
+               biomes: &'a BiomeMap,
+               water: &'a WaterMap) -> CliffVaults<'a> {
         CliffVaults {
             rng: rng,
             height_grid: height_grid,
+            biomes: biomes,
+            water: water,
         }
     }
+
+    /// Check that there's no water in the `size` area ending at `pos`, or on the row just in front
+    /// of it.
+    fn check_dry(&self, pos: V2, size: V2) -> bool {
+        let area = Region::new(pos - size, pos + V2::new(0, 1));
+        area.points_inclusive().all(|p| {
+            let val = self.height_grid.get_value(p).unwrap();
+            !self.water.is_water(p, val)
+        })
+    }
 }
 
 pub struct Temporary {
-    loaded_chunks: [bool; 3 * 3],
     pattern_grid: PatternGrid<u32>,
     entrances: Vec<V3>,
     ramps: Vec<V3>,
@@ -36,38 +54,13 @@ pub struct Temporary {
 
 impl Temporary {
     fn check_placement(&self, pos: V2, size: V2) -> bool {
-        // `expand` before the check means we not only reject placement that overlaps other chunks,
-        // but also placement that is too close to the edge of the center chunk.  This is a
-        // temporary measure to deal with the fact that cave entrances placed right at the chunk
-        // edge may not get a chance to influence cave generation (and placing an entrance where
-        // there is no cave causes a crash). 
-        let area = Region::new(pos - size, pos).expand(scalar(1));
+        // Reject any candidate that extends beyond the center chunk, or comes within 3 tiles of
+        // its edge.  `Caves` forces the area within 3 tiles of an entrance to be open, but the
+        // cave cells on the chunk edge are shared with the neighboring chunk, which doesn't know
+        // about this chunk's entrances.
+        let area = Region::new(pos - size, pos).expand(scalar(3));
         let chunk_area = area.div_round(CHUNK_SIZE);
-        if chunk_area != Region::new(scalar(1), scalar(2)) {
-            // TODO: For now, just reject any candidate that extends beyond the center
-            // chunk.  Later, fix to allow candidates that extend into not-yet-generated
-            // chunks, and add extra constraints as needed (like for trees).
-            return false;
-        }
-        /*
-        if !chunk_area.contains(scalar(1)) {
-            // Discard candidates that don't at least partially overlap the center chunk.
-            return false;
-        }
-
-        let chunk_bounds = Region::new(scalar(0), scalar(3));
-        for cpos in chunk_area.points() {
-            if self.loaded_chunks[chunk_bounds.index(cpos)] {
-                // We can't modify already-generated chunks, so discard candidates that
-                // would extend into those chunks.
-                return false;
-            }
-
-            if 
-        }
-        */
-
-        true
+        chunk_area == Region::new(scalar(1), scalar(2))
     }
 }
 
@@ -78,18 +71,12 @@ impl<'a> LocalProperty for CliffVaults<'a> {
 
     fn init(&mut self, _: &ChunkSummary) -> Temporary {
         Temporary {
-            loaded_chunks: [false; 3 * 3],
             pattern_grid: PatternGrid::new(scalar(CHUNK_SIZE * 3 + 1), 2, V2::new(4, 4)),
             entrances: Vec::new(),
             ramps: Vec::new(),
         }
     }
 
-    fn load(&mut self, tmp: &mut Temporary, dir: V2, _summ: &ChunkSummary) {
-        let bounds = Region::new(scalar(0), scalar(3));
-        tmp.loaded_chunks[bounds.index(dir + scalar(1))] = true;
-    }
-
     fn generate(&mut self, tmp: &mut Temporary) {
         for layer in 0 .. CHUNK_SIZE as u8 / 2 {
             let cutoff = provider::cutoff(layer);
@@ -103,7 +90,8 @@ impl<'a> LocalProperty for CliffVaults<'a> {
 
             let mut candidates = tmp.pattern_grid.find(RAMP_PATTERN, RAMP_MASK);
             util::filter_in_place(&mut candidates, |&pos| {
-                tmp.check_placement(pos, V2::new(3, 3))
+                tmp.check_placement(pos, V2::new(3, 3)) &&
+                    self.check_dry(pos, V2::new(3, 3))
             });
             let ramp_pos = self.rng.choose(&candidates).map(|&x| x);
             if let Some(pos) = ramp_pos {
@@ -120,11 +108,15 @@ impl<'a> LocalProperty for CliffVaults<'a> {
                         return false;
                     }
                 }
-                tmp.check_placement(pos, V2::new(3, 1))
+                tmp.check_placement(pos, V2::new(3, 1)) &&
+                    self.check_dry(pos, V2::new(3, 1))
             });
             let entrance_pos = self.rng.choose(&candidates).map(|&x| x);
             if let Some(pos) = entrance_pos {
-                tmp.entrances.push((pos - scalar(CHUNK_SIZE)).extend(layer as i32 * 2));
+                let chance = BIOMES[self.biomes.get(pos) as usize].cave_chance;
+                if chance >= 100 || self.rng.gen_range(0, 100) < chance {
+                    tmp.entrances.push((pos - scalar(CHUNK_SIZE)).extend(layer as i32 * 2));
+                }
             }
         }
     }
@@ -2,22 +2,28 @@ use libphysics::{CHUNK_BITS, CHUNK_SIZE};
 use libserver_types::*;
 
 use StdRng;
+use derive_seed;
 use algo::dsc::{DscGrid, Phase};
 use prop::LocalProperty;
 
 use super::summary::ChunkSummary;
+use super::constrain_edges;
 
 
 pub struct Heightmap {
+    seed: u64,
+    cpos: V2,
     rng: StdRng,
     super_heightmap: [u8; 4 * 4],
 }
 
 impl Heightmap {
-    pub fn new<F>(cpos: V2, rng: StdRng, mut f: F) -> Heightmap
+    pub fn new<F>(seed: u64, cpos: V2, rng: StdRng, mut f: F) -> Heightmap
             where F: FnMut(V2) -> u8 {
         let mut g = |x, y| f(V2::new(x, y) + cpos - scalar(1));
         Heightmap {
+            seed: seed,
+            cpos: cpos,
             rng: rng,
             super_heightmap:
                 [g(0, 0), g(1, 0), g(2, 0), g(3, 0),
@@ -36,41 +42,25 @@ impl LocalProperty for Heightmap {
     fn init(&mut self, _: &ChunkSummary) -> DscGrid {
         let mut grid = DscGrid::new(scalar(CHUNK_SIZE * 3), CHUNK_BITS as u8);
 
+        // Tile position of the grid's (0, 0).
+        let base = (self.cpos - scalar(1)) * scalar(CHUNK_SIZE);
+
+        // Each corner is shared by four chunks, so pick its exact value based only on its
+        // position.
         let super_bounds = Region::<V2>::new(scalar(0), scalar(4));
         for offset in super_bounds.points() {
             let level = self.super_heightmap[super_bounds.index(offset)];
             let pos = offset * scalar(CHUNK_SIZE);
-            grid.set_range(pos, level - 1, level);
-        }
-
-        grid
-    }
-
-    fn load(&mut self, grid: &mut DscGrid, dir: V2, summ: &ChunkSummary) {
-        let base = (dir + scalar(1)) * scalar(CHUNK_SIZE);
-        let bounds = Region::new(base,
-                                 base + scalar(CHUNK_SIZE + 1));
-        for pos in bounds.points() {
-            let val = summ.heightmap[bounds.index(pos)];
+            let abs_pos = base + pos;
+            let h = derive_seed(self.seed, ("corner", abs_pos.x, abs_pos.y));
+            let val = level - 1 + (h % 2) as u8;
             grid.set_range(pos, val, val);
         }
 
-        // Set "constrained" flag for all edges/corners shared with the center chunk.
-        let center_bounds = Region::new(scalar(CHUNK_SIZE),
-                                        scalar(CHUNK_SIZE * 2 + 1));
-        for pos in bounds.intersect(center_bounds).points() {
-            grid.set_constrained(pos);
-        }
+        // Edge points are all diamond-phase, which never get a random offset in `generate`.
+        constrain_edges(&mut grid, self.seed, base, CHUNK_BITS as u8, |_, _, _| 0);
 
-        // Apply additional constraints.
-        for &(old_pos, (low, high)) in &summ.heightmap_constraints {
-            let pos = old_pos + base - scalar(CHUNK_SIZE);
-            if !grid.bounds().contains(pos) {
-                continue;
-            }
-            grid.set_range(pos, low, high);
-            grid.set_constrained(pos);
-        }
+        grid
     }
 
     fn generate(&mut self, grid: &mut DscGrid) {
@@ -1,10 +1,19 @@
+use std::cmp;
 use rand::Rng;
 
 use libserver_types::*;
 
+use StdRng;
+use {derive_seed, seeded_rng};
+use algo::dsc::DscGrid;
+
 
 mod summary;
+mod biome;
 mod super_heightmap;
+mod super_moisture;
+mod super_rivers;
+mod water;
 mod heightmap;
 mod caves;
 mod trees;
@@ -47,3 +56,46 @@ pub fn power<R: Rng>(rng: &mut R, cpos: V2) -> u8 {
 pub fn exp_power<R: Rng>(rng: &mut R, cpos: V2) -> u8 {
     (15 - power(rng, cpos)).leading_zeros() as u8 - (8 - 4)
 }
+
+/// Fix the values on the four edges of the center cell of a 3x3 `DscGrid`.  These edges are
+/// shared with the neighboring chunks (or superchunks), so their values are filled in by
+/// one-dimensional midpoint displacement between the corners, using an RNG seeded from `seed`
+/// and the position of the edge.  Both neighbors compute the same values, whichever one is
+/// generated first.  The four corners must already have exact values set with `set_range`.
+///
+/// `base` is the position of the grid's `(0, 0)`, in grid units.  `get_max_offset` gets the
+/// position and level of each point, along with an RNG that is also specific to the edge.
+pub fn constrain_edges<F>(grid: &mut DscGrid, seed: u64, base: V2, bits: u8, mut get_max_offset: F)
+        where F: FnMut(&mut StdRng, V2, u8) -> u8 {
+    let size = 1 << bits;
+    let center: V2 = scalar(size);
+    for &(start, dir) in &[(center, V2::new(1, 0)),
+                           (center, V2::new(0, 1)),
+                           (center + V2::new(0, size), V2::new(1, 0)),
+                           (center + V2::new(size, 0), V2::new(0, 1))] {
+        let abs_start = base + start;
+        let edge_seed = derive_seed(seed, ("edge", abs_start.x, abs_start.y, dir.x != 0));
+        let mut rng = seeded_rng(edge_seed);
+        let mut offset_rng = seeded_rng(derive_seed(edge_seed, "offset"));
+
+        for level in (0 .. bits).rev() {
+            let step = 1 << (level + 1);
+            let half = 1 << level;
+            for i in 0 .. size / step {
+                let a = start + dir * scalar(i * step);
+                let b = a + dir * scalar(step);
+                let pos = a + dir * scalar(half);
+                let sum = grid.get_range(a).unwrap().0 as i32 + grid.get_range(b).unwrap().0 as i32;
+                let max = get_max_offset(&mut offset_rng, base + pos, level) as i32;
+                let val = sum / 2 + rng.gen_range(-max, max + 1);
+                let val = cmp::max(0, cmp::min(255, val)) as u8;
+                grid.set_range(pos, val, val);
+                grid.set_constrained(pos);
+            }
+        }
+    }
+
+    for &offset in &[V2::new(0, 0), V2::new(size, 0), V2::new(0, size), V2::new(size, size)] {
+        grid.set_constrained(center + offset);
+    }
+}
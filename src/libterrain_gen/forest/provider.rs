@@ -1,3 +1,5 @@
+use std::hash::Hash;
+use std::sync::Mutex;
 use rand::Rng;
 
 use libserver_types::*;
@@ -7,13 +9,18 @@ use libserver_config::Storage;
 
 use {GenChunk, GenStructure};
 use StdRng;
+use {derive_seed, seeded_rng};
 use cache::Cache;
 use prop::LocalProperty;
 
 use super::summary::ChunkSummary;
 use super::summary::{SuperchunkSummary, SUPERCHUNK_SIZE};
 
+use super::biome::{BiomeMap, BIOMES};
 use super::super_heightmap::SuperHeightmap;
+use super::super_moisture::SuperMoisture;
+use super::super_rivers::SuperRivers;
+use super::water::WaterMap;
 use super::heightmap::Heightmap;
 use super::caves::Caves;
 use super::trees::Trees;
@@ -21,34 +28,67 @@ use super::treasure::Treasure;
 use super::cliff_vaults::CliffVaults;
 
 
+/// Forest terrain generator.  A single `Provider` can be shared by several worker threads.  The
+/// summary caches are protected by locks, and the worker pool ensures that adjacent chunks are
+/// never generated at the same time.
 pub struct Provider<'d> {
     data: &'d Data,
-    rng: StdRng,
-    cache: Cache<'d, ChunkSummary>,
-    super_cache: Cache<'d, SuperchunkSummary>,
+    seed: u64,
+    cache: Mutex<Cache<'d, ChunkSummary>>,
+    super_cache: Mutex<Cache<'d, SuperchunkSummary>>,
+    /// Held while generating superchunk summaries, so that two threads don't try to generate the
+    /// same superchunk at once.
+    super_lock: Mutex<()>,
 }
 
 impl<'d> Provider<'d> {
-    pub fn new(data: &'d Data, storage: &'d Storage, rng: StdRng) -> Provider<'d> {
+    pub fn new(data: &'d Data, storage: &'d Storage, seed: u64) -> Provider<'d> {
         Provider {
             data: data,
-            rng: rng,
-            cache: Cache::new(storage, "chunk"),
-            super_cache: Cache::new(storage, "superchunk"),
+            seed: seed,
+            cache: Mutex::new(Cache::new(storage, "chunk")),
+            super_cache: Mutex::new(Cache::new(storage, "superchunk")),
+            super_lock: Mutex::new(()),
         }
     }
 
-    fn get_super_heightmap(&mut self,
-                           pid: Stable<PlaneId>,
-                           scpos: V2) -> &[u8] {
-        if let Err(_) = self.super_cache.load(pid, scpos) {
-            SuperHeightmap::new(scpos, self.rng.gen())
-                .generate_into(&mut self.super_cache, pid, scpos);
+    /// Get a seed for one step of the generation process, derived from the world seed, the
+    /// plane, and `key`.  Steps use this to compute values that are shared between neighboring
+    /// chunks.
+    fn seed<K: Hash>(&self, pid: Stable<PlaneId>, key: K) -> u64 {
+        let plane_seed = derive_seed(self.seed, pid.unwrap());
+        derive_seed(plane_seed, key)
+    }
+
+    /// Get an RNG for one step of the generation process.  `key` should identify both the step
+    /// and the chunk.  Together with `seed`, this means every generated value depends only on the
+    /// world seed and the chunk position, and not on which chunks were generated first.
+    fn rng<K: Hash>(&self, pid: Stable<PlaneId>, key: K) -> StdRng {
+        seeded_rng(self.seed(pid, key))
+    }
+
+    fn load_super_heightmap(&self,
+                            pid: Stable<PlaneId>,
+                            scpos: V2) {
+        let _guard = self.super_lock.lock().unwrap();
+        // Release the cache lock before generating.  `generate_into` needs to take it again.
+        let missing = self.super_cache.lock().unwrap().load(pid, scpos).is_err();
+        if missing {
+            let rng = self.rng(pid, ("super_heightmap", scpos.x, scpos.y));
+            SuperHeightmap::new(self.seed(pid, "super_heightmap"), scpos, rng)
+                .generate_into(&self.super_cache, pid, scpos);
+
+            let rng = self.rng(pid, ("super_moisture", scpos.x, scpos.y));
+            SuperMoisture::new(self.seed(pid, "super_moisture"), scpos, rng)
+                .generate_into(&self.super_cache, pid, scpos);
+
+            let rng = self.rng(pid, ("super_rivers", scpos.x, scpos.y));
+            SuperRivers::new(self.seed(pid, "super_rivers"), scpos, rng)
+                .generate_into(&self.super_cache, pid, scpos);
         }
-        &self.super_cache.get(pid, scpos).ds_levels
     }
 
-    fn super_height(&mut self,
+    fn super_height(&self,
                     pid: Stable<PlaneId>,
                     cpos: V2) -> u8 {
         if cpos == scalar(0){
@@ -58,48 +98,127 @@ impl<'d> Provider<'d> {
         let scpos = cpos.div_floor(scalar(SUPERCHUNK_SIZE));
         let base = scpos * scalar(SUPERCHUNK_SIZE);
         let bounds = Region::new(base, base + scalar(SUPERCHUNK_SIZE + 1));
-        let heightmap = self.get_super_heightmap(pid, scpos);
-        heightmap[bounds.index(cpos)]
+        self.load_super_heightmap(pid, scpos);
+        let mut super_cache = self.super_cache.lock().unwrap();
+        // The summary may have been evicted since `load_super_heightmap` released the lock.
+        super_cache.load(pid, scpos).unwrap();
+        super_cache.get(pid, scpos).ds_levels[bounds.index(cpos)]
+    }
+
+    /// Get the superchunk height and moisture at a chunk corner, for choosing biomes.  Returns
+    /// `None` if the superchunk has no biome data.
+    fn super_biome(&self,
+                   pid: Stable<PlaneId>,
+                   cpos: V2) -> Option<(u8, u8)> {
+        let height = self.super_height(pid, cpos);
+
+        let scpos = cpos.div_floor(scalar(SUPERCHUNK_SIZE));
+        let base = scpos * scalar(SUPERCHUNK_SIZE);
+        let bounds = Region::new(base, base + scalar(SUPERCHUNK_SIZE + 1));
+        self.load_super_heightmap(pid, scpos);
+        let mut super_cache = self.super_cache.lock().unwrap();
+        super_cache.load(pid, scpos).unwrap();
+        let summ = super_cache.get(pid, scpos);
+        if summ.has_biomes {
+            Some((height, summ.moisture[bounds.index(cpos)]))
+        } else {
+            None
+        }
+    }
+
+    fn biome_map(&self,
+                 pid: Stable<PlaneId>,
+                 cpos: V2) -> BiomeMap {
+        let plane_seed = derive_seed(self.seed, pid.unwrap());
+        BiomeMap::new(plane_seed, cpos, |cpos| self.super_biome(pid, cpos))
+    }
+
+    /// Get the river and lake flags at a chunk corner.  Superchunks generated before rivers were
+    /// added have no water.
+    fn super_rivers(&self,
+                    pid: Stable<PlaneId>,
+                    cpos: V2) -> u8 {
+        let scpos = cpos.div_floor(scalar(SUPERCHUNK_SIZE));
+        let base = scpos * scalar(SUPERCHUNK_SIZE);
+        let bounds = Region::new(base, base + scalar(SUPERCHUNK_SIZE + 1));
+        self.load_super_heightmap(pid, scpos);
+        let mut super_cache = self.super_cache.lock().unwrap();
+        super_cache.load(pid, scpos).unwrap();
+        let summ = super_cache.get(pid, scpos);
+        if summ.has_rivers {
+            summ.rivers[bounds.index(cpos)]
+        } else {
+            0
+        }
+    }
+
+    fn water_map(&self,
+                 pid: Stable<PlaneId>,
+                 cpos: V2) -> WaterMap {
+        let plane_seed = derive_seed(self.seed, pid.unwrap());
+        WaterMap::new(plane_seed, cpos, |cpos| self.super_rivers(pid, cpos))
     }
 
-    fn generate_summary(&mut self,
+    fn generate_summary(&self,
                         pid: Stable<PlaneId>,
-                        cpos: V2) {
-        let height_grid = Heightmap::new(cpos, self.rng.gen(),
+                        cpos: V2,
+                        biomes: &BiomeMap,
+                        water: &WaterMap) {
+        // If the summary already exists, this chunk was generated before.  Generating it again
+        // would give the same result, since it depends only on the seed and position.
+        if let Ok(_) = self.cache.lock().unwrap().load(pid, cpos) {
+            return;
+        }
+
+        let rng = self.rng(pid, ("heightmap", cpos.x, cpos.y));
+        let height_grid = Heightmap::new(self.seed(pid, "heightmap"), cpos, rng,
                                          |cpos| self.super_height(pid, cpos))
-                              .generate_into(&mut self.cache, pid, cpos);
+                              .generate_into(&self.cache, pid, cpos);
 
-        Trees::new(self.rng.gen(), &height_grid)
-            .generate_into(&mut self.cache, pid, cpos);
+        Trees::new(self.rng(pid, ("trees", cpos.x, cpos.y)), &height_grid, biomes, water)
+            .generate_into(&self.cache, pid, cpos);
 
-        CliffVaults::new(self.rng.gen(), &height_grid)
-            .generate_into(&mut self.cache, pid, cpos);
+        CliffVaults::new(self.rng(pid, ("cliff_vaults", cpos.x, cpos.y)),
+                         &height_grid, biomes, water)
+            .generate_into(&self.cache, pid, cpos);
 
         for layer in 0 .. CHUNK_SIZE as u8 / 2 {
             let layer_cutoff = layer * 2 + 100;
 
-            let cave_grid = Caves::new(self.rng.gen(),
+            let cave_grid = Caves::new(self.seed(pid, "caves"),
+                                       cpos,
+                                       self.rng(pid, ("caves", layer, cpos.x, cpos.y)),
                                        layer,
                                        layer_cutoff,
                                        &height_grid)
-                                .generate_into(&mut self.cache, pid, cpos);
+                                .generate_into(&self.cache, pid, cpos);
 
-            Treasure::new(self.rng.gen(),
+            Treasure::new(self.rng(pid, ("treasure", layer, cpos.x, cpos.y)),
                           layer,
                           &cave_grid)
-                .generate_into(&mut self.cache, pid, cpos);
+                .generate_into(&self.cache, pid, cpos);
         }
     }
 
 
-    pub fn generate(&mut self,
+    pub fn generate(&self,
                     pid: Stable<PlaneId>,
                     cpos: V2) -> GenChunk {
-        self.generate_summary(pid, cpos);
+        let biomes = self.biome_map(pid, cpos);
+        let water = self.water_map(pid, cpos);
+        self.generate_summary(pid, cpos, &biomes, &water);
 
 
         let mut gc = GenChunk::new();
-        let summ = self.cache.get(pid, cpos);
+        let mut rng = self.rng(pid, ("chunk", cpos.x, cpos.y));
+        // Copy the summary instead of holding the cache lock while building the chunk, so other
+        // workers can keep generating summaries in the meantime.
+        let summ = {
+            let mut cache = self.cache.lock().unwrap();
+            cache.load(pid, cpos).unwrap();
+            cache.get_copy(pid, cpos)
+        };
+        let summ = &*summ;
         // Bounds of the heightmap and cave grids, which assign a value to every vertex.
         let grid_bounds = Region::<V2>::new(scalar(0), scalar(CHUNK_SIZE + 1));
         // Bounds of the actual chunk, which assigns a block to every cell.
@@ -118,22 +237,49 @@ impl<'d> Provider<'d> {
         let loot_tables = &self.data.loot_tables;
         let item_data = &self.data.item_data;
 
-        // Grass layer
-        let grass_ids = [
-            block_id!("grass/center/v0"),
-            block_id!("grass/center/v1"),
-            block_id!("grass/center/v2"),
-            block_id!("grass/center/v3"),
-        ];
+        let floor_type = |pos: V2, layer: u8| {
+            if layer == 0 {
+                BIOMES[biomes.get_local(pos) as usize].floor_type
+            } else {
+                "dirt"
+            }
+        };
+
+        // Ground layer
+        let floor_ids = BIOMES.iter().map(|b| {
+            b.floor_blocks.iter().map(|name| block_id!("{}", name)).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        for pos in bounds.points() {
+            let ids = &floor_ids[biomes.get_local(pos) as usize];
+            gc.set_block(pos.extend(0), *rng.choose(ids).unwrap());
+        }
+
+        // Rivers and lakes
+        let water_center_ids = (0 .. 4).map(|i| block_id!("water_grass/center/v{}", i))
+                                       .collect::<Vec<_>>();
         for pos in bounds.points() {
-            gc.set_block(pos.extend(0), *self.rng.choose(&grass_ids).unwrap());
+            let mut key = 0;
+            for &(dx, dy) in &[(0, 1), (1, 1), (1, 0), (0, 0)] {
+                let vert = pos + V2::new(dx, dy);
+                let height = summ.heightmap[grid_bounds.index(vert)];
+                key = key * 2 + water.is_water_local(vert, height) as usize;
+            }
+            if key == 0 {
+                continue;
+            }
+
+            let id = if key == WATER_KEYS.len() - 1 {
+                *rng.choose(&water_center_ids).unwrap()
+            } else {
+                block_id!("water_grass/{}", WATER_KEYS[key])
+            };
+            gc.set_block(pos.extend(0), id);
         }
 
         // Cave/hill layers
         for layer in 0 .. CHUNK_SIZE as u8 / 2 {
-            let floor_type = if layer == 0 { "grass" } else { "dirt" };
-
             for pos in bounds.points() {
+                let floor_type = floor_type(pos, layer);
                 let (cave_key, top_key) = get_cell_keys(summ, pos, layer);
                 if cave_key == OUTSIDE_KEY {
                     continue;
@@ -156,7 +302,7 @@ impl<'d> Provider<'d> {
             info!("placing entrance at {:?}", pos);
             let base = pos.reduce() - V2::new(3, 1);
             let layer = pos.z as u8 / 2;
-            let floor_type = if layer == 0 { "grass" } else { "dirt" };
+            let floor_type = floor_type(pos.reduce(), layer);
 
             for (i, &side) in ["left", "center", "right"].iter().enumerate() {
                 let side_pos = base + V2::new(i as i32, 0);
@@ -173,7 +319,7 @@ impl<'d> Provider<'d> {
             info!("placing ramp at {:?}", pos);
             let base = pos.reduce() - V2::new(3, 3);
             let layer = pos.z as u8 / 2;
-            let floor_type = if layer == 0 { "grass" } else { "dirt" };
+            let floor_type = floor_type(pos.reduce(), layer);
             for offset in Region::new(scalar(0), scalar(3)).points() {
                 let (cave_key, _) = get_cell_keys(summ, base + offset, layer);
                 info!("  {:?} => {}", offset, cave_key);
@@ -216,7 +362,7 @@ impl<'d> Provider<'d> {
 
 
         // Trees/rocks
-        for &pos in &self.cache.get(pid, cpos).tree_offsets {
+        for &pos in &summ.tree_offsets {
             // Make sure the area near spawn is clear of structures.
             let abs_pos = pos + cpos * scalar(CHUNK_SIZE);
             if abs_pos.dot(abs_pos) < 5 * 5 {
@@ -227,10 +373,11 @@ impl<'d> Provider<'d> {
             let layer = if height < 100 { 0 } else { (height - 100) / 2 + 1 };
             let z = layer as i32 * 2;
 
+            let biome = &BIOMES[biomes.get_local(pos) as usize];
             let opt_id = if layer == 0 {
-                loot_tables.eval_structure_table(&mut self.rng, "forest/floor")
+                loot_tables.eval_structure_table(&mut rng, biome.floor_table)
             } else {
-                loot_tables.eval_structure_table(&mut self.rng, "forest/hill")
+                loot_tables.eval_structure_table(&mut rng, biome.hill_table)
             };
 
             if let Some(id) = opt_id {
@@ -244,12 +391,13 @@ impl<'d> Provider<'d> {
         let chest_id = template_id!("chest");
         for layer in 0 .. CHUNK_SIZE as u8 / 2 {
             let layer_z = layer as i32 * 2;
-            for &pos in &self.cache.get(pid, cpos).treasure_offsets[layer as usize] {
-                let opt_id = loot_tables.eval_structure_table(&mut self.rng, "cave/floor");
+            for &pos in &summ.treasure_offsets[layer as usize] {
+                let biome = &BIOMES[biomes.get_local(pos) as usize];
+                let opt_id = loot_tables.eval_structure_table(&mut rng, biome.cave_table);
                 if let Some(id) = opt_id {
                     let mut gs = GenStructure::new(pos.extend(layer_z), id);
                     if id == chest_id {
-                        let contents = loot_tables.eval_item_table(&mut self.rng, "cave/chest");
+                        let contents = loot_tables.eval_item_table(&mut rng, biome.chest_table);
                         let mut s = String::new();
                         for (item_id, count) in contents {
                             s.push_str(&format!("{}:{},", item_data.name(item_id), count));
@@ -300,5 +448,283 @@ fn get_cell_keys(summ: &ChunkSummary, pos: V2, layer: u8) -> (u8, u8) {
     (acc_cave, acc_top)
 }
 
+/// `water_grass` block names, indexed by which corners of the tile are covered by water.  This
+/// uses the same order as the `cave_top` keys.  Tiles with no water keep their ground block, and
+/// tiles entirely covered by water use a random `center` variant.
+static WATER_KEYS: [&'static str; 16] = [
+    "",
+    "corner/outer/se",
+    "corner/outer/sw",
+    "edge/s",
+    "corner/outer/nw",
+    "cross/nw",
+    "edge/w",
+    "corner/inner/sw",
+    "corner/outer/ne",
+    "edge/e",
+    "cross/ne",
+    "corner/inner/se",
+    "edge/n",
+    "corner/inner/ne",
+    "corner/inner/nw",
+    "center",
+];
+
 const OUTSIDE_KEY: u8 = 1 + 1*3 + 1*3*3 + 1*3*3*3;
 const CAVE_KEY: u8 = 2 + 2*3 + 2*3*3 + 2*3*3*3;
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use libphysics::CHUNK_SIZE;
+    use libserver_types::*;
+
+    use cache::Summary;
+    use test_util::{empty_data, temp_storage};
+
+    use super::Provider;
+    use super::super::summary::{ChunkSummary, SUPERCHUNK_SIZE};
+    use super::super::super_rivers::{RIVER_E, RIVER_S, RIVER_W, RIVER_N, LAKE_SHIFT};
+
+    const SEED: u64 = 0x1234_5678;
+
+    fn pid() -> Stable<PlaneId> {
+        Stable::new(2)
+    }
+
+    /// Generate summaries for `chunks`, in order, using a fresh provider.  Returns the serialized
+    /// summary of each chunk, and of each superchunk that was generated along the way.
+    fn generate_in_order(name: &str,
+                         chunks: &[V2]) -> (HashMap<V2, Vec<u8>>, HashMap<V2, Vec<u8>>) {
+        let data = empty_data();
+        let storage = temp_storage(name);
+        let provider = Provider::new(&data, &storage, SEED);
+
+        for &cpos in chunks {
+            let biomes = provider.biome_map(pid(), cpos);
+            let water = provider.water_map(pid(), cpos);
+            provider.generate_summary(pid(), cpos, &biomes, &water);
+        }
+
+        let mut summaries = HashMap::new();
+        let mut cache = provider.cache.lock().unwrap();
+        for &cpos in chunks {
+            cache.load(pid(), cpos).unwrap();
+            let mut buf = Vec::new();
+            cache.get(pid(), cpos).write_to(&mut buf).unwrap();
+            summaries.insert(cpos, buf);
+        }
+
+        let mut super_summaries = HashMap::new();
+        let mut super_cache = provider.super_cache.lock().unwrap();
+        for &cpos in chunks {
+            // Include the superchunks for all four corners of the chunk.
+            for &offset in &[V2::new(0, 0), V2::new(1, 0), V2::new(0, 1), V2::new(1, 1)] {
+                let scpos = (cpos + offset).div_floor(scalar(SUPERCHUNK_SIZE));
+                super_cache.load(pid(), scpos).unwrap();
+                let mut buf = Vec::new();
+                super_cache.get(pid(), scpos).write_to(&mut buf).unwrap();
+                super_summaries.insert(scpos, buf);
+            }
+        }
+
+        (summaries, super_summaries)
+    }
+
+    fn check_same(a: &HashMap<V2, Vec<u8>>, b: &HashMap<V2, Vec<u8>>, what: &str) {
+        assert_eq!(a.len(), b.len());
+        for (pos, summ) in a {
+            assert!(b[pos] == *summ, "{} summary at {:?} depends on generation order", what, pos);
+        }
+    }
+
+    /// A block of chunks that straddles the corner of four superchunks.
+    fn chunk_block() -> Vec<V2> {
+        let base = scalar::<V2>(SUPERCHUNK_SIZE) - scalar(2);
+        Region::new(base, base + scalar(4)).points().collect()
+    }
+
+    #[test]
+    fn order_reversed() {
+        let forward = chunk_block();
+        let mut backward = forward.clone();
+        backward.reverse();
+
+        let (chunks_a, supers_a) = generate_in_order("forest-order-forward", &forward);
+        let (chunks_b, supers_b) = generate_in_order("forest-order-backward", &backward);
+        check_same(&chunks_a, &chunks_b, "chunk");
+        check_same(&supers_a, &supers_b, "superchunk");
+    }
+
+    #[test]
+    fn order_interleaved() {
+        // Generate every other chunk first, so the rest are generated with all their neighbors
+        // already present.  Compare against generating each chunk with no neighbors at all.
+        let all = chunk_block();
+        let mut interleaved = all.iter().cloned().filter(|p| (p.x + p.y) % 2 == 0)
+                                 .collect::<Vec<_>>();
+        interleaved.extend(all.iter().cloned().filter(|p| (p.x + p.y) % 2 != 0));
+
+        let (chunks_a, supers_a) = generate_in_order("forest-order-interleaved", &interleaved);
+        let mut chunks_b = HashMap::new();
+        let mut supers_b = HashMap::new();
+        for (i, &cpos) in all.iter().enumerate() {
+            let (chunks, supers) =
+                generate_in_order(&format!("forest-order-single-{}", i), &[cpos]);
+            chunks_b.extend(chunks.into_iter());
+            supers_b.extend(supers.into_iter());
+        }
+        check_same(&chunks_a, &chunks_b, "chunk");
+        check_same(&supers_a, &supers_b, "superchunk");
+    }
+
+    #[test]
+    fn shared_edges_match() {
+        // Neighboring chunks must agree on the heightmap and cave walls along their shared edges.
+        let (chunks, _) = generate_in_order("forest-shared-edges", &chunk_block());
+        let bounds = Region::<V2>::new(scalar(0), scalar(CHUNK_SIZE + 1));
+
+        for (&cpos, buf) in &chunks {
+            let a = ChunkSummary::read_from(&buf[..]).unwrap();
+            for &dir in &[V2::new(1, 0), V2::new(0, 1)] {
+                let other = match chunks.get(&(cpos + dir)) {
+                    Some(buf) => ChunkSummary::read_from(&buf[..]).unwrap(),
+                    None => continue,
+                };
+                // Step along the edge, perpendicular to `dir`.
+                let along = V2::new(dir.y, dir.x);
+                for i in 0 .. CHUNK_SIZE + 1 {
+                    let pa = bounds.index(dir * scalar(CHUNK_SIZE) + along * scalar(i));
+                    let pb = bounds.index(along * scalar(i));
+                    assert_eq!(a.heightmap[pa], other.heightmap[pb]);
+                    for layer in 0 .. CHUNK_SIZE as u8 / 2 {
+                        assert_eq!(a.cave_wall_layer(layer).get(pa),
+                                   other.cave_wall_layer(layer).get(pb));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rivers_cross_superchunk_borders() {
+        // A river that reaches a superchunk border must continue in the neighboring superchunk,
+        // either as another river segment or as a lake on the crossing point.
+        let data = empty_data();
+        let storage = temp_storage("forest-river-borders");
+        let provider = Provider::new(&data, &storage, SEED);
+        let flags = |cpos: V2| provider.super_rivers(pid(), cpos);
+
+        let mut crossings = 0;
+        for &(dir, bit, back_bit) in &[(V2::new(1, 0), RIVER_E, RIVER_W),
+                                       (V2::new(0, 1), RIVER_S, RIVER_N)] {
+            let along = V2::new(dir.y, dir.x);
+            for border in 1 .. 3 {
+                for i in 0 .. 3 * SUPERCHUNK_SIZE {
+                    let corner = dir * scalar(border * SUPERCHUNK_SIZE) + along * scalar(i);
+                    // Segments touching the border are always recorded on the inner corner.
+                    if flags(corner - dir) & bit == 0 {
+                        continue;
+                    }
+                    crossings += 1;
+
+                    let here = flags(corner);
+                    assert!(here & bit != 0 || here >> LAKE_SHIFT != 0 ||
+                                flags(corner + dir) & back_bit != 0,
+                            "river stops at superchunk border at {:?}", corner);
+                }
+            }
+        }
+        assert!(crossings > 0, "no rivers reached a superchunk border");
+    }
+
+    #[test]
+    fn water_matches_across_superchunk_border() {
+        // Chunks on either side of a superchunk border must agree on where the water is.  Each
+        // `WaterMap` covers its chunk and the ring of chunks around it.
+        let data = empty_data();
+        let storage = temp_storage("forest-water-borders");
+        let provider = Provider::new(&data, &storage, SEED);
+
+        // The outer edge of each grid can miss water from corners just outside it.
+        let valid = Region::<V2>::new(scalar(1), scalar(3 * CHUNK_SIZE));
+        let mut wet = 0;
+        for &dir in &[V2::new(1, 0), V2::new(0, 1)] {
+            let along = V2::new(dir.y, dir.x);
+            for i in 0 .. SUPERCHUNK_SIZE {
+                let cpos = dir * scalar(SUPERCHUNK_SIZE - 1) + along * scalar(i);
+                let a = provider.water_map(pid(), cpos);
+                let b = provider.water_map(pid(), cpos + dir);
+
+                for pos in valid.points() {
+                    let a_pos = pos + dir * scalar(CHUNK_SIZE);
+                    if !valid.contains(a_pos) {
+                        continue;
+                    }
+                    assert!(a.get(a_pos) == b.get(pos),
+                            "water differs between chunks {:?} and {:?} at {:?}",
+                            cpos, cpos + dir, a_pos);
+                    wet += b.get(pos) as usize;
+                }
+            }
+        }
+        assert!(wet > 0, "no water near the superchunk borders");
+    }
+
+    #[test]
+    fn trees_and_vaults_stay_dry() {
+        let data = empty_data();
+        let storage = temp_storage("forest-dry");
+        let provider = Provider::new(&data, &storage, SEED);
+
+        // Generate the chunks around some of the chunk corners that have water.
+        let mut chunks = Vec::new();
+        for corner in Region::<V2>::new(scalar(1), scalar(SUPERCHUNK_SIZE)).points() {
+            if chunks.len() >= 8 {
+                break;
+            }
+            if provider.super_rivers(pid(), corner) != 0 {
+                chunks.push(corner - scalar(1));
+                chunks.push(corner);
+            }
+        }
+
+        let bounds = Region::<V2>::new(scalar(0), scalar(CHUNK_SIZE + 1));
+        let mut wet = 0;
+        for &cpos in &chunks {
+            let biomes = provider.biome_map(pid(), cpos);
+            let water = provider.water_map(pid(), cpos);
+            provider.generate_summary(pid(), cpos, &biomes, &water);
+
+            let mut cache = provider.cache.lock().unwrap();
+            cache.load(pid(), cpos).unwrap();
+            let summ = cache.get(pid(), cpos);
+            let is_water = |pos: V2| {
+                water.is_water_local(pos, summ.heightmap[bounds.index(pos)])
+            };
+            let check_dry = |area: Region<V2>, what: &str| {
+                for pos in area.points_inclusive() {
+                    assert!(!is_water(pos), "{} in chunk {:?} covers water at {:?}",
+                            what, cpos, pos);
+                }
+            };
+
+            wet += bounds.points().filter(|&pos| is_water(pos)).count();
+            for &pos in &summ.tree_offsets {
+                check_dry(Region::new(pos, pos + V2::new(4, 2)), "tree");
+            }
+            // Same areas as `CliffVaults::check_dry`, including the row in front of each vault.
+            for &pos in &summ.cave_entrances {
+                let pos = pos.reduce();
+                check_dry(Region::new(pos - V2::new(3, 1), pos + V2::new(0, 1)), "cave entrance");
+            }
+            for &pos in &summ.natural_ramps {
+                let pos = pos.reduce();
+                check_dry(Region::new(pos - V2::new(3, 3), pos + V2::new(0, 1)), "ramp");
+            }
+        }
+        assert!(wet > 0, "no water in the generated chunks");
+    }
+}
@@ -1,4 +1,3 @@
-use std::fs::File;
 use std::io::{self, Write};
 use std::mem;
 use std::ptr;
@@ -16,6 +15,8 @@ pub struct ChunkSummary {
     /// The value at each diamond-square vertex in the chunk.
     pub heightmap: [u8; ((CHUNK_SIZE + 1) * (CHUNK_SIZE + 1)) as usize],
 
+    /// No longer generated.  This and `cave_wall_constraints` are kept so that older summaries
+    /// still load.
     pub heightmap_constraints: Vec<(V2, (u8, u8))>,
 
     pub cave_entrances: Vec<V3>,
@@ -28,6 +29,7 @@ pub struct ChunkSummary {
             (((CHUNK_SIZE + 1) * (CHUNK_SIZE + 1) + 7) / 8) as usize];
             (CHUNK_SIZE / 2) as usize],
 
+    /// No longer generated, like `heightmap_constraints`.
     pub cave_wall_constraints: [Vec<(V2, bool)>; CHUNK_SIZE as usize / 2],
 
     /// Offsets of all trees/rocks in the chunk.
@@ -57,13 +59,13 @@ fn vec_per_layer<T>() -> [Vec<T>; CHUNK_SIZE as usize / 2] {
     }
 }
 
-unsafe fn write_vec<T>(f: &mut File, v: &Vec<T>) -> io::Result<()> {
+unsafe fn write_vec<W: Write, T>(f: &mut W, v: &Vec<T>) -> io::Result<()> {
     try!(f.write_bytes(v.len().to_u32().unwrap()));
     try!(f.write_all(transmute_slice(v)));
     Ok(())
 }
 
-unsafe fn read_vec<T>(f: &mut File) -> io::Result<Vec<T>> {
+unsafe fn read_vec<R: io::Read, T>(f: &mut R) -> io::Result<Vec<T>> {
     let len = try!(f.read_bytes::<u32>()) as usize;
     let mut v = Vec::with_capacity(len);
     v.set_len(len);
@@ -85,7 +87,7 @@ impl Summary for ChunkSummary {
         })
     }
 
-    fn write_to(&self, mut f: File) -> io::Result<()> {
+    fn write_to<W: Write>(&self, mut f: W) -> io::Result<()> {
         try!(f.write_all(&self.heightmap));
         try!(unsafe { write_vec(&mut f, &self.heightmap_constraints) });
         try!(unsafe { write_vec(&mut f, &self.cave_entrances) });
@@ -105,7 +107,7 @@ impl Summary for ChunkSummary {
         Ok(())
     }
 
-    fn read_from(mut f: File) -> io::Result<Box<ChunkSummary>> {
+    fn read_from<R: io::Read>(mut f: R) -> io::Result<Box<ChunkSummary>> {
         let mut summary = ChunkSummary::alloc();
 
         try!(f.read_exact(&mut summary.heightmap));
@@ -134,26 +136,78 @@ pub const SUPERCHUNK_SIZE: i32 = 1 << SUPERCHUNK_BITS;
 
 pub struct SuperchunkSummary {
     pub ds_levels: [u8; ((SUPERCHUNK_SIZE + 1) * (SUPERCHUNK_SIZE + 1)) as usize],
+
+    /// Moisture level at each chunk corner, used along with `ds_levels` to choose biomes.
+    pub moisture: [u8; ((SUPERCHUNK_SIZE + 1) * (SUPERCHUNK_SIZE + 1)) as usize],
+
+    /// `false` for superchunks generated before biomes were added.  These are treated as plain
+    /// forest, to match the chunks that were already generated inside them.
+    pub has_biomes: bool,
+
+    /// River and lake flags at each chunk corner.  See `super_rivers` for the meaning of each
+    /// bit.
+    pub rivers: [u8; ((SUPERCHUNK_SIZE + 1) * (SUPERCHUNK_SIZE + 1)) as usize],
+
+    /// `false` for superchunks generated before rivers were added.  These have no water.  Rivers
+    /// are only generated along with biomes, so this is never set without `has_biomes`.
+    pub has_rivers: bool,
 }
 
 impl Summary for SuperchunkSummary {
     fn alloc() -> Box<SuperchunkSummary> {
         Box::new(SuperchunkSummary {
             ds_levels: unsafe { mem::zeroed() },
+            moisture: unsafe { mem::zeroed() },
+            has_biomes: false,
+            rivers: unsafe { mem::zeroed() },
+            has_rivers: false,
         })
     }
 
-    fn write_to(&self, mut f: File) -> io::Result<()> {
+    fn write_to<W: Write>(&self, mut f: W) -> io::Result<()> {
         try!(f.write_all(&self.ds_levels));
+        if self.has_biomes {
+            try!(f.write_all(&self.moisture));
+        }
+        if self.has_rivers {
+            try!(f.write_all(&self.rivers));
+        }
 
         Ok(())
     }
 
-    fn read_from(mut f: File) -> io::Result<Box<SuperchunkSummary>> {
+    fn read_from<R: io::Read>(mut f: R) -> io::Result<Box<SuperchunkSummary>> {
         let mut summary = SuperchunkSummary::alloc();
 
         try!(f.read_exact(&mut summary.ds_levels));
 
+        // Older summaries end after `ds_levels` (before biomes) or after `moisture` (before
+        // rivers).
+        let mut rest = Vec::new();
+        try!(io::Read::read_to_end(&mut f, &mut rest));
+        let moisture_len = summary.moisture.len();
+        let rivers_len = summary.rivers.len();
+        if rest.len() != 0 &&
+           rest.len() != moisture_len &&
+           rest.len() != moisture_len + rivers_len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "bad biome data in superchunk summary"));
+        }
+
+        if rest.len() >= moisture_len {
+            for (dest, &val) in summary.moisture.iter_mut().zip(rest[.. moisture_len].iter()) {
+                *dest = val;
+            }
+            summary.has_biomes = true;
+        }
+
+        if rest.len() == moisture_len + rivers_len {
+            for (dest, &val) in summary.rivers.iter_mut().zip(rest[moisture_len ..].iter()) {
+                *dest = val;
+            }
+            summary.has_rivers = true;
+        }
+
         Ok(summary)
     }
 }
@@ -3,21 +3,24 @@ use rand::Rng;
 use libserver_types::*;
 
 use StdRng;
+use {derive_seed, seeded_rng};
 use algo::dsc::DscGrid;
 use prop::LocalProperty;
 
 use super::summary::{SuperchunkSummary, SUPERCHUNK_BITS, SUPERCHUNK_SIZE};
-use super::{power, exp_power};
+use super::{power, exp_power, constrain_edges};
 
 
 pub struct SuperHeightmap {
+    seed: u64,
     scpos: V2,
     rng: StdRng,
 }
 
 impl SuperHeightmap {
-    pub fn new(scpos: V2, rng: StdRng) -> SuperHeightmap {
+    pub fn new(seed: u64, scpos: V2, rng: StdRng) -> SuperHeightmap {
         SuperHeightmap {
+            seed: seed,
             scpos: scpos,
             rng: rng,
         }
@@ -32,31 +35,27 @@ impl LocalProperty for SuperHeightmap {
     fn init(&mut self, _: &SuperchunkSummary) -> DscGrid {
         let mut grid = DscGrid::new(scalar(SUPERCHUNK_SIZE * 3), SUPERCHUNK_BITS as u8);
 
+        // Chunk coordinate of the grid's (0, 0).
+        let base = (self.scpos - scalar(1)) * scalar(SUPERCHUNK_SIZE);
+
+        // Corners and edges are shared with the neighboring superchunks, so they use RNGs based
+        // only on their position.
         for step in Region::<V2>::new(scalar(0), scalar(4)).points() {
             let pos = step * scalar(SUPERCHUNK_SIZE);
-            let cpos = (self.scpos - scalar(1)) * scalar(SUPERCHUNK_SIZE) + pos;
-            let pow = power(&mut self.rng, cpos);
-            grid.set_range(pos, 98, 99 + pow / 2);
-        }
-
-        grid
-    }
-
-    fn load(&mut self, grid: &mut DscGrid, dir: V2, summ: &SuperchunkSummary) {
-        let base = (dir + scalar(1)) * scalar(SUPERCHUNK_SIZE);
-        let bounds = Region::new(base,
-                                 base + scalar(SUPERCHUNK_SIZE + 1));
-        for pos in bounds.points() {
-            let val = summ.ds_levels[bounds.index(pos)];
+            let cpos = base + pos;
+            let mut rng = seeded_rng(derive_seed(self.seed, ("corner", cpos.x, cpos.y)));
+            let pow = power(&mut rng, cpos);
+            let val = rng.gen_range(98, 99 + pow / 2 + 1);
             grid.set_range(pos, val, val);
         }
 
-        // Set "constrained" flag for all edges/corners shared with the center chunk.
-        let center_bounds = Region::new(scalar(SUPERCHUNK_SIZE),
-                                        scalar(SUPERCHUNK_SIZE * 2 + 1));
-        for pos in bounds.intersect(center_bounds).points() {
-            grid.set_constrained(pos);
-        }
+        constrain_edges(&mut grid, self.seed, base, SUPERCHUNK_BITS as u8,
+                        |rng, cpos, level| {
+                            let ep = exp_power(rng, cpos);
+                            if 4 - level <= ep { 2 } else { 1 }
+                        });
+
+        grid
     }
 
     fn generate(&mut self, grid: &mut DscGrid) {
@@ -0,0 +1,78 @@
+use libserver_types::*;
+
+use StdRng;
+use derive_seed;
+use algo::dsc::DscGrid;
+use prop::LocalProperty;
+
+use super::summary::{SuperchunkSummary, SUPERCHUNK_BITS, SUPERCHUNK_SIZE};
+use super::biome::SPAWN_MOISTURE;
+use super::constrain_edges;
+
+
+/// Generates the moisture map used for choosing biomes.  This runs right after `SuperHeightmap`,
+/// on the same summary.
+pub struct SuperMoisture {
+    seed: u64,
+    scpos: V2,
+    rng: StdRng,
+}
+
+impl SuperMoisture {
+    pub fn new(seed: u64, scpos: V2, rng: StdRng) -> SuperMoisture {
+        SuperMoisture {
+            seed: seed,
+            scpos: scpos,
+            rng: rng,
+        }
+    }
+}
+
+impl LocalProperty for SuperMoisture {
+    type Summary = SuperchunkSummary;
+    type Temporary = DscGrid;
+    type Result = ();
+
+    fn init(&mut self, _: &SuperchunkSummary) -> DscGrid {
+        let mut grid = DscGrid::new(scalar(SUPERCHUNK_SIZE * 3), SUPERCHUNK_BITS as u8);
+
+        // Chunk coordinate of the grid's (0, 0).
+        let base = (self.scpos - scalar(1)) * scalar(SUPERCHUNK_SIZE);
+
+        for step in Region::<V2>::new(scalar(0), scalar(4)).points() {
+            let pos = step * scalar(SUPERCHUNK_SIZE);
+            let cpos = base + pos;
+            let val =
+                if cpos == scalar(0) {
+                    // Always start the player in the forest.
+                    SPAWN_MOISTURE
+                } else {
+                    derive_seed(self.seed, ("corner", cpos.x, cpos.y)) as u8
+                };
+            grid.set_range(pos, val, val);
+        }
+
+        constrain_edges(&mut grid, self.seed, base, SUPERCHUNK_BITS as u8,
+                        |_, _, level| 6 * (level + 1));
+
+        grid
+    }
+
+    fn generate(&mut self, grid: &mut DscGrid) {
+        // Large offsets at the coarse levels, so biomes are big, and small ones at the fine levels,
+        // so the borders wander a bit.
+        grid.fill(&mut self.rng,
+                  |_offset, level, _phase| 6 * (level + 1));
+    }
+
+    fn save(&mut self, grid: DscGrid, summ: &mut SuperchunkSummary) {
+        let base = scalar(SUPERCHUNK_SIZE);
+        let bounds = Region::new(base,
+                                 base + scalar(SUPERCHUNK_SIZE + 1));
+        for pos in bounds.points() {
+            let val = grid.get_value(pos).unwrap();
+            summ.moisture[bounds.index(pos)] = val;
+        }
+        summ.has_biomes = true;
+    }
+}
@@ -0,0 +1,305 @@
+use rand::Rng;
+
+use libserver_types::*;
+
+use StdRng;
+use derive_seed;
+use prop::LocalProperty;
+
+use super::summary::{SuperchunkSummary, SUPERCHUNK_SIZE};
+
+
+// Flags for each chunk corner in `SuperchunkSummary::rivers`.  A river segment connects two
+// adjacent corners, and is recorded on at least one of them.
+pub const RIVER_E: u8 = 0x01;
+pub const RIVER_S: u8 = 0x02;
+pub const RIVER_W: u8 = 0x04;
+pub const RIVER_N: u8 = 0x08;
+// 0x10 was used by older summaries to mark rivers leaving the superchunk, and is now ignored.
+/// The top three bits give the size of a lake centered on the corner, or zero for no lake.
+pub const LAKE_SHIFT: u8 = 5;
+const MAX_LAKE_SIZE: u8 = 7;
+
+pub static RIVER_DIRS: [(u8, V2); 4] = [
+    (RIVER_E, V2 { x:  1, y:  0 }),
+    (RIVER_S, V2 { x:  0, y:  1 }),
+    (RIVER_W, V2 { x: -1, y:  0 }),
+    (RIVER_N, V2 { x:  0, y: -1 }),
+];
+
+fn opposite(bit: u8) -> u8 {
+    ((bit << 2) | (bit >> 2)) & 0x0f
+}
+
+/// Rivers only flow through corners at or below this height, so they stay out of the hills.
+const MAX_RIVER_HEIGHT: u8 = 99;
+/// One in this many superchunk edges has a river crossing it.
+const CROSSING_CHANCE: u64 = 2;
+/// Maximum number of new rivers to start in each superchunk.
+const MAX_SOURCES: u32 = 3;
+/// Number of random spots to check when looking for a place to start a river.
+const SOURCE_TRIES: u32 = 20;
+/// Maximum length of a river within one superchunk, in chunks.
+const MAX_RIVER_LENGTH: usize = 48;
+/// Rivers and lakes stay out of the chunks around spawn.
+const SPAWN_CLEARANCE: i32 = 1;
+
+const CORNERS: usize = ((SUPERCHUNK_SIZE + 1) * (SUPERCHUNK_SIZE + 1)) as usize;
+
+
+/// Traces rivers downhill across the superchunk heightmap.  Rivers start at the foot of hills or
+/// at a crossing point on the edge of the superchunk, and end in a lake when they reach a basin
+/// with nowhere lower to go.  This runs after `SuperHeightmap` and `SuperMoisture`, on the same
+/// summary.
+///
+/// Each crossing point is chosen from a hash of the edge's position, and both superchunks that
+/// share the edge trace a river away from it.  This way the river continues on both sides, no
+/// matter which superchunk is generated first.
+pub struct SuperRivers {
+    seed: u64,
+    scpos: V2,
+    rng: StdRng,
+}
+
+impl SuperRivers {
+    pub fn new(seed: u64, scpos: V2, rng: StdRng) -> SuperRivers {
+        SuperRivers {
+            seed: seed,
+            scpos: scpos,
+            rng: rng,
+        }
+    }
+
+    /// Choose where a river crosses the superchunk edge that starts at `start` and runs toward
+    /// `dir`, if anywhere.  This depends only on the position of the edge and the heights along
+    /// it, which are the same in both superchunks that share the edge.
+    fn crossing(&self, tmp: &Temporary, start: V2, dir: V2) -> Option<V2> {
+        let abs_start = self.scpos * scalar(SUPERCHUNK_SIZE) + start;
+        let h = derive_seed(self.seed, ("crossing", abs_start.x, abs_start.y, dir.x != 0));
+        if h % CROSSING_CHANCE != 0 {
+            return None;
+        }
+
+        // Never use the corners, which are shared by four superchunks.
+        let t = 1 + ((h >> 8) % (SUPERCHUNK_SIZE - 1) as u64) as i32;
+        let pos = start + dir * scalar(t);
+        if tmp.heights[corner_bounds().index(pos)] > MAX_RIVER_HEIGHT || self.near_spawn(pos) {
+            return None;
+        }
+        Some(pos)
+    }
+
+    fn near_spawn(&self, pos: V2) -> bool {
+        let cpos = self.scpos * scalar(SUPERCHUNK_SIZE) + pos;
+        cpos.abs().max() <= SPAWN_CLEARANCE
+    }
+
+    fn is_spring(&self, tmp: &Temporary, pos: V2) -> bool {
+        let bounds = corner_bounds();
+        let idx = bounds.index(pos);
+        if tmp.wet[idx] || tmp.heights[idx] > MAX_RIVER_HEIGHT || self.near_spawn(pos) {
+            return false;
+        }
+
+        RIVER_DIRS.iter().any(|&(_, dir)| {
+            bounds.contains(pos + dir) && tmp.heights[bounds.index(pos + dir)] > MAX_RIVER_HEIGHT
+        })
+    }
+
+    fn can_flow(&self, tmp: &Temporary, pos: V2, next: V2) -> bool {
+        let bounds = corner_bounds();
+        if !bounds.contains(next) {
+            return false;
+        }
+        let idx = bounds.index(next);
+
+        // Rivers can't run along the edge of the superchunk, since the neighbor on the other side
+        // would need to draw part of it.  They can only reach the edge at a crossing point, where
+        // the neighbor also has a river.
+        if is_edge(pos) && is_edge(next) {
+            return false;
+        }
+        if is_edge(next) && !tmp.crossing[idx] {
+            return false;
+        }
+
+        !self.near_spawn(next) &&
+            tmp.heights[idx] <= MAX_RIVER_HEIGHT &&
+            tmp.heights[idx] <= tmp.heights[bounds.index(pos)]
+    }
+
+    fn trace(&mut self, tmp: &mut Temporary, start: V2) {
+        let bounds = corner_bounds();
+        let mut visited = vec![start];
+        tmp.wet[bounds.index(start)] = true;
+
+        let mut pos = start;
+        let mut last_dir = None;
+        for len in 0 .. MAX_RIVER_LENGTH {
+            let mut best = None;
+            for &(bit, dir) in &RIVER_DIRS {
+                let next = pos + dir;
+                if !self.can_flow(tmp, pos, next) || visited.contains(&next) {
+                    continue;
+                }
+
+                // Always flow downhill when possible.  On flat ground, prefer to keep going in the
+                // same direction, but wander a bit.
+                let height = tmp.heights[bounds.index(next)] as i32;
+                let wander =
+                    if last_dir == Some(dir) { self.rng.gen_range(0, 4) }
+                    else { self.rng.gen_range(2, 8) };
+                let score = height * 8 + wander;
+                match best {
+                    Some((best_score, _, _)) if best_score <= score => {},
+                    _ => { best = Some((score, bit, dir)); },
+                }
+            }
+
+            let (bit, dir) = match best {
+                Some((_, bit, dir)) => (bit, dir),
+                None => {
+                    // Nowhere lower to go, so the water collects here.
+                    add_lake(tmp, pos, len);
+                    return;
+                },
+            };
+
+            let next = pos + dir;
+            let joined = tmp.wet[bounds.index(next)];
+            add_segment(tmp, pos, bit);
+            visited.push(next);
+
+            if joined {
+                // Flowed into another river or lake, or reached a crossing point.
+                return;
+            }
+
+            pos = next;
+            last_dir = Some(dir);
+        }
+
+        add_lake(tmp, pos, MAX_RIVER_LENGTH);
+    }
+}
+
+fn corner_bounds() -> Region<V2> {
+    Region::new(scalar(0), scalar(SUPERCHUNK_SIZE + 1))
+}
+
+fn is_edge(pos: V2) -> bool {
+    pos.x == 0 || pos.x == SUPERCHUNK_SIZE ||
+    pos.y == 0 || pos.y == SUPERCHUNK_SIZE
+}
+
+/// Check if the flags for a corner are stored in this superchunk's summary.  Corners on the east
+/// and south edges belong to the neighboring superchunks instead.
+fn is_owned(pos: V2) -> bool {
+    pos.x < SUPERCHUNK_SIZE && pos.y < SUPERCHUNK_SIZE
+}
+
+/// Record a river segment from `pos` in direction `bit`.  The flag goes on whichever end belongs
+/// to this superchunk.
+fn add_segment(tmp: &mut Temporary, pos: V2, bit: u8) {
+    let bounds = corner_bounds();
+    let dir = RIVER_DIRS.iter().find(|&&(b, _)| b == bit).unwrap().1;
+    let idx = bounds.index(pos);
+    let next_idx = bounds.index(pos + dir);
+
+    if is_owned(pos) {
+        tmp.flags[idx] |= bit;
+    } else {
+        tmp.flags[next_idx] |= opposite(bit);
+    }
+    tmp.wet[idx] = true;
+    tmp.wet[next_idx] = true;
+}
+
+/// Place a lake at the end of a river.  Longer rivers carry more water, so they get bigger lakes.
+fn add_lake(tmp: &mut Temporary, pos: V2, river_len: usize) {
+    let idx = corner_bounds().index(pos);
+    if !is_owned(pos) {
+        return;
+    }
+
+    let size = (1 + river_len / 6) as u8;
+    let size = if size > MAX_LAKE_SIZE { MAX_LAKE_SIZE } else { size };
+    let old_size = tmp.flags[idx] >> LAKE_SHIFT;
+    if size > old_size {
+        tmp.flags[idx] = (tmp.flags[idx] & !(MAX_LAKE_SIZE << LAKE_SHIFT)) | (size << LAKE_SHIFT);
+    }
+    tmp.wet[idx] = true;
+}
+
+pub struct Temporary {
+    heights: [u8; CORNERS],
+    flags: [u8; CORNERS],
+    /// Corners that already have water.
+    wet: [bool; CORNERS],
+    /// Points where a river crosses the edge of the superchunk.
+    crossing: [bool; CORNERS],
+}
+
+impl LocalProperty for SuperRivers {
+    type Summary = SuperchunkSummary;
+    type Temporary = Temporary;
+    type Result = ();
+
+    fn init(&mut self, summ: &SuperchunkSummary) -> Temporary {
+        let mut tmp = Temporary {
+            heights: [0; CORNERS],
+            flags: [0; CORNERS],
+            wet: [false; CORNERS],
+            crossing: [false; CORNERS],
+        };
+        for (dest, &val) in tmp.heights.iter_mut().zip(summ.ds_levels.iter()) {
+            *dest = val;
+        }
+        tmp
+    }
+
+    fn generate(&mut self, tmp: &mut Temporary) {
+        let bounds = corner_bounds();
+
+        // Mark all the crossing points before tracing any rivers, so that rivers from inside
+        // the superchunk can flow into them.
+        let size = SUPERCHUNK_SIZE;
+        let edges = [(V2::new(0, 0), V2::new(1, 0)),
+                     (V2::new(0, 0), V2::new(0, 1)),
+                     (V2::new(0, size), V2::new(1, 0)),
+                     (V2::new(size, 0), V2::new(0, 1))];
+        let mut crossings = Vec::new();
+        for &(start, dir) in &edges {
+            if let Some(pos) = self.crossing(tmp, start, dir) {
+                let idx = bounds.index(pos);
+                tmp.crossing[idx] = true;
+                tmp.wet[idx] = true;
+                crossings.push(pos);
+            }
+        }
+
+        for &pos in &crossings {
+            self.trace(tmp, pos);
+        }
+
+        // Start new rivers at springs near the foot of hills.
+        let count = self.rng.gen_range(0, MAX_SOURCES + 1);
+        for _ in 0 .. count {
+            for _ in 0 .. SOURCE_TRIES {
+                let pos = V2::new(self.rng.gen_range(1, SUPERCHUNK_SIZE),
+                                  self.rng.gen_range(1, SUPERCHUNK_SIZE));
+                if self.is_spring(tmp, pos) {
+                    self.trace(tmp, pos);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn save(&mut self, tmp: Temporary, summ: &mut SuperchunkSummary) {
+        for (dest, &val) in summ.rivers.iter_mut().zip(tmp.flags.iter()) {
+            *dest = val;
+        }
+        summ.has_rivers = true;
+    }
+}
@@ -43,13 +43,6 @@ impl<'a> LocalProperty for Treasure<'a> {
         DiskSampler::new(scalar(CHUNK_SIZE * 3), 3, 6)
     }
 
-    fn load(&mut self, samp: &mut DiskSampler, dir: V2, summ: &ChunkSummary) {
-        let base = (dir + scalar(1)) * scalar(CHUNK_SIZE);
-        for &pos in &summ.treasure_offsets[self.layer as usize] {
-            samp.add_init_point(pos + base);
-        }
-    }
-
     fn generate(&mut self, samp: &mut DiskSampler) {
         samp.generate(&mut self.rng, 30);
     }
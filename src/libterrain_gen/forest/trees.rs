@@ -1,3 +1,5 @@
+use rand::Rng;
+
 use libphysics::CHUNK_SIZE;
 use libserver_types::*;
 
@@ -7,18 +9,27 @@ use algo::dsc::DscGrid;
 use prop::LocalProperty;
 
 use super::summary::ChunkSummary;
+use super::biome::{BiomeMap, BIOMES};
+use super::water::WaterMap;
 
 
 pub struct Trees<'a> {
     rng: StdRng,
     height_grid: &'a DscGrid,
+    biomes: &'a BiomeMap,
+    water: &'a WaterMap,
 }
 
 impl<'a> Trees<'a> {
-    pub fn new(rng: StdRng, height_grid: &'a DscGrid) -> Trees<'a> {
+    pub fn new(rng: StdRng,
+               height_grid: &'a DscGrid,
+               biomes: &'a BiomeMap,
+               water: &'a WaterMap) -> Trees<'a> {
         Trees {
             rng: rng,
             height_grid: height_grid,
+            biomes: biomes,
+            water: water,
         }
     }
 
@@ -30,26 +41,10 @@ impl<'a> Trees<'a> {
         footprint.points_inclusive()
             .all(|offset| {
                 let val = self.height_grid.get_value(pos + offset).unwrap();
-                val.saturating_sub(98) / 2 == target_height.saturating_sub(98) / 2
+                val.saturating_sub(98) / 2 == target_height.saturating_sub(98) / 2 &&
+                    !self.water.is_water(pos + offset, val)
             })
     }
-
-    fn add_placement_constraints(&self, pos: V2, summ: &mut ChunkSummary) {
-        let target_height = self.height_grid.get_value(pos).unwrap() / 2 * 2;
-        let low = target_height;
-        let high = target_height + 1;
-
-        let footprint = Region::new(scalar(0), V2::new(4, 2));
-        let center_bounds = Region::new(scalar(CHUNK_SIZE),
-                                        scalar(CHUNK_SIZE * 2 + 1));
-
-        for offset in footprint.points_inclusive() {
-            if center_bounds.contains(offset) {
-                continue;
-            }
-            summ.heightmap_constraints.push((pos + offset, (low, high)));
-        }
-    }
 }
 
 impl<'a> LocalProperty for Trees<'a> {
@@ -62,13 +57,6 @@ impl<'a> LocalProperty for Trees<'a> {
         DiskSampler::new(scalar(CHUNK_SIZE * 3), 4, 8)
     }
 
-    fn load(&mut self, samp: &mut DiskSampler, dir: V2, summ: &ChunkSummary) {
-        let base = (dir + scalar(1)) * scalar(CHUNK_SIZE);
-        for &pos in &summ.tree_offsets {
-            samp.add_init_point(pos + base);
-        }
-    }
-
     fn generate(&mut self, samp: &mut DiskSampler) {
         samp.generate(&mut self.rng, 30);
     }
@@ -79,15 +67,20 @@ impl<'a> LocalProperty for Trees<'a> {
 
         summ.tree_offsets = Vec::new();
         for &pos in samp.points() {
-            if !bounds.contains(pos) || !self.check_placement(pos) {
+            // TODO: hardcoded size of "tree" template
+            // Keep the whole structure inside this chunk (edges included), since the heightmap
+            // outside it doesn't match what the neighboring chunks generate.  Spacing is only
+            // enforced between trees in the same chunk.
+            if !bounds.contains(pos) || !bounds.contains_inclusive(pos + V2::new(4, 2)) {
+                continue;
+            }
+            if !self.check_placement(pos) {
                 continue;
             }
 
-            // TODO: hardcoded size of "tree" template
-            if !bounds.contains_inclusive(pos + V2::new(4, 2)) {
-                // The structure extends beyond the bounds of the chunk.  Add extra constraints so
-                // that the neighboring chunk will include appropriate terrain.
-                self.add_placement_constraints(pos, summ);
+            let density = BIOMES[self.biomes.get(pos) as usize].tree_density;
+            if density < 100 && self.rng.gen_range(0, 100) >= density {
+                continue;
             }
 
             summ.tree_offsets.push(pos - bounds.min);
@@ -0,0 +1,116 @@
+use libphysics::CHUNK_SIZE;
+use libserver_types::*;
+
+use derive_seed;
+
+use super::provider;
+use super::super_rivers::{RIVER_DIRS, LAKE_SHIFT};
+
+
+/// Maximum distance that a river bends away from the straight line between two chunk corners.
+const MAX_BEND: u64 = 4;
+/// Length of the shallow crossing in the middle of each wide river segment.
+const FORD_LENGTH: i32 = 3;
+
+
+/// Water lookup for the 3x3 chunk area around a chunk, built from the river and lake flags on the
+/// surrounding chunk corners.  Like `BiomeMap`, the exact course of each river comes from a hash
+/// of its position, so chunks on both sides of a river agree on where the water is.
+pub struct WaterMap {
+    seed: u64,
+    /// Tile position of grid point (0, 0).
+    base: V2,
+    /// River flags at each chunk corner.
+    corners: [u8; 4 * 4],
+}
+
+impl WaterMap {
+    pub fn new<F>(seed: u64, cpos: V2, mut f: F) -> WaterMap
+            where F: FnMut(V2) -> u8 {
+        let mut corners = [0; 4 * 4];
+        let corner_bounds = Region::<V2>::new(scalar(0), scalar(4));
+        for offset in corner_bounds.points() {
+            corners[corner_bounds.index(offset)] = f(cpos - scalar(1) + offset);
+        }
+
+        WaterMap {
+            seed: seed,
+            base: (cpos - scalar(1)) * scalar(CHUNK_SIZE),
+            corners: corners,
+        }
+    }
+
+    /// Check if a river segment starting at grid position `start` and running toward `dir`
+    /// covers `pos`.
+    fn in_river(&self, start: V2, dir: V2, pos: V2) -> bool {
+        // Always measure from the west/north end, so both ends of the segment see the same river.
+        let start = if dir.x < 0 || dir.y < 0 { start + dir * scalar(CHUNK_SIZE) } else { start };
+        let horiz = dir.x != 0;
+        let (t, across) = if horiz { (pos.x - start.x, pos.y - start.y) }
+                          else { (pos.y - start.y, pos.x - start.x) };
+        if t < 0 || t > CHUNK_SIZE {
+            return false;
+        }
+
+        let world_start = self.base + start;
+        let h = derive_seed(self.seed, ("river", world_start.x, world_start.y, horiz));
+
+        // Bend the river into a curve that meets the corners at both ends.
+        let bend = (h % (2 * MAX_BEND + 1)) as i32 - MAX_BEND as i32;
+        let offset = bend * t * (CHUNK_SIZE - t) / (CHUNK_SIZE * CHUNK_SIZE / 4);
+
+        // Some segments are narrow streams, and the rest are wide enough to have deep water in
+        // the middle, except at a ford where players can wade across.
+        let wide = (h >> 8) % 3 != 0;
+        let ford_start = CHUNK_SIZE / 4 + ((h >> 16) % (CHUNK_SIZE / 2 - 2) as u64) as i32;
+        let in_ford = t >= ford_start && t < ford_start + FORD_LENGTH;
+        let half_width = if wide && !in_ford { 1 } else { 0 };
+
+        (across - offset).abs() <= half_width
+    }
+
+    fn in_lake(&self, center: V2, size: u8, pos: V2) -> bool {
+        let world_center = self.base + center;
+        let h = derive_seed(self.seed, ("lake", world_center.x, world_center.y));
+        let rx = size as i32 + 2 + (h % 3) as i32;
+        let ry = size as i32 + 2 + ((h >> 8) % 3) as i32;
+        let d = pos - center;
+        d.x * d.x * ry * ry + d.y * d.y * rx * rx <= rx * rx * ry * ry
+    }
+
+    /// Check if a grid position, in the range `0 .. 3 * CHUNK_SIZE + 1`, is in a river or lake.
+    /// This doesn't account for hills; see `is_water`.
+    pub fn get(&self, pos: V2) -> bool {
+        let corner_bounds = Region::<V2>::new(scalar(0), scalar(4));
+        for corner in corner_bounds.points() {
+            let flags = self.corners[corner_bounds.index(corner)];
+            if flags == 0 {
+                continue;
+            }
+            let corner_pos = corner * scalar(CHUNK_SIZE);
+
+            let lake_size = flags >> LAKE_SHIFT;
+            if lake_size > 0 && self.in_lake(corner_pos, lake_size, pos) {
+                return true;
+            }
+
+            for &(bit, dir) in &RIVER_DIRS {
+                if flags & bit != 0 && self.in_river(corner_pos, dir, pos) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Check if a grid position with the given height is covered by water.  Water only appears
+    /// on the ground layer, so rivers that run into the side of a hill disappear under it.
+    pub fn is_water(&self, pos: V2, height: u8) -> bool {
+        height < provider::cutoff(0) && self.get(pos)
+    }
+
+    /// Like `is_water`, but takes a position within the center chunk.
+    pub fn is_water_local(&self, pos: V2, height: u8) -> bool {
+        self.is_water(pos + scalar(CHUNK_SIZE), height)
+    }
+}
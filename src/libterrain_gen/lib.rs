@@ -15,25 +15,49 @@ extern crate server_config as libserver_config;
 extern crate server_types as libserver_types;
 extern crate server_util as libserver_util;
 extern crate terrain_gen_algo as libterrain_gen_algo;
+#[cfg(test)] extern crate rustc_serialize;
 
 use std::collections::HashMap;
-use rand::XorShiftRng;
+use std::hash::{Hash, Hasher, SipHasher};
+use rand::{SeedableRng, XorShiftRng};
 
 use libphysics::CHUNK_SIZE;
 use libserver_types::*;
 
 pub use libterrain_gen_algo as algo;
+pub use registry::GenConfig;
 
 pub mod worker;
+pub mod registry;
 mod prop;
 mod cache;
 
 mod forest;
 mod dungeon;
 
+#[cfg(test)]
+mod test_util;
+
 
 pub type StdRng = XorShiftRng;
 
+/// Derive a new seed from `seed` and some additional key (such as a plane ID or chunk position).
+/// The result depends only on the inputs, so anything generated from a derived seed is the same
+/// no matter what order it was requested in.
+pub fn derive_seed<K: Hash>(seed: u64, key: K) -> u64 {
+    let mut h = SipHasher::new_with_keys(seed, 0x00012345_e0e0e0e0);
+    key.hash(&mut h);
+    h.finish()
+}
+
+/// Build an `StdRng` from a 64-bit seed.
+pub fn seeded_rng(seed: u64) -> StdRng {
+    let lo = seed as u32;
+    let hi = (seed >> 32) as u32;
+    // `XorShiftRng` rejects an all-zero seed.  `lo` and `lo ^ 0xe0e0e0e0` can't both be zero.
+    SeedableRng::from_seed([lo, hi, lo ^ 0xe0e0e0e0, hi ^ 0x00012345])
+}
+
 
 pub struct GenChunk {
     pub blocks: Box<BlockChunk>,
@@ -1,8 +1,16 @@
+use std::sync::Mutex;
+
 use libserver_types::*;
 
 use cache::{Cache, Summary};
 
 
+/// One step of generating a chunk (or superchunk) summary.
+///
+/// Each step sees only the summary of the chunk being generated, never those of its neighbors.
+/// Values that are shared with a neighbor, such as the edges of a heightmap, must be computed
+/// from the seed and their position alone (see `forest::constrain_edges`), so that both chunks
+/// agree on them no matter which one is generated first.
 // TODO: remove Self/Temporary distinction
 pub trait LocalProperty {
     type Summary: Summary;
@@ -13,12 +21,6 @@ pub trait LocalProperty {
     fn init(&mut self,
             summ: &Self::Summary) -> Self::Temporary;
 
-    /// Load data from an adjacent chunk's summary into temporary storage.
-    fn load(&mut self,
-            tmp: &mut Self::Temporary,
-            dir: V2,
-            summ: &Self::Summary);
-
     /// Generate data for the current chunk and write it to temporary storage.
     fn generate(&mut self,
                 tmp: &mut Self::Temporary);
@@ -29,11 +31,15 @@ pub trait LocalProperty {
             summ: &mut Self::Summary) -> Self::Result;
 
     /// Generate a chunk summary into the named cache.
+    ///
+    /// The cache may be shared with other worker threads, so the lock is held only while reading
+    /// and writing summaries, and is released while running `generate`.
     fn generate_into(&mut self,
-                     cache: &mut Cache<Self::Summary>,
+                     cache: &Mutex<Cache<Self::Summary>>,
                      pid: Stable<PlaneId>,
                      cpos: V2) -> Self::Result {
         let mut tmp = {
+            let mut cache = cache.lock().unwrap();
             let summ =
                 if let Ok(_) = cache.load(pid, cpos) {
                     cache.get(pid, cpos)
@@ -43,35 +49,18 @@ pub trait LocalProperty {
             self.init(summ)
         };
 
-        for &dir in &DIRS {
-            match cache.load(pid, cpos + dir) {
-                Ok(_) => {},
-                Err(_) => continue,
-            }
-            let summ = cache.get(pid, cpos + dir);
-            self.load(&mut tmp, dir, summ);
-        }
-
         self.generate(&mut tmp);
 
-        // Summary was previously loaded.  We assume it's still around (requires cache size of at
-        // least 9).
+        let mut cache = cache.lock().unwrap();
+        // Another thread may have evicted the summary while the lock was released.  Eviction
+        // writes it to disk, so it can be loaded again.
+        if let Err(_) = cache.load(pid, cpos) {
+            cache.create(pid, cpos);
+        }
         self.save(tmp, cache.get_mut(pid, cpos))
     }
 }
 
-static DIRS: [V2; 8] = [
-    V2 { x:  1, y:  0 },
-    V2 { x:  1, y:  1 },
-    V2 { x:  0, y:  1 },
-    V2 { x: -1, y:  1 },
-    V2 { x: -1, y:  0 },
-    V2 { x: -1, y: -1 },
-    V2 { x:  0, y: -1 },
-    V2 { x:  1, y: -1 },
-];
-
-
 pub trait GlobalProperty {
     type Summary: Summary;
     type Temporary;
@@ -0,0 +1,122 @@
+//! Registry of terrain generators.  Each plane records the kind of generator to use for its
+//! chunks (see `GenConfig`), and the worker looks up that kind here.
+use std::borrow::ToOwned;
+use std::collections::{BTreeMap, HashMap};
+
+use libphysics::CHUNK_SIZE;
+use libserver_types::*;
+use libserver_config::Data;
+use libserver_config::Storage;
+
+use GenChunk;
+use forest::Provider as ForestProvider;
+use dungeon::Provider as DungeonProvider;
+
+
+/// Generator kinds included in the standard registry.
+pub const KINDS: [&'static str; 4] = ["forest", "dungeon", "flat", "void"];
+
+/// The terrain generator for a plane, along with its generator-specific parameters.  This is
+/// chosen when the plane is created and saved with the plane.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GenConfig {
+    pub kind: String,
+    pub params: BTreeMap<String, String>,
+}
+
+impl GenConfig {
+    pub fn new(kind: &str) -> GenConfig {
+        GenConfig {
+            kind: kind.to_owned(),
+            params: BTreeMap::new(),
+        }
+    }
+
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params.get(key).map(|s| &**s)
+    }
+}
+
+
+/// A terrain generator.  Generators are shared by all the worker threads.
+pub trait Generator: Sync {
+    fn generate(&self, pid: Stable<PlaneId>, cpos: V2, config: &GenConfig) -> GenChunk;
+}
+
+impl<'d> Generator for ForestProvider<'d> {
+    fn generate(&self, pid: Stable<PlaneId>, cpos: V2, _config: &GenConfig) -> GenChunk {
+        ForestProvider::generate(self, pid, cpos)
+    }
+}
+
+impl<'d> Generator for DungeonProvider<'d> {
+    fn generate(&self, pid: Stable<PlaneId>, cpos: V2, config: &GenConfig) -> GenChunk {
+        DungeonProvider::generate(self, pid, cpos, config)
+    }
+}
+
+/// Generates a single layer of floor at `z = 0`.  The `block` parameter gives the name of the
+/// floor block, and defaults to plain grass.
+pub struct Flat<'d> {
+    data: &'d Data,
+}
+
+impl<'d> Generator for Flat<'d> {
+    fn generate(&self, _pid: Stable<PlaneId>, _cpos: V2, config: &GenConfig) -> GenChunk {
+        let mut gc = GenChunk::new();
+        let name = config.param("block").unwrap_or("grass/center/v0");
+        let block = match self.data.block_data.find_id(name) {
+            Some(x) => x,
+            None => {
+                warn!("flat plane: unknown block {:?}", name);
+                return gc;
+            },
+        };
+
+        let bounds = Region::<V2>::new(scalar(0), scalar(CHUNK_SIZE));
+        for pos in bounds.points() {
+            gc.set_block(pos.extend(0), block);
+        }
+        gc
+    }
+}
+
+/// Generates nothing at all.
+pub struct Void;
+
+impl Generator for Void {
+    fn generate(&self, _pid: Stable<PlaneId>, _cpos: V2, _config: &GenConfig) -> GenChunk {
+        GenChunk::new()
+    }
+}
+
+
+pub struct Registry<'d> {
+    gens: HashMap<&'static str, Box<Generator + 'd>>,
+}
+
+impl<'d> Registry<'d> {
+    pub fn new() -> Registry<'d> {
+        Registry {
+            gens: HashMap::new(),
+        }
+    }
+
+    /// Build a registry containing all the generators listed in `KINDS`.
+    pub fn standard(data: &'d Data, storage: &'d Storage, seed: u64) -> Registry<'d> {
+        let mut r = Registry::new();
+        r.register("forest", Box::new(ForestProvider::new(data, storage, seed)));
+        r.register("dungeon", Box::new(DungeonProvider::new(data, storage, seed)));
+        r.register("flat", Box::new(Flat { data: data }));
+        r.register("void", Box::new(Void));
+        r
+    }
+
+    pub fn register(&mut self, kind: &'static str, gen: Box<Generator + 'd>) {
+        self.gens.insert(kind, gen);
+    }
+
+    pub fn get(&self, kind: &str) -> Option<&(Generator + 'd)> {
+        self.gens.get(kind).map(|g| &**g)
+    }
+}
@@ -0,0 +1,25 @@
+//! Helpers for the unit tests.
+
+use std::env;
+use std::fs;
+use rustc_serialize::json::Json;
+
+use libserver_config::{Data, Storage};
+
+
+/// Build a `Data` with no blocks, items, templates, or vaults.  This is enough for generating
+/// forest summaries, which don't look anything up by name.
+pub fn empty_data() -> Data {
+    let empty = || Json::Array(Vec::new());
+    let loot_tables = Json::from_str(r#"{"items": [], "structures": []}"#).unwrap();
+    Data::from_json(empty(), empty(), empty(), empty(), empty(),
+                    loot_tables, empty(), Json::Null).unwrap()
+}
+
+/// Create a `Storage` in a fresh temporary directory.  Tests run in parallel, so each one must
+/// use a different `name`.
+pub fn temp_storage(name: &str) -> Storage {
+    let path = env::temp_dir().join(format!("outpost-terrain-gen-test-{}", name));
+    let _ = fs::remove_dir_all(&path);
+    Storage::new(&path)
+}
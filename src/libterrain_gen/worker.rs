@@ -1,5 +1,7 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, Condvar};
 use std::sync::mpsc::{Sender, Receiver};
-use rand::{Rng, SeedableRng};
+use std::thread;
 use time;
 
 use libserver_types::*;
@@ -7,68 +9,212 @@ use libserver_config::Data;
 use libserver_config::Storage;
 
 use GenChunk;
-use StdRng;
-use forest::Provider as ForestProvider;
-use dungeon::Provider as DungeonProvider;
+use registry::{GenConfig, Registry};
 
 
+/// Priority of a generation request.  Requests with lower values are handled first.
+pub type Priority = u32;
+
 pub enum Command {
-    Generate(Stable<PlaneId>, V2),
+    /// Generate a chunk using the plane's generator config.
+    Generate(Stable<PlaneId>, V2, Priority, GenConfig),
+    /// Drop a pending request.  Has no effect if the chunk is already being generated.
+    Cancel(Stable<PlaneId>, V2),
+    /// Change the priority of a pending request.
+    Reprioritize(Stable<PlaneId>, V2, Priority),
 }
 
 pub type Response = (Stable<PlaneId>, V2, GenChunk);
 
+/// Run the terrain generator, using a pool of `num_threads` worker threads.  Returns after `recv`
+/// is closed and all the workers have stopped.
 pub fn run(data: &Data,
            storage: &Storage,
+           seed: u64,
+           num_threads: usize,
            recv: Receiver<Command>,
            send: Sender<Response>) {
-    let mut w = Worker::new(data, storage);
+    let w = Worker::new(data, storage, seed);
+    let queue = Queue::new();
+
+    // Dropping `guards` waits for the worker threads to finish.
+    let mut guards = Vec::with_capacity(num_threads);
+    for _ in 0 .. num_threads {
+        let w = &w;
+        let queue = &queue;
+        let send = send.clone();
+        guards.push(thread::scoped(move || {
+            while let Some((pid, cpos, config)) = queue.take() {
+                let gc = w.generate_chunk(pid, cpos, &config);
+                // Mark the request as finished before sending the response.  Otherwise, a new
+                // request for the same chunk could arrive after the response was sent but get
+                // discarded as a duplicate.
+                queue.finish(pid, cpos);
+                if let Err(_) = send.send((pid, cpos, gc)) {
+                    break;
+                }
+            }
+        }));
+    }
 
     for cmd in recv.iter() {
         use self::Command::*;
         match cmd {
-            Generate(pid, cpos) => {
-                let gc = w.generate_forest_chunk(pid, cpos);
-                send.send((pid, cpos, gc)).unwrap();
-            },
+            Generate(pid, cpos, priority, config) => queue.add(pid, cpos, priority, config),
+            Cancel(pid, cpos) => queue.cancel(pid, cpos),
+            Reprioritize(pid, cpos, priority) => queue.reprioritize(pid, cpos, priority),
         }
     }
+
+    queue.shut_down();
 }
 
 
 struct Worker<'d> {
-    forest: ForestProvider<'d>,
-    dungeon: DungeonProvider<'d>,
+    registry: Registry<'d>,
 }
 
 impl<'d> Worker<'d> {
-    fn new(data: &'d Data, storage: &'d Storage) -> Worker<'d> {
-        let mut rng: StdRng = SeedableRng::from_seed([0xe0e0e0e0,
-                                                      0x00012345,
-                                                      0xe0e0e0e0,
-                                                      0x00012345]);
-
+    fn new(data: &'d Data, storage: &'d Storage, seed: u64) -> Worker<'d> {
         Worker {
-            forest: ForestProvider::new(data, storage, rng.gen()),
-            dungeon: DungeonProvider::new(data, storage, rng.gen()),
+            registry: Registry::standard(data, storage, seed),
         }
     }
 
-    pub fn generate_forest_chunk(&mut self, pid: Stable<PlaneId>, cpos: V2) -> GenChunk {
+    pub fn generate_chunk(&self,
+                          pid: Stable<PlaneId>,
+                          cpos: V2,
+                          config: &GenConfig) -> GenChunk {
+        let gen = match self.registry.get(&config.kind) {
+            Some(x) => x,
+            None => {
+                warn!("plane {} has unknown generator kind {:?}", pid.unwrap(), config.kind);
+                return GenChunk::new();
+            },
+        };
+
         let start = now();
-        let gc =
-            if pid == STABLE_PLANE_FOREST {
-                self.forest.generate(pid, cpos)
-            } else {
-                self.dungeon.generate(pid, cpos)
-            };
+        let gc = gen.generate(pid, cpos, config);
         let end = now();
-        info!("generated {} {:?} in {} ms", pid.unwrap(), cpos, end - start);
+        info!("generated {} {:?} ({}) in {} ms", pid.unwrap(), cpos, config.kind, end - start);
         gc
     }
 }
 
 
+/// Queue of pending requests, shared by all the worker threads.
+///
+/// The queue de-duplicates requests for the same chunk, and hands out the most important request
+/// first.  It never hands out a chunk that is adjacent to one that is currently being generated,
+/// since generating a chunk reads the summaries of its neighbors.
+struct Queue {
+    state: Mutex<QueueState>,
+    cond: Condvar,
+}
+
+struct QueueState {
+    /// Requests that have not been started yet.  The `u64` is a sequence number, used to handle
+    /// requests with equal priority in FIFO order.
+    pending: HashMap<(Stable<PlaneId>, V2), (Priority, u64, GenConfig)>,
+    /// Chunks that are currently being generated.
+    in_progress: HashSet<(Stable<PlaneId>, V2)>,
+    next_seq: u64,
+    shut_down: bool,
+}
+
+impl Queue {
+    fn new() -> Queue {
+        Queue {
+            state: Mutex::new(QueueState {
+                pending: HashMap::new(),
+                in_progress: HashSet::new(),
+                next_seq: 0,
+                shut_down: false,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    fn add(&self, pid: Stable<PlaneId>, cpos: V2, priority: Priority, config: GenConfig) {
+        let mut state = self.state.lock().unwrap();
+        let key = (pid, cpos);
+        if state.in_progress.contains(&key) {
+            // The response to the current request will satisfy this one as well.
+            return;
+        }
+
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        let entry = state.pending.entry(key).or_insert((priority, seq, config));
+        if priority < entry.0 {
+            entry.0 = priority;
+        }
+        self.cond.notify_one();
+    }
+
+    fn cancel(&self, pid: Stable<PlaneId>, cpos: V2) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(&(pid, cpos));
+    }
+
+    fn reprioritize(&self, pid: Stable<PlaneId>, cpos: V2, priority: Priority) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(entry) = state.pending.get_mut(&(pid, cpos)) {
+            entry.0 = priority;
+        }
+    }
+
+    /// Wait for a request that is ready to be handled.  Returns `None` once the queue is shut
+    /// down.
+    fn take(&self) -> Option<(Stable<PlaneId>, V2, GenConfig)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.shut_down {
+                return None;
+            }
+
+            if let Some(key) = state.choose() {
+                let (_, _, config) = state.pending.remove(&key).unwrap();
+                state.in_progress.insert(key);
+                return Some((key.0, key.1, config));
+            }
+
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+
+    fn finish(&self, pid: Stable<PlaneId>, cpos: V2) {
+        let mut state = self.state.lock().unwrap();
+        state.in_progress.remove(&(pid, cpos));
+        // Requests adjacent to this chunk may be ready now.
+        self.cond.notify_all();
+    }
+
+    fn shut_down(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.shut_down = true;
+        self.cond.notify_all();
+    }
+}
+
+impl QueueState {
+    fn choose(&self) -> Option<(Stable<PlaneId>, V2)> {
+        self.pending.iter()
+            .filter(|&(&(pid, cpos), _)| !self.conflicts(pid, cpos))
+            .min_by(|&(_, &(priority, seq, _))| (priority, seq))
+            .map(|(&key, _)| key)
+    }
+
+    fn conflicts(&self, pid: Stable<PlaneId>, cpos: V2) -> bool {
+        self.in_progress.iter().any(|&(other_pid, other_cpos)| {
+            other_pid == pid &&
+            (other_cpos.x - cpos.x).abs() <= 1 &&
+            (other_cpos.y - cpos.y).abs() <= 1
+        })
+    }
+}
+
+
 fn now() -> u64 {
     let timespec = time::get_time();
     (timespec.sec as u64 * 1000) + (timespec.nsec / 1000000) as u64
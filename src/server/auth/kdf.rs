@@ -0,0 +1,224 @@
+//! PBKDF2-HMAC-SHA256 key derivation, used for hashing client secrets.
+
+/// Derive `out.len()` bytes of key material from `password` and `salt`.
+pub fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    let hmac = Hmac::new(password);
+
+    for (i, chunk) in out.chunks_mut(DIGEST_LEN).enumerate() {
+        let block_idx = i as u32 + 1;
+        let idx_bytes = [(block_idx >> 24) as u8,
+                         (block_idx >> 16) as u8,
+                         (block_idx >>  8) as u8,
+                         (block_idx      ) as u8];
+
+        let mut u = hmac.digest(&[salt, &idx_bytes[..]]);
+        let mut t = u;
+        for _ in 1 .. iterations {
+            u = hmac.digest(&[&u[..]]);
+            for j in 0 .. DIGEST_LEN {
+                t[j] ^= u[j];
+            }
+        }
+
+        for (dest, &src) in chunk.iter_mut().zip(t.iter()) {
+            *dest = src;
+        }
+    }
+}
+
+/// Compare two byte strings in time that depends only on their lengths.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0, |acc, (&x, &y)| acc | (x ^ y)) == 0
+}
+
+
+const DIGEST_LEN: usize = 32;
+const BLOCK_LEN: usize = 64;
+
+type Digest = [u8; DIGEST_LEN];
+
+/// HMAC-SHA256 with a fixed key.  The hash states after absorbing the padded keys are computed
+/// once up front, since PBKDF2 computes many HMACs with the same key.
+struct Hmac {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl Hmac {
+    fn new(key: &[u8]) -> Hmac {
+        let mut key_block = [0_u8; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            let mut sha = Sha256::new();
+            sha.update(key);
+            for (dest, &src) in key_block.iter_mut().zip(sha.finish().iter()) {
+                *dest = src;
+            }
+        } else {
+            for (dest, &src) in key_block.iter_mut().zip(key.iter()) {
+                *dest = src;
+            }
+        }
+
+        let mut ipad = [0_u8; BLOCK_LEN];
+        let mut opad = [0_u8; BLOCK_LEN];
+        for i in 0 .. BLOCK_LEN {
+            ipad[i] = key_block[i] ^ 0x36;
+            opad[i] = key_block[i] ^ 0x5c;
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&ipad);
+        let mut outer = Sha256::new();
+        outer.update(&opad);
+
+        Hmac {
+            inner: inner,
+            outer: outer,
+        }
+    }
+
+    /// Compute the HMAC of the concatenation of `parts`.
+    fn digest(&self, parts: &[&[u8]]) -> Digest {
+        let mut inner = self.inner;
+        for part in parts {
+            inner.update(part);
+        }
+        let inner_digest = inner.finish();
+
+        let mut outer = self.outer;
+        outer.update(&inner_digest);
+        outer.finish()
+    }
+}
+
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+struct Sha256 {
+    state: [u32; 8],
+    buf: [u8; BLOCK_LEN],
+    buf_len: usize,
+    /// Total number of bytes hashed so far.
+    len: u64,
+}
+
+// `Clone` for arrays is only implemented up to length 32, so `#[derive]` can't be used here.
+impl Copy for Sha256 {}
+impl Clone for Sha256 {
+    fn clone(&self) -> Sha256 {
+        *self
+    }
+}
+
+impl Sha256 {
+    fn new() -> Sha256 {
+        Sha256 {
+            state: H0,
+            buf: [0; BLOCK_LEN],
+            buf_len: 0,
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.len += data.len() as u64;
+        for &b in data {
+            self.buf[self.buf_len] = b;
+            self.buf_len += 1;
+            if self.buf_len == BLOCK_LEN {
+                compress(&mut self.state, &self.buf);
+                self.buf_len = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Digest {
+        let bit_len = self.len * 8;
+
+        self.update(&[0x80]);
+        while self.buf_len != BLOCK_LEN - 8 {
+            self.update(&[0]);
+        }
+        let mut len_bytes = [0_u8; 8];
+        for i in 0 .. 8 {
+            len_bytes[i] = (bit_len >> (56 - 8 * i)) as u8;
+        }
+        self.update(&len_bytes);
+
+        let mut out = [0_u8; DIGEST_LEN];
+        for (i, &word) in self.state.iter().enumerate() {
+            out[4 * i + 0] = (word >> 24) as u8;
+            out[4 * i + 1] = (word >> 16) as u8;
+            out[4 * i + 2] = (word >>  8) as u8;
+            out[4 * i + 3] = (word      ) as u8;
+        }
+        out
+    }
+}
+
+fn compress(state: &mut [u32; 8], block: &[u8; BLOCK_LEN]) {
+    let mut w = [0_u32; 64];
+    for i in 0 .. 16 {
+        w[i] = ((block[4 * i + 0] as u32) << 24) |
+               ((block[4 * i + 1] as u32) << 16) |
+               ((block[4 * i + 2] as u32) <<  8) |
+               ((block[4 * i + 3] as u32)      );
+    }
+    for i in 16 .. 64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let mut a = state[0];
+    let mut b = state[1];
+    let mut c = state[2];
+    let mut d = state[3];
+    let mut e = state[4];
+    let mut f = state[5];
+    let mut g = state[6];
+    let mut h = state[7];
+
+    for i in 0 .. 64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    state[0] = state[0].wrapping_add(a);
+    state[1] = state[1].wrapping_add(b);
+    state[2] = state[2].wrapping_add(c);
+    state[3] = state[3].wrapping_add(d);
+    state[4] = state[4].wrapping_add(e);
+    state[5] = state[5].wrapping_add(f);
+    state[6] = state[6].wrapping_add(g);
+    state[7] = state[7].wrapping_add(h);
+}
@@ -0,0 +1,527 @@
+//! Account database.  Secrets are stored as salted PBKDF2 hashes.
+//!
+//! Hashing a secret is deliberately slow, so the engine doesn't do it on its own thread.  Instead,
+//! `start_login` and `start_register` hand the work to a background thread, and the engine passes
+//! each `AuthEvent` that comes back to `process` to finish the request.  The blocking `login` and
+//! `register` methods do the same steps in one call.
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+use std::hash::{SipHasher, Hash, Hasher};
+use std::path::Path;
+use std::result;
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread;
+use rand::{self, Rng};
+use rustc_serialize::hex::{ToHex, FromHex};
+use time;
+
+use rusqlite::{SqliteConnection, SqliteError};
+use rusqlite::types::ToSql;
+use rusqlite_ffi::SQLITE_CONSTRAINT;
+
+use types::*;
+use util::StrError;
+
+mod kdf;
+
+
+pub struct Auth {
+    conn: SqliteConnection,
+    send: Sender<Command>,
+    recv: Receiver<AuthEvent>,
+    /// Wires with a login or registration waiting on the hashing thread.
+    pending: HashSet<WireId>,
+}
+
+/// Work for the hashing thread.
+enum Command {
+    /// Check a login secret against the stored hash, if the account exists.
+    Login(WireId, String, Secret, Option<String>),
+    /// Hash the secret for a new account.  The `u32` is the appearance, passed through unchanged.
+    Register(WireId, String, Secret, u32),
+}
+
+/// Opaque result from the hashing thread.  Pass it to `Auth::process`.
+pub struct AuthEvent(WireId, Hashed);
+
+enum Hashed {
+    /// Name, the stored hash that was checked, the result, and the replacement hash if the old one
+    /// needs upgrading.
+    Login(String, Option<String>, SecretMatch, Option<String>),
+    /// Name, appearance, and the new hash.
+    Register(String, u32, String),
+}
+
+/// A finished request, as returned by `Auth::process`.
+pub enum AuthResult {
+    Login(WireId, String, Result<LoginResult>),
+    /// Wire, name, appearance, and whether the account was created (`false` if the name is
+    /// already in use).
+    Register(WireId, String, u32, Result<bool>),
+}
+
+/// Outcome of a login attempt.
+pub enum LoginResult {
+    Ok,
+    /// There is no account with the given name, or the secret doesn't match.
+    BadSecret,
+    Banned(Ban),
+}
+
+pub struct Ban {
+    pub reason: String,
+    /// Unix time (in seconds) when the ban expires, or `None` if the ban is permanent.
+    pub expires: Option<i64>,
+}
+
+/// Columns added to the `auth` table after its initial version.  These get added to existing
+/// databases when the server starts up.
+const EXTRA_COLUMNS: [(&'static str, &'static str); 4] = [
+    ("banned",      "INTEGER NOT NULL DEFAULT 0"),
+    ("ban_reason",  "TEXT"),
+    ("ban_expires", "INTEGER"),
+    ("last_login",  "INTEGER"),
+];
+
+impl Auth {
+    pub fn new<P: AsRef<Path>>(db_path: &P) -> Result<Auth> {
+        let conn = try!(SqliteConnection::open(db_path));
+        try!(conn.execute("CREATE TABLE IF NOT EXISTS auth (
+                           name      TEXT NOT NULL UNIQUE,
+                           secret    TEXT NOT NULL
+                           )", &[]));
+
+        let mut columns = Vec::new();
+        {
+            let mut stmt = try!(conn.prepare("PRAGMA table_info(auth)"));
+            for row in try!(stmt.query(&[])) {
+                let row = try!(row);
+                let name: String = row.get(1);
+                columns.push(name);
+            }
+        }
+        for &(name, decl) in EXTRA_COLUMNS.iter() {
+            if !columns.iter().any(|c| c == name) {
+                info!("adding column {} to auth table", name);
+                try!(conn.execute(&format!("ALTER TABLE auth ADD COLUMN {} {}", name, decl),
+                                  &[]));
+            }
+        }
+
+        let (send_cmd, recv_cmd) = mpsc::channel();
+        let (send_result, recv_result) = mpsc::channel();
+        thread::spawn(move || run_hasher(recv_cmd, send_result));
+
+        Ok(Auth {
+            conn: conn,
+            send: send_cmd,
+            recv: recv_result,
+            pending: HashSet::new(),
+        })
+    }
+
+    pub fn receiver(&self) -> &Receiver<AuthEvent> {
+        &self.recv
+    }
+
+    /// Start checking a login on the hashing thread.  The result arrives later as an `AuthEvent`.
+    /// Returns `false` (and does nothing) if the wire already has a request in progress.
+    pub fn start_login(&mut self, wire_id: WireId, name: String, secret: Secret) -> Result<bool> {
+        if self.pending.contains(&wire_id) {
+            return Ok(false);
+        }
+        let hash = try!(self.stored_hash(&*name));
+        self.pending.insert(wire_id);
+        self.send.send(Command::Login(wire_id, name, secret, hash)).unwrap();
+        Ok(true)
+    }
+
+    /// Start hashing the secret for a new account on the hashing thread.  The result arrives
+    /// later as an `AuthEvent`.  Returns `false` (and does nothing) if the wire already has a
+    /// request in progress.
+    pub fn start_register(&mut self,
+                          wire_id: WireId,
+                          name: String,
+                          secret: Secret,
+                          appearance: u32) -> bool {
+        if !self.pending.insert(wire_id) {
+            return false;
+        }
+        self.send.send(Command::Register(wire_id, name, secret, appearance)).unwrap();
+        true
+    }
+
+    /// Forget about any request in progress for a wire that has closed.  Its result will be
+    /// discarded by `process`.
+    pub fn cancel(&mut self, wire_id: WireId) {
+        self.pending.remove(&wire_id);
+    }
+
+    /// Finish a request using the result from the hashing thread.  Returns `None` if the request
+    /// was cancelled.
+    pub fn process(&mut self, evt: AuthEvent) -> Option<AuthResult> {
+        let AuthEvent(wire_id, hashed) = evt;
+        if !self.pending.remove(&wire_id) {
+            return None;
+        }
+        Some(match hashed {
+            Hashed::Login(name, old_hash, m, new_hash) => {
+                let result = self.finish_login(&*name, old_hash, m, new_hash);
+                AuthResult::Login(wire_id, name, result)
+            },
+            Hashed::Register(name, appearance, hash) => {
+                let result = self.insert(&*name, &*hash);
+                AuthResult::Register(wire_id, name, appearance, result)
+            },
+        })
+    }
+
+    /// Create an account, hashing the secret on the current thread.  Returns `false` if the name
+    /// is already in use.
+    pub fn register(&mut self, name: &str, secret: &Secret) -> Result<bool> {
+        let hash = hash_secret(secret);
+        self.insert(name, &*hash)
+    }
+
+    fn insert(&mut self, name: &str, hash: &str) -> Result<bool> {
+        let result = self.conn.execute("INSERT INTO auth (name, secret)
+                                        VALUES ($1, $2)",
+                                       &[&name as &ToSql,
+                                         &hash as &ToSql]);
+        match result {
+            Ok(_) => Ok(true),
+            // Constraint violation means the username is already registered.
+            Err(ref e) if e.code == SQLITE_CONSTRAINT => Ok(false),
+            Err(e) => Err(Error::Sqlite(e)),
+        }
+    }
+
+    /// Check a login, hashing the secret on the current thread.
+    pub fn login(&mut self, name: &str, secret: &Secret) -> Result<LoginResult> {
+        let old_hash = try!(self.stored_hash(name));
+        let (m, new_hash) = check_login(secret, old_hash.as_ref().map(|h| &**h));
+        self.finish_login(name, old_hash, m, new_hash)
+    }
+
+    fn stored_hash(&mut self, name: &str) -> Result<Option<String>> {
+        let mut stmt = try!(self.conn.prepare("SELECT secret FROM auth WHERE name = $1"));
+        for row in try!(stmt.query(&[&name as &ToSql])) {
+            let row = try!(row);
+            return Ok(Some(row.get(0)));
+        }
+        Ok(None)
+    }
+
+    /// Apply the result of `check_login`.  `old_hash` is the hash the secret was checked against.
+    fn finish_login(&mut self,
+                    name: &str,
+                    old_hash: Option<String>,
+                    m: SecretMatch,
+                    new_hash: Option<String>) -> Result<LoginResult> {
+        let old_hash = unwrap_or!(old_hash, return Ok(LoginResult::BadSecret));
+        // Check the secret before the ban, so that the ban reason is only revealed to the owner
+        // of the account.
+        if let SecretMatch::No = m {
+            return Ok(LoginResult::BadSecret);
+        }
+
+        // The account may have changed while the secret was being checked, so read it again.
+        let (hash, ban) = {
+            let mut stmt = try!(self.conn.prepare(
+                    "SELECT secret, banned, ban_reason, ban_expires FROM auth WHERE name = $1"));
+            let mut rows = try!(stmt.query(&[&name as &ToSql]));
+            let row = match rows.next() {
+                Some(row) => try!(row),
+                None => return Ok(LoginResult::BadSecret),
+            };
+
+            let hash: String = row.get(0);
+            let banned: i64 = row.get(1);
+            let ban =
+                if banned != 0 {
+                    let reason: Option<String> = row.get(2);
+                    Some(Ban {
+                        reason: reason.unwrap_or_else(String::new),
+                        expires: row.get(3),
+                    })
+                } else {
+                    None
+                };
+            (hash, ban)
+        };
+        if hash != old_hash {
+            // The secret was changed in the meantime.
+            return Ok(LoginResult::BadSecret);
+        }
+
+        if let Some(new_hash) = new_hash {
+            info!("rehashing secret for {}", name);
+            try!(self.set_hash(name, &*new_hash));
+        }
+
+        let now = unix_time();
+        if let Some(ban) = ban {
+            match ban.expires {
+                Some(expires) if expires <= now => {
+                    info!("ban on {} has expired", name);
+                    try!(self.unban(name));
+                },
+                _ => return Ok(LoginResult::Banned(ban)),
+            }
+        }
+
+        try!(self.conn.execute("UPDATE auth SET last_login = $2 WHERE name = $1",
+                               &[&name as &ToSql,
+                                 &now as &ToSql]));
+        Ok(LoginResult::Ok)
+    }
+
+    /// Get the Unix time (in seconds) of the last successful login to the account.  Returns `None`
+    /// if there is no such account or if it has never been logged into.
+    pub fn last_login(&mut self, name: &str) -> Result<Option<i64>> {
+        let mut stmt = try!(self.conn.prepare("SELECT last_login FROM auth WHERE name = $1"));
+        for row in try!(stmt.query(&[&name as &ToSql])) {
+            let row = try!(row);
+            return Ok(row.get(0));
+        }
+        Ok(None)
+    }
+
+    /// Replace the secret of an existing account, hashing it on the current thread.  Returns
+    /// `false` if there is no such account.
+    pub fn change_secret(&mut self, name: &str, secret: &Secret) -> Result<bool> {
+        let hash = hash_secret(secret);
+        self.set_hash(name, &*hash)
+    }
+
+    fn set_hash(&mut self, name: &str, hash: &str) -> Result<bool> {
+        let count = try!(self.conn.execute("UPDATE auth SET secret = $2 WHERE name = $1",
+                                           &[&name as &ToSql,
+                                             &hash as &ToSql]));
+        Ok(count > 0)
+    }
+
+    /// Delete an account, freeing up its name for registration.  Returns `false` if there is no
+    /// such account.
+    pub fn delete(&mut self, name: &str) -> Result<bool> {
+        let count = try!(self.conn.execute("DELETE FROM auth WHERE name = $1",
+                                           &[&name as &ToSql]));
+        Ok(count > 0)
+    }
+
+    /// Prevent an account from logging in, either permanently or until the Unix time `expires`
+    /// (in seconds).  Replaces any existing ban.  Returns `false` if there is no such account.
+    pub fn ban(&mut self, name: &str, reason: &str, expires: Option<i64>) -> Result<bool> {
+        let count = try!(self.conn.execute("UPDATE auth
+                                            SET banned = 1, ban_reason = $2, ban_expires = $3
+                                            WHERE name = $1",
+                                           &[&name as &ToSql,
+                                             &reason as &ToSql,
+                                             &expires as &ToSql]));
+        Ok(count > 0)
+    }
+
+    /// Lift the ban on an account.  Returns `false` if there is no such account.
+    pub fn unban(&mut self, name: &str) -> Result<bool> {
+        let count = try!(self.conn.execute("UPDATE auth
+                                            SET banned = 0, ban_reason = NULL, ban_expires = NULL
+                                            WHERE name = $1",
+                                           &[&name as &ToSql]));
+        Ok(count > 0)
+    }
+}
+
+/// Current Unix time in seconds, as used for the timestamps in the auth database.
+pub fn unix_time() -> i64 {
+    time::get_time().sec
+}
+
+fn run_hasher(recv: Receiver<Command>, send: Sender<AuthEvent>) {
+    // `recv` fails once the `Auth` is dropped.
+    while let Ok(cmd) = recv.recv() {
+        let evt = match cmd {
+            Command::Login(wire_id, name, secret, hash) => {
+                let (m, new_hash) = check_login(&secret, hash.as_ref().map(|h| &**h));
+                AuthEvent(wire_id, Hashed::Login(name, hash, m, new_hash))
+            },
+            Command::Register(wire_id, name, secret, appearance) => {
+                let hash = hash_secret(&secret);
+                AuthEvent(wire_id, Hashed::Register(name, appearance, hash))
+            },
+        };
+        if send.send(evt).is_err() {
+            break;
+        }
+    }
+}
+
+
+
+pub type Secret = [u32; 4];
+
+/// Current version of the hash format.  Hashes with older versions are replaced on the next
+/// successful login.
+const HASH_VERSION: u32 = 1;
+/// Number of PBKDF2 iterations for new hashes.  The iteration count is stored with each hash, so
+/// this can be raised without invalidating existing hashes.
+const KDF_ITERATIONS: u32 = 10000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+fn secret_bytes(s: &Secret) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    for (i, &x) in s.iter().enumerate() {
+        bytes[4 * i + 0] = (x      ) as u8;
+        bytes[4 * i + 1] = (x >>  8) as u8;
+        bytes[4 * i + 2] = (x >> 16) as u8;
+        bytes[4 * i + 3] = (x >> 24) as u8;
+    }
+    bytes
+}
+
+fn hash_secret(s: &Secret) -> String {
+    let mut salt = [0; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+
+    let mut hash = [0; HASH_LEN];
+    kdf::pbkdf2(&secret_bytes(s), &salt, KDF_ITERATIONS, &mut hash);
+
+    format!("{};{};{};{}", HASH_VERSION, KDF_ITERATIONS, salt.to_hex(), hash.to_hex())
+}
+
+enum SecretMatch {
+    Yes,
+    No,
+    YesNeedsRehash,
+}
+
+/// Check a login secret against the stored hash, or `None` if there is no such account.  If the
+/// hash needs upgrading, also returns the replacement.
+///
+/// This takes about as long for a missing account as for an existing one, so the timing doesn't
+/// reveal which names are registered.
+fn check_login(s: &Secret, hash: Option<&str>) -> (SecretMatch, Option<String>) {
+    let hash = unwrap_or!(hash, {
+        let mut dummy = [0; HASH_LEN];
+        kdf::pbkdf2(&secret_bytes(s), &[0; SALT_LEN], KDF_ITERATIONS, &mut dummy);
+        return (SecretMatch::No, None);
+    });
+    match check_secret(s, hash) {
+        SecretMatch::YesNeedsRehash => (SecretMatch::YesNeedsRehash, Some(hash_secret(s))),
+        m => (m, None),
+    }
+}
+
+fn check_secret(s: &Secret, hash: &str) -> SecretMatch {
+    let idx = unwrap_or!(hash.find(';'), return SecretMatch::No);
+    let version: u32 = unwrap_or!(hash[..idx].parse().ok(), return SecretMatch::No);
+    let params = &hash[(idx + 1)..];
+
+    let result = match version {
+        0 => check_secret_v0(s, params),
+        1 => check_secret_v1(s, params),
+        _ => None,
+    };
+
+    match result {
+        Some(SecretMatch::Yes) if version < HASH_VERSION => SecretMatch::YesNeedsRehash,
+        Some(m) => m,
+        None => {
+            warn!("malformed secret hash (version {})", version);
+            SecretMatch::No
+        },
+    }
+}
+
+/// Check against an old-style hash, which uses a randomly keyed `SipHasher`.  Returns `None` if
+/// the hash is malformed.
+fn check_secret_v0(s: &Secret, params: &str) -> Option<SecretMatch> {
+    let mut iter = params.split(';');
+    let salt0 = unwrap_or!(iter.next().and_then(|x| x.parse().ok()), return None);
+    let salt1 = unwrap_or!(iter.next().and_then(|x| x.parse().ok()), return None);
+    let expect_hash = unwrap_or!(iter.next().and_then(|x| x.parse().ok()), return None);
+
+    let mut sip = SipHasher::new_with_keys(salt0, salt1);
+    for x in s.iter() {
+        x.hash(&mut sip);
+    }
+    let hash: u64 = sip.finish();
+
+    if hash == expect_hash {
+        Some(SecretMatch::Yes)
+    } else {
+        Some(SecretMatch::No)
+    }
+}
+
+/// Check against a PBKDF2 hash.  Returns `None` if the hash is malformed.
+fn check_secret_v1(s: &Secret, params: &str) -> Option<SecretMatch> {
+    let mut iter = params.split(';');
+    let iterations: u32 = unwrap_or!(iter.next().and_then(|x| x.parse().ok()), return None);
+    let salt = unwrap_or!(iter.next().and_then(|x| x.from_hex().ok()), return None);
+    let expect_hash = unwrap_or!(iter.next().and_then(|x| x.from_hex().ok()), return None);
+    if iterations == 0 || expect_hash.len() == 0 {
+        return None;
+    }
+
+    let mut hash = vec![0; expect_hash.len()];
+    kdf::pbkdf2(&secret_bytes(s), &salt, iterations, &mut hash);
+
+    if !kdf::constant_time_eq(&hash, &expect_hash) {
+        Some(SecretMatch::No)
+    } else if iterations < KDF_ITERATIONS {
+        Some(SecretMatch::YesNeedsRehash)
+    } else {
+        Some(SecretMatch::Yes)
+    }
+}
+
+
+#[derive(Debug)]
+pub enum Error {
+    Str(StrError),
+    Sqlite(SqliteError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Str(ref e) => e.fmt(f),
+            Error::Sqlite(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Str(ref e) => e.description(),
+            Error::Sqlite(ref e) => &*e.message,
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Str(ref e) => Some(e as &error::Error),
+            // SqliteError doesn't implement Error.
+            Error::Sqlite(_) => None,
+        }
+    }
+}
+
+impl From<StrError> for Error {
+    fn from(e: StrError) -> Error {
+        Error::Str(e)
+    }
+}
+
+impl From<SqliteError> for Error {
+    fn from(e: SqliteError) -> Error {
+        Error::Sqlite(e)
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+
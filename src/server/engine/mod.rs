@@ -1,10 +1,12 @@
 use std::boxed::FnBox;
 use std::error::Error;
+use std::io::Read;
 use std::sync::mpsc::{Sender, Receiver};
+use rustc_serialize::json::Json;
 
 use types::*;
 
-use auth::{Auth, Secret};
+use auth::{self, Auth, AuthEvent, AuthResult, LoginResult};
 use cache::TerrainCache;
 use chunks::Chunks;
 use data::Data;
@@ -12,7 +14,7 @@ use logic;
 use logic::extra::Extra;
 use messages::{Messages, MessageEvent};
 use messages::{Event, ControlEvent, WireEvent, ClientEvent};
-use messages::SyncKind;
+use messages::{SyncKind, RateLimits};
 use messages::{ControlResponse, WireResponse, ClientResponse};
 use msg::{Request, Response};
 use physics::Physics;
@@ -63,6 +65,14 @@ impl<'d> Engine<'d> {
            storage: &'d Storage,
            receiver: Receiver<(WireId, Request)>,
            sender: Sender<(WireId, Response)>) -> Engine<'d> {
+        let mut messages = Messages::new(receiver, sender);
+        if let Some(mut file) = storage.open_rate_limit_data() {
+            let mut content = String::new();
+            file.read_to_string(&mut content).unwrap();
+            let json = Json::from_str(&content).unwrap();
+            messages.set_rate_limits(RateLimits::from_json(&json).unwrap());
+        }
+
         Engine {
             data: data,
             storage: storage,
@@ -72,14 +82,15 @@ impl<'d> Engine<'d> {
             script: ScriptEngine::new(&storage.script_dir()),
 
             extra: Extra::new(),
-            messages: Messages::new(receiver, sender),
+            messages: messages,
             timer: Timer::new(),
             physics: Physics::new(data),
             vision: Vision::new(),
             auth: Auth::new(&storage.auth_db_path()).unwrap(),
             chunks: Chunks::new(storage),
             cache: TerrainCache::new(),
-            terrain_gen: TerrainGen::new(data, storage),
+            terrain_gen: TerrainGen::new(data, storage,
+                                         logic::lifecycle::load_world_seed(storage)),
         }
     }
 
@@ -92,20 +103,31 @@ impl<'d> Engine<'d> {
         }
 
         loop {
+            // Handle events queued while processing the last one.
+            while let Some(evt) = self.messages.take_deferred() {
+                match self.handle_control(evt) {
+                    Continue => {},
+                    result => warn!("ignoring {:?} from deferred event", result),
+                }
+            }
+
             enum Event {
                 FromTimer(TimerEvent),
                 FromMessage(MessageEvent),
                 FromTerrainGen(TerrainGenEvent),
+                FromAuth(AuthEvent),
             }
 
             let evt = {
                 let recv_timer = self.timer.receiver();
                 let recv_message = self.messages.receiver();
                 let recv_terrain_gen = self.terrain_gen.receiver();
+                let recv_auth = self.auth.receiver();
                 select! {
                     evt = recv_timer.recv() => Event::FromTimer(evt.unwrap()),
                     evt = recv_message.recv() => Event::FromMessage(evt.unwrap()),
-                    evt = recv_terrain_gen.recv() => Event::FromTerrainGen(evt.unwrap())
+                    evt = recv_terrain_gen.recv() => Event::FromTerrainGen(evt.unwrap()),
+                    evt = recv_auth.recv() => Event::FromAuth(evt.unwrap())
                 }
             };
 
@@ -129,6 +151,11 @@ impl<'d> Engine<'d> {
                 Event::FromTerrainGen(evt) => {
                     self.as_ref().as_terrain_gen_fragment().process(evt);
                 },
+                Event::FromAuth(evt) => {
+                    let result = unwrap_or!(self.auth.process(evt), continue);
+                    self.now = self.messages.world_now();
+                    self.handle_auth(result);
+                },
             }
         }
 
@@ -159,6 +186,7 @@ impl<'d> Engine<'d> {
                 if let Some(cid) = opt_cid {
                     self.cleanup_client(cid);
                 }
+                self.auth.cancel(wire_id);
                 self.messages.send_control(WireClosed(wire_id));
             },
 
@@ -185,6 +213,24 @@ impl<'d> Engine<'d> {
                     return HandlerResult::Restart;
                 }
             },
+
+            Save => {
+                logic::autosave::start(self.as_ref());
+            },
+
+            ReloadScripts => {
+                logic::lifecycle::reload_scripts(self.as_ref());
+            },
+
+            KickClient(cid, msg) => {
+                if self.messages.client_to_wire(cid).is_some() {
+                    self.kick_client(cid, msg);
+                }
+            },
+
+            RemoveClientSave(name) => {
+                warn_on_err!(self.storage.remove_client_file(&name));
+            },
         }
         HandlerResult::Continue
     }
@@ -196,15 +242,9 @@ impl<'d> Engine<'d> {
         use messages::WireResponse::*;
         match evt {
             Login(name, secret) => {
-                match self.auth.login(&*name, &secret) {
-                    Ok(true) => {
-                        warn_on_err!(logic::client::login(self.as_ref(), wire_id, &*name));
-                    },
-                    Ok(false) => {
-                        info!("{:?}: login as {} failed: bad name/secret",
-                              wire_id, name);
-                        self.kick_wire(wire_id, "login failed")
-                    },
+                match self.auth.start_login(wire_id, name.clone(), secret) {
+                    Ok(true) => {},
+                    Ok(false) => self.kick_wire(wire_id, "bad request"),
                     Err(e) => {
                         info!("{:?}: login as {} failed: auth error: {}",
                               wire_id, name, e.description());
@@ -214,8 +254,16 @@ impl<'d> Engine<'d> {
             },
 
             Register(name, secret, appearance) => {
-                let (code, msg) = self.do_register(wire_id, name, secret, appearance);
-                self.messages.send_wire(wire_id, RegisterResult(code, msg));
+                if let Err(msg) = name_valid(&*name) {
+                    self.messages.send_wire(wire_id, RegisterResult(1, String::from(msg)));
+                } else if !self.auth.start_register(wire_id, name, secret, appearance) {
+                    self.kick_wire(wire_id, "bad request");
+                }
+            },
+
+            RateLimited(class) => {
+                logic::input::record_wire_rate_limited(self.as_ref(), wire_id, class);
+                self.kick_wire(wire_id, "the server is busy; please try again later");
             },
 
             BadRequest => {
@@ -249,7 +297,7 @@ impl<'d> Engine<'d> {
             },
 
             CraftRecipe(station_sid, iid, recipe_id, count) => {
-                warn_on_err!(logic::items::craft_recipe(self.as_ref(),
+                warn_on_err!(logic::items::craft_recipe(self.as_ref(), cid,
                                                         station_sid, iid, recipe_id, count));
             },
 
@@ -258,18 +306,28 @@ impl<'d> Engine<'d> {
             },
 
             Interact(time, args) => {
+                let time = logic::input::clamp_action_time(self.now, cid, time);
                 self.timer.schedule(time,
-                                    move |eng| logic::input::interact(eng, cid, args));
+                                    move |eng| logic::input::interact(eng, cid, time, args));
             },
 
             UseItem(time, item_id, args) => {
-                self.timer.schedule(time,
-                                    move |eng| logic::input::use_item(eng, cid, item_id, args));
+                let time = logic::input::clamp_action_time(self.now, cid, time);
+                self.timer.schedule(time, move |eng| {
+                    logic::input::use_item(eng, cid, time, item_id, args)
+                });
             },
 
             UseAbility(time, item_id, args) => {
-                self.timer.schedule(time,
-                                    move |eng| logic::input::use_ability(eng, cid, item_id, args));
+                let time = logic::input::clamp_action_time(self.now, cid, time);
+                self.timer.schedule(time, move |eng| {
+                    logic::input::use_ability(eng, cid, time, item_id, args)
+                });
+            },
+
+            RateLimited(class) => {
+                logic::input::record_rate_limited(self.as_ref(), cid, class);
+                self.kick_client(cid, format!("too many {} requests", class.name()));
             },
 
             BadRequest => {
@@ -302,6 +360,7 @@ impl<'d> Engine<'d> {
     pub fn kick_wire<'a, S: Into<String>>(&mut self, wire_id: WireId, msg: S) {
         self.messages.send_wire(wire_id, WireResponse::KickReason(msg.into()));
         self.cleanup_wire(wire_id);
+        self.auth.cancel(wire_id);
         self.messages.send_control(ControlResponse::WireClosed(wire_id));
     }
 
@@ -311,16 +370,44 @@ impl<'d> Engine<'d> {
     }
 
 
-    fn do_register(&mut self,
-                   wire_id: WireId,
-                   name: String,
-                   secret: Secret,
-                   appearance: u32) -> (u32, String) {
-        if let Err(msg) = name_valid(&*name) {
-            return (1, String::from(msg));
+    fn handle_auth(&mut self, result: AuthResult) {
+        match result {
+            AuthResult::Login(wire_id, name, result) => {
+                match result {
+                    Ok(LoginResult::Ok) => {
+                        warn_on_err!(logic::client::login(self.as_ref(), wire_id, &*name));
+                    },
+                    Ok(LoginResult::BadSecret) => {
+                        info!("{:?}: login as {} failed: bad name/secret",
+                              wire_id, name);
+                        self.kick_wire(wire_id, "login failed")
+                    },
+                    Ok(LoginResult::Banned(ban)) => {
+                        info!("{:?}: login as {} failed: account is banned",
+                              wire_id, name);
+                        self.kick_wire(wire_id, format!("banned: {}", ban.reason))
+                    },
+                    Err(e) => {
+                        info!("{:?}: login as {} failed: auth error: {}",
+                              wire_id, name, e.description());
+                        self.kick_wire(wire_id, "login failed")
+                    },
+                }
+            },
+
+            AuthResult::Register(wire_id, name, appearance, result) => {
+                let (code, msg) = self.finish_register(wire_id, name, appearance, result);
+                self.messages.send_wire(wire_id, WireResponse::RegisterResult(code, msg));
+            },
         }
+    }
 
-        match self.auth.register(&*name, &secret) {
+    fn finish_register(&mut self,
+                       wire_id: WireId,
+                       name: String,
+                       appearance: u32,
+                       result: auth::Result<bool>) -> (u32, String) {
+        match result {
             Ok(true) => {
                 info!("{:?}: registered as {}", wire_id, name);
                 match logic::client::register(self.as_ref(), &*name, appearance) {
@@ -0,0 +1,222 @@
+//! Periodic saving of the live world, so a crash loses at most a few minutes of changes.
+//!
+//! An autosave writes every loaded client and plane, every terrain chunk that has changed since it
+//! was last written (see `Extra::dirty_terrain_chunks`), and finally the world and misc files.
+//! The work is split into small steps run from the timer, so the engine can keep handling other
+//! events while the save is in progress.
+//!
+//! Each terrain chunk is written in the same step as every client whose view includes it, so the
+//! two capture the world at the same instant, with no game events in between.  This keeps an item
+//! moved between a container in the chunk and a player's inventory from being saved in both places
+//! or in neither, as long as the whole step reaches the disk.  The files are still written one at
+//! a time, so a crash in the middle of a step can leave some of them old and some new.
+//!
+//! Getting the script `extra` table of an entity, inventory, or structure marks the owning chunk
+//! dirty, since the script may change it.
+use std::collections::HashSet;
+use std::mem;
+
+use types::*;
+use util::now;
+
+use engine::glue::*;
+use engine::split::EngineRef;
+use logic;
+use timer;
+use world::Fragment;
+use world::flags;
+use world::object::*;
+use world::save::{self, ObjectWriter};
+
+
+/// Default time between autosaves, in milliseconds.
+const DEFAULT_INTERVAL: Time = 5 * 60 * 1000;
+
+/// Maximum number of objects to write in a single step of an autosave.
+const STEP_SIZE: usize = 8;
+
+/// Delay between steps of an autosave, in milliseconds.
+const STEP_DELAY: Time = 20;
+
+
+pub enum SaveItem {
+    TerrainChunk(PlaneId, V2),
+    Plane(PlaneId),
+    Client(ClientId),
+    World,
+}
+
+pub struct Autosave {
+    /// Time between autosaves, in milliseconds.  Zero disables autosave.
+    interval: Time,
+    /// Timer for the next autosave, or for the next step of the autosave in progress.
+    timer: Option<timer::Cookie>,
+    /// Objects not yet written by the autosave in progress, in reverse order.
+    queue: Vec<SaveItem>,
+    running: bool,
+    /// Real time when the autosave in progress was started.
+    start_time: Time,
+}
+
+impl Autosave {
+    pub fn new() -> Autosave {
+        Autosave {
+            interval: DEFAULT_INTERVAL,
+            timer: None,
+            queue: Vec::new(),
+            running: false,
+            start_time: 0,
+        }
+    }
+}
+
+
+/// Schedule the next autosave, replacing any previously scheduled one.  Does nothing while an
+/// autosave is running, since it schedules the next one itself when it finishes.
+pub fn schedule(mut eng: EngineRef) {
+    if eng.extra().autosave.running {
+        return;
+    }
+    if let Some(cookie) = eng.extra_mut().autosave.timer.take() {
+        eng.timer_mut().cancel(cookie);
+    }
+
+    let interval = eng.extra().autosave.interval;
+    if interval == 0 {
+        return;
+    }
+    let when = eng.now() + interval;
+    let cookie = eng.timer_mut().schedule(when, |eng| start(eng));
+    eng.extra_mut().autosave.timer = Some(cookie);
+}
+
+/// Change the time between autosaves.  An `interval` of zero disables autosave.
+pub fn set_interval(mut eng: EngineRef, interval: Time) {
+    eng.extra_mut().autosave.interval = interval;
+    schedule(eng);
+}
+
+/// Start an autosave right away.  Does nothing if an autosave is already running.
+pub fn start(mut eng: EngineRef) {
+    if eng.extra().autosave.running {
+        return;
+    }
+    if let Some(cookie) = eng.extra_mut().autosave.timer.take() {
+        eng.timer_mut().cancel(cookie);
+    }
+
+    // Terrain chunks go first, since saving a chunk records its stable ID in the plane.  The world
+    // file goes last, since it records the next available IDs.
+    let mut items = Vec::new();
+    let dirty = mem::replace(&mut eng.extra_mut().dirty_terrain_chunks, HashSet::new());
+    for (pid, cpos) in dirty.into_iter() {
+        items.push(SaveItem::TerrainChunk(pid, cpos));
+    }
+    for p in eng.world().planes() {
+        items.push(SaveItem::Plane(p.id()));
+    }
+    for c in eng.world().clients() {
+        items.push(SaveItem::Client(c.id()));
+    }
+    items.push(SaveItem::World);
+    items.reverse();
+
+    info!("autosave: writing {} objects", items.len());
+    {
+        let a = &mut eng.extra_mut().autosave;
+        a.queue = items;
+        a.running = true;
+        a.start_time = now();
+    }
+    step(eng);
+}
+
+fn step(mut eng: EngineRef) {
+    let mut viewers = HashSet::new();
+    for _ in 0 .. STEP_SIZE {
+        let item = unwrap_or!(eng.extra_mut().autosave.queue.pop(), break);
+        if let SaveItem::TerrainChunk(pid, cpos) = item {
+            viewers.extend(eng.vision().chunk_viewers(pid, cpos).into_iter());
+        }
+        warn_on_err!(save_item(eng.borrow(), item));
+    }
+
+    // Save the clients that can see this step's chunks before returning to the event loop.
+    for cid in viewers.into_iter() {
+        warn_on_err!(save_item(eng.borrow(), SaveItem::Client(cid)));
+    }
+
+    if eng.extra().autosave.queue.len() > 0 {
+        let when = eng.now() + STEP_DELAY;
+        let cookie = eng.timer_mut().schedule(when, |eng| step(eng));
+        eng.extra_mut().autosave.timer = Some(cookie);
+    } else {
+        let start_time = {
+            let a = &mut eng.extra_mut().autosave;
+            a.timer = None;
+            a.running = false;
+            a.start_time
+        };
+        info!("autosave: finished in {} ms", now() - start_time);
+        schedule(eng);
+    }
+}
+
+fn save_item(mut eng: EngineRef, item: SaveItem) -> save::Result<()> {
+    // Objects may have been unloaded (and saved) since the autosave started.  Those are skipped.
+    match item {
+        SaveItem::TerrainChunk(pid, cpos) => {
+            let pending = {
+                let p = unwrap_or!(eng.world().get_plane(pid), return Ok(()));
+                let tc = unwrap_or!(p.get_terrain_chunk(cpos), return Ok(()));
+                tc.flags().contains(flags::TC_GENERATION_PENDING)
+            };
+            if pending {
+                // Don't save chunks that are not fully generated.  The chunk will be marked dirty
+                // again when generation finishes.
+                return Ok(());
+            }
+
+            let stable_tcid =
+                eng.as_hidden_world_fragment().plane_mut(pid).save_terrain_chunk(cpos);
+            let (h, eng) = eng.borrow().0.split_off();
+            let h = SaveWriteHooks(h);
+            let p = eng.world().plane(pid);
+            let tc = p.terrain_chunk(cpos);
+            let file = eng.storage().create_terrain_chunk_file(stable_tcid);
+            let mut sw = ObjectWriter::new(file, h);
+            try!(sw.save_terrain_chunk(&tc));
+            try!(sw.into_inner().commit());
+        },
+
+        SaveItem::Plane(pid) => {
+            if eng.world().get_plane(pid).is_none() {
+                return Ok(());
+            }
+
+            let stable_pid = eng.as_hidden_world_fragment().plane_mut(pid).stable_id();
+            let (h, eng) = eng.borrow().0.split_off();
+            let h = SaveWriteHooks(h);
+            let p = eng.world().plane(pid);
+            let file = try!(eng.storage().create_plane_file(stable_pid));
+            let mut sw = ObjectWriter::new(file, h);
+            try!(sw.save_plane(&p));
+            try!(sw.into_inner().commit());
+        },
+
+        SaveItem::Client(cid) => {
+            let (h, eng) = eng.borrow().0.split_off();
+            let h = SaveWriteHooks(h);
+            let c = unwrap_or!(eng.world().get_client(cid), return Ok(()));
+            let file = try!(eng.storage().create_client_file(c.name()));
+            let mut sw = ObjectWriter::new(file, h);
+            try!(sw.save_client(&c));
+            try!(sw.into_inner().commit());
+        },
+
+        SaveItem::World => {
+            logic::lifecycle::save_world(eng);
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! Server-driven movement for NPC entities.
+//!
+//! Every entity has a `Behavior`, which is `Idle` unless a script sets something else.  Other
+//! behaviors run in steps from the timer.  Each step picks a new target velocity for the entity,
+//! which goes through the physics engine just like a client's input, and schedules the next step.
+//! Client pawns can't be given a behavior, since their movement comes from client input.
+use rand::{self, Rng};
+
+use types::*;
+use util::StrResult;
+
+use engine::glue::*;
+use engine::split::EngineRef;
+use physics;
+use world::{self, Behavior};
+use world::Fragment;
+use world::object::*;
+
+
+/// Walking speed of NPCs, in pixels per second.  This is the same as a client's walking speed.
+const WALK_SPEED: i32 = 50;
+
+/// `WalkTo` is finished once the entity is within this many pixels of its target on each axis.
+const ARRIVE_DIST: i32 = 2;
+
+/// Longest time between steps of a moving entity, in milliseconds.
+const MAX_STEP_DELAY: Time = 1000;
+
+/// Range of times (in milliseconds) that a wandering entity spends walking in one direction.
+const WANDER_WALK_TIME: (Time, Time) = (1000, 3000);
+/// Range of times (in milliseconds) that a wandering entity spends standing still between walks.
+const WANDER_PAUSE_TIME: (Time, Time) = (1000, 5000);
+
+const DIRS: [(i32, i32); 8] = [
+    ( 1,  0),
+    ( 1,  1),
+    ( 0,  1),
+    (-1,  1),
+    (-1,  0),
+    (-1, -1),
+    ( 0, -1),
+    ( 1, -1),
+];
+
+
+/// Change the behavior of entity `eid`.  The new behavior takes effect immediately.
+pub fn set_behavior(mut eng: EngineRef, eid: EntityId, behavior: Behavior) -> StrResult<()> {
+    {
+        let mut wf = eng.as_world_fragment();
+        let mut e = unwrap!(wf.get_entity_mut(eid));
+        if e.pawn_owner().is_some() {
+            fail!("can't set the behavior of a client's pawn");
+        }
+        e.set_behavior(behavior);
+    }
+    step(eng, eid);
+    Ok(())
+}
+
+/// Run one step of entity `eid`'s behavior, and schedule the next step if needed.
+pub fn step(mut eng: EngineRef, eid: EntityId) {
+    if let Some(cookie) = eng.extra_mut().entity_behavior_timer.remove(&eid) {
+        eng.timer_mut().cancel(cookie);
+    }
+
+    let now = eng.now();
+    let (behavior, pos, velocity, in_limbo) = {
+        let e = unwrap_or!(eng.world().get_entity(eid));
+        (e.behavior(), e.pos(now), e.target_velocity(), e.plane_id() == PLANE_LIMBO)
+    };
+
+    if in_limbo && behavior != Behavior::Idle {
+        // The entity's plane isn't loaded, so there is no terrain to walk on.  Check again later.
+        schedule(eng, eid, now + MAX_STEP_DELAY);
+        return;
+    }
+
+    let (mut new_behavior, new_velocity, mut delay) = match behavior {
+        Behavior::Idle => (Behavior::Idle, scalar(0), None),
+        Behavior::WalkTo(target) => walk_to_step(pos, target),
+        Behavior::Wander { home, radius } => wander_step(behavior, pos, velocity, home, radius),
+    };
+
+    warn_on_err!(physics::Fragment::set_velocity(
+            &mut eng.as_physics_fragment(), now, eid, new_velocity));
+
+    // An entity that can't move at all in the chosen direction is blocked by terrain.  `WalkTo`
+    // gives up instead of pushing against the wall forever, and `Wander` tries another direction
+    // after a short pause.
+    let stuck = {
+        let e = unwrap_or!(eng.world().get_entity(eid));
+        new_velocity != scalar(0) && e.motion().start_pos == e.motion().end_pos
+    };
+    if stuck {
+        warn_on_err!(physics::Fragment::set_velocity(
+                &mut eng.as_physics_fragment(), now, eid, scalar(0)));
+        match new_behavior {
+            Behavior::WalkTo(_) => {
+                new_behavior = Behavior::Idle;
+                delay = None;
+            },
+            _ => {
+                delay = Some(WANDER_PAUSE_TIME.0);
+            },
+        }
+    }
+
+    if new_behavior != behavior {
+        let mut wf = eng.as_world_fragment();
+        let mut e = unwrap_or!(wf.get_entity_mut(eid));
+        e.set_behavior(new_behavior);
+    }
+
+    if let Some(delay) = delay {
+        schedule(eng, eid, now + delay);
+    }
+}
+
+fn schedule(mut eng: EngineRef, eid: EntityId, when: Time) {
+    let mut wf = eng.as_world_fragment();
+    world::Fragment::with_hooks(&mut wf, |h| h.schedule_behavior_update(eid, when));
+}
+
+fn walk_to_step(pos: V3, target: V3) -> (Behavior, V3, Option<Time>) {
+    let delta = target - pos;
+    let dir_x = if delta.x.abs() > ARRIVE_DIST { delta.x.signum() } else { 0 };
+    let dir_y = if delta.y.abs() > ARRIVE_DIST { delta.y.signum() } else { 0 };
+    if dir_x == 0 && dir_y == 0 {
+        return (Behavior::Idle, scalar(0), None);
+    }
+
+    // Run the next step when the entity lines up with the target along one axis, so it can turn
+    // to walk along the other.
+    let dist =
+        if dir_x == 0 {
+            delta.y.abs()
+        } else if dir_y == 0 {
+            delta.x.abs()
+        } else {
+            delta.x.abs().min(delta.y.abs())
+        };
+    let delay = (dist as Time * 1000 / WALK_SPEED as Time).min(MAX_STEP_DELAY);
+
+    let velocity = V3::new(dir_x, dir_y, 0) * scalar(WALK_SPEED);
+    (Behavior::WalkTo(target), velocity, Some(delay))
+}
+
+fn wander_step(behavior: Behavior,
+               pos: V3,
+               velocity: V3,
+               home: V3,
+               radius: i32) -> (Behavior, V3, Option<Time>) {
+    let mut rng = rand::thread_rng();
+
+    if velocity != scalar(0) {
+        // Stand still for a while after each walk.
+        let delay = rng.gen_range(WANDER_PAUSE_TIME.0, WANDER_PAUSE_TIME.1);
+        return (behavior, scalar(0), Some(delay));
+    }
+
+    let offset = pos - home;
+    let dir =
+        if offset.x.abs() > radius || offset.y.abs() > radius {
+            // Too far from home.  Head back toward it.
+            V3::new(-offset.x.signum(), -offset.y.signum(), 0)
+        } else {
+            let (x, y) = DIRS[rng.gen_range(0, DIRS.len())];
+            V3::new(x, y, 0)
+        };
+    let delay = rng.gen_range(WANDER_WALK_TIME.0, WANDER_WALK_TIME.1);
+    (behavior, dir * scalar(WALK_SPEED), Some(delay))
+}
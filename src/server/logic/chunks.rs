@@ -41,9 +41,14 @@ impl<'a, 'd> chunks::Provider for ChunkProvider<'a, 'd> {
 
     fn load_plane(&mut self, stable_pid: Stable<PlaneId>) -> save::Result<()> {
         trace!("load_plane({:?})", stable_pid);
-        let file = unwrap!(self.storage().open_plane_file(stable_pid));
-        let mut sr = ObjectReader::new(file);
-        try!(sr.load_plane(&mut self.as_save_read_fragment()));
+        let storage = self.storage();
+        let file = storage.open_plane_file(stable_pid);
+        let backup = storage.open_backup_file(storage.plane_path(stable_pid));
+        let result = save::load_with_backup(file, backup, |file| {
+            let mut sr = ObjectReader::new(file);
+            sr.load_plane(&mut self.as_save_read_fragment())
+        });
+        try!(unwrap!(result));
         Ok(())
     }
 
@@ -55,9 +60,10 @@ impl<'a, 'd> chunks::Provider for ChunkProvider<'a, 'd> {
             let h = SaveWriteHooks(h);
             let p = eng.world().plane(pid);
 
-            let file = eng.storage().create_plane_file(stable_pid);
+            let file = try!(eng.storage().create_plane_file(stable_pid));
             let mut sw = ObjectWriter::new(file, h);
             try!(sw.save_plane(&p));
+            try!(sw.into_inner().commit());
         }
         try!(world::Fragment::destroy_plane(&mut self.as_hidden_world_fragment(), pid));
         Ok(())
@@ -67,11 +73,18 @@ impl<'a, 'd> chunks::Provider for ChunkProvider<'a, 'd> {
         // TODO(plane): use PlaneId for filename and gen
         trace!("load_terrain_chunk({:?}, {:?})", pid, cpos);
         let opt_tcid = self.world().plane(pid).get_saved_terrain_chunk_id(cpos);
-        let opt_file = opt_tcid.and_then(|tcid| self.storage().open_terrain_chunk_file(tcid));
-        if let Some(file) = opt_file {
+        let storage = self.storage();
+        let opt_file = opt_tcid.and_then(|tcid| storage.open_terrain_chunk_file(tcid));
+        let opt_backup = opt_tcid.and_then(|tcid| storage.open_terrain_chunk_backup(tcid));
+        let opt_result = save::load_with_backup(opt_file, opt_backup, |file| {
             let mut sr = ObjectReader::new(file);
+            sr.load_terrain_chunk(&mut self.as_save_read_fragment(), pid, cpos)
+        });
+        if let Some(result) = opt_result {
             // TODO: do something intelligent if loading fails, so the whole server doesn't crash
-            try!(sr.load_terrain_chunk(&mut self.as_save_read_fragment(), pid, cpos));
+            try!(result);
+            // The chunk matches the copy on disk, so the autosave can skip it.
+            self.extra_mut().dirty_terrain_chunks.remove(&(pid, cpos));
         } else {
             trace!("generating terrain for {:?} {:?}", pid, cpos);
             try!(self.as_terrain_gen_fragment().generate(pid, cpos));
@@ -83,7 +96,7 @@ impl<'a, 'd> chunks::Provider for ChunkProvider<'a, 'd> {
         trace!("unload_terrain_chunk({:?}, {:?})", pid, cpos);
         // TODO(plane): use PlaneId for filename
         let stable_tcid = self.as_hidden_world_fragment().plane_mut(pid).save_terrain_chunk(cpos);
-        let tcid = {
+        let (tcid, pending) = {
             let (h, eng) = self.borrow().0.split_off();
             let h = SaveWriteHooks(h);
             let p = eng.world().plane(pid);
@@ -92,15 +105,21 @@ impl<'a, 'd> chunks::Provider for ChunkProvider<'a, 'd> {
             // Don't save chunks that are not fully generated, since they are filled with "empty'
             // block instead of real data.  Instead, let the generated data be discarded, and let
             // the chunk be regenerated the next time it is needed.
-            if !tc.flags().contains(flags::TC_GENERATION_PENDING) {
+            let pending = tc.flags().contains(flags::TC_GENERATION_PENDING);
+            if !pending {
                 let file = eng.storage().create_terrain_chunk_file(stable_tcid);
                 let mut sw = ObjectWriter::new(file, h);
                 try!(sw.save_terrain_chunk(&tc));
+                try!(sw.into_inner().commit());
             }
 
-            tc.id()
+            (tc.id(), pending)
         };
         trace!("unload_terrain_chunk({:?}, {:?}): tcid = {:?}", pid, cpos, tcid);
+        if pending {
+            // Nobody needs this chunk anymore, so don't waste time generating it.
+            self.as_terrain_gen_fragment().cancel(pid, cpos);
+        }
         try!(world::Fragment::destroy_terrain_chunk(&mut self.as_hidden_world_fragment(), tcid));
         Ok(())
     }
@@ -9,15 +9,13 @@ use engine::split::EngineRef;
 use logic;
 use messages::{ClientResponse, SyncKind};
 use script;
+use terrain_gen;
 use world;
 use world::object::*;
 use world::save::{self, ObjectReader, ObjectWriter};
 use vision::{self, vision_region};
 
 
-const DAY_NIGHT_CYCLE_TICKS: u32 = 24_000;
-const DAY_NIGHT_CYCLE_MS: u32 = 24 * 60 * 1000;
-
 pub fn register(mut eng: EngineRef, name: &str, appearance: u32) -> save::Result<()> {
     let pawn_id;
     let cid;
@@ -40,9 +38,10 @@ pub fn register(mut eng: EngineRef, name: &str, appearance: u32) -> save::Result
         let (h, eng) = eng.borrow().0.split_off();
         let h = SaveWriteHooks(h);
         let c = eng.world().client(cid);
-        let file = eng.storage().create_client_file(c.name());
+        let file = try!(eng.storage().create_client_file(c.name()));
         let mut sw = ObjectWriter::new(file, h);
         try!(sw.save_client(&c));
+        try!(sw.into_inner().commit());
     }
     try!(world::Fragment::destroy_client(&mut eng.as_hidden_world_fragment(), cid));
 
@@ -59,13 +58,16 @@ pub fn login(mut eng: EngineRef, wire_id: WireId, name: &str) -> save::Result<()
     // Load the client into the world.
     // NB: SaveReadFragment uses HiddenWorldFragment, which means it does not send updates to
     // clients.
-    let cid =
-        if let Some(file) = eng.storage().open_client_file(name) {
+    let cid = {
+        let storage = eng.storage();
+        let file = storage.open_client_file(name);
+        let backup = storage.open_backup_file(storage.client_path(name));
+        let result = save::load_with_backup(file, backup, |file| {
             let mut sr = ObjectReader::new(file);
-            try!(sr.load_client(&mut eng.as_save_read_fragment(), name.to_owned()))
-        } else {
-            fail!("client file not found");
-        };
+            sr.load_client(&mut eng.as_save_read_fragment(), name.to_owned())
+        });
+        try!(unwrap!(result, "client file not found"))
+    };
 
     // Tell Vision about the client's entity (or entities).
     {
@@ -104,12 +106,14 @@ pub fn login(mut eng: EngineRef, wire_id: WireId, name: &str) -> save::Result<()
     eng.messages_mut().add_client(cid, wire_id, name);
 
     // Send the client's startup messages.
+    // The day/night settings here are only defaults.  Vision sends the settings for the client's
+    // actual plane.
     let opt_eid = eng.world().client(cid).pawn_id();
-    let cycle_base = (now % DAY_NIGHT_CYCLE_MS as Time) as u32;
+    let cycle_base = (now % world::DAY_NIGHT_CYCLE_MS as Time) as u32;
     eng.messages_mut().send_client(cid, ClientResponse::Init(opt_eid,
                                                              now,
                                                              cycle_base,
-                                                             DAY_NIGHT_CYCLE_MS));
+                                                             world::DAY_NIGHT_CYCLE_MS));
 
     vision::Fragment::add_client(&mut eng.as_vision_fragment(), cid, pawn_pid, region);
     warn_on_err!(script::ScriptEngine::cb_login(eng.borrow().unwrap(), cid));
@@ -134,9 +138,10 @@ pub fn logout(mut eng: EngineRef, cid: ClientId) -> save::Result<()> {
         let (h, eng) = eng.borrow().0.split_off();
         let h = SaveWriteHooks(h);
         let c = eng.world().client(cid);
-        let file = eng.storage().create_client_file(c.name());
+        let file = try!(eng.storage().create_client_file(c.name()));
         let mut sw = ObjectWriter::new(file, h);
         try!(sw.save_client(&c));
+        try!(sw.into_inner().commit());
     }
     try!(world::Fragment::destroy_client(&mut eng.as_world_fragment(), cid));
     Ok(())
@@ -172,6 +177,11 @@ pub fn update_view(mut eng: EngineRef, cid: ClientId) {
 
     vision::Fragment::set_client_view(&mut eng.as_vision_fragment(), cid, new_pid, new_region);
 
+    // Chunks that were already waiting on terrain generation may be closer or farther now.
+    for cpos in new_region.points().filter(|&p| old_region.contains(p) && !plane_change) {
+        terrain_gen::Fragment::reprioritize(&mut eng.as_terrain_gen_fragment(), new_pid, cpos);
+    }
+
     for cpos in old_region.points().filter(|&p| !new_region.contains(p) || plane_change) {
         logic::chunks::unload_chunk(eng.borrow(), old_pid, cpos);
     }
@@ -1,13 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use types::*;
 
+use logic::autosave::Autosave;
 use timer;
 
 
 pub struct Extra {
     pub client_view_update_timer: HashMap<ClientId, timer::Cookie>,
     pub entity_physics_update_timer: HashMap<EntityId, timer::Cookie>,
+    /// Timer for the next step of each entity's `Behavior`.  See `logic::behavior`.
+    pub entity_behavior_timer: HashMap<EntityId, timer::Cookie>,
+    pub structure_crafting_timer: HashMap<StructureId, timer::Cookie>,
+    /// The `Dialog::Crafting` most recently shown to each client.
+    pub client_crafting_dialog: HashMap<ClientId, (TemplateId, StructureId, InventoryId)>,
+    /// Loaded terrain chunks that have changed since they were last written to disk.
+    pub dirty_terrain_chunks: HashSet<(PlaneId, V2)>,
+    pub autosave: Autosave,
 }
 
 impl Extra {
@@ -15,6 +24,11 @@ impl Extra {
         Extra {
             client_view_update_timer: HashMap::new(),
             entity_physics_update_timer: HashMap::new(),
+            entity_behavior_timer: HashMap::new(),
+            structure_crafting_timer: HashMap::new(),
+            client_crafting_dialog: HashMap::new(),
+            dirty_terrain_chunks: HashSet::new(),
+            autosave: Autosave::new(),
         }
     }
 }
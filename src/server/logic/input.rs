@@ -1,8 +1,12 @@
+use libphysics::{CHUNK_BITS, CHUNK_MASK, CHUNK_SIZE, TILE_SIZE};
+
 use types::*;
+use util::StrResult;
+use util::now;
 
 use engine::split::EngineRef;
 use input::{InputBits};
-use messages::ClientResponse;
+use messages::{ClientResponse, RequestClass};
 use msg::ExtraArg;
 use physics;
 use script;
@@ -20,18 +24,143 @@ pub fn input(mut eng: EngineRef, cid: ClientId, input: InputBits) {
     }
 }
 
-pub fn interact(eng: EngineRef, cid: ClientId, args: Option<ExtraArg>) {
+/// Maximum distance (in milliseconds) into the future of the timestamp on an action request
+/// (`Interact`, `UseItem`, or `UseAbility`).
+pub const MAX_ACTION_LEAD: Time = 1000;
+/// Maximum distance (in pixels) between a client's pawn and the target of an action.
+const ACTION_REACH: i32 = TILE_SIZE * 3 / 2;
+
+/// Clamp the timestamp on an action request to the window `now ..= now + MAX_ACTION_LEAD`.
+pub fn clamp_action_time(now: Time, cid: ClientId, time: Time) -> Time {
+    if time < now {
+        warn!("{:?}: clamping action with past timestamp ({} < {})", cid, time, now);
+        now
+    } else if time > now + MAX_ACTION_LEAD {
+        warn!("{:?}: clamping action with future timestamp ({} > {})", cid, time, now);
+        now + MAX_ACTION_LEAD
+    } else {
+        time
+    }
+}
+
+/// Check that the client's pawn could reach the target of an action it requested at `time`.
+///
+/// The target is the one the script callback will act on: the tile in front of the pawn as of
+/// now (the same one `core.util.hit_tile` picks), or the structure occupying that tile.  This
+/// applies to requests with `ExtraArg`s too, since their callbacks find the target the same way.
+/// The pawn's position at `time`, taken from its motion history, must be within `ACTION_REACH` of
+/// the target, and no solid terrain may lie between them.
+fn check_action_target(eng: &EngineRef, cid: ClientId, time: Time) -> StrResult<()> {
+    let now = eng.now();
+    let w = eng.world();
+    let c = unwrap!(w.get_client(cid));
+    let e = unwrap!(c.pawn(), "client has no pawn");
+    let pid = e.plane_id();
+
+    // TODO: hardcoded constants based on entity size and tile size (same as in hit_tile)
+    let center_offset = scalar(TILE_SIZE / 2);
+    let target_px = e.pos(now) + center_offset + e.facing() * scalar(TILE_SIZE);
+    let target_tile = target_px.div_floor(scalar(TILE_SIZE));
+
+    let mut target_bounds = Region::new(target_tile, target_tile + scalar(1));
+    let chunk = target_tile.reduce().div_floor(scalar(CHUNK_SIZE));
+    for s in w.chunk_structures(pid, chunk) {
+        if s.bounds().contains(target_tile) {
+            target_bounds = s.bounds();
+            break;
+        }
+    }
+
+    let then_center = e.pos_at(time) + center_offset;
+    let target_px_bounds = target_bounds * scalar(TILE_SIZE);
+    let nearest_px = target_px_bounds.clamp_point(then_center);
+    let dist = (nearest_px - then_center).abs().max();
+    if dist > ACTION_REACH {
+        fail!("action target is out of reach");
+    }
+
+    // Walk the line from the pawn to the target in quarter-tile steps.  The tiles at either end
+    // are allowed to be solid: the pawn may be standing on a ramp, and the target may be a wall.
+    let start_tile = then_center.div_floor(scalar(TILE_SIZE));
+    let steps = (nearest_px - then_center).abs().max() / (TILE_SIZE / 4) + 1;
+    for i in 1 .. steps {
+        let px = then_center + (nearest_px - then_center) * scalar(i) / scalar(steps);
+        let tile = px.div_floor(scalar(TILE_SIZE));
+        if tile == start_tile || target_bounds.contains(tile) {
+            continue;
+        }
+        if block_shape(eng, pid, tile) == Shape::Solid {
+            fail!("action target is not in line of sight");
+        }
+    }
+
+    Ok(())
+}
+
+fn block_shape(eng: &EngineRef, pid: PlaneId, tile: V3) -> Shape {
+    if tile.z < 0 || tile.z >= CHUNK_SIZE {
+        return Shape::Empty;
+    }
+
+    let offset = tile & scalar(CHUNK_MASK);
+    let cpos = (tile >> CHUNK_BITS).reduce();
+    if let Some(entry) = eng.cache().get(pid, cpos) {
+        let idx = Region::new(scalar(0), scalar(CHUNK_SIZE)).index(offset);
+        entry.shape[idx]
+    } else {
+        Shape::Empty
+    }
+}
+
+pub fn interact(eng: EngineRef, cid: ClientId, time: Time, args: Option<ExtraArg>) {
+    if let Err(e) = check_action_target(&eng, cid, time) {
+        warn!("{:?}: dropping interact: {}", cid, e);
+        return;
+    }
     warn_on_err!(script::ScriptEngine::cb_interact(eng.unwrap(), cid, args));
 }
 
-pub fn use_item(eng: EngineRef, cid: ClientId, item_id: ItemId, args: Option<ExtraArg>) {
+pub fn use_item(eng: EngineRef,
+                cid: ClientId,
+                time: Time,
+                item_id: ItemId,
+                args: Option<ExtraArg>) {
+    if let Err(e) = check_action_target(&eng, cid, time) {
+        warn!("{:?}: dropping use_item: {}", cid, e);
+        return;
+    }
     warn_on_err!(script::ScriptEngine::cb_use_item(eng.unwrap(), cid, item_id, args));
 }
 
-pub fn use_ability(eng: EngineRef, cid: ClientId, item_id: ItemId, args: Option<ExtraArg>) {
+pub fn use_ability(eng: EngineRef,
+                   cid: ClientId,
+                   time: Time,
+                   item_id: ItemId,
+                   args: Option<ExtraArg>) {
+    if let Err(e) = check_action_target(&eng, cid, time) {
+        warn!("{:?}: dropping use_ability: {}", cid, e);
+        return;
+    }
     warn_on_err!(script::ScriptEngine::cb_use_ability(eng.unwrap(), cid, item_id, args));
 }
 
+/// Record that a client exceeded its rate limit, so operators can see who is flooding.  The
+/// caller is responsible for kicking the client.
+pub fn record_rate_limited(eng: EngineRef, cid: ClientId, class: RequestClass) {
+    let name = unwrap_or!(eng.world().get_client(cid)).name().to_owned();
+    write_rate_limit_log(eng, &name, class);
+}
+
+/// Like `record_rate_limited`, but for a connection that hasn't logged in yet.
+pub fn record_wire_rate_limited(eng: EngineRef, wire_id: WireId, class: RequestClass) {
+    write_rate_limit_log(eng, &format!("{:?}", wire_id), class);
+}
+
+fn write_rate_limit_log(eng: EngineRef, who: &str, class: RequestClass) {
+    let line = format!("{}\t{}\t{}\n", now() / 1000, who, class.name());
+    warn_on_err!(eng.storage().write_rate_limit_log(&line));
+}
+
 pub fn open_inventory(eng: EngineRef, cid: ClientId) {
     warn_on_err!(script::ScriptEngine::cb_open_inventory(eng.unwrap(), cid));
 }
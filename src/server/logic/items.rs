@@ -1,5 +1,6 @@
 use std::cmp;
 use std::u8;
+use libphysics::TILE_SIZE;
 
 use types::*;
 use util::StrResult;
@@ -7,6 +8,7 @@ use util::StrResult;
 use engine::split::EngineRef;
 use messages::{ClientResponse, Dialog};
 use world;
+use world::CraftingJob;
 use world::object::*;
 use vision;
 
@@ -52,6 +54,7 @@ pub fn open_crafting(mut eng: EngineRef,
     };
 
     vision::Fragment::subscribe_inventory(&mut eng.as_vision_fragment(), cid, iid);
+    eng.extra_mut().client_crafting_dialog.insert(cid, (template_id, sid, iid));
     let dialog = Dialog::Crafting(template_id, sid, iid);
     eng.messages_mut().send_client(cid, ClientResponse::OpenDialog(dialog));
 
@@ -127,14 +130,56 @@ pub fn move_items2(mut eng: EngineRef,
 }
 
 
+/// Maximum distance (in pixels) between a client's pawn and a crafting station it is using.
+const CRAFTING_REACH: i32 = 2 * TILE_SIZE;
+
 pub fn craft_recipe(mut eng: EngineRef,
+                    cid: ClientId,
                     station_sid: StructureId,
                     iid: InventoryId,
                     recipe_id: RecipeId,
                     count: u16) -> StrResult<()> {
     let recipe = unwrap!(eng.world().data().recipes.get_recipe(recipe_id));
+    let now = eng.now();
+
+    {
+        let s = unwrap!(eng.world().get_structure(station_sid));
+
+        // The client can only use the station (and inventory) from the crafting dialog it was
+        // most recently shown.  This also catches stations that have been replaced by a different
+        // structure since the dialog was opened.
+        match eng.extra().client_crafting_dialog.get(&cid) {
+            Some(&(template_id, sid, dialog_iid))
+                    if template_id == s.template_id() &&
+                       sid == station_sid &&
+                       dialog_iid == iid => {},
+            _ => fail!("no crafting dialog is open for that station"),
+        }
+
+        if let Some(station_template) = recipe.station {
+            if s.template_id() != station_template {
+                fail!("recipe cannot be crafted at that station");
+            }
+        }
+
+        let c = unwrap!(eng.world().get_client(cid));
+        let pawn = unwrap!(c.pawn());
+        if pawn.plane_id() != s.plane_id() {
+            fail!("crafting station is on a different plane");
+        }
+        // Pawn hitboxes are 32x32 px, so measure from the center of the base.
+        let pawn_pos = pawn.pos(now) + V3::new(TILE_SIZE / 2, TILE_SIZE / 2, 0);
+        let station_bounds = s.bounds() * scalar(TILE_SIZE);
+        let dist = (station_bounds.clamp_point(pawn_pos) - pawn_pos).abs().max();
+        if dist > CRAFTING_REACH {
+            fail!("crafting station is out of reach");
+        }
+    }
+
+    if recipe.time > 0 {
+        return start_crafting_job(eng, station_sid, iid, recipe_id, count);
+    }
 
-    let _ = station_sid; // TODO
     let mut wf = eng.as_world_fragment();
     let mut i = unwrap!(world::Fragment::get_inventory_mut(&mut wf, iid));
 
@@ -163,3 +208,125 @@ pub fn craft_recipe(mut eng: EngineRef,
     }
     Ok(())
 }
+
+/// How long to wait before trying again to finish a crafting job whose outputs don't fit in the
+/// station's inventory.
+const CRAFTING_RETRY_DELAY: Time = 5000;
+
+/// Queue a timed crafting job at the station.  The inputs are removed from inventory `iid`
+/// immediately, and the outputs are added to the station's inventory when the job finishes.
+fn start_crafting_job(mut eng: EngineRef,
+                      station_sid: StructureId,
+                      iid: InventoryId,
+                      recipe_id: RecipeId,
+                      count: u16) -> StrResult<()> {
+    let recipe = eng.world().data().recipes.recipe(recipe_id);
+    let now = eng.now();
+
+    {
+        let s = unwrap!(eng.world().get_structure(station_sid));
+        if crafting_output_inventory(&s).is_none() {
+            fail!("crafting station has no inventory to hold outputs");
+        }
+    }
+
+    // The outputs are added all at once when the job finishes, so the total must fit in a u16.
+    for &num_produced in recipe.outputs.values() {
+        if count.checked_mul(num_produced as u16).is_none() {
+            fail!("too many crafts requested");
+        }
+    }
+
+    let mut wf = eng.as_world_fragment();
+
+    let real_count = {
+        let mut i = unwrap!(world::Fragment::get_inventory_mut(&mut wf, iid));
+
+        let mut count = count;
+        for (&item_id, &num_required) in recipe.inputs.iter() {
+            count = cmp::min(count, i.count(item_id) / num_required as u16);
+        }
+
+        for (&item_id, &num_required) in recipe.inputs.iter() {
+            i.bulk_remove(item_id, count * num_required as u16);
+        }
+        count
+    };
+
+    if real_count == 0 {
+        return Ok(());
+    }
+
+    {
+        let mut s = unwrap!(world::Fragment::get_structure_mut(&mut wf, station_sid));
+        // Jobs run one at a time, so the new job starts when the last one in the queue finishes.
+        let start_time = match s.crafting_jobs().back() {
+            Some(job) => cmp::max(now, job.end_time),
+            None => now,
+        };
+        s.crafting_jobs_mut().push_back(CraftingJob {
+            recipe: recipe_id,
+            count: real_count,
+            end_time: start_time + recipe.time as Time * real_count as Time,
+        });
+    }
+
+    world::Fragment::with_hooks(&mut wf, |h| h.crafting_jobs_changed(station_sid));
+    Ok(())
+}
+
+/// Finish the first crafting job at station `sid`, adding its outputs to the station's inventory.
+pub fn finish_crafting_job(mut eng: EngineRef, sid: StructureId) {
+    eng.extra_mut().structure_crafting_timer.remove(&sid);
+    let now = eng.now();
+
+    let (job, opt_iid) = {
+        let s = unwrap_or!(eng.world().get_structure(sid));
+        let job = *unwrap_or!(s.crafting_jobs().front());
+        (job, crafting_output_inventory(&s))
+    };
+    let recipe = eng.world().data().recipes.recipe(job.recipe);
+
+    let fits = match opt_iid {
+        Some(iid) => {
+            let i = eng.world().inventory(iid);
+            recipe.outputs.iter().all(|(&item_id, &num_produced)| {
+                match job.count.checked_mul(num_produced as u16) {
+                    Some(total) => i.count_space(item_id) >= total,
+                    None => false,
+                }
+            })
+        },
+        None => false,
+    };
+
+    let mut wf = eng.as_world_fragment();
+
+    if !fits {
+        // Leave the job at the front of the queue until there is room for its outputs.  Jobs run
+        // one at a time, so the rest of the queue is pushed back by the same amount.
+        let delay = now + CRAFTING_RETRY_DELAY - job.end_time;
+        let mut s = world::Fragment::structure_mut(&mut wf, sid);
+        for queued in s.crafting_jobs_mut().iter_mut() {
+            queued.end_time += delay;
+        }
+    } else {
+        world::Fragment::structure_mut(&mut wf, sid).crafting_jobs_mut().pop_front();
+
+        // `fits` is only true if `opt_iid` is `Some`.
+        let mut i = world::Fragment::inventory_mut(&mut wf, opt_iid.unwrap());
+        for (&item_id, &num_produced) in recipe.outputs.iter() {
+            i.bulk_add(item_id, job.count * num_produced as u16);
+        }
+    }
+
+    world::Fragment::with_hooks(&mut wf, |h| h.crafting_jobs_changed(sid));
+}
+
+/// Get the inventory where a crafting station puts the outputs of its timed jobs.  If the
+/// station has several inventories, the one with the lowest ID is used.
+fn crafting_output_inventory<'d, S: StructureRef<'d>>(s: &S) -> Option<InventoryId> {
+    s.child_inventories()
+     .map(|i| i.id())
+     .min_by(|iid| iid.unwrap())
+}
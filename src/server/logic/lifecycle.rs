@@ -1,5 +1,9 @@
 use std::fs::File;
+use std::io;
+use std::mem;
+use rand;
 
+use libterrain_gen::GenConfig;
 use types::*;
 use libserver_util::bytes::{ReadBytes, WriteBytes};
 use util::now;
@@ -8,15 +12,50 @@ use engine::glue::*;
 use engine::split::EngineRef;
 use logic;
 use messages::{ClientResponse, SyncKind};
+use script::ScriptEngine;
+use storage::Storage;
 use wire::{WireWriter, WireReader};
-use world::Fragment;
+use world::{flags, Fragment, PlaneEnv, StructureFlags};
 use world::object::*;
-use world::save::{ObjectReader, ObjectWriter};
+use world::save::{self, ObjectReader, ObjectWriter};
+use world::save::{AnyId, ReadFragment, ReadHooks, WriteHooks};
+use world::save::reader::ReaderWrapper;
+use world::save::writer::WriterWrapper;
 
 
+/// Seed used for worlds whose misc file predates the world seed.
+const LEGACY_WORLD_SEED: u64 = 0x00012345_e0e0e0e0;
+
+/// Get the terrain generation seed for the current world.  This runs before the `Engine` is
+/// constructed, since the seed is needed to start the terrain generator.
+///
+/// The misc file holds `(world_time, seed)`.  For a brand new world, this picks a random seed and
+/// writes the misc file right away, so chunks generated before the first shutdown stay
+/// consistent with the ones generated afterward.
+pub fn load_world_seed(storage: &Storage) -> u64 {
+    if let Some(mut file) = open_misc_file(storage) {
+        let _world_time: Time = file.read_bytes().unwrap();
+        file.read_bytes().unwrap_or(LEGACY_WORLD_SEED)
+    } else {
+        let seed = rand::random();
+        info!("new world: using terrain seed {:016x}", seed);
+        warn_on_err!(storage.create_misc_file().and_then(|mut file| {
+            try!(file.write_bytes(0 as Time));
+            try!(file.write_bytes(seed));
+            file.commit()
+        }));
+        seed
+    }
+}
+
+/// Open the misc file, or its previous version if the current one is missing.
+fn open_misc_file(storage: &Storage) -> Option<File> {
+    storage.open_misc_file().or_else(|| storage.open_backup_file(storage.misc_path()))
+}
+
 pub fn start_up(mut eng: EngineRef) {
     let world_time =
-        if let Some(mut file) = eng.storage().open_misc_file() {
+        if let Some(mut file) = open_misc_file(eng.storage()) {
             file.read_bytes().unwrap()
         } else {
             0
@@ -27,28 +66,54 @@ pub fn start_up(mut eng: EngineRef) {
     eng.timer_mut().set_world_time(unix_time, world_time);
     eng.borrow().unwrap().now = world_time;
 
-    if let Some(file) = eng.storage().open_world_file() {
-        let mut sr = ObjectReader::new(file);
-        sr.load_world(&mut eng.as_save_read_fragment()).unwrap();
+    {
+        let storage = eng.storage();
+        let file = storage.open_world_file();
+        let backup = storage.open_backup_file(storage.world_path());
+        let result = save::load_with_backup(file, backup, |file| {
+            let mut sr = ObjectReader::new(file);
+            sr.load_world(&mut eng.as_save_read_fragment())
+        });
+        if let Some(Err(e)) = result {
+            panic!("failed to load world file: {}", e);
+        }
     }
 
-    if let Some(file) = eng.storage().open_plane_file(STABLE_PLANE_LIMBO) {
-        let mut sr = ObjectReader::new(file);
-        sr.load_plane(&mut eng.as_save_read_fragment()).unwrap();
-    } else {
+    if !load_start_plane(eng.borrow(), STABLE_PLANE_LIMBO) {
         let name = "Limbo".to_owned();
-        let stable_pid = eng.as_hidden_world_fragment().create_plane(name).unwrap().stable_id();
-        assert!(stable_pid == STABLE_PLANE_LIMBO);
+        let mut wf = eng.as_hidden_world_fragment();
+        let mut p = wf.create_plane(name, GenConfig::new("void")).unwrap();
+        p.set_env(PlaneEnv::dark());
+        assert!(p.stable_id() == STABLE_PLANE_LIMBO);
     }
 
-    if let Some(file) = eng.storage().open_plane_file(STABLE_PLANE_FOREST) {
-        let mut sr = ObjectReader::new(file);
-        sr.load_plane(&mut eng.as_save_read_fragment()).unwrap();
-    } else {
+    if !load_start_plane(eng.borrow(), STABLE_PLANE_FOREST) {
         let name = "Everfree Forest".to_owned();
-        let stable_pid = eng.as_hidden_world_fragment().create_plane(name).unwrap().stable_id();
+        let gen = GenConfig::new("forest");
+        let stable_pid = eng.as_hidden_world_fragment().create_plane(name, gen)
+                            .unwrap().stable_id();
         assert!(stable_pid == STABLE_PLANE_FOREST);
     }
+
+    logic::autosave::schedule(eng);
+}
+
+/// Load one of the planes that always exists.  Returns `false` if the plane has never been saved.
+/// Panics if neither the plane file nor its previous version can be read, rather than replacing
+/// the plane with an empty one.
+fn load_start_plane(mut eng: EngineRef, stable_pid: Stable<PlaneId>) -> bool {
+    let storage = eng.storage();
+    let file = storage.open_plane_file(stable_pid);
+    let backup = storage.open_backup_file(storage.plane_path(stable_pid));
+    let result = save::load_with_backup(file, backup, |file| {
+        let mut sr = ObjectReader::new(file);
+        sr.load_plane(&mut eng.as_save_read_fragment())
+    });
+    match result {
+        Some(Ok(_)) => true,
+        Some(Err(e)) => panic!("failed to load plane {}: {}", stable_pid.unwrap(), e),
+        None => false,
+    }
 }
 
 
@@ -66,18 +131,32 @@ pub fn shut_down(mut eng: EngineRef) {
         logic::chunks::unload_plane(eng.borrow(), pid);
     }
 
-    {
-        let (h, eng) = eng.borrow().0.split_off();
-        let h = SaveWriteHooks(h);
-        let file = eng.storage().create_world_file();
-        let mut sw = ObjectWriter::new(file, h);
-        warn_on_err!(sw.save_world(eng.world()));
-    }
+    save_world(eng.borrow());
+}
 
-    {
-        let mut file = eng.storage().create_misc_file();
-        warn_on_err!(file.write_bytes(eng.now()));
-    }
+/// Write the world and misc files.  Everything else (clients, planes, and terrain chunks) is
+/// saved separately.
+pub fn save_world(mut eng: EngineRef) {
+    warn_on_err!(save_world_file(eng.borrow()));
+    warn_on_err!(save_misc_file(eng.borrow()));
+}
+
+fn save_world_file(mut eng: EngineRef) -> save::Result<()> {
+    let (h, eng) = eng.borrow().0.split_off();
+    let h = SaveWriteHooks(h);
+    let file = try!(eng.storage().create_world_file());
+    let mut sw = ObjectWriter::new(file, h);
+    try!(sw.save_world(eng.world()));
+    try!(sw.into_inner().commit());
+    Ok(())
+}
+
+fn save_misc_file(eng: EngineRef) -> save::Result<()> {
+    let mut file = try!(eng.storage().create_misc_file());
+    try!(file.write_bytes(eng.now()));
+    try!(file.write_bytes(eng.terrain_gen().seed()));
+    try!(file.commit());
+    Ok(())
 }
 
 
@@ -115,3 +194,105 @@ pub fn post_restart(mut eng: EngineRef, file: File) {
     let msg = ClientResponse::ChatUpdate("***\tServer restarted".to_owned());
     eng.messages().broadcast_clients(msg);
 }
+
+
+/// Replace the script engine with a fresh one, re-running the bootstrap script so that changes to
+/// the scripts on disk take effect without a restart.  Script data attached to objects is carried
+/// over by writing it out of the old Lua state and reading it into the new one, using the same
+/// hooks as saving and loading.  Other Lua state is lost, and pending script timers are
+/// cancelled.
+pub fn reload_scripts(mut eng: EngineRef) {
+    info!("reloading scripts...");
+    // Load the new scripts first, so that a broken script leaves the old ones running.
+    let script = match ScriptEngine::load(&eng.storage().script_dir()) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("failed to reload scripts: {}", e);
+            return;
+        },
+    };
+
+    let mut objs = Vec::new();
+    let mut w = WriterWrapper::new(Vec::new());
+    if let Err(e) = write_script_extras(eng.borrow(), &mut objs, &mut w) {
+        warn!("failed to reload scripts: error saving script data: {}", e);
+        // Writing ran the unload hooks, which cancel structure timers.  Run the load hooks to
+        // restart them, since the old scripts stay in charge.
+        for &(aid, sflags) in &objs {
+            if let AnyId::Structure(sid) = aid {
+                if sflags.contains(flags::S_HAS_SAVE_HOOKS) {
+                    warn_on_err!(ScriptEngine::cb_structure_load(eng.borrow().unwrap(), sid));
+                }
+            }
+        }
+        return;
+    }
+
+    // Only now is the new engine certain to be swapped in.  Its Lua state won't have the
+    // callbacks for the old timers, so they must not fire.
+    warn_on_err!(ScriptEngine::cb_cancel_timers(eng.borrow().unwrap()));
+    *eng.script_mut() = script;
+
+    // Every object referenced from the script data was written by `write_script_extras`, so
+    // seeding the reader with the writer's IDs lets it find them all without creating anything.
+    let bytes = mem::replace(w.writer_mut(), Vec::new());
+    let mut r = ReaderWrapper::new(io::Cursor::new(bytes));
+    for (&aid, &save_id) in w.id_map() {
+        r.id_map_mut().insert(save_id, aid);
+    }
+    warn_on_err!(read_script_extras(eng.borrow(), &objs, &mut r));
+    info!("reloaded scripts");
+}
+
+/// Write out the script data for the world and for every object, in order.  Each object (along
+/// with its structure flags, if any) is added to `objs` just before it is written, so on error the
+/// last entry may be only partly written.
+fn write_script_extras(mut eng: EngineRef,
+                       objs: &mut Vec<(AnyId, StructureFlags)>,
+                       w: &mut WriterWrapper<Vec<u8>>) -> save::Result<()> {
+    let (h, eng) = eng.borrow().0.split_off();
+    let mut h = SaveWriteHooks(h);
+    let world = eng.world();
+
+    try!(h.post_write_world(w, world));
+    for c in world.clients() {
+        objs.push((AnyId::Client(c.id()), StructureFlags::empty()));
+        try!(h.post_write_client(w, &c));
+    }
+    for e in world.entities() {
+        objs.push((AnyId::Entity(e.id()), StructureFlags::empty()));
+        try!(h.post_write_entity(w, &e));
+    }
+    for i in world.inventories() {
+        objs.push((AnyId::Inventory(i.id()), StructureFlags::empty()));
+        try!(h.post_write_inventory(w, &i));
+    }
+    for p in world.planes() {
+        objs.push((AnyId::Plane(p.id()), StructureFlags::empty()));
+        try!(h.post_write_plane(w, &p));
+    }
+    for s in world.structures() {
+        objs.push((AnyId::Structure(s.id()), s.flags()));
+        try!(h.post_write_structure(w, &s));
+    }
+
+    Ok(())
+}
+
+fn read_script_extras(mut eng: EngineRef,
+                      objs: &[(AnyId, StructureFlags)],
+                      r: &mut ReaderWrapper<io::Cursor<Vec<u8>>>) -> save::Result<()> {
+    let mut rf = eng.as_save_read_fragment();
+    try!(rf.with_hooks(|h| h.post_read_world(r)));
+    for &(aid, flags) in objs {
+        try!(rf.with_hooks(|h| match aid {
+            AnyId::Client(cid) => h.post_read_client(r, cid),
+            AnyId::Entity(eid) => h.post_read_entity(r, eid),
+            AnyId::Inventory(iid) => h.post_read_inventory(r, iid),
+            AnyId::Plane(pid) => h.post_read_plane(r, pid),
+            AnyId::TerrainChunk(tcid) => h.post_read_terrain_chunk(r, tcid),
+            AnyId::Structure(sid) => h.post_read_structure(r, sid, flags),
+        }));
+    }
+    Ok(())
+}
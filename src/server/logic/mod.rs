@@ -1,3 +1,5 @@
+pub mod autosave;
+pub mod behavior;
 pub mod chunks;
 pub mod client;
 pub mod input;
@@ -81,12 +81,25 @@ impl<'a, 'd> vision::Hooks for VisionHooks<'a, 'd> {
                        cid: ClientId,
                        _: PlaneId,
                        pid: PlaneId) {
-        // TODO: super hack.  add a flags field to the plane or something.
-        let is_dark = match self.world().get_plane(pid) {
-            Some(p) => p.name() != "Everfree Forest",
-            None => true,
+        self.on_plane_update(cid, pid);
+    }
+
+    fn on_plane_update(&mut self, cid: ClientId, pid: PlaneId) {
+        let env = match self.world().get_plane(pid) {
+            Some(p) => p.env(),
+            None => world::PlaneEnv::dark(),
         };
-        self.messages().send_client(cid, ClientResponse::PlaneFlags(is_dark as u32));
+
+        // Flags: 1 = fixed ambient light (no day/night cycle), 2 = fixed time of day
+        let flags = (env.ambient.is_some() as u32) | ((env.fixed_time.is_some() as u32) << 1);
+        let now = self.now();
+        let cycle_base = (now % env.cycle_ms as Time) as u32;
+        self.messages().send_client(cid, ClientResponse::PlaneFlags(flags));
+        self.messages().send_client(cid, ClientResponse::PlaneEnv(now,
+                                                                  cycle_base,
+                                                                  env.cycle_ms,
+                                                                  env.fixed_time.unwrap_or(0),
+                                                                  env.ambient.unwrap_or(0)));
     }
 
 
@@ -11,7 +11,7 @@ use engine::split::{Open, EngineRef};
 use logic;
 use messages::{ClientResponse, SyncKind};
 use physics;
-use world::{self, World, Entity, Structure};
+use world::{self, World, Entity, EntityAttachment, InventoryAttachment, Structure, Behavior};
 use world::object::*;
 use vision::{self, vision_region};
 
@@ -28,6 +28,7 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         self.script_mut().cb_client_destroyed(cid);
         // TODO: should this be here or in logic::clients?
         vision::Fragment::remove_client(&mut self.$as_vision_fragment(), cid);
+        self.extra_mut().client_crafting_dialog.remove(&cid);
     }
 
     fn on_client_change_pawn(&mut self,
@@ -41,6 +42,11 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
     }
 
 
+    fn on_plane_env_change(&mut self, pid: PlaneId) {
+        vision::Fragment::update_plane(&mut self.$as_vision_fragment(), pid);
+    }
+
+
     fn on_terrain_chunk_create(&mut self, tcid: TerrainChunkId) {
         let (pid, cpos) = {
             let tc = self.world().terrain_chunk(tcid);
@@ -48,14 +54,20 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         };
         vision::Fragment::add_terrain_chunk(&mut self.$as_vision_fragment(), tcid, pid, cpos);
 
-        let Open { world, cache, .. } = (**self).open();
-        warn_on_err!(cache.add_chunk(world, pid, cpos));
+        {
+            let Open { world, cache, .. } = (**self).open();
+            warn_on_err!(cache.add_chunk(world, pid, cpos));
+        }
+
+        // Chunks loaded from a save file are marked clean again once loading finishes.
+        self.extra_mut().dirty_terrain_chunks.insert((pid, cpos));
     }
 
     fn on_terrain_chunk_destroy(&mut self, tcid: TerrainChunkId, pid: PlaneId, cpos: V2) {
         vision::Fragment::remove_terrain_chunk(&mut self.$as_vision_fragment(), tcid);
 
         self.cache_mut().remove_chunk(pid, cpos);
+        self.extra_mut().dirty_terrain_chunks.remove(&(pid, cpos));
     }
 
     fn on_terrain_chunk_update(&mut self, tcid: TerrainChunkId) {
@@ -66,17 +78,23 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         };
         vision::Fragment::update_terrain_chunk(&mut self.$as_vision_fragment(), tcid);
 
-        let Open { world, cache, .. } = (**self).open();
-        cache.update_region(world, pid, bounds);
+        {
+            let Open { world, cache, .. } = (**self).open();
+            cache.update_region(world, pid, bounds);
+        }
+
+        let cpos = bounds.min.reduce().div_floor(scalar(CHUNK_SIZE));
+        self.extra_mut().dirty_terrain_chunks.insert((pid, cpos));
     }
 
 
     fn on_entity_create(&mut self, eid: EntityId) {
-        let (plane, area, end_time) = {
+        let (plane, area, end_time, behavior) = {
             let e = self.world().entity(eid);
             (e.plane_id(),
              entity_area(self.world().entity(eid)),
-             e.motion().end_time())
+             e.motion().end_time(),
+             e.behavior())
         };
         trace!("entity {:?} created at {:?}", eid, plane);
         // TODO: use a default plane/area for add_entity, then just call on_motion_change
@@ -84,9 +102,18 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         self.schedule_physics_update(eid, end_time);
         // Might have an owner pre-set, if it's been loaded instead of newly created.
         self.schedule_view_update(eid);
+        // Entities loaded from a save file may already have a behavior.
+        if behavior != Behavior::Idle {
+            let now = self.now();
+            self.schedule_behavior_update(eid, now);
+        }
+        self.mark_entity_dirty(eid);
     }
 
     fn on_entity_destroy(&mut self, eid: EntityId) {
+        if let Some(cookie) = self.extra_mut().entity_behavior_timer.remove(&eid) {
+            self.timer_mut().cancel(cookie);
+        }
         self.script_mut().cb_entity_destroyed(eid);
         vision::Fragment::remove_entity(&mut self.$as_vision_fragment(), eid);
     }
@@ -102,12 +129,17 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         vision::Fragment::set_entity_area(&mut self.$as_vision_fragment(), eid, plane, area);
         self.schedule_physics_update(eid, end_time);
         self.schedule_view_update(eid);
+        self.mark_entity_dirty(eid);
     }
 
     fn on_entity_appearance_change(&mut self, eid: EntityId) {
         vision::Fragment::update_entity_appearance(&mut self.$as_vision_fragment(), eid);
     }
 
+    fn on_entity_behavior_change(&mut self, eid: EntityId) {
+        self.mark_entity_dirty(eid);
+    }
+
     fn on_entity_plane_change(&mut self, eid: EntityId) {
         trace!("entity {:?} plane changed", eid);
         self.on_entity_motion_change(eid);
@@ -121,9 +153,15 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         };
         vision::Fragment::add_structure(&mut self.$as_vision_fragment(), sid, pid, area);
 
-        let Open { world, cache, .. } = (**self).open();
-        let s = world.structure(sid);
-        cache.update_region(world, pid, s.bounds());
+        {
+            let Open { world, cache, .. } = (**self).open();
+            let s = world.structure(sid);
+            cache.update_region(world, pid, s.bounds());
+        }
+
+        // Structures loaded from a save file may already have crafting jobs in progress.
+        self.schedule_crafting_update(sid);
+        self.mark_structure_dirty(sid);
     }
 
     fn on_structure_destroy(&mut self,
@@ -131,12 +169,18 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
                             old_pid: PlaneId,
                             old_bounds: Region) {
         vision::Fragment::remove_structure(&mut self.$as_vision_fragment(), sid);
+        if let Some(cookie) = self.extra_mut().structure_crafting_timer.remove(&sid) {
+            self.timer_mut().cancel(cookie);
+        }
 
         {
             let Open { world, cache, .. } = (**self).open();
             cache.update_region(world, old_pid, old_bounds);
         }
 
+        let cpos = old_bounds.min.reduce().div_floor(scalar(CHUNK_SIZE));
+        self.extra_mut().dirty_terrain_chunks.insert((old_pid, cpos));
+
         self.script_mut().cb_structure_destroyed(sid);
     }
 
@@ -156,9 +200,13 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
         vision::Fragment::set_structure_area(&mut self.$as_vision_fragment(), sid, pid, area);
         vision::Fragment::change_structure_template(&mut self.$as_vision_fragment(), sid);
 
-        let Open { world, cache, .. } = (**self).open();
-        let s = world.structure(sid);
-        cache.update_region(world, pid, old_bounds.join(s.bounds()));
+        {
+            let Open { world, cache, .. } = (**self).open();
+            let s = world.structure(sid);
+            cache.update_region(world, pid, old_bounds.join(s.bounds()));
+        }
+
+        self.mark_structure_dirty(sid);
     }
 
     fn check_structure_placement(&self,
@@ -204,6 +252,7 @@ impl<'a, 'd> world::Hooks for $WorldHooks<'a, 'd> {
                            iid: InventoryId,
                            slot_idx: u8) {
         vision::Fragment::update_inventory(&mut self.$as_vision_fragment(), iid, slot_idx);
+        self.mark_inventory_dirty(iid);
     }
 }
 
@@ -216,6 +265,79 @@ impl<'a, 'd> $WorldHooks<'a, 'd> {
         self.extra_mut().entity_physics_update_timer.insert(eid, cookie);
     }
 
+    /// Schedule the next step of entity `eid`'s behavior for time `when`, replacing any existing
+    /// timer.
+    pub fn schedule_behavior_update(&mut self, eid: EntityId, when: Time) {
+        if let Some(cookie) = self.extra_mut().entity_behavior_timer.remove(&eid) {
+            self.timer_mut().cancel(cookie);
+        }
+        let cookie = self.timer_mut().schedule(when, move |eng| {
+            logic::behavior::step(eng, eid);
+        });
+        self.extra_mut().entity_behavior_timer.insert(eid, cookie);
+    }
+
+    /// Schedule a timer to finish the first crafting job of structure `sid`, replacing any
+    /// existing timer.
+    pub fn schedule_crafting_update(&mut self, sid: StructureId) {
+        if let Some(cookie) = self.extra_mut().structure_crafting_timer.remove(&sid) {
+            self.timer_mut().cancel(cookie);
+        }
+
+        let when = {
+            let s = unwrap_or!(self.world().get_structure(sid));
+            let job = unwrap_or!(s.crafting_jobs().front());
+            job.end_time
+        };
+        let cookie = self.timer_mut().schedule(when, move |eng| {
+            logic::items::finish_crafting_job(eng, sid);
+        });
+        self.extra_mut().structure_crafting_timer.insert(sid, cookie);
+    }
+
+    /// Update after structure `sid`'s crafting queue changes: reschedule the crafting timer and
+    /// mark the owning chunk as needing to be autosaved.
+    pub fn crafting_jobs_changed(&mut self, sid: StructureId) {
+        self.schedule_crafting_update(sid);
+        self.mark_structure_dirty(sid);
+    }
+
+    /// Mark the terrain chunk that owns structure `sid` as needing to be autosaved.
+    pub fn mark_structure_dirty(&mut self, sid: StructureId) {
+        let (pid, cpos) = {
+            let s = unwrap_or!(self.world().get_structure(sid));
+            (s.plane_id(), s.pos().reduce().div_floor(scalar(CHUNK_SIZE)))
+        };
+        self.extra_mut().dirty_terrain_chunks.insert((pid, cpos));
+    }
+
+    /// Mark the terrain chunks that own entity `eid` as needing to be autosaved.  Only entities
+    /// attached to chunks are saved with them.
+    pub fn mark_entity_dirty(&mut self, eid: EntityId) {
+        let (pid, area) = {
+            let e = unwrap_or!(self.world().get_entity(eid));
+            if e.attachment() != EntityAttachment::Chunk {
+                return;
+            }
+            (e.plane_id(), entity_area(e))
+        };
+        for &cpos in area.iter() {
+            self.extra_mut().dirty_terrain_chunks.insert((pid, cpos));
+        }
+    }
+
+    /// Mark the terrain chunk that owns inventory `iid` (through its structure or entity) as
+    /// needing to be autosaved.
+    pub fn mark_inventory_dirty(&mut self, iid: InventoryId) {
+        let attachment = unwrap_or!(self.world().get_inventory(iid)).attachment();
+        match attachment {
+            InventoryAttachment::Structure(sid) => self.mark_structure_dirty(sid),
+            InventoryAttachment::Entity(eid) => self.mark_entity_dirty(eid),
+            // Inventories attached to clients and the world are written by every autosave.
+            _ => {},
+        }
+    }
+
     pub fn schedule_view_update(&mut self, eid: EntityId) {
         let now = self.now();
         let cid;
@@ -313,6 +435,10 @@ pub fn structure_area(s: ObjectRef<Structure>) -> SmallSet<V2> {
 // structures (floor + empty space above), or they can instead be placed over a Layer 1 structure
 // with no shape restrictions.  In the case of placement over an existing Layer 1 structure, the
 // script doing the placement is responsible for enforcing any additional invariants.
+//
+// Terrain ramps (facing any direction) count as neither floor nor empty space, so structures of
+// Layers 0 and 1 can never overlap them.  Templates may still contain ramp shapes of their own;
+// those only need to meet the usual requirements of the template's layer.
 
 const PLACEMENT_MASK: [u8; 3] = [
     0x1,    // Layer 0 can be placed under existing structures.
@@ -9,6 +9,7 @@ use std::path::Path;
 use std::ptr;
 use std::slice;
 use std::str;
+use std::usize;
 
 use libc;
 use libc::{c_void, c_int, c_char, size_t};
@@ -22,6 +23,8 @@ mod ffi {
 
     pub type lua_Alloc = extern "C" fn(*mut c_void, *mut c_void, size_t, size_t) -> *mut c_void;
     pub type lua_CFunction = extern "C" fn(*mut lua_State) -> c_int;
+    pub type lua_Debug = c_void;
+    pub type lua_Hook = extern "C" fn(*mut lua_State, *mut lua_Debug);
     pub type lua_Integer = ptrdiff_t;
     pub type lua_Number = c_double;
     pub type lua_Reader = extern "C" fn(*mut lua_State, data: *mut c_void, size: *mut size_t) -> *const c_char;
@@ -31,6 +34,10 @@ mod ffi {
         pub fn lua_newstate(f: lua_Alloc, ud: *mut c_void) -> *mut lua_State;
         pub fn luaL_newstate() -> *mut lua_State;
         pub fn lua_close(L: *mut lua_State);
+        pub fn lua_getallocf(L: *mut lua_State, ud: *mut *mut c_void) -> lua_Alloc;
+
+        pub fn lua_sethook(L: *mut lua_State, f: lua_Hook, mask: c_int, count: c_int) -> c_int;
+        pub fn lua_gethookcount(L: *mut lua_State) -> c_int;
 
         pub fn lua_load(L: *mut lua_State, reader: lua_Reader, data: *mut c_void, chunkname: *const c_char);
         pub fn lua_pcall(L: *mut lua_State, nargs: c_int, nresults: c_int, errfunc: c_int) -> c_int;
@@ -84,20 +91,56 @@ mod ffi {
 }
 
 
+const LUA_MASKCOUNT: c_int = 1 << 3;
+
+/// Number of instructions between checks of the instruction limit.
+const HOOK_INTERVAL: c_int = 1000;
+
+/// Resource limits for a Lua state.  The limits apply to each outermost call into Lua (see
+/// `LuaState::pcall`), including any nested calls it makes back into Lua through Rust.
+struct Limits {
+    /// Total size of all memory currently allocated by the Lua state.
+    mem_used: usize,
+    /// Allocations that would raise `mem_used` above this amount fail.
+    mem_limit: usize,
+    /// Memory each call may allocate beyond what was in use when the call started.  Zero means
+    /// no limit.
+    mem_per_call: usize,
+    /// Number of instructions the current call may still execute.
+    instrs_left: isize,
+    /// Instructions each call may execute.  Zero means no limit.
+    instrs_per_call: isize,
+    /// Number of `pcall`s currently running.
+    depth: u32,
+}
+
 #[unsafe_no_drop_flag]
 pub struct OwnedLuaState {
     L: *mut lua_State,
+    /// Owned pointer to the `Limits`, which is also the userdata for `lua_alloc`.
+    limits: *mut Limits,
 }
 
 impl OwnedLuaState {
     pub fn new() -> LuaResult<'static, OwnedLuaState> {
-        let L = unsafe { ffi::luaL_newstate() };
+        let limits = Box::new(Limits {
+            mem_used: 0,
+            mem_limit: usize::MAX,
+            mem_per_call: 0,
+            instrs_left: 0,
+            instrs_per_call: 0,
+            depth: 0,
+        });
+        let limits: *mut Limits = unsafe { mem::transmute(limits) };
+        let L = unsafe { ffi::lua_newstate(lua_alloc, limits as *mut c_void) };
 
         if L.is_null() {
+            let _: Box<Limits> = unsafe { mem::transmute(limits) };
             Err((ErrorType::ErrMem, "failed to allocate memory"))
         } else {
             Ok(OwnedLuaState {
                 L: L,
+                limits: limits,
             })
         }
     }
@@ -105,6 +148,18 @@ impl OwnedLuaState {
     pub fn get<'a>(&'a mut self) -> LuaState<'a> {
         unsafe { LuaState::new(self.L) }
     }
+
+    /// Limit the number of instructions executed and the amount of memory allocated by each call
+    /// into Lua.  A call that exceeds either limit fails with an error.  Passing zero disables the
+    /// corresponding limit.
+    pub fn set_limits(&mut self, instructions: usize, memory: usize) {
+        let limits = unsafe { &mut *self.limits };
+        limits.instrs_per_call = instructions as isize;
+        limits.mem_per_call = memory;
+        if instructions > 0 {
+            unsafe { ffi::lua_sethook(self.L, limit_hook, LUA_MASKCOUNT, HOOK_INTERVAL) };
+        }
+    }
 }
 
 impl Drop for OwnedLuaState {
@@ -115,20 +170,67 @@ impl Drop for OwnedLuaState {
 
         unsafe { ffi::lua_close(self.L) };
         self.L = ptr::null_mut();
+        // The allocator uses `limits`, so it can only be freed after the state is closed.
+        let _: Box<Limits> = unsafe { mem::transmute(self.limits) };
+        self.limits = ptr::null_mut();
     }
 }
 
-fn lua_alloc(_userdata: *mut c_void,
-             ptr: *mut c_void,
-             _old_size: size_t,
-             new_size: size_t) -> *mut c_void {
+extern "C" fn lua_alloc(userdata: *mut c_void,
+                        ptr: *mut c_void,
+                        old_size: size_t,
+                        new_size: size_t) -> *mut c_void {
+    let limits = unsafe { &mut *(userdata as *mut Limits) };
     if new_size == 0 {
         unsafe { libc::free(ptr) };
+        limits.mem_used -= old_size as usize;
         ptr::null_mut()
     } else {
+        // Only growth can fail.  Lua assumes that shrinking a block always succeeds.
+        if new_size > old_size &&
+           limits.mem_used + (new_size - old_size) as usize > limits.mem_limit {
+            return ptr::null_mut();
+        }
         // NB: ptr is guaranteed to be null when requesting a new allocation (i.e., when old_size
         // is 0).
-        unsafe { libc::realloc(ptr, new_size) }
+        let new_ptr = unsafe { libc::realloc(ptr, new_size) };
+        if !new_ptr.is_null() {
+            limits.mem_used = limits.mem_used + new_size as usize - old_size as usize;
+        }
+        new_ptr
+    }
+}
+
+unsafe fn get_limits<'a>(L: *mut lua_State) -> &'a mut Limits {
+    let mut userdata = ptr::null_mut();
+    ffi::lua_getallocf(L, &mut userdata);
+    &mut *(userdata as *mut Limits)
+}
+
+extern "C" fn limit_hook(L: *mut lua_State, _ar: *mut ffi::lua_Debug) {
+    unsafe {
+        let limits = get_limits(L);
+        if limits.depth == 0 || limits.instrs_per_call == 0 {
+            return;
+        }
+
+        let count = ffi::lua_gethookcount(L);
+        limits.instrs_left -= count as isize;
+        if limits.instrs_left >= 0 {
+            if count != HOOK_INTERVAL {
+                ffi::lua_sethook(L, limit_hook, LUA_MASKCOUNT, HOOK_INTERVAL);
+            }
+            return;
+        }
+
+        // Run the hook on every instruction from now on, so the error is raised again as soon
+        // as the script leaves any `pcall` that caught it.
+        if count != 1 {
+            ffi::lua_sethook(L, limit_hook, LUA_MASKCOUNT, 1);
+        }
+        let msg = "instruction limit exceeded";
+        ffi::lua_pushlstring(L, msg.as_ptr() as *const c_char, msg.len() as size_t);
+        ffi::lua_error(L);
     }
 }
 
@@ -462,7 +564,22 @@ impl<'a> LuaState<'a> {
     // Calling functions
 
     pub fn pcall(&mut self, num_args: c_int, num_results: c_int, err_func: c_int) -> LuaResult<()> {
+        let limits = unsafe { get_limits(self.L) };
+        if limits.depth == 0 {
+            // Starting a new call from outside Lua.  Reset the limits.
+            limits.instrs_left = limits.instrs_per_call;
+            if limits.mem_per_call > 0 {
+                limits.mem_limit = limits.mem_used.saturating_add(limits.mem_per_call);
+            }
+        }
+
+        limits.depth += 1;
         let code = unsafe { ffi::lua_pcall(self.L, num_args, num_results, err_func) };
+        limits.depth -= 1;
+
+        if limits.depth == 0 {
+            limits.mem_limit = usize::MAX;
+        }
         make_result(self, code)
     }
 
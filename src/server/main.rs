@@ -43,7 +43,7 @@ extern crate server_types as libserver_types;
 #[macro_use] extern crate server_util as libserver_util;
 
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use rustc_serialize::json;
 
 
@@ -86,6 +86,7 @@ fn read_json(mut file: File) -> json::Json {
 
 fn main() {
     use std::env;
+    use std::process;
     use std::sync::mpsc::channel;
     use std::thread;
 
@@ -93,6 +94,14 @@ fn main() {
 
     let args = env::args().collect::<Vec<_>>();
     let storage = storage::Storage::new(&args[1]);
+    if storage.has_legacy_files().unwrap() {
+        let _ = writeln!(io::stderr(),
+                         "error: terrain chunks in {} are saved in the old one-file-per-chunk \
+                          layout; convert them with `save_tool convert-regions {}` before \
+                          starting the server",
+                         args[1], args[1]);
+        process::exit(1);
+    }
 
     let block_json = read_json(storage.open_block_data());
     let item_json = read_json(storage.open_item_data());
@@ -100,12 +109,17 @@ fn main() {
     let template_json = read_json(storage.open_template_data());
     let animation_json = read_json(storage.open_animation_data());
     let loot_table_json = read_json(storage.open_loot_table_data());
+    let vault_json = read_json(storage.open_vault_data());
+    let migration_json = storage.open_migration_data()
+                                .map_or(json::Json::Null, read_json);
     let data = data::Data::from_json(block_json,
                                      item_json,
                                      recipe_json,
                                      template_json,
                                      animation_json,
-                                     loot_table_json).unwrap();
+                                     loot_table_json,
+                                     vault_json,
+                                     migration_json).unwrap();
 
     let (req_send, req_recv) = channel();
     let (resp_send, resp_recv) = channel();
@@ -8,6 +8,8 @@ use types::*;
 use msg;
 use world;
 
+use super::rate_limit::Buckets;
+
 
 pub struct Clients {
     clients: HashMap<ClientId, ClientInfo>,
@@ -20,6 +22,7 @@ pub struct ClientInfo {
     name: String,
     chunk_offset: (u8, u8),
     last_check: Time,
+    buckets: Buckets,
 }
 
 impl Clients {
@@ -85,6 +88,7 @@ impl ClientInfo {
             name: String::from(name),
             chunk_offset: (offset_x, offset_y),
             last_check: TIME_MIN,
+            buckets: Buckets::new(),
         }
     }
 
@@ -92,6 +96,10 @@ impl ClientInfo {
         self.wire_id
     }
 
+    pub fn buckets_mut(&mut self) -> &mut Buckets {
+        &mut self.buckets
+    }
+
     pub fn local_chunk_index(&self, cpos: V2) -> u16 {
         let cx = (cpos.x + self.chunk_offset.0 as i32) & LOCAL_MASK;
         let cy = (cpos.y + self.chunk_offset.1 as i32) & LOCAL_MASK;
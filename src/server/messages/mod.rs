@@ -1,4 +1,5 @@
 use std::cmp;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::mem;
 use std::sync::mpsc::{Sender, Receiver};
@@ -14,9 +15,12 @@ use msg::{Request, Response, InitData, ExtraArg};
 use world::{self, Motion};
 
 use self::clients::Clients;
+use self::rate_limit::Buckets;
+pub use self::rate_limit::{RequestClass, RateLimit, RateLimits};
 
 
 mod clients;
+mod rate_limit;
 
 
 pub struct Messages {
@@ -24,6 +28,11 @@ pub struct Messages {
     recv: Receiver<(WireId, Request)>,
     clients: Clients,
     time_base: Time,
+    rate_limits: RateLimits,
+    /// Server-wide bucket for `Login` requests from wires that haven't logged in.
+    login_buckets: Buckets,
+    /// Control events queued by the server itself.  See `defer_control`.
+    deferred: VecDeque<ControlEvent>,
 }
 
 pub enum Event {
@@ -39,11 +48,21 @@ pub enum ControlEvent {
     ReplCommand(u16, String),
     Shutdown,
     Restart(bool, bool),
+    Save,
+    ReloadScripts,
+
+    // Server-internal events, queued with `defer_control`.
+    /// Kick the client, if it's still connected.
+    KickClient(ClientId, String),
+    /// Delete the client's save file.  Queued after `KickClient` when deleting an account, so
+    /// that the save written on logout is deleted too.
+    RemoveClientSave(String),
 }
 
 pub enum WireEvent {
     Login(String, Secret),
     Register(String, Secret, u32),
+    RateLimited(RequestClass),
     BadRequest,
 }
 
@@ -58,6 +77,8 @@ pub enum ClientEvent {
     UseItem(Time, ItemId, Option<ExtraArg>),
     UseAbility(Time, ItemId, Option<ExtraArg>),
 
+    /// The client exceeded its rate limit for this class of requests.
+    RateLimited(RequestClass),
     BadRequest,
 }
 
@@ -102,6 +123,10 @@ pub enum ClientResponse {
     InventoryGone(InventoryId),
 
     PlaneFlags(u32),
+    /// Day/night settings for the client's current plane: current time, cycle base, cycle length,
+    /// fixed time of day, and ambient color.  `PlaneFlags` indicates which of the last two are in
+    /// use.
+    PlaneEnv(Time, u32, u32, u32, u32),
     SyncStatus(SyncKind),
 
     GetInteractArgs(u32, ExtraArg),
@@ -139,6 +164,9 @@ impl Messages {
             recv: recv,
             clients: Clients::new(),
             time_base: 0,
+            rate_limits: RateLimits::new(),
+            login_buckets: Buckets::new(),
+            deferred: VecDeque::new(),
         }
     }
 
@@ -153,7 +181,7 @@ impl Messages {
         unix_time - self.time_base
     }
 
-    fn world_now(&self) -> Time {
+    pub fn world_now(&self) -> Time {
         self.world_time(now())
     }
 
@@ -196,8 +224,29 @@ impl Messages {
     }
 
 
+    // Rate limiting
+
+    pub fn set_rate_limit(&mut self, class: RequestClass, limit: RateLimit) {
+        self.rate_limits.set(class, limit);
+    }
+
+    pub fn set_rate_limits(&mut self, limits: RateLimits) {
+        self.rate_limits = limits;
+    }
+
+
     // Event processing
 
+    /// Queue a control event to be handled once the current event handler returns.  This is for
+    /// operations like kicking a client, which are unsafe to do from inside a script callback.
+    pub fn defer_control(&mut self, evt: ControlEvent) {
+        self.deferred.push_back(evt);
+    }
+
+    pub fn take_deferred(&mut self) -> Option<ControlEvent> {
+        self.deferred.pop_front()
+    }
+
     pub fn receiver(&self) -> &Receiver<MessageEvent> {
         cast_receiver(&self.recv)
     }
@@ -237,6 +286,10 @@ impl Messages {
                 Some(Event::Control(ControlEvent::Shutdown)),
             Request::Restart(server, client) =>
                 Some(Event::Control(ControlEvent::Restart(server, client))),
+            Request::Save =>
+                Some(Event::Control(ControlEvent::Save)),
+            Request::ReloadScripts =>
+                Some(Event::Control(ControlEvent::ReloadScripts)),
 
             _ => {
                 warn!("bad control request: {:?}", req);
@@ -246,6 +299,13 @@ impl Messages {
     }
 
     fn handle_pre_login_req(&mut self, now: Time, wire_id: WireId, req: Request) -> Option<Event> {
+        let class = RequestClass::of(&req);
+        if class == RequestClass::Login &&
+           !self.login_buckets.take(&self.rate_limits, class, now) {
+            warn!("{:?}: server-wide rate limit for {} requests exceeded", wire_id, class.name());
+            return Some(Event::Wire(wire_id, WireEvent::RateLimited(class)));
+        }
+
         match req {
             Request::Ping(cookie) => {
                 self.send_raw(wire_id, Response::Pong(cookie, now.to_local()));
@@ -267,6 +327,14 @@ impl Messages {
                          wire_id: WireId,
                          cid: ClientId,
                          req: Request) -> Option<Event> {
+        let class = RequestClass::of(&req);
+        if let Some(info) = self.clients.get_mut(cid) {
+            if !info.buckets_mut().take(&self.rate_limits, class, now) {
+                warn!("{:?} exceeded rate limit for {} requests", cid, class.name());
+                return Some(Event::Client(cid, ClientEvent::RateLimited(class)));
+            }
+        }
+
         match self.try_handle_client_req(now, wire_id, req) {
             Ok(evt) => evt.map(|e| Event::Client(cid, e)),
             Err(e) => {
@@ -305,6 +373,7 @@ impl Messages {
                 Ok(Some(ClientEvent::Chat(msg))),
 
 
+            // Actions can't happen in the past.  `logic::input` bounds how far in the future.
             Request::Interact(time) => {
                 let time = cmp::max(time.to_global(now), now);
                 Ok(Some(ClientEvent::Interact(time, None)))
@@ -444,6 +513,12 @@ impl Messages {
             ClientResponse::PlaneFlags(flags) =>
                 self.send_raw(wire_id, Response::PlaneFlags(flags)),
 
+            ClientResponse::PlaneEnv(now, cycle_base, cycle_ms, fixed_time, ambient) => {
+                let resp = Response::PlaneEnv(now.to_local(), cycle_base, cycle_ms,
+                                              fixed_time, ambient);
+                self.send_raw(wire_id, resp);
+            },
+
             ClientResponse::SyncStatus(kind) => {
                 let arg = match kind {
                     SyncKind::Loading => 0,
@@ -0,0 +1,195 @@
+//! Per-client rate limiting of requests.  Each client has a token bucket for each class of
+//! request.  Every request takes one token from its bucket, and the buckets refill at a steady
+//! rate up to a fixed capacity.
+//!
+//! `Login` requests (which include registrations) come from connections that have no client yet,
+//! and are expensive to handle because of secret hashing.  They share a single server-wide bucket.
+use std::u32;
+use rustc_serialize::json::Json;
+
+use types::*;
+use util::StrResult;
+
+use msg::Request;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RequestClass {
+    Input,
+    Chat,
+    Inventory,
+    Crafting,
+    Action,
+    Login,
+    Other,
+}
+
+pub const NUM_CLASSES: usize = 7;
+
+impl RequestClass {
+    pub fn of(req: &Request) -> RequestClass {
+        use msg::Request::*;
+        match *req {
+            Input(..) => RequestClass::Input,
+            Chat(..) => RequestClass::Chat,
+            UnsubscribeInventory(..) |
+            MoveItem(..) => RequestClass::Inventory,
+            CraftRecipe(..) => RequestClass::Crafting,
+            Interact(..) |
+            UseItem(..) |
+            UseAbility(..) |
+            InteractWithArgs(..) |
+            UseItemWithArgs(..) |
+            UseAbilityWithArgs(..) => RequestClass::Action,
+            Login(..) |
+            Register(..) => RequestClass::Login,
+            _ => RequestClass::Other,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RequestClass> {
+        match name {
+            "input" => Some(RequestClass::Input),
+            "chat" => Some(RequestClass::Chat),
+            "inventory" => Some(RequestClass::Inventory),
+            "crafting" => Some(RequestClass::Crafting),
+            "action" => Some(RequestClass::Action),
+            "login" => Some(RequestClass::Login),
+            "other" => Some(RequestClass::Other),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RequestClass::Input => "input",
+            RequestClass::Chat => "chat",
+            RequestClass::Inventory => "inventory",
+            RequestClass::Crafting => "crafting",
+            RequestClass::Action => "action",
+            RequestClass::Login => "login",
+            RequestClass::Other => "other",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// Number of requests per second allowed over the long term.
+    pub rate: u32,
+    /// Number of requests that can be sent in a burst after a period of inactivity.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(rate: u32, burst: u32) -> RateLimit {
+        RateLimit {
+            rate: rate,
+            burst: burst,
+        }
+    }
+}
+
+pub struct RateLimits {
+    limits: [RateLimit; NUM_CLASSES],
+}
+
+impl RateLimits {
+    pub fn new() -> RateLimits {
+        let mut limits = [RateLimit::new(10, 20); NUM_CLASSES];
+        // The client sends an `Input` on every key press and release.
+        limits[RequestClass::Input.index()] = RateLimit::new(30, 60);
+        limits[RequestClass::Chat.index()] = RateLimit::new(2, 10);
+        // Shift-clicking moves a stack at a time, but dragging items around can be quick.
+        limits[RequestClass::Inventory.index()] = RateLimit::new(20, 40);
+        // Server-wide, and each one costs a secret hash on the auth thread.
+        limits[RequestClass::Login.index()] = RateLimit::new(5, 20);
+        RateLimits {
+            limits: limits,
+        }
+    }
+
+    /// Read limits from a JSON object mapping class names to `{"rate": r, "burst": b}`.  Classes
+    /// that aren't mentioned keep their defaults.
+    pub fn from_json(json: &Json) -> StrResult<RateLimits> {
+        let mut limits = RateLimits::new();
+        let obj = unwrap!(json.as_object(), "rate limits must be an object");
+        for (name, limit) in obj.iter() {
+            let class = unwrap!(RequestClass::from_name(name), "unknown request class");
+            let rate = unwrap!(limit.find("rate").and_then(|j| j.as_u64()),
+                               "rate limit is missing \"rate\"");
+            let burst = unwrap!(limit.find("burst").and_then(|j| j.as_u64()),
+                                "rate limit is missing \"burst\"");
+            if rate > u32::MAX as u64 || burst > u32::MAX as u64 {
+                fail!("rate limit is out of range");
+            }
+            limits.set(class, RateLimit::new(rate as u32, burst as u32));
+        }
+        Ok(limits)
+    }
+
+    pub fn get(&self, class: RequestClass) -> RateLimit {
+        self.limits[class.index()]
+    }
+
+    pub fn set(&mut self, class: RequestClass, limit: RateLimit) {
+        self.limits[class.index()] = limit;
+    }
+}
+
+
+/// Tokens are tracked in thousandths, so that buckets can be refilled with millisecond precision.
+const TOKEN_SCALE: u64 = 1000;
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    tokens: u64,
+    last_refill: Time,
+}
+
+pub struct Buckets {
+    buckets: [Bucket; NUM_CLASSES],
+}
+
+impl Buckets {
+    pub fn new() -> Buckets {
+        Buckets {
+            // A `last_refill` of `TIME_MIN` means the bucket gets filled on first use.
+            buckets: [Bucket { tokens: 0, last_refill: TIME_MIN }; NUM_CLASSES],
+        }
+    }
+
+    /// Try to take a token for a request of class `class`.  Returns `false` if the client has
+    /// exceeded the rate limit for that class.
+    pub fn take(&mut self, limits: &RateLimits, class: RequestClass, now: Time) -> bool {
+        let limit = limits.get(class);
+        let b = &mut self.buckets[class.index()];
+
+        let capacity = limit.burst as u64 * TOKEN_SCALE;
+        if b.last_refill == TIME_MIN {
+            b.tokens = capacity;
+            b.last_refill = now;
+        } else if now > b.last_refill {
+            // `rate` is per second and `TOKEN_SCALE` is 1000, so each millisecond adds `rate`
+            // scaled tokens.
+            let elapsed = (now - b.last_refill) as u64;
+            b.tokens = b.tokens.saturating_add(elapsed.saturating_mul(limit.rate as u64));
+            b.last_refill = now;
+        }
+        if b.tokens > capacity {
+            b.tokens = capacity;
+        }
+
+        if b.tokens >= TOKEN_SCALE {
+            b.tokens -= TOKEN_SCALE;
+            true
+        } else {
+            false
+        }
+    }
+}
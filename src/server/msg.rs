@@ -93,6 +93,7 @@ mod op {
         InventoryUpdate = 0x8019,
         InventoryAppear = 0x801a,
         InventoryGone = 0x801b,
+        PlaneEnv = 0x801c,
 
         // Deprecated responses
         PlayerMotion = 0x8002,
@@ -108,6 +109,8 @@ mod op {
         RestartServer = 0xff06,
         RestartClient = 0xff07,
         RestartBoth = 0xff08,
+        Save = 0xff09,
+        ReloadScripts = 0xff0a,
     }
 }
 
@@ -137,6 +140,8 @@ pub enum Request {
     ReplCommand(u16, String),
     Shutdown,
     Restart(bool, bool),
+    Save,
+    ReloadScripts,
 
     // Server-internal messages
     BadMessage(Opcode),
@@ -228,6 +233,12 @@ impl Request {
             op::RestartBoth => {
                 Restart(true, true)
             },
+            op::Save => {
+                Save
+            },
+            op::ReloadScripts => {
+                ReloadScripts
+            },
             _ => BadMessage(opcode),
         };
 
@@ -267,6 +278,7 @@ pub enum Response {
     InventoryUpdate(InventoryId, u8, (u8, u8, ItemId)),
     InventoryAppear(InventoryId, Vec<(u8, u8, ItemId)>),
     InventoryGone(InventoryId),
+    PlaneEnv(LocalTime, u32, u32, u32, u32),
 
     ClientRemoved(WireId),
     ReplResult(u16, String),
@@ -325,6 +337,8 @@ impl Response {
                 ww.write_msg(id, (op::InventoryAppear, inventory_id, all_slot_data)),
             InventoryGone(inventory_id) =>
                 ww.write_msg(id, (op::InventoryGone, inventory_id)),
+            PlaneEnv(now, cycle_base, cycle_ms, fixed_time, ambient) =>
+                ww.write_msg(id, (op::PlaneEnv, now, cycle_base, cycle_ms, fixed_time, ambient)),
 
             ClientRemoved(wire_id) =>
                 ww.write_msg(id, (op::ClientRemoved, wire_id)),
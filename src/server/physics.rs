@@ -2,6 +2,7 @@
 //! `libphysics`, so that it can be compiled to asm.js for use on the client.  This system just
 //! provides the glue to connect the physics engine to entities and the rest of the `World`.
 use libphysics::{self, ShapeSource};
+use libphysics::path::{Pathfinder, Node, HeapEntry};
 use libphysics::{CHUNK_SIZE, CHUNK_BITS, CHUNK_MASK, TILE_SIZE};
 
 use types::*;
@@ -54,6 +55,36 @@ impl<'a> ShapeSource for ChunksSource<'a> {
 }
 
 
+/// Size of the storage for a single `find_path` search.  This limits how far the search can go
+/// before giving up.
+const PATH_SEARCH_NODES: usize = 8192;
+
+/// Find a walking path for an entity of `size` from `start` to `goal` on plane `pid`.  All
+/// positions and sizes are in pixels.  Returns the position of each tile along the path, including
+/// the tiles containing both endpoints.  For ramp tiles, the position is the bottom of the ramp.
+/// Only chunks in the terrain cache are considered, so paths can't pass through unloaded parts of
+/// the plane.
+pub fn find_path(cache: &TerrainCache,
+                 pid: PlaneId,
+                 start: V3,
+                 goal: V3,
+                 size: V3) -> Option<Vec<V3>> {
+    let source = ChunksSource {
+        cache: cache,
+        base_tile: scalar(0),
+        plane: pid,
+    };
+    let start_tile = start.div_floor(scalar(TILE_SIZE));
+    let goal_tile = goal.div_floor(scalar(TILE_SIZE));
+
+    let mut nodes = vec![Node::new(); PATH_SEARCH_NODES];
+    let mut heap = vec![HeapEntry::new(); PATH_SEARCH_NODES];
+    let mut pathfinder = Pathfinder::new(&mut nodes, &mut heap);
+    pathfinder.find_path(&source, start_tile, goal_tile, size)
+              .map(|path| path.map(|tile| tile * scalar(TILE_SIZE)).collect())
+}
+
+
 pub trait Fragment<'d> {
     fn with_cache<F, R>(&mut self, f: F) -> R
         where F: FnOnce(&mut Physics<'d>, &TerrainCache, &World<'d>) -> R;
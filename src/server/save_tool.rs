@@ -0,0 +1,197 @@
+//! Command-line tool for inspecting and editing save files.
+//!
+//!     save_tool export <dist-dir> <kind> <save-file>
+//!     save_tool import <dist-dir> <json-file> <save-file>
+//!     save_tool convert-regions <dist-dir>
+//!
+//! `export` prints the contents of a save file as JSON.  `kind` is one of `world`, `client`,
+//! `plane`, or `terrain_chunk`.  `import` reads JSON in the same format (usually an edited export)
+//! and writes it back out as a save file.  Nothing is written unless the whole file converts
+//! successfully, and the previous version is kept as `<save-file>.bak`.  Both use the data files
+//! from `dist-dir` to map block, item, structure, and recipe IDs to names.
+//!
+//! Terrain chunks are stored in region files rather than individually.  To export or import a
+//! saved terrain chunk, give `terrain_chunk:<id>` as the `save-file`, where `<id>` is the chunk's
+//! stable ID in hex.
+//!
+//! `convert-regions` moves the terrain chunks and terrain generation summaries in the save
+//! directory under `dist-dir` from the old one-file-per-chunk layout into region files.  The
+//! server refuses to start until this has been done.
+#![crate_name = "save_tool"]
+#![allow(non_upper_case_globals)]
+#![allow(dead_code)]
+
+#![feature(
+    raw,
+)]
+
+#[macro_use] extern crate bitflags;
+extern crate env_logger;
+#[macro_use] extern crate log;
+extern crate rustc_serialize;
+extern crate time;
+
+extern crate physics as libphysics;
+extern crate server_config as libserver_config;
+extern crate server_types as libserver_types;
+#[macro_use] extern crate server_util as libserver_util;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+use rustc_serialize::json;
+
+use types::*;
+
+use world::save::json as save_json;
+use world::save::json::FileKind;
+
+
+#[macro_use] mod util;
+
+mod types;
+mod input;
+mod world;
+
+mod data {
+    pub use libserver_config::data::*;
+}
+
+mod storage {
+    pub use libserver_config::storage::*;
+}
+
+
+fn read_json(mut file: File) -> json::Json {
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    json::Json::from_str(&content).unwrap()
+}
+
+fn load_data(dist_dir: &str) -> data::Data {
+    let storage = storage::Storage::new(&dist_dir);
+
+    let block_json = read_json(storage.open_block_data());
+    let item_json = read_json(storage.open_item_data());
+    let recipe_json = read_json(storage.open_recipe_data());
+    let template_json = read_json(storage.open_template_data());
+    let animation_json = read_json(storage.open_animation_data());
+    let loot_table_json = read_json(storage.open_loot_table_data());
+    let vault_json = read_json(storage.open_vault_data());
+    let migration_json = storage.open_migration_data()
+                                .map_or(json::Json::Null, read_json);
+    data::Data::from_json(block_json,
+                          item_json,
+                          recipe_json,
+                          template_json,
+                          animation_json,
+                          loot_table_json,
+                          vault_json,
+                          migration_json).unwrap()
+}
+
+fn io_error(what: &str, path: &str, e: io::Error) -> util::StringError {
+    util::StringError { msg: format!("{} {}: {}", what, path, e) }
+}
+
+fn open_input(path: &str) -> util::StringResult<File> {
+    File::open(path).map_err(|e| io_error("can't open", path, e))
+}
+
+/// Read the JSON file given on the command line.
+fn read_input_json(path: &str) -> util::StringResult<json::Json> {
+    let mut content = String::new();
+    try!(try!(open_input(path)).read_to_string(&mut content)
+             .map_err(|e| io_error("can't read", path, e)));
+    json::Json::from_str(&content).map_err(|e| util::StringError {
+        msg: format!("can't parse {}: {}", path, e),
+    })
+}
+
+fn import(data: &data::Data,
+          dist_dir: &str,
+          json_path: &str,
+          save_path: &str) -> util::StringResult<()> {
+    // Convert everything before touching the existing save, so a mistake in the JSON leaves the
+    // original intact.  Then write the new file the same way the server does, keeping the old
+    // version as a backup.
+    let j = try!(read_input_json(json_path));
+    let mut buf = Vec::new();
+    try!(save_json::import(data, &j, &mut buf));
+
+    if let Some(stable_tcid) = terrain_chunk_ref(save_path) {
+        let storage = storage::Storage::new(&dist_dir);
+        let mut file = storage.create_terrain_chunk_file(stable_tcid);
+        try!(file.write_all(&buf).map_err(|e| io_error("can't write", save_path, e)));
+        file.commit().map_err(|e| io_error("can't write", save_path, e))
+    } else {
+        let mut file = try!(storage::SaveFile::create_at(save_path)
+                                .map_err(|e| io_error("can't create", save_path, e)));
+        try!(file.write_all(&buf).map_err(|e| io_error("can't write", save_path, e)));
+        file.commit().map_err(|e| io_error("can't write", save_path, e))
+    }
+}
+
+/// Parse a `terrain_chunk:<id>` reference to a terrain chunk in a region file.
+fn terrain_chunk_ref(s: &str) -> Option<Stable<TerrainChunkId>> {
+    const PREFIX: &'static str = "terrain_chunk:";
+    if !s.starts_with(PREFIX) {
+        return None;
+    }
+    u64::from_str_radix(&s[PREFIX.len() ..], 16).ok().map(Stable::new)
+}
+
+fn usage() -> ! {
+    let _ = writeln!(io::stderr(),
+                     "usage: save_tool export <dist-dir> <kind> <save-file>\n       \
+                      save_tool import <dist-dir> <json-file> <save-file>\n       \
+                      save_tool convert-regions <dist-dir>");
+    process::exit(2);
+}
+
+fn main() {
+    env_logger::init().unwrap();
+
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 2 {
+        usage();
+    }
+
+    let result = match &*args[1] {
+        "export" if args.len() == 5 => {
+            let data = load_data(&args[2]);
+            let kind = FileKind::from_name(&args[3]).unwrap_or_else(|| usage());
+            let result =
+                if let Some(stable_tcid) = terrain_chunk_ref(&args[4]) {
+                    let storage = storage::Storage::new(&args[2]);
+                    let file = unwrap_or!(storage.open_terrain_chunk_file(stable_tcid), {
+                        let _ = writeln!(io::stderr(), "error: no such terrain chunk");
+                        process::exit(1);
+                    });
+                    save_json::export(&data, kind, file)
+                } else {
+                    open_input(&args[4]).and_then(|file| save_json::export(&data, kind, file))
+                };
+            result.map(|j| {
+                println!("{}", json::as_pretty_json(&j));
+            })
+        },
+        "import" if args.len() == 5 => {
+            let data = load_data(&args[2]);
+            import(&data, &args[2], &args[3], &args[4])
+        },
+        "convert-regions" if args.len() == 3 => {
+            let storage = storage::Storage::new(&args[2]);
+            storage.convert_legacy_files().map(|count| {
+                println!("converted {} files", count);
+            }).map_err(|e| util::StringError { msg: e.to_string() })
+        },
+        _ => usage(),
+    };
+
+    if let Err(e) = result {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
+    }
+}
@@ -37,6 +37,11 @@ const FFI_LIB_NAME: &'static str = "outpost_ffi";
 
 const BOOTSTRAP_FILE: &'static str = "bootstrap.lua";
 
+/// Maximum number of Lua instructions a single callback may run before it is aborted.
+const INSTRUCTION_LIMIT: usize = 10_000_000;
+/// Maximum amount of memory (in bytes) a single callback may allocate before it is aborted.
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Nil;
@@ -47,6 +52,16 @@ pub struct ScriptEngine {
 
 impl ScriptEngine {
     pub fn new(script_dir: &Path) -> ScriptEngine {
+        match ScriptEngine::load(script_dir) {
+            Ok(x) => x,
+            Err(e) => panic!("failed to load scripts: {}", e),
+        }
+    }
+
+    /// Create a new Lua state and run the bootstrap script from `script_dir`.  Unlike `new`, this
+    /// reports errors in the scripts instead of panicking, so it can be used to reload the
+    /// scripts on a running server.
+    pub fn load(script_dir: &Path) -> StringResult<ScriptEngine> {
         // OwnedLuaState::new() should return Err only on out-of-memory.
         let mut owned_lua = OwnedLuaState::new().unwrap();
 
@@ -85,13 +100,19 @@ impl ScriptEngine {
 
 
             // Finally, actually run the startup script.
-            lua.load_file(&script_dir.join(BOOTSTRAP_FILE)).unwrap();
-            lua.pcall(0, 0, 0).unwrap();
+            try!(lua.load_file(&script_dir.join(BOOTSTRAP_FILE))
+                    .map_err(|(e, s)| StringError { msg: format!("{:?}: {}", e, s) }));
+            try!(lua.pcall(0, 0, 0)
+                    .map_err(|(e, s)| StringError { msg: format!("{:?}: {}", e, s) }));
         }
 
-        ScriptEngine {
+        // Startup is allowed to take as long as it needs, but after that, a runaway callback
+        // should fail instead of hanging the server.
+        owned_lua.set_limits(INSTRUCTION_LIMIT, MEMORY_LIMIT);
+
+        Ok(ScriptEngine {
             owned_lua: owned_lua,
-        }
+        })
     }
 
     fn with_context<F, R, E>(&mut self,
@@ -201,6 +222,29 @@ impl ScriptEngine {
     }
 
 
+    /// Cancel all pending script timers.  This runs before the scripts are reloaded, since the
+    /// new Lua state won't know about the old timers' callbacks.
+    pub fn cb_cancel_timers(eng: &mut engine::Engine) -> StringResult<()> {
+        ScriptEngine::with_engine(eng, |lua| {
+            run_callback(lua,
+                         "outpost_callback_cancel_timers",
+                         ())
+        })
+    }
+
+
+    /// Run the load hook for a structure whose unload hook already ran, putting its script data
+    /// back in its loaded form.  This is for undoing a save that was abandoned partway through.
+    pub fn cb_structure_load(eng: &mut engine::Engine,
+                             sid: StructureId) -> StringResult<()> {
+        ScriptEngine::with_engine(eng, |lua| {
+            run_callback(lua,
+                         "outpost_callback_structure_load",
+                         sid.unwrap())
+        })
+    }
+
+
     pub fn cb_eval(eng: &mut engine::Engine,
                    code: &str) -> Result<String, String> {
         ScriptEngine::with_engine(eng, |lua| {
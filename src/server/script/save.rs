@@ -14,6 +14,7 @@ use world::{self, World, Client, Entity, Inventory, Plane, TerrainChunk, Structu
 use world::StructureFlags;
 use world::object::*;
 use world::save::{self, Writer, Reader};
+use world::save::extra::Tag;
 
 use super::traits::{ToLua, Userdata, metatable_key};
 use super::userdata;
@@ -99,54 +100,6 @@ impl<'a> From<Error> for save::Error {
 pub type Result<T> = result::Result<T, Error>;
 
 
-
-macro_rules! primitive_enum {
-    (enum $name:ident: $prim:ty { $($variant:ident = $disr:expr,)* }) => {
-        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
-        enum $name {
-            $($variant = $disr,)*
-        }
-
-        impl $name {
-            pub fn from_primitive(x: $prim) -> Option<$name> {
-                match x {
-                    $( $disr => Some($name::$variant), )*
-                    _ => None,
-                }
-            }
-        }
-    };
-}
-
-primitive_enum! {
-    enum Tag: u8 {
-        Nil =           0x00,
-        Bool =          0x01,
-        SmallInt =      0x02,
-        LargeInt =      0x03,
-        Float =         0x04,
-        SmallString =   0x05,
-        LargeString =   0x06,
-        Table =         0x07,
-
-        World =         0x10,
-        Client =        0x11,
-        Entity =        0x12,
-        Inventory =     0x13,
-        Structure =     0x14,
-
-        StableClient =      0x20,
-        StableEntity =      0x21,
-        StableInventory =   0x22,
-        StablePlane =       0x23,
-        StableStructure =   0x24,
-
-        V3 =            0x30,
-        TimeU =         0x31,
-    }
-}
-
-
 pub type WriteHooks<'a, 'd> = ::engine::glue::SaveWriteHooks<'a, 'd>;
 
 impl<'a, 'd> save::WriteHooks for WriteHooks<'a, 'd> {
@@ -390,6 +390,18 @@ impl<T: ToLua> ToLua for Option<T> {
     fn count() -> c_int { <T as ToLua>::count() }
 }
 
+/// Vectors become Lua arrays.  This only makes sense for element types that push a single value.
+impl<T: ToLua> ToLua for Vec<T> {
+    fn to_lua(self, lua: &mut LuaState) {
+        lua.push_table_prealloc(self.len() as c_int, 0);
+        for (i, x) in self.into_iter().enumerate() {
+            lua.push_integer(i as isize + 1);
+            x.to_lua(lua);
+            lua.set_table_raw(-3);
+        }
+    }
+}
+
 impl<T: ToLua> ToLua for StrResult<T> {
     fn to_lua(self, lua: &mut LuaState) {
         match self {
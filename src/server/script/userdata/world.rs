@@ -1,21 +1,25 @@
 use std::borrow::ToOwned;
 
 use libphysics::CHUNK_SIZE;
+use libterrain_gen::GenConfig;
+use libterrain_gen::registry;
 
 use types::*;
-use util::StrResult;
+use util::{StrError, StrResult};
 
+use auth;
 use engine::Engine;
 use engine::glue::WorldFragment;
 use logic;
 use lua::LuaState;
-use messages::ClientResponse;
+use messages::{ClientResponse, ControlEvent, RequestClass, RateLimit};
 use msg;
+use physics;
 use script::traits::Userdata;
 use script::userdata::TakeOptWrapper;
 use script::userdata::extra_arg::ExtraArg;
 use world;
-use world::Fragment;
+use world::{Behavior, Fragment, PlaneEnv};
 use world::object::*;
 
 
@@ -46,10 +50,36 @@ impl Userdata for World {
                   .map(|e| Entity { id: e.id() })
             }
 
-            fn create_plane(!full wf: WorldFragment,
-                            _w: World,
-                            name: String) -> StrResult<Plane> {
-                wf.create_plane(name)
+            fn create_plane_raw(!full wf: WorldFragment,
+                                _w: World,
+                                name: String,
+                                kind: String,
+                                params: TakeOptWrapper<msg::ExtraArg>) -> StrResult<Plane> {
+                if !registry::KINDS.contains(&&*kind) {
+                    fail!("unknown plane generator kind");
+                }
+                // `params` is a map whose keys and values are all converted to strings.
+                let params = match unwrap!(params.0) {
+                    msg::ExtraArg::Map(m) => m,
+                    _ => fail!("expected ExtraArg::Map for plane generator params"),
+                };
+                let mut gen = GenConfig::new(&kind);
+                for (k, v) in params.into_iter() {
+                    let key = match k {
+                        msg::SimpleArg::Int(i) => i.to_string(),
+                        msg::SimpleArg::Str(s) => s,
+                    };
+                    let value = match v {
+                        msg::ExtraArg::Int(i) => i.to_string(),
+                        msg::ExtraArg::Str(s) => s,
+                        _ => fail!("plane generator params must be numbers or strings"),
+                    };
+                    if gen.params.insert(key, value).is_some() {
+                        fail!("duplicate key in plane generator params");
+                    }
+                }
+
+                wf.create_plane(name, gen)
                   .map(|p| Plane { id: p.id() })
             }
 
@@ -141,10 +171,76 @@ impl Userdata for World {
                              id: InventoryId) -> Option<Inventory> {
                 w.get_inventory(id).map(|_| Inventory { id: id })
             }
+
+            fn ban_account(!full eng: &mut Engine,
+                           _w: World,
+                           name: String,
+                           reason: String,
+                           duration: u32) -> StrResult<bool> {
+                // A duration of zero means the ban is permanent.
+                let expires =
+                    if duration == 0 {
+                        None
+                    } else {
+                        Some(auth::unix_time() + duration as i64)
+                    };
+                let ok = try!(eng.auth.ban(&name, &reason, expires).map_err(auth_error));
+                if let Some(cid) = eng.messages.name_to_client(&name) {
+                    let msg = format!("banned: {}", reason);
+                    eng.messages.defer_control(ControlEvent::KickClient(cid, msg));
+                }
+                Ok(ok)
+            }
+
+            fn unban_account(!full eng: &mut Engine,
+                             _w: World,
+                             name: String) -> StrResult<bool> {
+                eng.auth.unban(&name).map_err(auth_error)
+            }
+
+            fn delete_account(!full eng: &mut Engine,
+                              _w: World,
+                              name: String) -> StrResult<bool> {
+                let ok = try!(eng.auth.delete(&name).map_err(auth_error));
+                if ok {
+                    // The kick saves the client, so remove the save file only after that.  This
+                    // keeps anyone who registers the name later from getting the old character.
+                    if let Some(cid) = eng.messages.name_to_client(&name) {
+                        let msg = "account deleted".to_owned();
+                        eng.messages.defer_control(ControlEvent::KickClient(cid, msg));
+                    }
+                    eng.messages.defer_control(ControlEvent::RemoveClientSave(name));
+                }
+                Ok(ok)
+            }
+
+            fn save(!full eng: &mut Engine, _w: World) -> () {
+                logic::autosave::start(eng.as_ref());
+            }
+
+            fn set_autosave_interval(!full eng: &mut Engine, _w: World, secs: u32) -> () {
+                logic::autosave::set_interval(eng.as_ref(), secs as Time * 1000);
+            }
+
+            fn set_rate_limit(!full eng: &mut Engine,
+                              _w: World,
+                              class: String,
+                              rate: u32,
+                              burst: u32) -> StrResult<()> {
+                let class = unwrap!(RequestClass::from_name(&class),
+                                    "no such request class");
+                eng.messages.set_rate_limit(class, RateLimit::new(rate, burst));
+                Ok(())
+            }
         }
     }
 }
 
+fn auth_error(e: auth::Error) -> StrError {
+    warn!("auth database error: {}", e);
+    StrError::from("auth database error")
+}
+
 
 #[derive(Clone, Copy)]
 pub struct Client {
@@ -300,6 +396,10 @@ impl Userdata for Entity {
                 wf.destroy_entity(e.id)
             }
 
+            fn mark_dirty(!full wf: WorldFragment, e: Entity) -> () {
+                wf.with_hooks(|h| h.mark_entity_dirty(e.id));
+            }
+
             fn plane(!partial w: &world::World, e: Entity) -> Option<Plane> {
                 w.get_entity(e.id)
                  .map(|e| Plane { id: e.plane_id() })
@@ -358,6 +458,40 @@ impl Userdata for Entity {
                 logic::world::teleport_entity_stable_plane(wf, e.id, p.id, pos)
             }
 
+            fn behavior(!partial w: &world::World, e: Entity) -> Option<&'static str> {
+                w.get_entity(e.id).map(|e| match e.behavior() {
+                    Behavior::Idle => "idle",
+                    Behavior::WalkTo(_) => "walk_to",
+                    Behavior::Wander { .. } => "wander",
+                })
+            }
+
+            fn set_behavior_raw(!full eng: &mut Engine,
+                                e: Entity,
+                                name: String,
+                                radius: i32) -> StrResult<()> {
+                let behavior = match &*name {
+                    "idle" => Behavior::Idle,
+                    "wander" => {
+                        let now = eng.now();
+                        let home = unwrap!(eng.world.get_entity(e.id)).pos(now);
+                        Behavior::Wander { home: home, radius: radius }
+                    },
+                    _ => fail!("unknown behavior"),
+                };
+                logic::behavior::set_behavior(eng.as_ref(), e.id, behavior)
+            }
+
+            fn walk_to(!full eng: &mut Engine,
+                       e: Entity,
+                       pos: V3) -> StrResult<()> {
+                logic::behavior::set_behavior(eng.as_ref(), e.id, Behavior::WalkTo(pos))
+            }
+
+            fn stop(!full eng: &mut Engine, e: Entity) -> StrResult<()> {
+                logic::behavior::set_behavior(eng.as_ref(), e.id, Behavior::Idle)
+            }
+
             // TODO: come up with a lua representation of attachment so we can unify these methods
             // and also return the previous attachment (like the underlying op does)
             fn attach_to_world(!full wf: WorldFragment,
@@ -403,6 +537,10 @@ impl Userdata for Inventory {
                   .map(|mut i| StableInventory { id: i.stable_id() })
             }
 
+            fn mark_dirty(!full wf: WorldFragment, i: Inventory) -> () {
+                wf.with_hooks(|h| h.mark_inventory_dirty(i.id));
+            }
+
             fn destroy(!full wf: WorldFragment, i: Inventory) -> StrResult<()> {
                 wf.destroy_inventory(i.id)
             }
@@ -490,6 +628,38 @@ impl Userdata for Plane {
                  .map(|p| p.name().to_owned())
             }
 
+            fn env_raw(!partial w: &world::World,
+                       p: Plane) -> Option<(u32, Option<u32>, Option<u32>)> {
+                w.get_plane(p.id)
+                 .map(|p| {
+                     let env = p.env();
+                     (env.cycle_ms, env.fixed_time, env.ambient)
+                 })
+            }
+
+            fn set_env_raw(!full wf: WorldFragment,
+                           p: Plane,
+                           cycle_ms: i32,
+                           fixed_time: i32,
+                           ambient: i32) -> StrResult<()> {
+                if cycle_ms <= 0 {
+                    fail!("cycle length must be positive");
+                }
+                if fixed_time >= world::DAY_NIGHT_CYCLE_TICKS as i32 {
+                    fail!("time of day is out of range");
+                }
+                if ambient > 0xffffff {
+                    fail!("ambient color is out of range");
+                }
+                let mut p = unwrap!(wf.get_plane_mut(p.id));
+                p.set_env(PlaneEnv {
+                    cycle_ms: cycle_ms as u32,
+                    fixed_time: if fixed_time >= 0 { Some(fixed_time as u32) } else { None },
+                    ambient: if ambient >= 0 { Some(ambient as u32) } else { None },
+                });
+                Ok(())
+            }
+
             fn set_interior(!full wf: WorldFragment,
                             plane: Plane,
                             pos: V3,
@@ -522,6 +692,14 @@ impl Userdata for Plane {
                 let block_id = tc.blocks()[idx];
                 Some(w.data().block_data.name(block_id).to_owned())
             }
+
+            fn find_path(!full eng: &mut Engine,
+                         plane: Plane,
+                         from: V3,
+                         to: V3,
+                         size: V3) -> Option<Vec<V3>> {
+                physics::find_path(&eng.cache, plane.id, from, to, size)
+            }
         }
     }
 }
@@ -555,6 +733,10 @@ impl Userdata for Structure {
                 wf.destroy_structure(s.id)
             }
 
+            fn mark_dirty(!full wf: WorldFragment, s: Structure) -> () {
+                wf.with_hooks(|h| h.mark_structure_dirty(s.id));
+            }
+
             fn plane(!partial w: &world::World, s: Structure) -> Option<Plane> {
                 w.get_structure(s.id)
                  .map(|s| Plane { id: s.plane_id() })
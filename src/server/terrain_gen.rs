@@ -7,14 +7,20 @@
 //! finishes generating that chunk, the system replaces the blank `TerrainChunk` with the final
 //! version.
 //!
+//! The worker actually runs a pool of `WORKER_COUNT` threads.  Each request is tagged with a
+//! priority based on its distance from the nearest client, so that chunks the player is about to
+//! see get generated before ones at the edge of the view.
+//!
 //! In the overall architecture, the `TerrainGen` system is used to implement part of the
 //! `chunks::Provider`, which is responsible for loading or generating new chunks.  It also
 //! interfaces with the main `Enigne` loop so that "terrain gen finished" messages can be handled
 //! immediately.
+use std::cmp;
 use std::sync::mpsc::{self, Sender, Receiver};
 use std::thread::{self, JoinGuard};
+use std::u32;
 
-use libphysics::CHUNK_SIZE;
+use libphysics::{CHUNK_SIZE, TILE_SIZE};
 use libterrain_gen::worker;
 use types::*;
 use util::StrResult;
@@ -22,7 +28,7 @@ use util::StrResult;
 use data::Data;
 use script::ScriptEngine;
 use storage::Storage;
-use world::Fragment as World_Fragment;
+use world::{World, Fragment as World_Fragment};
 use world::Hooks;
 use world::StructureAttachment;
 use world::flags;
@@ -30,27 +36,36 @@ use world::object::*;
 
 pub type TerrainGenEvent = worker::Response;
 
+/// Number of terrain generation threads to run.
+const WORKER_COUNT: usize = 4;
+
 pub struct TerrainGen<'d> {
     send: Sender<worker::Command>,
     recv: Receiver<worker::Response>,
     guard: JoinGuard<'d, ()>,
+    seed: u64,
 }
 
 impl<'d> TerrainGen<'d> {
-    pub fn new(data: &'d Data, storage: &'d Storage) -> TerrainGen<'d> {
+    pub fn new(data: &'d Data, storage: &'d Storage, seed: u64) -> TerrainGen<'d> {
         let (send_cmd, recv_cmd) = mpsc::channel();
         let (send_result, recv_result) = mpsc::channel();
         let guard = thread::scoped(move || {
-            worker::run(data, storage, recv_cmd, send_result);
+            worker::run(data, storage, seed, WORKER_COUNT, recv_cmd, send_result);
         });
 
         TerrainGen {
             send: send_cmd,
             recv: recv_result,
             guard: guard,
+            seed: seed,
         }
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn receiver(&self) -> &Receiver<TerrainGenEvent> {
         &self.recv
     }
@@ -66,11 +81,47 @@ pub trait Fragment<'d> {
     fn generate(&mut self,
                 pid: PlaneId,
                 cpos: V2) -> StrResult<TerrainChunkId> {
-        let stable_pid = self.with_world(|wf| wf.plane_mut(pid).stable_id());
-        self.terrain_gen_mut().send.send(worker::Command::Generate(stable_pid, cpos)).unwrap();
+        let (stable_pid, priority, config) = self.with_world(|wf| {
+            (wf.plane_mut(pid).stable_id(),
+             chunk_priority(wf.world(), pid, cpos),
+             wf.world().plane(pid).gen_config().clone())
+        });
+        let cmd = worker::Command::Generate(stable_pid, cpos, priority, config);
+        self.terrain_gen_mut().send.send(cmd).unwrap();
         self.with_world(move |wf| { wf.create_terrain_chunk(pid, cpos).map(|tc| tc.id()) })
     }
 
+    /// Cancel generation of a chunk that is being unloaded.  If the worker has already started on
+    /// the chunk, the result will still arrive, but `process` will discard it.
+    fn cancel(&mut self,
+              pid: PlaneId,
+              cpos: V2) {
+        let stable_pid = self.with_world(|wf| wf.plane_mut(pid).stable_id());
+        let cmd = worker::Command::Cancel(stable_pid, cpos);
+        self.terrain_gen_mut().send.send(cmd).unwrap();
+    }
+
+    /// Recompute the priority of a pending chunk, such as after a client moves.  Does nothing if
+    /// the chunk is not waiting on generation.
+    fn reprioritize(&mut self,
+                    pid: PlaneId,
+                    cpos: V2) {
+        let opt_req = self.with_world(|wf| {
+            {
+                let p = wf.world().plane(pid);
+                let tc = unwrap_or!(p.get_terrain_chunk(cpos), return None);
+                if !tc.flags().contains(flags::TC_GENERATION_PENDING) {
+                    return None;
+                }
+            }
+            let priority = chunk_priority(wf.world(), pid, cpos);
+            Some((wf.plane_mut(pid).stable_id(), priority))
+        });
+        let (stable_pid, priority) = unwrap_or!(opt_req);
+        let cmd = worker::Command::Reprioritize(stable_pid, cpos, priority);
+        self.terrain_gen_mut().send.send(cmd).unwrap();
+    }
+
     fn process(&mut self, evt: TerrainGenEvent) {
         let (stable_pid, cpos, gc) = evt;
         self.with_world(move |wf| {
@@ -120,3 +171,22 @@ pub trait Fragment<'d> {
     }
 
 }
+
+
+/// Compute the priority for generating a chunk: the distance (in chunks) from the chunk to the
+/// nearest client pawn on the same plane.
+fn chunk_priority(w: &World, pid: PlaneId, cpos: V2) -> worker::Priority {
+    let mut best = u32::MAX;
+    for c in w.clients() {
+        let pawn = unwrap_or!(c.pawn(), continue);
+        if pawn.plane_id() != pid {
+            continue;
+        }
+        // Use the destination of the pawn's current motion, since that's where the client will
+        // be looking soon.
+        let pawn_cpos = pawn.motion().end_pos.reduce().div_floor(scalar(CHUNK_SIZE * TILE_SIZE));
+        let dist = (cpos - pawn_cpos).abs().max() as u32;
+        best = cmp::min(best, dist);
+    }
+    best
+}
@@ -97,6 +97,7 @@ pub trait Hooks {
                        cid: ClientId,
                        old_pid: PlaneId,
                        new_pid: PlaneId) {}
+    fn on_plane_update(&mut self, cid: ClientId, pid: PlaneId) {}
 
     fn on_terrain_chunk_appear(&mut self,
                                cid: ClientId,
@@ -256,6 +257,18 @@ impl Vision {
         }
     }
 
+    /// Notify all clients currently viewing plane `pid` that the plane's properties have changed.
+    pub fn update_plane<H>(&mut self,
+                           pid: PlaneId,
+                           h: &mut H)
+            where H: Hooks {
+        for (raw_cid, viewer) in self.viewers.iter() {
+            if viewer.plane == pid {
+                h.on_plane_update(ClientId(raw_cid as u16), pid);
+            }
+        }
+    }
+
     pub fn client_view_plane(&self, cid: ClientId) -> Option<PlaneId> {
         self.viewers.get(&(cid.unwrap() as usize)).map(|c| c.plane)
     }
@@ -264,6 +277,11 @@ impl Vision {
         self.viewers.get(&(cid.unwrap() as usize)).map(|c| c.view)
     }
 
+    /// Get the clients whose view includes chunk `cpos` of plane `pid`.
+    pub fn chunk_viewers(&self, pid: PlaneId, cpos: V2) -> Vec<ClientId> {
+        self.viewers_by_pos.get(&(pid, cpos)).map(|x| x.iter()).unwrap_iter().cloned().collect()
+    }
+
 
     pub fn add_entity<H>(&mut self,
                          eid: EntityId,
@@ -598,6 +616,7 @@ gen_Fragment! {
     fn add_client(cid: ClientId, plane: PlaneId, view: Region<V2>);
     fn remove_client(cid: ClientId);
     fn set_client_view(cid: ClientId, plane: PlaneId, view: Region<V2>);
+    fn update_plane(pid: PlaneId);
 
     fn add_entity(eid: EntityId, plane: PlaneId, area: SmallSet<V2>);
     fn remove_entity(eid: EntityId);
@@ -1,3 +1,4 @@
+use libterrain_gen::GenConfig;
 use types::*;
 
 use world::World;
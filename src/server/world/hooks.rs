@@ -16,6 +16,7 @@ pub trait Hooks {
     fn on_entity_destroy(&mut self, eid: EntityId) {}
     fn on_entity_motion_change(&mut self, eid: EntityId) {}
     fn on_entity_appearance_change(&mut self, eid: EntityId) {}
+    fn on_entity_behavior_change(&mut self, eid: EntityId) {}
     fn on_entity_plane_change(&mut self, eid: EntityId) {}
 
     fn on_inventory_create(&mut self, iid: InventoryId) {}
@@ -26,6 +27,7 @@ pub trait Hooks {
 
     fn on_plane_create(&mut self, pid: PlaneId) {}
     fn on_plane_destroy(&mut self, pid: PlaneId) {}
+    fn on_plane_env_change(&mut self, pid: PlaneId) {}
 
     fn on_terrain_chunk_create(&mut self, tcid: TerrainChunkId) {}
     fn on_terrain_chunk_destroy(&mut self, tcid: TerrainChunkId, plane_id: PlaneId, cpos: V2) {}
@@ -1,7 +1,8 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use data::Data;
 use input::InputBits;
+use libterrain_gen::GenConfig;
 use types::*;
 use util::stable_id_map::StableIdMap;
 
@@ -13,8 +14,12 @@ pub use self::types::{
     EntityAttachment,
     StructureAttachment,
     InventoryAttachment,
+    CraftingJob,
+    Behavior,
+    PlaneEnv,
     Motion,
 };
+pub use self::types::{DAY_NIGHT_CYCLE_TICKS, DAY_NIGHT_CYCLE_MS};
 pub use self::world::{EntitiesById, StructuresById, InventoriesById};
 
 macro_rules! bad {
@@ -91,10 +96,14 @@ pub struct Entity {
     plane: PlaneId,
 
     motion: Motion,
+    /// The most recent previous motions, oldest first, so the position at a recent time can be
+    /// reconstructed.  This is not saved.
+    motion_history: VecDeque<Motion>,
     anim: AnimId,
     facing: V3,
     target_velocity: V3,
     appearance: u32,
+    behavior: Behavior,
 
     stable_id: StableId,
     attachment: EntityAttachment,
@@ -133,6 +142,10 @@ pub struct Plane {
     loaded_chunks: HashMap<V2, TerrainChunkId>,
     saved_chunks: HashMap<V2, Stable<TerrainChunkId>>,
 
+    env: PlaneEnv,
+    /// Generator for new chunks of this plane.
+    gen: GenConfig,
+
     stable_id: StableId,
 }
 impl_IntrusiveStableId!(Plane, stable_id);
@@ -159,6 +172,8 @@ pub struct Structure {
     flags: StructureFlags,
     attachment: StructureAttachment,
     child_inventories: HashSet<InventoryId>,
+    /// Crafting jobs waiting to finish, in order of completion.
+    crafting_jobs: VecDeque<CraftingJob>,
 }
 impl_IntrusiveStableId!(Structure, stable_id);
 
@@ -1,5 +1,6 @@
-use std::collections::hash_set;
+use std::collections::{hash_set, VecDeque};
 use std::marker::PhantomData;
+use std::mem;
 use std::ops::{Deref, DerefMut};
 
 use libphysics::CHUNK_SIZE;
@@ -11,13 +12,18 @@ use world::{Client, Entity, Inventory, Plane, TerrainChunk, Structure};
 use world::{EntitiesById, StructuresById, InventoriesById};
 use world::{EntityAttachment, StructureAttachment, InventoryAttachment};
 use world::{TerrainChunkFlags, StructureFlags};
-use world::Motion;
+use world::{Motion, Behavior, CraftingJob, PlaneEnv};
 use world::Item;
 use world::fragment::Fragment;
 use world::hooks::Hooks;
 use world::ops::{self, OpResult};
 
 
+/// Number of previous motions kept in `Entity::motion_history`.  This only needs to cover the
+/// short delay between an action's timestamp and when the action runs.
+const MOTION_HISTORY_LEN: usize = 8;
+
+
 pub trait Object: 'static {
     type Id: Copy;
 
@@ -292,7 +298,14 @@ pub trait EntityRefMut<'d, F: Fragment<'d>>: ObjectRefMutBase<'d, Entity, F> {
     fn set_motion(&mut self, motion: Motion) {
         let eid = self.id();
         // TODO: update entity-by-chunk cache
-        self.obj_mut().motion = motion;
+        {
+            let e = self.obj_mut();
+            let old = mem::replace(&mut e.motion, motion);
+            if e.motion_history.len() == MOTION_HISTORY_LEN {
+                e.motion_history.pop_front();
+            }
+            e.motion_history.push_back(old);
+        }
         self.fragment_mut().with_hooks(|h| h.on_entity_motion_change(eid));
     }
 
@@ -302,6 +315,12 @@ pub trait EntityRefMut<'d, F: Fragment<'d>>: ObjectRefMutBase<'d, Entity, F> {
         self.fragment_mut().with_hooks(|h| h.on_entity_appearance_change(eid));
     }
 
+    fn set_behavior(&mut self, behavior: Behavior) {
+        let eid = self.id();
+        self.obj_mut().behavior = behavior;
+        self.fragment_mut().with_hooks(|h| h.on_entity_behavior_change(eid));
+    }
+
     fn set_attachment(&mut self, attach: EntityAttachment) -> OpResult<EntityAttachment> {
         let eid = self.id();
         ops::entity::attach(self.fragment_mut(), eid, attach)
@@ -412,6 +431,12 @@ pub trait PlaneRefMut<'d, F: Fragment<'d>>: ObjectRefMutBase<'d, Plane, F> {
         self.world_mut().planes.pin(pid)
     }
 
+    fn set_env(&mut self, env: PlaneEnv) {
+        let pid = self.id();
+        self.obj_mut().env = env;
+        self.fragment_mut().with_hooks(|h| h.on_plane_env_change(pid));
+    }
+
     fn get_terrain_chunk_mut<'b>(&'b mut self, cpos: V2)
                                  -> Option<ObjectRefMut<'b, 'd, TerrainChunk, F>> {
         let &tcid = unwrap_or!(self.obj().loaded_chunks.get(&cpos), return None);
@@ -522,6 +547,10 @@ pub trait StructureRef<'d>: ObjectRefBase<'d, Structure> {
             -> InventoriesById<'b, 'd, hash_set::Iter<'b, InventoryId>> {
         InventoriesById::new(self.world(), self.obj().child_inventories.iter())
     }
+
+    fn crafting_jobs(&self) -> &VecDeque<CraftingJob> {
+        &self.obj().crafting_jobs
+    }
 }
 impl<'a, 'd> StructureRef<'d> for ObjectRef<'a, 'd, Structure> { }
 impl<'a, 'd, F: Fragment<'d>> StructureRef<'d> for ObjectRefMut<'a, 'd, Structure, F> { }
@@ -541,6 +570,10 @@ pub trait StructureRefMut<'d, F: Fragment<'d>>: ObjectRefMutBase<'d, Structure,
         self.obj_mut().flags = flags;
     }
 
+    fn crafting_jobs_mut(&mut self) -> &mut VecDeque<CraftingJob> {
+        &mut self.obj_mut().crafting_jobs
+    }
+
     fn set_attachment(&mut self, attach: StructureAttachment) -> OpResult<StructureAttachment> {
         let sid = self.id();
         ops::structure::attach(self.fragment_mut(), sid, attach)
@@ -1,10 +1,10 @@
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::mem::replace;
 
 use types::*;
 use util::{multimap_insert, multimap_remove};
 
-use world::{Entity, EntityAttachment, Motion};
+use world::{Entity, EntityAttachment, Behavior, Motion};
 use world::{Fragment, Hooks};
 use world::ops::{self, OpResult};
 
@@ -21,10 +21,12 @@ pub fn create<'d, F>(f: &mut F,
         plane: PLANE_LIMBO,
 
         motion: Motion::fixed(pos),
+        motion_history: VecDeque::new(),
         anim: anim,
         facing: V3::new(1, 0, 0),
         target_velocity: scalar(0),
         appearance: appearance,
+        behavior: Behavior::Idle,
 
         stable_id: NO_STABLE_ID,
         attachment: EntityAttachment::World,
@@ -44,10 +46,12 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> EntityId
         plane: PLANE_LIMBO,
 
         motion: Motion::fixed(scalar(0)),
+        motion_history: VecDeque::new(),
         anim: 0,
         facing: scalar(0),
         target_velocity: scalar(0),
         appearance: 0,
+        behavior: Behavior::Idle,
 
         stable_id: NO_STABLE_ID,
         attachment: EntityAttachment::World,
@@ -1,13 +1,14 @@
 use std::collections::HashMap;
 
+use libterrain_gen::GenConfig;
 use types::*;
 
-use world::Plane;
+use world::{Plane, PlaneEnv};
 use world::{Fragment, Hooks};
 use world::ops::{self, OpResult};
 
 
-pub fn create<'d, F>(f: &mut F, name: String) -> OpResult<PlaneId>
+pub fn create<'d, F>(f: &mut F, name: String, gen: GenConfig) -> OpResult<PlaneId>
         where F: Fragment<'d> {
     let p = Plane {
         name: name,
@@ -15,6 +16,9 @@ pub fn create<'d, F>(f: &mut F, name: String) -> OpResult<PlaneId>
         loaded_chunks: HashMap::new(),
         saved_chunks: HashMap::new(),
 
+        env: PlaneEnv::new(),
+        gen: gen,
+
         stable_id: NO_STABLE_ID,
     };
 
@@ -32,6 +36,9 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> PlaneId
         loaded_chunks: HashMap::new(),
         saved_chunks: HashMap::new(),
 
+        env: PlaneEnv::new(),
+        gen: GenConfig::new("void"),
+
         stable_id: NO_STABLE_ID,
     }).unwrap();     // Shouldn't fail when stable_id == NO_STABLE_ID
     pid
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use libphysics::CHUNK_SIZE;
 use types::*;
@@ -34,6 +34,7 @@ pub fn create<'d, F>(f: &mut F,
         flags: StructureFlags::empty(),
         attachment: StructureAttachment::Plane,
         child_inventories: HashSet::new(),
+        crafting_jobs: VecDeque::new(),
     };
 
     let sid = unwrap!(f.world_mut().structures.insert(s));
@@ -53,6 +54,7 @@ pub fn create_unchecked<'d, F>(f: &mut F) -> StructureId
         flags: StructureFlags::empty(),
         attachment: StructureAttachment::Plane,
         child_inventories: HashSet::new(),
+        crafting_jobs: VecDeque::new(),
     }).unwrap();     // Shouldn't fail when stable_id == NO_STABLE_ID
     sid
 }
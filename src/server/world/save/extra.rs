@@ -0,0 +1,220 @@
+//! Encoding of the script data ("extra") attached to saved objects.  The script engine converts
+//! these values directly to and from Lua.  `Value` is used by tools that need to work with saves
+//! without running any scripts.
+use types::*;
+
+use util::Convert;
+use world::Fragment;
+
+use super::Result;
+use super::{Reader, Writer};
+
+
+macro_rules! primitive_enum {
+    (enum $name:ident: $prim:ty { $($variant:ident = $disr:expr,)* }) => {
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        pub enum $name {
+            $($variant = $disr,)*
+        }
+
+        impl $name {
+            pub fn from_primitive(x: $prim) -> Option<$name> {
+                match x {
+                    $( $disr => Some($name::$variant), )*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+primitive_enum! {
+    enum Tag: u8 {
+        Nil =           0x00,
+        Bool =          0x01,
+        SmallInt =      0x02,
+        LargeInt =      0x03,
+        Float =         0x04,
+        SmallString =   0x05,
+        LargeString =   0x06,
+        Table =         0x07,
+
+        World =         0x10,
+        Client =        0x11,
+        Entity =        0x12,
+        Inventory =     0x13,
+        Structure =     0x14,
+
+        StableClient =      0x20,
+        StableEntity =      0x21,
+        StableInventory =   0x22,
+        StablePlane =       0x23,
+        StableStructure =   0x24,
+
+        V3 =            0x30,
+        TimeU =         0x31,
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(i32),
+    Float(f64),
+    Str(String),
+    /// Table entries, in the order they appear in the file.
+    Table(Vec<(Value, Value)>),
+
+    World,
+    Client(ClientId),
+    Entity(EntityId),
+    Inventory(InventoryId),
+    Structure(StructureId),
+
+    StableClient(StableId),
+    StableEntity(StableId),
+    StableInventory(StableId),
+    StablePlane(StableId),
+    StableStructure(StableId),
+
+    V3(V3),
+    TimeU(Time),
+}
+
+pub fn read_value<'d, R: Reader, F: Fragment<'d>>(r: &mut R, f: &mut F) -> Result<Value> {
+    let (tag, a, b): (u8, u8, u16) = try!(r.read());
+    let tag = unwrap!(Tag::from_primitive(tag));
+    let v = match tag {
+        Tag::Nil => Value::Nil,
+        Tag::Bool => Value::Bool(a != 0),
+        Tag::SmallInt => Value::Int(b as i16 as i32),
+        Tag::LargeInt => Value::Int(try!(r.read())),
+        Tag::Float => Value::Float(try!(r.read())),
+        Tag::SmallString => Value::Str(try!(r.read_str_bytes(b as usize))),
+        Tag::LargeString => Value::Str(try!(r.read_str())),
+        Tag::Table => {
+            let mut entries = Vec::new();
+            loop {
+                let k = try!(read_value(r, f));
+                if let Value::Nil = k {
+                    break;
+                }
+                let v = try!(read_value(r, f));
+                entries.push((k, v));
+            }
+            Value::Table(entries)
+        },
+
+        Tag::World => Value::World,
+        Tag::Client => Value::Client(try!(r.read_id(f))),
+        Tag::Entity => Value::Entity(try!(r.read_id(f))),
+        Tag::Inventory => Value::Inventory(try!(r.read_id(f))),
+        Tag::Structure => Value::Structure(try!(r.read_id(f))),
+
+        Tag::StableClient => Value::StableClient(try!(r.read())),
+        Tag::StableEntity => Value::StableEntity(try!(r.read())),
+        Tag::StableInventory => Value::StableInventory(try!(r.read())),
+        Tag::StablePlane => Value::StablePlane(try!(r.read())),
+        Tag::StableStructure => Value::StableStructure(try!(r.read())),
+
+        Tag::V3 => {
+            let (x, y, z) = try!(r.read());
+            Value::V3(V3::new(x, y, z))
+        },
+        Tag::TimeU => Value::TimeU(try!(r.read())),
+    };
+    Ok(v)
+}
+
+pub fn write_value<W: Writer>(w: &mut W, v: &Value) -> Result<()> {
+    match *v {
+        Value::Nil => try!(w.write(Tag::Nil as u8)),
+        Value::Bool(b) => try!(w.write((Tag::Bool as u8, b as u8))),
+        Value::Int(i) => {
+            match i.to_i16() {
+                Some(small_i) => {
+                    try!(w.write((Tag::SmallInt as u8, 0u8, small_i)));
+                },
+                None => {
+                    try!(w.write(Tag::LargeInt as u8));
+                    try!(w.write(i));
+                },
+            }
+        },
+        Value::Float(f) => {
+            try!(w.write(Tag::Float as u8));
+            try!(w.write(f));
+        },
+        Value::Str(ref s) => {
+            match s.len().to_u16() {
+                Some(small_len) => {
+                    try!(w.write((Tag::SmallString as u8, 0u8, small_len)));
+                    try!(w.write_str_bytes(s));
+                },
+                None => {
+                    try!(w.write(Tag::LargeString as u8));
+                    try!(w.write_str(s));
+                },
+            }
+        },
+        Value::Table(ref entries) => {
+            try!(w.write(Tag::Table as u8));
+            for &(ref k, ref v) in entries.iter() {
+                try!(write_value(w, k));
+                try!(write_value(w, v));
+            }
+            try!(write_value(w, &Value::Nil));
+        },
+
+        Value::World => try!(w.write(Tag::World as u8)),
+        Value::Client(cid) => {
+            try!(w.write(Tag::Client as u8));
+            try!(w.write_id(cid));
+        },
+        Value::Entity(eid) => {
+            try!(w.write(Tag::Entity as u8));
+            try!(w.write_id(eid));
+        },
+        Value::Inventory(iid) => {
+            try!(w.write(Tag::Inventory as u8));
+            try!(w.write_id(iid));
+        },
+        Value::Structure(sid) => {
+            try!(w.write(Tag::Structure as u8));
+            try!(w.write_id(sid));
+        },
+
+        Value::StableClient(id) => {
+            try!(w.write(Tag::StableClient as u8));
+            try!(w.write(id));
+        },
+        Value::StableEntity(id) => {
+            try!(w.write(Tag::StableEntity as u8));
+            try!(w.write(id));
+        },
+        Value::StableInventory(id) => {
+            try!(w.write(Tag::StableInventory as u8));
+            try!(w.write(id));
+        },
+        Value::StablePlane(id) => {
+            try!(w.write(Tag::StablePlane as u8));
+            try!(w.write(id));
+        },
+        Value::StableStructure(id) => {
+            try!(w.write(Tag::StableStructure as u8));
+            try!(w.write(id));
+        },
+
+        Value::V3(v) => {
+            try!(w.write(Tag::V3 as u8));
+            try!(w.write((v.x, v.y, v.z)));
+        },
+        Value::TimeU(t) => {
+            try!(w.write(Tag::TimeU as u8));
+            try!(w.write(t));
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,1096 @@
+//! Conversion of save files to and from JSON, for inspecting and editing saves by hand.
+//!
+//! Export loads the file into a scratch `World` with `ObjectReader`, then converts the loaded
+//! objects to JSON.  Import goes the other way: it builds the objects in a scratch `World` and
+//! writes them out with `ObjectWriter`.  Script data is handled as `extra::Value`s, so no scripts
+//! are run in either direction.
+//!
+//! Objects refer to each other (for example, a client to its pawn, or script data to an entity)
+//! using their `id` fields.  These IDs are meaningful only within a single JSON file.  Stable IDs
+//! are exported as-is.
+use std::borrow::ToOwned;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use rustc_serialize::json::{self, Json};
+
+use libterrain_gen::GenConfig;
+use types::*;
+use util::Convert;
+use util::IntrusiveStableId;
+use util::StringResult;
+
+use data::{Data, StructureTemplate};
+use world::{self, World, Client, Entity, Inventory, Plane, TerrainChunk, Structure};
+use world::{EntityAttachment, StructureAttachment, InventoryAttachment};
+use world::{TerrainChunkFlags, StructureFlags};
+use world::{Behavior, CraftingJob, PlaneEnv};
+use world::Item;
+use world::object::*;
+use world::ops;
+
+use super::Result;
+use super::{AnyId, ToAnyId};
+use super::{ObjectReader, ReadHooks, ReadFragment};
+use super::{ObjectWriter, WriteHooks};
+use super::extra::{self, Value};
+use super::reader::{Reader, ReadId};
+use super::writer::Writer;
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileKind {
+    World,
+    Client,
+    Plane,
+    TerrainChunk,
+}
+
+impl FileKind {
+    pub fn from_name(name: &str) -> Option<FileKind> {
+        match name {
+            "world" => Some(FileKind::World),
+            "client" => Some(FileKind::Client),
+            "plane" => Some(FileKind::Plane),
+            "terrain_chunk" => Some(FileKind::TerrainChunk),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FileKind::World => "world",
+            FileKind::Client => "client",
+            FileKind::Plane => "plane",
+            FileKind::TerrainChunk => "terrain_chunk",
+        }
+    }
+}
+
+
+/// Convert a save file of the given kind to JSON.
+pub fn export<R: io::Read>(data: &Data, kind: FileKind, file: R) -> StringResult<Json> {
+    let mut s = Scratch::new(data);
+    let mut sr = ObjectReader::new(file);
+
+    let obj = match kind {
+        FileKind::World => {
+            try!(sr.load_world(&mut s));
+            world_to_json(&s.extras, &s.world)
+        },
+        FileKind::Client => {
+            // The name is not stored in the file.
+            let cid = try!(sr.load_client(&mut s, String::new()));
+            client_to_json(&s.extras, &s.world.client(cid))
+        },
+        FileKind::Plane => {
+            let pid = try!(sr.load_plane(&mut s));
+            plane_to_json(&s.extras, &s.world.plane(pid))
+        },
+        FileKind::TerrainChunk => {
+            let pid = s.placeholder_plane();
+            let tcid = try!(sr.load_terrain_chunk(&mut s, pid, scalar(0)));
+            terrain_chunk_to_json(&s.extras, &s.world.terrain_chunk(tcid))
+        },
+    };
+
+    let mut result = BTreeMap::new();
+    result.insert("kind".to_owned(), Json::String(kind.name().to_owned()));
+    result.insert("object".to_owned(), obj);
+    Ok(Json::Object(result))
+}
+
+/// Convert JSON produced by `export` back into a save file, writing it to `out`.
+pub fn import<W: io::Write>(data: &Data, json: &Json, out: W) -> StringResult<()> {
+    let kind_name = try!(get_str(json, "kind"));
+    let kind = match FileKind::from_name(kind_name) {
+        Some(k) => k,
+        None => fail!("unrecognized file kind \"{}\"", kind_name),
+    };
+    let j = try!(field(json, "object"));
+
+    let mut imp = Importer::new(data);
+    match kind {
+        FileKind::World => {
+            try!(imp.import_world(j));
+            let s = try!(imp.finish());
+            try!(s.writer(out).save_world(&s.world));
+        },
+        FileKind::Client => {
+            let cid = try!(imp.import_client(j));
+            let s = try!(imp.finish());
+            try!(s.writer(out).save_client(&s.world.client(cid)));
+        },
+        FileKind::Plane => {
+            let pid = try!(imp.import_plane(j));
+            let s = try!(imp.finish());
+            try!(s.writer(out).save_plane(&s.world.plane(pid)));
+        },
+        FileKind::TerrainChunk => {
+            let pid = imp.s.placeholder_plane();
+            let tcid = try!(imp.import_terrain_chunk(j, pid));
+            let s = try!(imp.finish());
+            try!(s.writer(out).save_terrain_chunk(&s.world.terrain_chunk(tcid)));
+        },
+    }
+    Ok(())
+}
+
+
+/// Script data for the objects in a `Scratch` world.  Objects with no entry have `nil` extra data.
+struct Extras {
+    world: Value,
+    objs: HashMap<AnyId, Value>,
+}
+
+impl Extras {
+    fn new() -> Extras {
+        Extras {
+            world: Value::Nil,
+            objs: HashMap::new(),
+        }
+    }
+
+    fn get<T: ToAnyId>(&self, id: T) -> Json {
+        self.objs.get(&id.to_any_id()).map_or(Json::Null, value_to_json)
+    }
+}
+
+/// A world that exists only to hold the objects of a single save file.
+struct Scratch<'d> {
+    world: World<'d>,
+    extras: Extras,
+}
+
+impl<'d> Scratch<'d> {
+    fn new(data: &'d Data) -> Scratch<'d> {
+        Scratch {
+            world: World::new(data),
+            extras: Extras::new(),
+        }
+    }
+
+    /// Create a plane to hold a terrain chunk.  Terrain chunk files don't record their plane or
+    /// position, so the chunk always goes at (0, 0) of an otherwise empty plane.
+    fn placeholder_plane(&mut self) -> PlaneId {
+        let pid = ops::plane::create_unchecked(self);
+        ops::plane::post_init(self, pid);
+        pid
+    }
+
+    /// Get an `ObjectWriter` that writes the extra data recorded in `self.extras`.
+    fn writer<W: io::Write>(&self, out: W) -> ObjectWriter<W, ExtraWriter> {
+        ObjectWriter::new(out, ExtraWriter { extras: &self.extras })
+    }
+
+    fn read_extra<R: Reader, T: ToAnyId>(&mut self, r: &mut R, id: T) -> Result<()> {
+        let v = try!(extra::read_value(r, self));
+        self.extras.objs.insert(id.to_any_id(), v);
+        Ok(())
+    }
+}
+
+struct NoHooks;
+
+impl world::Hooks for NoHooks {
+    fn check_structure_placement(&self,
+                                 _template: &StructureTemplate,
+                                 _plane_id: PlaneId,
+                                 _pos: V3) -> bool {
+        true
+    }
+
+    fn check_structure_replacement(&self,
+                                   _sid: StructureId,
+                                   _new_template: &StructureTemplate,
+                                   _plane_id: PlaneId,
+                                   _pos: V3) -> bool {
+        true
+    }
+}
+
+impl<'d> world::Fragment<'d> for Scratch<'d> {
+    fn world(&self) -> &World<'d> {
+        &self.world
+    }
+
+    fn world_mut(&mut self) -> &mut World<'d> {
+        &mut self.world
+    }
+
+    type H = NoHooks;
+    fn with_hooks<F, R>(&mut self, f: F) -> R
+            where F: FnOnce(&mut NoHooks) -> R {
+        f(&mut NoHooks)
+    }
+}
+
+impl<'d> ReadFragment<'d> for Scratch<'d> {
+    type WF = Scratch<'d>;
+    fn with_world<F, R>(&mut self, f: F) -> R
+            where F: FnOnce(&mut Scratch<'d>) -> R {
+        f(self)
+    }
+
+    type H = Scratch<'d>;
+    fn with_hooks<F, R>(&mut self, f: F) -> R
+            where F: FnOnce(&mut Scratch<'d>) -> R {
+        f(self)
+    }
+}
+
+impl<'d> ReadHooks for Scratch<'d> {
+    fn post_read_world<R: Reader>(&mut self,
+                                  reader: &mut R) -> Result<()> {
+        let v = try!(extra::read_value(reader, self));
+        self.extras.world = v;
+        Ok(())
+    }
+
+    fn post_read_client<R: Reader>(&mut self,
+                                   reader: &mut R,
+                                   cid: ClientId) -> Result<()> {
+        self.read_extra(reader, cid)
+    }
+
+    fn post_read_entity<R: Reader>(&mut self,
+                                   reader: &mut R,
+                                   eid: EntityId) -> Result<()> {
+        self.read_extra(reader, eid)
+    }
+
+    fn post_read_inventory<R: Reader>(&mut self,
+                                      reader: &mut R,
+                                      iid: InventoryId) -> Result<()> {
+        self.read_extra(reader, iid)
+    }
+
+    fn post_read_plane<R: Reader>(&mut self,
+                                  reader: &mut R,
+                                  pid: PlaneId) -> Result<()> {
+        self.read_extra(reader, pid)
+    }
+
+    // Terrain chunks have no script data.
+
+    fn post_read_structure<R: Reader>(&mut self,
+                                      reader: &mut R,
+                                      sid: StructureId,
+                                      _flags: StructureFlags) -> Result<()> {
+        self.read_extra(reader, sid)
+    }
+}
+
+
+struct ExtraWriter<'a> {
+    extras: &'a Extras,
+}
+
+impl<'a> ExtraWriter<'a> {
+    fn write_extra<W: Writer, T: ToAnyId>(&self, w: &mut W, id: T) -> Result<()> {
+        match self.extras.objs.get(&id.to_any_id()) {
+            Some(v) => extra::write_value(w, v),
+            None => extra::write_value(w, &Value::Nil),
+        }
+    }
+}
+
+impl<'a> WriteHooks for ExtraWriter<'a> {
+    fn post_write_world<W: Writer>(&mut self,
+                                   writer: &mut W,
+                                   _w: &World) -> Result<()> {
+        extra::write_value(writer, &self.extras.world)
+    }
+
+    fn post_write_client<W: Writer>(&mut self,
+                                    writer: &mut W,
+                                    c: &ObjectRef<Client>) -> Result<()> {
+        self.write_extra(writer, c.id())
+    }
+
+    fn post_write_entity<W: Writer>(&mut self,
+                                    writer: &mut W,
+                                    e: &ObjectRef<Entity>) -> Result<()> {
+        self.write_extra(writer, e.id())
+    }
+
+    fn post_write_inventory<W: Writer>(&mut self,
+                                       writer: &mut W,
+                                       i: &ObjectRef<Inventory>) -> Result<()> {
+        self.write_extra(writer, i.id())
+    }
+
+    fn post_write_plane<W: Writer>(&mut self,
+                                   writer: &mut W,
+                                   p: &ObjectRef<Plane>) -> Result<()> {
+        self.write_extra(writer, p.id())
+    }
+
+    fn post_write_structure<W: Writer>(&mut self,
+                                       writer: &mut W,
+                                       s: &ObjectRef<Structure>) -> Result<()> {
+        self.write_extra(writer, s.id())
+    }
+}
+
+
+// Export
+
+fn raw_id(id: AnyId) -> u64 {
+    match id {
+        AnyId::Client(id) => id.unwrap() as u64,
+        AnyId::Entity(id) => id.unwrap() as u64,
+        AnyId::Inventory(id) => id.unwrap() as u64,
+        AnyId::Plane(id) => id.unwrap() as u64,
+        AnyId::TerrainChunk(id) => id.unwrap() as u64,
+        AnyId::Structure(id) => id.unwrap() as u64,
+    }
+}
+
+fn id_json<T: ToAnyId>(id: T) -> Json {
+    Json::U64(raw_id(id.to_any_id()))
+}
+
+fn v3_json(v: V3) -> Json {
+    Json::Array(vec![Json::I64(v.x as i64),
+                     Json::I64(v.y as i64),
+                     Json::I64(v.z as i64)])
+}
+
+fn tagged(tag: &str, x: Json) -> Json {
+    let mut obj = BTreeMap::new();
+    obj.insert(tag.to_owned(), x);
+    Json::Object(obj)
+}
+
+fn set(obj: &mut json::Object, key: &str, x: Json) {
+    obj.insert(key.to_owned(), x);
+}
+
+/// Start the JSON object for `o`, with its `id` and `stable_id` fields filled in.
+fn object_header<O>(o: &ObjectRef<O>) -> json::Object
+        where O: Object+IntrusiveStableId,
+              <O as Object>::Id: ToAnyId {
+    let mut obj = BTreeMap::new();
+    set(&mut obj, "id", id_json(o.id()));
+    set(&mut obj, "stable_id", Json::U64(o.get_stable_id()));
+    obj
+}
+
+/// Collect objects in order of ID, so that exports of the same file come out the same.
+fn sorted<'a, 'd, O, I>(iter: I) -> Vec<ObjectRef<'a, 'd, O>>
+        where O: Object,
+              <O as Object>::Id: ToAnyId,
+              I: Iterator<Item=ObjectRef<'a, 'd, O>> {
+    let mut v = iter.collect::<Vec<_>>();
+    v.sort_by(|a, b| raw_id(a.id().to_any_id()).cmp(&raw_id(b.id().to_any_id())));
+    v
+}
+
+fn value_to_json(v: &Value) -> Json {
+    match *v {
+        Value::Nil => Json::Null,
+        Value::Bool(b) => Json::Boolean(b),
+        Value::Int(i) => Json::I64(i as i64),
+        Value::Float(f) => Json::F64(f),
+        Value::Str(ref s) => Json::String(s.clone()),
+        Value::Table(ref entries) => {
+            let entries = entries.iter().map(|&(ref k, ref v)| {
+                Json::Array(vec![value_to_json(k), value_to_json(v)])
+            }).collect();
+            tagged("table", Json::Array(entries))
+        },
+
+        Value::World => tagged("world", Json::Null),
+        Value::Client(id) => tagged("client", id_json(id)),
+        Value::Entity(id) => tagged("entity", id_json(id)),
+        Value::Inventory(id) => tagged("inventory", id_json(id)),
+        Value::Structure(id) => tagged("structure", id_json(id)),
+
+        Value::StableClient(id) => tagged("stable_client", Json::U64(id)),
+        Value::StableEntity(id) => tagged("stable_entity", Json::U64(id)),
+        Value::StableInventory(id) => tagged("stable_inventory", Json::U64(id)),
+        Value::StablePlane(id) => tagged("stable_plane", Json::U64(id)),
+        Value::StableStructure(id) => tagged("stable_structure", Json::U64(id)),
+
+        Value::V3(v) => tagged("v3", v3_json(v)),
+        Value::TimeU(t) => tagged("time", Json::I64(t)),
+    }
+}
+
+fn world_to_json(x: &Extras, w: &World) -> Json {
+    let mut next_ids = BTreeMap::new();
+    set(&mut next_ids, "client", Json::U64(w.clients.next_id()));
+    set(&mut next_ids, "entity", Json::U64(w.entities.next_id()));
+    set(&mut next_ids, "inventory", Json::U64(w.inventories.next_id()));
+    set(&mut next_ids, "plane", Json::U64(w.planes.next_id()));
+    set(&mut next_ids, "terrain_chunk", Json::U64(w.terrain_chunks.next_id()));
+    set(&mut next_ids, "structure", Json::U64(w.structures.next_id()));
+
+    let es = sorted(w.entities().filter(|e| e.attachment() == EntityAttachment::World));
+    let is = sorted(w.inventories().filter(|i| i.attachment() == InventoryAttachment::World));
+
+    let mut obj = BTreeMap::new();
+    set(&mut obj, "next_ids", Json::Object(next_ids));
+    set(&mut obj, "extra", value_to_json(&x.world));
+    set(&mut obj, "entities", Json::Array(es.iter().map(|e| entity_to_json(x, e)).collect()));
+    set(&mut obj, "inventories",
+        Json::Array(is.iter().map(|i| inventory_to_json(x, i)).collect()));
+    Json::Object(obj)
+}
+
+fn client_to_json(x: &Extras, c: &ObjectRef<Client>) -> Json {
+    let es = sorted(c.child_entities());
+    let is = sorted(c.child_inventories());
+
+    let mut obj = object_header(c);
+    set(&mut obj, "pawn", c.pawn.map_or(Json::Null, id_json));
+    set(&mut obj, "extra", x.get(c.id()));
+    set(&mut obj, "entities", Json::Array(es.iter().map(|e| entity_to_json(x, e)).collect()));
+    set(&mut obj, "inventories",
+        Json::Array(is.iter().map(|i| inventory_to_json(x, i)).collect()));
+    Json::Object(obj)
+}
+
+fn entity_to_json(x: &Extras, e: &ObjectRef<Entity>) -> Json {
+    let is = sorted(e.child_inventories());
+    let m = &e.motion;
+
+    let mut obj = object_header(e);
+    set(&mut obj, "stable_plane", Json::U64(e.stable_plane.unwrap()));
+    set(&mut obj, "start_pos", v3_json(m.start_pos));
+    set(&mut obj, "end_pos", v3_json(m.end_pos));
+    set(&mut obj, "start_time", Json::I64(m.start_time));
+    set(&mut obj, "duration", Json::U64(m.duration as u64));
+    set(&mut obj, "anim", Json::U64(e.anim as u64));
+    set(&mut obj, "facing", v3_json(e.facing));
+    set(&mut obj, "target_velocity", v3_json(e.target_velocity));
+    set(&mut obj, "appearance", Json::U64(e.appearance as u64));
+    set(&mut obj, "behavior", behavior_to_json(e.behavior));
+    set(&mut obj, "extra", x.get(e.id()));
+    set(&mut obj, "inventories",
+        Json::Array(is.iter().map(|i| inventory_to_json(x, i)).collect()));
+    Json::Object(obj)
+}
+
+fn behavior_to_json(b: Behavior) -> Json {
+    let mut obj = BTreeMap::new();
+    match b {
+        Behavior::Idle => {
+            set(&mut obj, "kind", Json::String("idle".to_owned()));
+        },
+        Behavior::WalkTo(target) => {
+            set(&mut obj, "kind", Json::String("walk_to".to_owned()));
+            set(&mut obj, "target", v3_json(target));
+        },
+        Behavior::Wander { home, radius } => {
+            set(&mut obj, "kind", Json::String("wander".to_owned()));
+            set(&mut obj, "home", v3_json(home));
+            set(&mut obj, "radius", Json::I64(radius as i64));
+        },
+    }
+    Json::Object(obj)
+}
+
+fn inventory_to_json(x: &Extras, i: &ObjectRef<Inventory>) -> Json {
+    let item_data = &i.world().data().item_data;
+    let contents = i.contents.iter().map(|slot| {
+        let (item_id, key, n) = match *slot {
+            Item::Empty => return Json::Null,
+            Item::Bulk(count, item_id) => (item_id, "count", count),
+            Item::Special(script_id, item_id) => (item_id, "special", script_id),
+        };
+        let mut obj = BTreeMap::new();
+        set(&mut obj, "item", Json::String(item_data.name(item_id).to_owned()));
+        set(&mut obj, key, Json::U64(n as u64));
+        Json::Object(obj)
+    }).collect();
+
+    let mut obj = object_header(i);
+    set(&mut obj, "contents", Json::Array(contents));
+    set(&mut obj, "extra", x.get(i.id()));
+    Json::Object(obj)
+}
+
+fn plane_to_json(x: &Extras, p: &ObjectRef<Plane>) -> Json {
+    let mut chunks = p.saved_chunks.iter().collect::<Vec<_>>();
+    chunks.sort_by(|&(a, _), &(b, _)| (a.x, a.y).cmp(&(b.x, b.y)));
+    let chunks = chunks.into_iter().map(|(&cpos, &stable_tcid)| {
+        let mut obj = BTreeMap::new();
+        set(&mut obj, "cpos", Json::Array(vec![Json::I64(cpos.x as i64),
+                                               Json::I64(cpos.y as i64)]));
+        set(&mut obj, "stable_id", Json::U64(stable_tcid.unwrap()));
+        Json::Object(obj)
+    }).collect();
+
+    let mut obj = object_header(p);
+    set(&mut obj, "name", Json::String(p.name.clone()));
+    set(&mut obj, "saved_chunks", Json::Array(chunks));
+    set(&mut obj, "env", plane_env_to_json(p.env));
+    set(&mut obj, "gen", gen_config_to_json(p.gen_config()));
+    set(&mut obj, "extra", x.get(p.id()));
+    Json::Object(obj)
+}
+
+fn plane_env_to_json(env: PlaneEnv) -> Json {
+    let opt = |x: Option<u32>| x.map_or(Json::Null, |x| Json::U64(x as u64));
+    let mut obj = BTreeMap::new();
+    set(&mut obj, "cycle_ms", Json::U64(env.cycle_ms as u64));
+    set(&mut obj, "fixed_time", opt(env.fixed_time));
+    set(&mut obj, "ambient", opt(env.ambient));
+    Json::Object(obj)
+}
+
+fn gen_config_to_json(gen: &GenConfig) -> Json {
+    let params = gen.params.iter()
+                    .map(|(k, v)| (k.clone(), Json::String(v.clone())))
+                    .collect();
+    let mut obj = BTreeMap::new();
+    set(&mut obj, "kind", Json::String(gen.kind.clone()));
+    set(&mut obj, "params", Json::Object(params));
+    Json::Object(obj)
+}
+
+fn terrain_chunk_to_json(x: &Extras, tc: &ObjectRef<TerrainChunk>) -> Json {
+    let block_data = &tc.world().data().block_data;
+
+    // Blocks are stored as indices into a list of block names, in order of first appearance.
+    let mut names = Vec::new();
+    let mut name_idx = HashMap::new();
+    let mut blocks = Vec::with_capacity(tc.blocks.len());
+    for &b in tc.blocks.iter() {
+        let idx = *name_idx.entry(b).or_insert_with(|| {
+            names.push(Json::String(block_data.name(b).to_owned()));
+            names.len() - 1
+        });
+        blocks.push(Json::U64(idx as u64));
+    }
+
+    let base = tc.base_pos();
+    let ss = sorted(tc.child_structures());
+
+    let mut obj = object_header(tc);
+    set(&mut obj, "flags", Json::U64(tc.flags.bits() as u64));
+    set(&mut obj, "block_names", Json::Array(names));
+    set(&mut obj, "blocks", Json::Array(blocks));
+    set(&mut obj, "structures",
+        Json::Array(ss.iter().map(|s| structure_to_json(x, s, base)).collect()));
+    Json::Object(obj)
+}
+
+fn structure_to_json(x: &Extras, s: &ObjectRef<Structure>, base: V3) -> Json {
+    let data = s.world().data();
+    let jobs = s.crafting_jobs.iter().map(|job| {
+        let mut obj = BTreeMap::new();
+        set(&mut obj, "recipe", Json::String(data.recipes.recipe(job.recipe).name.clone()));
+        set(&mut obj, "count", Json::U64(job.count as u64));
+        set(&mut obj, "end_time", Json::I64(job.end_time));
+        Json::Object(obj)
+    }).collect();
+    let is = sorted(s.child_inventories());
+
+    let mut obj = object_header(s);
+    set(&mut obj, "pos", v3_json(s.pos - base));
+    set(&mut obj, "template",
+        Json::String(data.structure_templates.template(s.template).name.clone()));
+    set(&mut obj, "flags", Json::U64(s.flags.bits() as u64));
+    set(&mut obj, "crafting_jobs", Json::Array(jobs));
+    set(&mut obj, "extra", x.get(s.id()));
+    set(&mut obj, "inventories",
+        Json::Array(is.iter().map(|i| inventory_to_json(x, i)).collect()));
+    Json::Object(obj)
+}
+
+
+// Import
+
+fn field<'a>(j: &'a Json, key: &str) -> StringResult<&'a Json> {
+    match j.find(key) {
+        Some(x) => Ok(x),
+        None => fail!("missing field \"{}\"", key),
+    }
+}
+
+fn get_array<'a>(j: &'a Json, key: &str) -> StringResult<&'a json::Array> {
+    match try!(field(j, key)).as_array() {
+        Some(x) => Ok(x),
+        None => fail!("expected array for field \"{}\"", key),
+    }
+}
+
+fn get_str<'a>(j: &'a Json, key: &str) -> StringResult<&'a str> {
+    match try!(field(j, key)).as_string() {
+        Some(x) => Ok(x),
+        None => fail!("expected string for field \"{}\"", key),
+    }
+}
+
+fn get_u64(j: &Json, key: &str) -> StringResult<u64> {
+    match try!(field(j, key)).as_u64() {
+        Some(x) => Ok(x),
+        None => fail!("expected unsigned integer for field \"{}\"", key),
+    }
+}
+
+fn get_i64(j: &Json, key: &str) -> StringResult<i64> {
+    match try!(field(j, key)).as_i64() {
+        Some(x) => Ok(x),
+        None => fail!("expected integer for field \"{}\"", key),
+    }
+}
+
+fn get_opt_u64(j: &Json, key: &str) -> StringResult<Option<u64>> {
+    match j.find(key) {
+        None | Some(&Json::Null) => Ok(None),
+        Some(_) => Ok(Some(try!(get_u64(j, key)))),
+    }
+}
+
+fn get_opt_u32(j: &Json, key: &str) -> StringResult<Option<u32>> {
+    match j.find(key) {
+        None | Some(&Json::Null) => Ok(None),
+        Some(_) => Ok(Some(try!(get_u32(j, key)))),
+    }
+}
+
+fn get_u8(j: &Json, key: &str) -> StringResult<u8> {
+    match try!(get_u64(j, key)).to_u8() {
+        Some(x) => Ok(x),
+        None => fail!("value out of range for field \"{}\"", key),
+    }
+}
+
+fn get_u16(j: &Json, key: &str) -> StringResult<u16> {
+    match try!(get_u64(j, key)).to_u16() {
+        Some(x) => Ok(x),
+        None => fail!("value out of range for field \"{}\"", key),
+    }
+}
+
+fn get_u32(j: &Json, key: &str) -> StringResult<u32> {
+    match try!(get_u64(j, key)).to_u32() {
+        Some(x) => Ok(x),
+        None => fail!("value out of range for field \"{}\"", key),
+    }
+}
+
+/// Get the object ID from a tagged value like `{"entity": 3}` in extra data.
+fn tagged_id(tag: &str, x: &Json) -> StringResult<u64> {
+    match x.as_u64() {
+        Some(id) => Ok(id),
+        None => fail!("expected unsigned integer for \"{}\" in extra data", tag),
+    }
+}
+
+fn coords(j: &Json, len: usize) -> Option<Vec<i32>> {
+    let xs = unwrap_or!(j.as_array(), return None);
+    if xs.len() != len {
+        return None;
+    }
+    let mut result = Vec::with_capacity(len);
+    for x in xs.iter() {
+        result.push(unwrap_or!(x.as_i64().and_then(|x| x.to_i32()), return None));
+    }
+    Some(result)
+}
+
+fn to_v3(j: &Json) -> Option<V3> {
+    coords(j, 3).map(|c| V3::new(c[0], c[1], c[2]))
+}
+
+fn get_v3(j: &Json, key: &str) -> StringResult<V3> {
+    match to_v3(try!(field(j, key))) {
+        Some(x) => Ok(x),
+        None => fail!("expected [x, y, z] for field \"{}\"", key),
+    }
+}
+
+fn get_behavior(j: &Json, key: &str) -> StringResult<Behavior> {
+    let b = try!(field(j, key));
+    let kind = try!(get_str(b, "kind"));
+    match kind {
+        "idle" => Ok(Behavior::Idle),
+        "walk_to" => Ok(Behavior::WalkTo(try!(get_v3(b, "target")))),
+        "wander" => {
+            let radius = unwrap!(try!(get_i64(b, "radius")).to_i32(),
+                                 "wander radius is out of range");
+            Ok(Behavior::Wander {
+                home: try!(get_v3(b, "home")),
+                radius: radius,
+            })
+        },
+        _ => fail!("unknown behavior \"{}\"", kind),
+    }
+}
+
+fn get_plane_env(j: &Json, key: &str) -> StringResult<PlaneEnv> {
+    let env = try!(field(j, key));
+    Ok(PlaneEnv {
+        cycle_ms: try!(get_u32(env, "cycle_ms")),
+        fixed_time: try!(get_opt_u32(env, "fixed_time")),
+        ambient: try!(get_opt_u32(env, "ambient")),
+    })
+}
+
+fn get_gen_config(j: &Json, key: &str) -> StringResult<GenConfig> {
+    let gen = try!(field(j, key));
+    let mut config = GenConfig::new(try!(get_str(gen, "kind")));
+    let params = match try!(field(gen, "params")).as_object() {
+        Some(x) => x,
+        None => fail!("expected object for field \"params\""),
+    };
+    for (k, v) in params.iter() {
+        let v = unwrap!(v.as_string(), "expected string for generator parameter");
+        config.params.insert(k.clone(), v.to_owned());
+    }
+    Ok(config)
+}
+
+fn get_v2(j: &Json, key: &str) -> StringResult<V2> {
+    match coords(try!(field(j, key)), 2) {
+        Some(c) => Ok(V2::new(c[0], c[1])),
+        None => fail!("expected [x, y] for field \"{}\"", key),
+    }
+}
+
+struct Importer<'a, 'd> {
+    s: Scratch<'d>,
+    /// Maps the object kind and `id` field from the JSON to the object created for it.
+    ids: HashMap<(&'static str, u64), AnyId>,
+    /// Extra data is converted only after all objects exist, since it may refer to any of them.
+    /// `None` means the extra data for the world.
+    pending_extras: Vec<(Option<AnyId>, &'a Json)>,
+}
+
+impl<'a, 'd> Importer<'a, 'd> {
+    fn new(data: &'d Data) -> Importer<'a, 'd> {
+        Importer {
+            s: Scratch::new(data),
+            ids: HashMap::new(),
+            pending_extras: Vec::new(),
+        }
+    }
+
+    fn data(&self) -> &'d Data {
+        self.s.world.data()
+    }
+
+    fn define<T: ToAnyId>(&mut self, kind: &'static str, j: &Json, id: T) -> StringResult<()> {
+        let raw = try!(get_u64(j, "id"));
+        if self.ids.insert((kind, raw), id.to_any_id()).is_some() {
+            fail!("{} id {} is used more than once", kind, raw);
+        }
+        Ok(())
+    }
+
+    fn lookup<T: ReadId>(&self, kind: &'static str, raw: u64) -> StringResult<T> {
+        match self.ids.get(&(kind, raw)) {
+            Some(&id) => Ok(try!(<T as ReadId>::from_any_id(id))),
+            None => fail!("reference to undefined {} id {}", kind, raw),
+        }
+    }
+
+    fn add_extra(&mut self, id: Option<AnyId>, j: &'a Json) {
+        if let Some(x) = j.find("extra") {
+            self.pending_extras.push((id, x));
+        }
+    }
+
+    fn finish(self) -> StringResult<Scratch<'d>> {
+        let mut extras = Extras::new();
+        for &(id, j) in self.pending_extras.iter() {
+            let v = try!(self.value_from_json(j));
+            match id {
+                Some(id) => { extras.objs.insert(id, v); },
+                None => extras.world = v,
+            }
+        }
+
+        let mut s = self.s;
+        s.extras = extras;
+        Ok(s)
+    }
+
+    fn value_from_json(&self, j: &Json) -> StringResult<Value> {
+        let v = match *j {
+            Json::Null => Value::Nil,
+            Json::Boolean(b) => Value::Bool(b),
+            Json::I64(i) => i.to_i32().map_or(Value::Float(i as f64), Value::Int),
+            Json::U64(i) => i.to_i32().map_or(Value::Float(i as f64), Value::Int),
+            Json::F64(f) => Value::Float(f),
+            Json::String(ref s) => Value::Str(s.clone()),
+            Json::Array(_) => fail!("extra data can't contain a bare array; use \"table\""),
+            Json::Object(ref obj) => {
+                if obj.len() != 1 {
+                    fail!("tagged value in extra data must have exactly one key");
+                }
+                let (tag, x) = obj.iter().next().unwrap();
+                let id = || tagged_id(tag, x);
+                match &**tag {
+                    "table" => {
+                        let entries = unwrap!(x.as_array(),
+                                              "expected array for \"table\" in extra data");
+                        let mut result = Vec::with_capacity(entries.len());
+                        for e in entries.iter() {
+                            let kv = unwrap!(e.as_array(),
+                                             "expected [key, value] in extra data table");
+                            if kv.len() != 2 {
+                                fail!("expected [key, value] in extra data table");
+                            }
+                            result.push((try!(self.value_from_json(&kv[0])),
+                                         try!(self.value_from_json(&kv[1]))));
+                        }
+                        Value::Table(result)
+                    },
+
+                    "world" => Value::World,
+                    "client" => Value::Client(try!(self.lookup("client", try!(id())))),
+                    "entity" => Value::Entity(try!(self.lookup("entity", try!(id())))),
+                    "inventory" => Value::Inventory(try!(self.lookup("inventory", try!(id())))),
+                    "structure" => Value::Structure(try!(self.lookup("structure", try!(id())))),
+
+                    "stable_client" => Value::StableClient(try!(id())),
+                    "stable_entity" => Value::StableEntity(try!(id())),
+                    "stable_inventory" => Value::StableInventory(try!(id())),
+                    "stable_plane" => Value::StablePlane(try!(id())),
+                    "stable_structure" => Value::StableStructure(try!(id())),
+
+                    "v3" => Value::V3(unwrap!(to_v3(x), "expected [x, y, z] for \"v3\"")),
+                    "time" => Value::TimeU(unwrap!(x.as_i64(), "expected integer for \"time\"")),
+                    _ => fail!("unrecognized tag \"{}\" in extra data", tag),
+                }
+            },
+        };
+        Ok(v)
+    }
+
+
+    fn import_world(&mut self, j: &'a Json) -> StringResult<()> {
+        let next = try!(field(j, "next_ids"));
+        let next_ids = [try!(get_u64(next, "client")),
+                        try!(get_u64(next, "entity")),
+                        try!(get_u64(next, "inventory")),
+                        try!(get_u64(next, "plane")),
+                        try!(get_u64(next, "terrain_chunk")),
+                        try!(get_u64(next, "structure"))];
+        if next_ids.iter().any(|&n| n == 0) {
+            fail!("next_ids must be nonzero");
+        }
+        {
+            let w = &mut self.s.world;
+            w.clients.set_next_id(next_ids[0]);
+            w.entities.set_next_id(next_ids[1]);
+            w.inventories.set_next_id(next_ids[2]);
+            w.planes.set_next_id(next_ids[3]);
+            w.terrain_chunks.set_next_id(next_ids[4]);
+            w.structures.set_next_id(next_ids[5]);
+        }
+        self.add_extra(None, j);
+
+        for ej in try!(get_array(j, "entities")).iter() {
+            try!(self.import_entity(ej));
+        }
+        for ij in try!(get_array(j, "inventories")).iter() {
+            try!(self.import_inventory(ij));
+        }
+        Ok(())
+    }
+
+    fn import_client(&mut self, j: &'a Json) -> StringResult<ClientId> {
+        let cid = ops::client::create_unchecked(&mut self.s);
+        try!(self.s.world.clients.set_stable_id(cid, try!(get_u64(j, "stable_id"))));
+        try!(self.define("client", j, cid));
+        self.add_extra(Some(cid.to_any_id()), j);
+
+        for ej in try!(get_array(j, "entities")).iter() {
+            let eid = try!(self.import_entity(ej));
+            try!(ops::entity::attach(&mut self.s, eid, EntityAttachment::Client(cid)));
+        }
+        for ij in try!(get_array(j, "inventories")).iter() {
+            let iid = try!(self.import_inventory(ij));
+            try!(ops::inventory::attach(&mut self.s, iid, InventoryAttachment::Client(cid)));
+        }
+
+        if let Some(raw) = try!(get_opt_u64(j, "pawn")) {
+            let eid = try!(self.lookup("entity", raw));
+            if self.s.world.entities[eid].attachment != EntityAttachment::Client(cid) {
+                fail!("client pawn must be one of the client's entities");
+            }
+            self.s.world.clients[cid].pawn = Some(eid);
+        }
+
+        Ok(cid)
+    }
+
+    fn import_entity(&mut self, j: &'a Json) -> StringResult<EntityId> {
+        let eid = ops::entity::create_unchecked(&mut self.s);
+        try!(self.s.world.entities.set_stable_id(eid, try!(get_u64(j, "stable_id"))));
+        try!(self.define("entity", j, eid));
+
+        {
+            let e = &mut self.s.world.entities[eid];
+            e.stable_plane = Stable::new(try!(get_u64(j, "stable_plane")));
+            e.motion.start_pos = try!(get_v3(j, "start_pos"));
+            e.motion.end_pos = try!(get_v3(j, "end_pos"));
+            e.motion.start_time = try!(get_i64(j, "start_time"));
+            e.motion.duration = try!(get_u16(j, "duration"));
+            e.anim = try!(get_u16(j, "anim"));
+            e.facing = try!(get_v3(j, "facing"));
+            e.target_velocity = try!(get_v3(j, "target_velocity"));
+            e.appearance = try!(get_u32(j, "appearance"));
+            e.behavior = try!(get_behavior(j, "behavior"));
+        }
+        ops::entity::post_init(&mut self.s, eid);
+        self.add_extra(Some(eid.to_any_id()), j);
+
+        for ij in try!(get_array(j, "inventories")).iter() {
+            let iid = try!(self.import_inventory(ij));
+            try!(ops::inventory::attach(&mut self.s, iid, InventoryAttachment::Entity(eid)));
+        }
+
+        Ok(eid)
+    }
+
+    fn import_inventory(&mut self, j: &'a Json) -> StringResult<InventoryId> {
+        let contents = try!(get_array(j, "contents"));
+        let size = unwrap!(contents.len().to_u8(), "inventory has too many slots");
+        let iid = ops::inventory::create_unchecked(&mut self.s, size);
+        try!(self.s.world.inventories.set_stable_id(iid, try!(get_u64(j, "stable_id"))));
+        try!(self.define("inventory", j, iid));
+
+        for (idx, slot) in contents.iter().enumerate() {
+            let item = try!(self.import_item(slot));
+            self.s.world.inventories[iid].contents[idx] = item;
+        }
+        self.add_extra(Some(iid.to_any_id()), j);
+
+        Ok(iid)
+    }
+
+    fn import_item(&self, j: &Json) -> StringResult<Item> {
+        if j.is_null() {
+            return Ok(Item::Empty);
+        }
+
+        let name = try!(get_str(j, "item"));
+        let item_id = match self.data().item_data.find_id(name) {
+            Some(id) => id,
+            None => fail!("undefined item \"{}\"", name),
+        };
+        if j.find("special").is_some() {
+            Ok(Item::Special(try!(get_u8(j, "special")), item_id))
+        } else {
+            let count = try!(get_u8(j, "count"));
+            if count == 0 {
+                fail!("item count must be nonzero");
+            }
+            Ok(Item::Bulk(count, item_id))
+        }
+    }
+
+    fn import_plane(&mut self, j: &'a Json) -> StringResult<PlaneId> {
+        let pid = ops::plane::create_unchecked(&mut self.s);
+        try!(self.s.world.planes.set_stable_id(pid, try!(get_u64(j, "stable_id"))));
+        try!(self.define("plane", j, pid));
+
+        {
+            let p = &mut self.s.world.planes[pid];
+            p.name = try!(get_str(j, "name")).to_owned();
+            for cj in try!(get_array(j, "saved_chunks")).iter() {
+                let cpos = try!(get_v2(cj, "cpos"));
+                let stable_tcid = Stable::new(try!(get_u64(cj, "stable_id")));
+                p.saved_chunks.insert(cpos, stable_tcid);
+            }
+            p.env = try!(get_plane_env(j, "env"));
+            p.gen = try!(get_gen_config(j, "gen"));
+        }
+        ops::plane::post_init(&mut self.s, pid);
+        self.add_extra(Some(pid.to_any_id()), j);
+
+        Ok(pid)
+    }
+
+    fn import_terrain_chunk(&mut self,
+                            j: &'a Json,
+                            pid: PlaneId) -> StringResult<TerrainChunkId> {
+        let block_data = &self.data().block_data;
+        let mut block_ids = Vec::new();
+        for nj in try!(get_array(j, "block_names")).iter() {
+            let name = unwrap!(nj.as_string(), "expected string in \"block_names\"");
+            match block_data.find_id(name) {
+                Some(id) => block_ids.push(id),
+                None => fail!("undefined block \"{}\"", name),
+            }
+        }
+        let blocks = try!(get_array(j, "blocks"));
+        if blocks.len() != CHUNK_TOTAL {
+            fail!("terrain chunk must have exactly {} blocks", CHUNK_TOTAL);
+        }
+
+        let tcid = ops::terrain_chunk::create_unchecked(&mut self.s);
+        try!(self.s.world.terrain_chunks.set_stable_id(tcid, try!(get_u64(j, "stable_id"))));
+        try!(self.define("terrain_chunk", j, tcid));
+
+        {
+            let tc = &mut self.s.world.terrain_chunks[tcid];
+            tc.plane = pid;
+            tc.cpos = scalar(0);
+            tc.flags = TerrainChunkFlags::from_bits_truncate(try!(get_u32(j, "flags")));
+            for (idx, bj) in blocks.iter().enumerate() {
+                let name_idx = unwrap!(bj.as_u64().and_then(|x| x.to_usize()),
+                                       "expected unsigned integer in \"blocks\"");
+                tc.blocks[idx] = *unwrap!(block_ids.get(name_idx),
+                                          "block index is out of range of \"block_names\"");
+            }
+        }
+        ops::terrain_chunk::post_init(&mut self.s, tcid);
+
+        for sj in try!(get_array(j, "structures")).iter() {
+            let sid = try!(self.import_structure(sj, pid));
+            try!(ops::structure::attach(&mut self.s, sid, StructureAttachment::Chunk));
+        }
+
+        Ok(tcid)
+    }
+
+    fn import_structure(&mut self, j: &'a Json, pid: PlaneId) -> StringResult<StructureId> {
+        let data = self.data();
+        let template_name = try!(get_str(j, "template"));
+        let template_id = match data.structure_templates.find_id(template_name) {
+            Some(id) => id,
+            None => fail!("undefined structure template \"{}\"", template_name),
+        };
+
+        let sid = ops::structure::create_unchecked(&mut self.s);
+        try!(self.s.world.structures.set_stable_id(sid, try!(get_u64(j, "stable_id"))));
+        try!(self.define("structure", j, sid));
+
+        {
+            let s = &mut self.s.world.structures[sid];
+            s.plane = pid;
+            s.pos = try!(get_v3(j, "pos"));
+            s.template = template_id;
+            s.flags = StructureFlags::from_bits_truncate(try!(get_u32(j, "flags")));
+            for job in try!(get_array(j, "crafting_jobs")).iter() {
+                let recipe_name = try!(get_str(job, "recipe"));
+                let recipe_id = match data.recipes.find_id(recipe_name) {
+                    Some(id) => id,
+                    None => fail!("undefined recipe \"{}\"", recipe_name),
+                };
+                s.crafting_jobs.push_back(CraftingJob {
+                    recipe: recipe_id,
+                    count: try!(get_u16(job, "count")),
+                    end_time: try!(get_i64(job, "end_time")),
+                });
+            }
+        }
+        try!(ops::structure::post_init(&mut self.s, sid));
+        self.add_extra(Some(sid.to_any_id()), j);
+
+        for ij in try!(get_array(j, "inventories")).iter() {
+            let iid = try!(self.import_inventory(ij));
+            try!(ops::inventory::attach(&mut self.s, iid, InventoryAttachment::Structure(sid)));
+        }
+
+        Ok(sid)
+    }
+}
@@ -14,6 +14,8 @@ pub mod writer;
 pub mod reader;
 mod object_writer;
 mod object_reader;
+pub mod extra;
+pub mod json;
 
 
 type SaveId = u32;
@@ -62,7 +64,33 @@ impl ToAnyId for StructureId {
 }
 
 
-const CURRENT_VERSION: u32 = 6;
+const CURRENT_VERSION: u32 = 11;
+
+
+/// Load an object from a save file, falling back to the previous generation of the file (see
+/// `Storage::open_backup_file`) if the current one is missing or unreadable.  Returns `None` if
+/// neither generation exists.
+///
+/// When `load` fails, it must leave the world as it found it, so that the backup is loaded into a
+/// clean world.  The `ObjectReader::load_*` methods do this by removing every object they created
+/// (and, for the world file, restoring the ID counters) before returning the error.
+pub fn load_with_backup<R, T, F>(current: Option<R>,
+                                 backup: Option<R>,
+                                 mut load: F) -> Option<Result<T>>
+        where F: FnMut(R) -> Result<T> {
+    if let Some(file) = current {
+        match load(file) {
+            Ok(x) => return Some(Ok(x)),
+            Err(e) => {
+                if backup.is_none() {
+                    return Some(Err(e));
+                }
+                warn!("error reading save file: {}; trying previous version", e);
+            },
+        }
+    }
+    backup.map(|file| load(file))
+}
 
 
 fn padding(len: usize) -> usize {
@@ -6,15 +6,19 @@ use std::raw;
 use std::result;
 
 use libphysics::CHUNK_SIZE;
+use libserver_util::bytes::ReadBytes;
+use libterrain_gen::GenConfig;
 use types::*;
 use util;
 use util::Convert;
+use util::crc32;
 
-use data::Data;
+use data::{Data, Migration};
 use world;
 use world::Item;
 use world::{EntityAttachment, StructureAttachment, InventoryAttachment};
 use world::{TerrainChunkFlags, StructureFlags};
+use world::{Behavior, CraftingJob, PlaneEnv};
 use world::object::*;
 use world::ops;
 
@@ -36,11 +40,18 @@ pub trait Fragment<'d> {
 
 
 pub struct ObjectReader<R: io::Read> {
-    r: ReaderWrapper<R>,
+    src: R,
+    /// The whole file is read into memory, so the checksum can be verified before parsing.
+    r: ReaderWrapper<io::Cursor<Vec<u8>>>,
     file_version: u32,
-    template_map: HashMap<TemplateId, TemplateId>,
-    item_map: HashMap<ItemId, ItemId>,
+    /// Maps template IDs in the file to current ones.  `None` means the template was dropped by a
+    /// migration.
+    template_map: HashMap<TemplateId, Option<TemplateId>>,
+    item_map: HashMap<ItemId, Option<ItemId>>,
     inited_objs: HashSet<AnyId>,
+    /// Descriptions of the migrations applied while reading the current file, logged together
+    /// once loading finishes.
+    applied_migrations: Vec<String>,
 }
 
 #[allow(unused_variables)]
@@ -79,20 +90,44 @@ pub trait ReadHooks {
 impl<R: io::Read> ObjectReader<R> {
     pub fn new(reader: R) -> ObjectReader<R> {
         ObjectReader {
-            r: ReaderWrapper::new(reader),
+            src: reader,
+            r: ReaderWrapper::new(io::Cursor::new(Vec::new())),
             file_version: 0,
             template_map: HashMap::new(),
             item_map: HashMap::new(),
             inited_objs: HashSet::new(),
+            applied_migrations: Vec::new(),
         }
     }
 
     fn read_file_header(&mut self) -> Result<()> {
-        let version: u32 = try!(self.r.read());
-        if version != CURRENT_VERSION && version != 3 {
+        let mut buf = Vec::new();
+        try!(self.src.read_to_end(&mut buf));
+        let mut cursor = io::Cursor::new(buf);
+
+        let version: u32 = try!(cursor.read_bytes());
+        if version != CURRENT_VERSION && version != 10 && version != 9 && version != 8 &&
+                version != 7 && version != 6 && version != 3 {
             fail!("file version does not match current version");
         }
+
+        // Files before version 8 have no checksum.  Truncation still shows up as an error when
+        // reading past the end.
+        if version >= 8 {
+            let len: u32 = try!(cursor.read_bytes());
+            let checksum: u32 = try!(cursor.read_bytes());
+            let start = cursor.position() as usize;
+            let body = &cursor.get_ref()[start..];
+            if body.len() != unwrap!(len.to_usize()) {
+                fail!("file is truncated or has trailing data");
+            }
+            if crc32(body) != checksum {
+                fail!("file checksum does not match");
+            }
+        }
+
         self.file_version = version;
+        self.r = ReaderWrapper::new(cursor);
         Ok(())
     }
 
@@ -104,7 +139,7 @@ impl<R: io::Read> ObjectReader<R> {
         Ok((id, stable_id))
     }
 
-    fn read_template_id(&mut self, data: &Data) -> Result<TemplateId> {
+    fn read_template_id(&mut self, data: &Data) -> Result<Option<TemplateId>> {
         let old_id = try!(self.r.read());
         match self.template_map.get(&old_id) {
             Some(&new_id) => return Ok(new_id),
@@ -118,11 +153,24 @@ impl<R: io::Read> ObjectReader<R> {
                            unwrap!(z.to_i32()));
         let name = try!(self.r.read_str_bytes(unwrap!(name_len.to_usize())));
 
-        let new_id = unwrap!(data.structure_templates.find_id(&*name));
-        let template = data.structure_templates.template(new_id);
-
-        if template.size != size {
-            fail!("template size does not match");
+        let templates = &data.structure_templates;
+        let new_id = try!(resolve_name(&mut self.applied_migrations,
+                                       "structure", &name, data.migrations.structure(&name),
+                                       |n| templates.find_id(n)));
+
+        if let Some(new_id) = new_id {
+            let new_size = templates.template(new_id).size;
+            if new_size != size {
+                if !data.migrations.structure_resize_allowed(&name) {
+                    warn!("structure \"{}\" changed size from {:?} to {:?}; add it to \
+                           \"resized_structures\" in migrations.json to load this file anyway",
+                          name, size, new_size);
+                    fail!("save file refers to a structure whose size has changed");
+                }
+                note_migration(&mut self.applied_migrations,
+                               format!("structure \"{}\" resized from {:?} to {:?}",
+                                       name, size, new_size));
+            }
         }
 
         self.template_map.insert(old_id, new_id);
@@ -201,6 +249,17 @@ impl<R: io::Read> ObjectReader<R> {
                 e.facing = facing;
                 e.target_velocity = target_velocity;
                 e.appearance = appearance;
+
+                if self.file_version > 8 {
+                    let kind: u8 = try!(self.r.read());
+                    let (pos, radius): (V3, i32) = try!(self.r.read());
+                    e.behavior = match kind {
+                        0 => Behavior::Idle,
+                        1 => Behavior::WalkTo(pos),
+                        2 => Behavior::Wander { home: pos, radius: radius },
+                        _ => fail!("bad entity behavior"),
+                    };
+                }
             }
             ops::entity::post_init(wf, eid);
             /*
@@ -246,19 +305,27 @@ impl<R: io::Read> ObjectReader<R> {
                         idx += 1;
                     },
                     1 => {
-                        let new_item_id = *unwrap!(self.item_map.get(&old_item_id));
-                        contents[idx] = Item::Bulk(x, new_item_id);
+                        contents[idx] = match *unwrap!(self.item_map.get(&old_item_id)) {
+                            Some(new_item_id) => Item::Bulk(x, new_item_id),
+                            None => Item::Empty,
+                        };
                         idx += 1;
                     },
                     2 => {
-                        let new_item_id = *unwrap!(self.item_map.get(&old_item_id));
-                        contents[idx] = Item::Special(x, new_item_id);
+                        contents[idx] = match *unwrap!(self.item_map.get(&old_item_id)) {
+                            Some(new_item_id) => Item::Special(x, new_item_id),
+                            None => Item::Empty,
+                        };
                         idx += 1;
                     },
                     255 => {
                         // Name map entry
                         let name = try!(self.r.read_str_bytes(unwrap!(x.to_usize())));
-                        let new_item_id = unwrap!(w.data.item_data.find_id(&*name));
+                        let item_data = &w.data.item_data;
+                        let new_item_id = try!(resolve_name(&mut self.applied_migrations,
+                                                            "item", &name,
+                                                            w.data.migrations.item(&name),
+                                                            |n| item_data.find_id(n)));
                         self.item_map.insert(old_item_id, new_item_id);
                     },
                     _ => fail!("unrecognized entry tag in inventory"),
@@ -292,6 +359,42 @@ impl<R: io::Read> ObjectReader<R> {
                     let stable_tcid = Stable::new(stable_tcid);
                     p.saved_chunks.insert(cpos, stable_tcid);
                 }
+
+                if self.file_version > 9 {
+                    let (cycle_ms, flags, fixed_time, ambient): (u32, u32, u32, u32) =
+                        try!(self.r.read());
+                    p.env = PlaneEnv {
+                        cycle_ms: cycle_ms,
+                        fixed_time: if flags & 2 != 0 { Some(fixed_time) } else { None },
+                        ambient: if flags & 1 != 0 { Some(ambient) } else { None },
+                    };
+                } else if p.name != "Everfree Forest" {
+                    // Older versions had no environment settings.  Only the forest had a
+                    // day/night cycle; every other plane was dark.
+                    p.env = PlaneEnv::dark();
+                }
+
+                if self.file_version > 10 {
+                    p.gen = GenConfig::new(&try!(self.r.read_str()));
+                    let params_count = try!(self.r.read_count());
+                    for _ in 0 .. params_count {
+                        let key = try!(self.r.read_str());
+                        let value = try!(self.r.read_str());
+                        p.gen.params.insert(key, value);
+                    }
+                } else {
+                    // Older versions used the forest generator for the forest and the dungeon
+                    // generator for everything else.
+                    let kind =
+                        if stable_id == STABLE_PLANE_FOREST.val { "forest" }
+                        else if stable_id == STABLE_PLANE_LIMBO.val { "void" }
+                        else { "dungeon" };
+                    p.gen = GenConfig::new(kind);
+                    if kind == "dungeon" {
+                        // Dungeons from older versions have only a single level.
+                        p.gen.params.insert("levels".to_owned(), "1".to_owned());
+                    }
+                }
             }
             ops::plane::post_init(wf, pid);
             Ok(())
@@ -340,9 +443,15 @@ impl<R: io::Read> ObjectReader<R> {
                 for _ in 0..block_id_count {
                     let (old_id, shape, name_len): (u16, u8, u8) = try!(self.r.read());
                     let name = try!(self.r.read_str_bytes(unwrap!(name_len.to_usize())));
-                    let new_id = unwrap!(block_data.find_id(&*name));
-
-                    if block_data.shape(new_id) as u8 != shape {
+                    let migration = data.migrations.block(&name);
+                    let new_id = try!(resolve_name(&mut self.applied_migrations,
+                                                   "block", &name, migration,
+                                                   |n| block_data.find_id(n)));
+                    // Dropped blocks are replaced with empty space.
+                    let new_id = new_id.unwrap_or_else(|| block_data.get_id("empty"));
+
+                    // A replacement is allowed to have a different shape.
+                    if migration.is_none() && block_data.shape(new_id) as u8 != shape {
                         fail!("block shape does not match");
                     }
 
@@ -365,41 +474,82 @@ impl<R: io::Read> ObjectReader<R> {
         let child_structure_count = try!(self.r.read_count());
         let base = cpos.extend(0) * scalar(CHUNK_SIZE);
         for _ in 0..child_structure_count {
-            let sid = try!(self.read_structure(f, plane, base));
-            try!(f.with_world(|wf| ops::structure::attach(wf, sid, StructureAttachment::Chunk)));
+            if let Some(sid) = try!(self.read_structure(f, plane, base)) {
+                try!(f.with_world(|wf| ops::structure::attach(wf, sid,
+                                                              StructureAttachment::Chunk)));
+            }
         }
 
         Ok(tcid)
     }
 
+    /// Read a structure.  Returns `None` if the structure's template was dropped by a migration,
+    /// in which case the structure and its child inventories are discarded.
     fn read_structure<'d, F: Fragment<'d>>(&mut self,
                                            f: &mut F,
                                            plane: PlaneId,
                                            base: V3)
-                                           -> Result<StructureId> {
+                                           -> Result<Option<StructureId>> {
         let (sid, stable_id) = try!(self.read_object_header(f));
 
-        let flags = try!(f.with_world(|wf| -> Result<_> {
-            let flags = {
+        let (flags, dropped) = try!(f.with_world(|wf| -> Result<_> {
+            let (flags, dropped) = {
                 let w = world::Fragment::world_mut(wf);
                 try!(w.structures.set_stable_id(sid, stable_id));
+                let data = w.data;
 
                 let s = &mut w.structures[sid];
 
                 s.plane = plane;
                 s.pos = base + try!(self.r.read());
-                s.template = try!(self.read_template_id(w.data));
+                let dropped = match try!(self.read_template_id(data)) {
+                    Some(tid) => {
+                        s.template = tid;
+                        false
+                    },
+                    None => true,
+                };
 
                 if self.file_version > 3 {
                     s.flags = StructureFlags::from_bits_truncate(try!(self.r.read()));
                 }
 
-                s.flags
+                if self.file_version > 6 {
+                    let job_count = try!(self.r.read_count());
+                    for _ in 0 .. job_count {
+                        let name = try!(self.r.read_str());
+                        let end_time = try!(self.r.read());
+                        let count = try!(self.r.read());
+                        let recipe = try!(resolve_name(&mut self.applied_migrations,
+                                                       "recipe", &name,
+                                                       data.migrations.recipe(&name),
+                                                       |n| data.recipes.find_id(n)));
+                        match recipe {
+                            Some(recipe) if !dropped => {
+                                s.crafting_jobs.push_back(CraftingJob {
+                                    recipe: recipe,
+                                    count: count,
+                                    end_time: end_time,
+                                });
+                            },
+                            _ => {},
+                        }
+                    }
+                }
+
+                (s.flags, dropped)
             };
-            try!(ops::structure::post_init(wf, sid));
-            Ok(flags)
+            if !dropped {
+                try!(ops::structure::post_init(wf, sid));
+            }
+            Ok((flags, dropped))
         }));
 
+        if dropped {
+            try!(self.discard_structure(f, sid));
+            return Ok(None);
+        }
+
         try!(f.with_hooks(|h| h.post_read_structure(&mut self.r, sid, flags)));
 
         let child_inventory_count = try!(self.r.read_count());
@@ -409,7 +559,53 @@ impl<R: io::Read> ObjectReader<R> {
                                                           InventoryAttachment::Structure(sid))));
         }
 
-        Ok(sid)
+        Ok(Some(sid))
+    }
+
+    /// Read and discard the rest of a structure whose template was dropped, then remove the
+    /// partially loaded structure and its child inventories from the world.  The structure was
+    /// never added to the lookup tables, and no `_create` or load hooks run for it.
+    ///
+    /// Fails if anything outside the structure's own record refers to the structure or its
+    /// inventories, since those references would be left dangling.
+    fn discard_structure<'d, F: Fragment<'d>>(&mut self,
+                                              f: &mut F,
+                                              sid: StructureId) -> Result<()> {
+        use world::Fragment;
+
+        // The object header is the only reference allowed before this point.
+        if self.r.ref_count(AnyId::Structure(sid)) > 1 {
+            warn!("structure {:?} has a dropped template, but other objects refer to it", sid);
+            fail!("save file refers to a structure whose template was dropped");
+        }
+        let known_objs = self.r.created_objs().clone();
+
+        // The script extras still have to be read to reach the rest of the record.  Passing empty
+        // flags skips the structure's load hook.
+        try!(f.with_hooks(|h| h.post_read_structure(&mut self.r, sid, StructureFlags::empty())));
+
+        let child_inventory_count = try!(self.r.read_count());
+        let mut iids = Vec::with_capacity(child_inventory_count);
+        for _ in 0..child_inventory_count {
+            let iid = try!(self.read_inventory(f));
+            if known_objs.contains(&AnyId::Inventory(iid)) {
+                warn!("inventory {:?} of dropped structure {:?} is referenced by other objects",
+                      iid, sid);
+                fail!("save file refers to an inventory whose structure was dropped");
+            }
+            iids.push(iid);
+        }
+
+        for iid in iids {
+            unwrap_warn(f.with_hooks(|h| h.cleanup_inventory(iid)));
+            f.with_world(|wf| wf.world_mut().inventories.remove(iid));
+            self.r.drop_created(AnyId::Inventory(iid));
+        }
+
+        unwrap_warn(f.with_hooks(|h| h.cleanup_structure(sid)));
+        f.with_world(|wf| wf.world_mut().structures.remove(sid));
+        self.r.drop_created(AnyId::Structure(sid));
+        Ok(())
     }
 
     fn read_world<'d, F: Fragment<'d>>(&mut self, f: &mut F) -> Result<()> {
@@ -449,6 +645,18 @@ impl<R: io::Read> ObjectReader<R> {
         let result = f(self, frag);
         let result = result.and_then(|x| { try!(self.check_objs()); Ok(x) });
 
+        if self.applied_migrations.len() > 0 {
+            let mut desc = String::new();
+            for m in &self.applied_migrations {
+                if desc.len() > 0 {
+                    desc.push_str(", ");
+                }
+                desc.push_str(m);
+            }
+            info!("save migrations applied: {}", desc);
+            self.applied_migrations.clear();
+        }
+
         if result.is_err() {
             self.cleanup(frag);
         } else {
@@ -477,9 +685,30 @@ impl<R: io::Read> ObjectReader<R> {
     }
 
     pub fn load_world<'d, F: Fragment<'d>>(&mut self, f: &mut F) -> Result<()> {
-        let result =  self.load_object(f, |sr, f| sr.read_world(f));
+        // `read_world` overwrites the ID counters before reading anything else, so put them back
+        // if loading fails.
+        let old_next_ids = f.with_world(|wf| {
+            let w = world::Fragment::world(wf);
+            (w.clients.next_id(),
+             w.entities.next_id(),
+             w.inventories.next_id(),
+             w.planes.next_id(),
+             w.terrain_chunks.next_id(),
+             w.structures.next_id())
+        });
+
+        let result = self.load_object(f, |sr, f| sr.read_world(f));
         if result.is_err() {
             unwrap_warn(f.with_hooks(|h| h.cleanup_world()));
+            f.with_world(|wf| {
+                let w = world::Fragment::world_mut(wf);
+                w.clients.set_next_id(old_next_ids.0);
+                w.entities.set_next_id(old_next_ids.1);
+                w.inventories.set_next_id(old_next_ids.2);
+                w.planes.set_next_id(old_next_ids.3);
+                w.terrain_chunks.set_next_id(old_next_ids.4);
+                w.structures.set_next_id(old_next_ids.5);
+            });
         }
         result
     }
@@ -558,6 +787,49 @@ impl<R: io::Read> ObjectReader<R> {
     }
 }
 
+/// Look up the current ID for a block, item, structure, or recipe name read from a save file,
+/// applying the migration for that name if there is one.  Returns `None` if objects with this
+/// name should be dropped.  Each migration that gets applied is added to `migrations`, as a
+/// record of what was changed.
+fn resolve_name<T, F>(migrations: &mut Vec<String>,
+                      kind: &str,
+                      name: &str,
+                      migration: Option<Migration>,
+                      find: F) -> Result<Option<T>>
+        where F: Fn(&str) -> Option<T> {
+    match migration {
+        Some(Migration::Replace(new_name)) => {
+            note_migration(migrations, format!("{} \"{}\" -> \"{}\"", kind, name, new_name));
+            match find(new_name) {
+                Some(id) => Ok(Some(id)),
+                None => {
+                    warn!("save migration: replacement {} \"{}\" is not defined",
+                          kind, new_name);
+                    fail!("migration refers to an undefined name");
+                },
+            }
+        },
+        Some(Migration::Drop) => {
+            note_migration(migrations, format!("{} \"{}\" dropped", kind, name));
+            Ok(None)
+        },
+        None => match find(name) {
+            Some(id) => Ok(Some(id)),
+            None => {
+                warn!("{} \"{}\" is not defined; add it to migrations.json to load this file",
+                      kind, name);
+                fail!("save file refers to an undefined name");
+            },
+        },
+    }
+}
+
+fn note_migration(migrations: &mut Vec<String>, desc: String) {
+    if !migrations.contains(&desc) {
+        migrations.push(desc);
+    }
+}
+
 fn unwrap_warn<T, E: error::Error>(r: result::Result<T, E>) {
     match r {
         Ok(_) => {},
@@ -4,12 +4,15 @@ use std::mem;
 use std::slice;
 
 use libphysics::CHUNK_SIZE;
+use libserver_util::bytes::WriteBytes;
 use types::*;
 
 use data::Data;
 use util::Convert;
+use util::crc32;
 use util::IntrusiveStableId;
 use world::{World, Client, Entity, Inventory, Plane, TerrainChunk, Structure};
+use world::Behavior;
 use world::Item;
 use world::object::*;
 
@@ -20,7 +23,9 @@ use super::CURRENT_VERSION;
 
 
 pub struct ObjectWriter<W: io::Write, H: WriteHooks> {
-    w: WriterWrapper<W>,
+    out: W,
+    /// The body of the file is buffered, so its length and checksum can be written in the header.
+    w: WriterWrapper<Vec<u8>>,
     hooks: H,
     objects_written: HashSet<AnyId>,
     seen_templates: HashSet<TemplateId>,
@@ -55,7 +60,8 @@ pub trait WriteHooks {
 impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
     pub fn new(writer: W, hooks: H) -> ObjectWriter<W, H> {
         ObjectWriter {
-            w: WriterWrapper::new(writer),
+            out: writer,
+            w: WriterWrapper::new(Vec::new()),
             hooks: hooks,
             objects_written: HashSet::new(),
             seen_templates: HashSet::new(),
@@ -63,8 +69,17 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
         }
     }
 
-    fn write_file_header(&mut self) -> Result<()> {
-        self.w.write(CURRENT_VERSION)
+    /// Get back the underlying writer, for example to commit it after saving.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_file_header(&mut self, body: &[u8]) -> Result<()> {
+        let len = unwrap!(body.len().to_u32());
+        try!(self.out.write_bytes(CURRENT_VERSION));
+        try!(self.out.write_bytes(len));
+        try!(self.out.write_bytes(crc32(body)));
+        Ok(())
     }
 
     fn write_object_header<O>(&mut self, o: &ObjectRef<O>) -> Result<()>
@@ -129,6 +144,15 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
                            e.target_velocity,
                            e.appearance)));
 
+        // Behavior: a tag, followed by a position and a radius.  Unused fields are zero.
+        let (kind, pos, radius) = match e.behavior {
+            Behavior::Idle => (0_u8, scalar(0), 0),
+            Behavior::WalkTo(target) => (1, target, 0),
+            Behavior::Wander { home, radius } => (2, home, radius),
+        };
+        try!(self.w.write(kind));
+        try!(self.w.write((pos, radius)));
+
         try!(self.hooks.post_write_entity(&mut self.w, e));
 
         // Children
@@ -191,6 +215,23 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
             try!(self.w.write((cpos, stable_tcid.unwrap())));
         }
 
+        // Environment: the cycle length, flags recording which of the optional fields are set
+        // (1 = `ambient`, 2 = `fixed_time`), and the two optional fields.  Unset fields are zero.
+        let env = p.env();
+        let flags = (env.ambient.is_some() as u32) | ((env.fixed_time.is_some() as u32) << 1);
+        try!(self.w.write((env.cycle_ms,
+                           flags,
+                           env.fixed_time.unwrap_or(0),
+                           env.ambient.unwrap_or(0))));
+
+        let gen = p.gen_config();
+        try!(self.w.write_str(&gen.kind));
+        try!(self.w.write_count(gen.params.len()));
+        for (k, v) in gen.params.iter() {
+            try!(self.w.write_str(k));
+            try!(self.w.write_str(v));
+        }
+
         try!(self.hooks.post_write_plane(&mut self.w, p));
         Ok(())
     }
@@ -246,6 +287,16 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
 
         try!(self.w.write(s.flags.bits()));
 
+        try!(self.w.write_count(s.crafting_jobs.len()));
+        let recipes = &s.world().data().recipes;
+        for job in s.crafting_jobs.iter() {
+            // Write the recipe name instead of the ID, since IDs can change when the data files
+            // are regenerated.
+            try!(self.w.write_str(&*recipes.recipe(job.recipe).name));
+            try!(self.w.write(job.end_time));
+            try!(self.w.write(job.count));
+        }
+
         try!(self.hooks.post_write_structure(&mut self.w, s));
 
         // Children
@@ -294,9 +345,13 @@ impl<W: io::Write, H: WriteHooks> ObjectWriter<W, H> {
 
     fn save_object<F>(&mut self, f: F) -> Result<()>
             where F: FnOnce(&mut ObjectWriter<W, H>) -> Result<()> {
-        try!(self.write_file_header());
         try!(f(self));
         try!(self.handle_missing());
+
+        let body = mem::replace(self.w.writer_mut(), Vec::new());
+        try!(self.write_file_header(&body));
+        try!(self.out.write_all(&body));
+        try!(self.out.flush());
         Ok(())
     }
 
@@ -40,6 +40,11 @@ pub struct ReaderWrapper<R: io::Read> {
     reader: R,
     id_map: HashMap<SaveId, AnyId>,
     created_objs: HashSet<AnyId>,
+    /// Number of times each object's ID has been read, including its own object header.
+    ref_counts: HashMap<AnyId, u32>,
+    /// Objects removed from the world by `drop_created`.  Reading one of their IDs again is an
+    /// error.
+    dropped_objs: HashSet<AnyId>,
 }
 
 impl<R: io::Read> ReaderWrapper<R> {
@@ -48,6 +53,8 @@ impl<R: io::Read> ReaderWrapper<R> {
             reader: reader,
             id_map: HashMap::new(),
             created_objs: HashSet::new(),
+            ref_counts: HashMap::new(),
+            dropped_objs: HashSet::new(),
         }
     }
 
@@ -63,19 +70,36 @@ impl<R: io::Read> ReaderWrapper<R> {
         &self.created_objs
     }
 
+    /// Get the number of times the ID of object `id` has been read so far.
+    pub fn ref_count(&self, id: AnyId) -> u32 {
+        self.ref_counts.get(&id).map_or(0, |&x| x)
+    }
+
+    /// Stop tracking an object that was created while reading but has since been removed from
+    /// the world, so it gets no `_create` hook or cleanup.  Any later reference to the object
+    /// makes the read fail.
+    pub fn drop_created(&mut self, id: AnyId) {
+        self.created_objs.remove(&id);
+        self.dropped_objs.insert(id);
+    }
+
     fn read_id_helper<'d, T: ReadId, F: Fragment<'d>>(&mut self,
                                                       f: &mut F,
                                                       save_id: SaveId) -> Result<T> {
         use std::collections::hash_map::Entry::{Occupied, Vacant};
-        match self.id_map.entry(save_id) {
-            Occupied(e) => ReadId::from_any_id(*e.get()),
+        let aid = match self.id_map.entry(save_id) {
+            Occupied(e) => *e.get(),
             Vacant(e) => {
                 let id = <T as ReadId>::fabricate(f);
                 self.created_objs.insert(id.to_any_id());
-                e.insert(id.to_any_id());
-                Ok(id)
+                *e.insert(id.to_any_id())
             },
+        };
+        if self.dropped_objs.contains(&aid) {
+            fail!("save file refers to an object that was dropped by a migration");
         }
+        *self.ref_counts.entry(aid).or_insert(0) += 1;
+        ReadId::from_any_id(aid)
     }
 }
 
@@ -112,7 +136,10 @@ impl<R: io::Read> Reader for ReaderWrapper<R> {
         let mut base = 0;
         while base < buf.len() {
             let n = try!(self.reader.read(&mut buf[base..]));
-            assert!(n > 0 && base + n <= buf.len());
+            if n == 0 {
+                fail!("unexpected end of file");
+            }
+            assert!(base + n <= buf.len());
             base += n;
         }
         Ok(())
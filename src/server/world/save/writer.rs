@@ -47,6 +47,10 @@ impl<W: io::Write> WriterWrapper<W> {
         &self.id_map
     }
 
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
     fn write_padding(&mut self, len: usize) -> Result<()> {
         let pad = padding(len);
         if pad > 0 {
@@ -4,6 +4,7 @@ use std::u8;
 use types::*;
 
 use input::InputBits;
+use libterrain_gen::GenConfig;
 
 pub use super::World;
 pub use super::{Client, Entity, Inventory, Plane, TerrainChunk, Structure};
@@ -23,6 +24,66 @@ pub enum StructureAttachment {
     Chunk,
 }
 
+/// A timed crafting job running at a station structure.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CraftingJob {
+    pub recipe: RecipeId,
+    /// Number of times to apply the recipe.  The inputs for all `count` crafts are removed when
+    /// the job is created.
+    pub count: u16,
+    /// Time when the job finishes and the outputs are produced.
+    pub end_time: Time,
+}
+
+/// Server-controlled movement of an NPC entity.  See `logic::behavior`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Behavior {
+    /// Stand still, or move only as directed by other code.  Every entity starts out `Idle`.
+    Idle,
+    /// Walk in a straight line toward a point, then switch to `Idle`.
+    WalkTo(V3),
+    /// Walk around at random, staying roughly within `radius` pixels of `home`.
+    Wander { home: V3, radius: i32 },
+}
+
+/// Number of ticks in one day/night cycle.  Times of day are given in ticks, starting from the
+/// beginning of the day.
+pub const DAY_NIGHT_CYCLE_TICKS: u32 = 24_000;
+/// Default length of one day/night cycle, in milliseconds.
+pub const DAY_NIGHT_CYCLE_MS: u32 = 24 * 60 * 1000;
+
+/// Lighting settings for a plane.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlaneEnv {
+    /// Length of one day/night cycle, in milliseconds.
+    pub cycle_ms: u32,
+    /// If set, the time of day stays fixed at this many ticks into the cycle.
+    pub fixed_time: Option<u32>,
+    /// If set, the ambient light is always this color (as `0xRRGGBB`), regardless of the time of
+    /// day.
+    pub ambient: Option<u32>,
+}
+
+impl PlaneEnv {
+    /// The environment of a surface plane: a normal day/night cycle.
+    pub fn new() -> PlaneEnv {
+        PlaneEnv {
+            cycle_ms: DAY_NIGHT_CYCLE_MS,
+            fixed_time: None,
+            ambient: None,
+        }
+    }
+
+    /// The environment of an underground plane, which has no sunlight at all.
+    pub fn dark() -> PlaneEnv {
+        PlaneEnv {
+            cycle_ms: DAY_NIGHT_CYCLE_MS,
+            fixed_time: None,
+            ambient: Some(0x000000),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum InventoryAttachment {
     World,
@@ -93,10 +154,34 @@ impl super::Entity {
         self.appearance
     }
 
+    pub fn behavior(&self) -> Behavior {
+        self.behavior
+    }
+
+    // No set_behavior here, since the owning chunks need to be marked dirty.  Use
+    // `EntityRefMut::set_behavior` instead.
+
     pub fn pos(&self, now: Time) -> V3 {
         self.motion.pos(now)
     }
 
+    /// Get the position at a past `time`, using the motion that was in effect then.  Times older
+    /// than the recorded history give the oldest known position.
+    pub fn pos_at(&self, time: Time) -> V3 {
+        if time >= self.motion.start_time {
+            return self.motion.pos(time);
+        }
+        for m in self.motion_history.iter().rev() {
+            if time >= m.start_time {
+                return m.pos(time);
+            }
+        }
+        match self.motion_history.front() {
+            Some(m) => m.start_pos,
+            None => self.motion.start_pos,
+        }
+    }
+
     pub fn attachment(&self) -> EntityAttachment {
         self.attachment
     }
@@ -171,6 +256,14 @@ impl super::Plane {
         &self.name
     }
 
+    pub fn env(&self) -> PlaneEnv {
+        self.env
+    }
+
+    pub fn gen_config(&self) -> &GenConfig {
+        &self.gen
+    }
+
     pub fn get_terrain_chunk_id(&self, cpos: V2) -> Option<TerrainChunkId> {
         self.loaded_chunks.get(&cpos).map(|&x| x)
     }
@@ -30,6 +30,8 @@ OP_REPL_COMMAND =   0xff03
 OP_REPL_RESULT =    0xff04
 OP_SHUTDOWN =       0xff05
 OP_RESTART =        0xff06
+OP_SAVE =           0xff09
+OP_RELOAD_SCRIPTS = 0xff0a
 
 
 def now():
@@ -255,6 +257,14 @@ class BackendStream(object):
         msg = struct.pack('HHH', 0, 2, OP_SHUTDOWN)
         self.impl.stdin.write(msg)
 
+    def send_save(self):
+        msg = struct.pack('HHH', 0, 2, OP_SAVE)
+        self.impl.stdin.write(msg)
+
+    def send_reload_scripts(self):
+        msg = struct.pack('HHH', 0, 2, OP_RELOAD_SCRIPTS)
+        self.impl.stdin.write(msg)
+
     @tornado.gen.coroutine
     def do_read(self):
         while True:
@@ -389,6 +399,10 @@ class ControlServer(object):
             print('got control command: %r' % text)
             if text == b'shutdown':
                 self.backend.send_shutdown()
+            elif text == b'save':
+                self.backend.send_save()
+            elif text == b'reload_scripts':
+                self.backend.send_reload_scripts()
             #else if text == 'restart':
                 #self.backend.send_restart()
 
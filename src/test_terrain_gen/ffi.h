@@ -12,7 +12,7 @@ typedef struct tg_extra_iter tg_extra_iter;
 typedef uint16_t block_id;
 typedef uint32_t template_id;
 
-tg_worker* worker_create(const char* path);
+tg_worker* worker_create(const char* path, uint64_t seed);
 void worker_destroy(tg_worker* w);
 void worker_request(tg_worker* w, uint64_t pid, int32_t x, int32_t y);
 tg_chunk* worker_get_response(tg_worker* w, uint64_t* pid_p, int32_t* x_p, int32_t* y_p);
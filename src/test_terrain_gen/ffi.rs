@@ -44,7 +44,7 @@ fn read_json(mut file: File) -> json::Json {
 }
 
 impl Worker {
-    fn new(path: &str) -> Worker {
+    fn new(path: &str, seed: u64) -> Worker {
         let storage = Box::new(Storage::new(&path.to_owned()));
 
         let block_json = read_json(storage.open_block_data());
@@ -53,12 +53,17 @@ impl Worker {
         let template_json = read_json(storage.open_template_data());
         let animation_json = read_json(storage.open_animation_data());
         let loot_table_json = read_json(storage.open_loot_table_data());
+        let vault_json = read_json(storage.open_vault_data());
+        let migration_json = storage.open_migration_data()
+                                    .map_or(json::Json::Null, read_json);
         let data = Box::new(Data::from_json(block_json,
                                             item_json,
                                             recipe_json,
                                             template_json,
                                             animation_json,
-                                            loot_table_json).unwrap());
+                                            loot_table_json,
+                                            vault_json,
+                                            migration_json).unwrap());
 
         let (send_cmd, recv_cmd) = mpsc::channel();
         let (send_result, recv_result) = mpsc::channel();
@@ -68,7 +73,7 @@ impl Worker {
             let storage_ref: &Storage = &*storage;
             let data_ref: &Data = &*data;
             let guard = thread::scoped(move || {
-                worker::run(data_ref, storage_ref, recv_cmd, send_result);
+                worker::run(data_ref, storage_ref, seed, 1, recv_cmd, send_result);
             });
             // Cast away the lifetimes so we can move `data` and `storage` into the struct.
             unsafe { mem::transmute(guard) }
@@ -98,11 +103,11 @@ fn init_logger() {
 
 
 #[no_mangle]
-pub unsafe extern "C" fn worker_create(path: *const c_char) -> *mut Worker {
+pub unsafe extern "C" fn worker_create(path: *const c_char, seed: u64) -> *mut Worker {
     init_logger();
     let c_str = CStr::from_ptr(path);
     let s = c_str.to_str().unwrap();
-    let ptr = Box::new(Worker::new(s));
+    let ptr = Box::new(Worker::new(s, seed));
     Box::into_raw(ptr)
 }
 
@@ -113,7 +118,7 @@ pub unsafe extern "C" fn worker_destroy(ptr: *mut Worker) {
 
 #[no_mangle]
 pub unsafe extern "C" fn worker_request(ptr: *mut Worker, pid: u64, x: i32, y: i32) {
-    let cmd = Command::Generate(Stable::new(pid), V2::new(x, y));
+    let cmd = Command::Generate(Stable::new(pid), V2::new(x, y), 0);
     (*ptr).send.send(cmd).unwrap();
 }
 
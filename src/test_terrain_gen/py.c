@@ -40,13 +40,14 @@ static void Worker_dealloc(Worker* self) {
 }
 
 static int Worker_init(Worker* self, PyObject* args, PyObject* kwds) {
-    static char* kwlist[] = {"path", NULL};
+    static char* kwlist[] = {"path", "seed", NULL};
     const char* path;
-    if (!PyArg_ParseTupleAndKeywords(args, kwds, "s", kwlist, &path)) {
+    uint64_t seed = 0;
+    if (!PyArg_ParseTupleAndKeywords(args, kwds, "s|K", kwlist, &path, &seed)) {
         return -1;
     }
 
-    self->ptr = worker_create(path);
+    self->ptr = worker_create(path, seed);
     return 0;
 }
 
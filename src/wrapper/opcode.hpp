@@ -11,6 +11,8 @@ enum opcode {
     OP_RESTART_SERVER =     0xff06,
     OP_RESTART_CLIENT =     0xff07,
     OP_RESTART_BOTH =       0xff08,
+    OP_SAVE =               0xff09,
+    OP_RELOAD_SCRIPTS =     0xff0a,
 };
 
 #endif // OUTPOST_WRAPPER_OPCODES_HPP
@@ -227,6 +227,25 @@ def read_obj(filename, load_func):
     with open(filename, 'rb') as f:
         return load_func(f.read())
 
+REGION_SIZE = 256
+REGION_HEADER_SIZE = 8
+REGION_ENTRY_SIZE = 16
+
+def read_region_entry(filename, idx):
+    with open(filename, 'rb') as f:
+        if f.read(4) != b'OREG':
+            raise IOError('%s is not a region file' % filename)
+        f.seek(REGION_HEADER_SIZE + idx * REGION_ENTRY_SIZE)
+        offset, length = struct.unpack('<II', f.read(8))
+        if offset == 0:
+            raise IOError('entry %d of %s is empty' % (idx, filename))
+        f.seek(offset)
+        return f.read(length)
+
+def read_terrain_chunk(save_dir, stable_id):
+    path = os.path.join(save_dir, 'terrain_chunks', '%x.region' % (stable_id.id // REGION_SIZE))
+    return load_terrain_chunk(read_region_entry(path, stable_id.id % REGION_SIZE))
+
 DIRS = (
         V2( 1, -1),
         V2( 0, -1),
@@ -252,8 +271,7 @@ def collect_player_chunks(save_dir, plane):
     i = 0
     count = len(plane.saved_chunks)
     for cpos, stable_id in plane.saved_chunks.items():
-        path = os.path.join(save_dir, 'terrain_chunks', '%x.terrain_chunk' % stable_id.id)
-        chunk = read_obj(path, load_terrain_chunk)
+        chunk = read_terrain_chunk(save_dir, stable_id)
         for s in chunk.child_structures:
             if s.template not in NATURAL_STRUCTURES:
                 modified.add(cpos)
@@ -285,9 +303,8 @@ class SliceCache(object):
         if chunk_id is None:
             return []
 
-        path = os.path.join(self.save_dir, 'terrain_chunks', '%x.terrain_chunk' % chunk_id.id)
         try:
-            chunk = read_obj(path, load_terrain_chunk)
+            chunk = read_terrain_chunk(self.save_dir, chunk_id)
         except IOError:
             return []
 
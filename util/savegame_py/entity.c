@@ -28,6 +28,9 @@ typedef struct _Entity {
     PyObject* facing;
     PyObject* target_velocity;
     uint32_t appearance;
+    uint8_t behavior;
+    PyObject* behavior_pos;
+    int32_t behavior_radius;
 
     PyObject* child_inventories;
 } Entity;
@@ -48,6 +51,9 @@ static PyMemberDef Entity_members[] = {
     {"facing", T_OBJECT, offsetof(Entity, facing), 0, NULL},
     {"target_velocity", T_OBJECT, offsetof(Entity, target_velocity), 0, NULL},
     {"appearance", T_UINT, offsetof(Entity, appearance), 0, NULL},
+    {"behavior", T_UBYTE, offsetof(Entity, behavior), 0, NULL},
+    {"behavior_pos", T_OBJECT, offsetof(Entity, behavior_pos), 0, NULL},
+    {"behavior_radius", T_INT, offsetof(Entity, behavior_radius), 0, NULL},
     {"child_inventories", T_OBJECT, offsetof(Entity, child_inventories), 0, NULL},
     {NULL}
 };
@@ -58,6 +64,7 @@ static void Entity_dealloc(Entity* self) {
     Py_XDECREF(self->motion);
     Py_XDECREF(self->facing);
     Py_XDECREF(self->target_velocity);
+    Py_XDECREF(self->behavior_pos);
     Py_XDECREF(self->child_inventories);
 
     if (self->save != NULL) {
@@ -199,6 +206,23 @@ Entity* entity_read(Reader* r, int version) {
 
     e->appearance = data.appearance;
 
+    if (version >= 9) {
+        // Behavior kind: 0 = idle, 1 = walk_to (target in `pos`), 2 = wander (home in `pos`)
+        struct {
+            uint8_t kind;
+            uint8_t pad[3];
+            struct V3Data pos;
+            int32_t radius;
+        } behavior;
+        READ(behavior);
+
+        e->behavior = behavior.kind;
+        e->behavior_pos = PyObject_CallFunction((PyObject*)&V3Type, "iii",
+                behavior.pos.x, behavior.pos.y, behavior.pos.z);
+        FAIL_IF(e->behavior_pos == NULL);
+        e->behavior_radius = behavior.radius;
+    }
+
 
     e->save->extra_raw = extra_read(r, version);
     FAIL_IF(e->save->extra_raw  == NULL);
@@ -23,7 +23,7 @@ static PyObject* py_load_client(PyObject* self, PyObject* args) {
     Reader* r = &rs;
 
     uint32_t version;
-    READ(version);
+    FAIL_IF(read_file_header(r, &version) < 0);
     result = client_read(r, version);
     FAIL_IF(result == NULL);
     FAIL_IF(client_read_post(r, result, version));
@@ -48,7 +48,7 @@ static PyObject* py_load_plane(PyObject* self, PyObject* args) {
     Reader* r = &rs;
 
     uint32_t version;
-    READ(version);
+    FAIL_IF(read_file_header(r, &version) < 0);
     result = plane_read(r, version);
     FAIL_IF(result == NULL);
     FAIL_IF(plane_read_post(r, result, version));
@@ -73,7 +73,7 @@ static PyObject* py_load_terrain_chunk(PyObject* self, PyObject* args) {
     Reader* r = &rs;
 
     uint32_t version;
-    READ(version);
+    FAIL_IF(read_file_header(r, &version) < 0);
     result = terrain_chunk_read(r, version);
     FAIL_IF(result == NULL);
     FAIL_IF(terrain_chunk_read_post(r, result, version));
@@ -23,6 +23,11 @@ typedef struct _Plane {
 
     PyObject* name;
     PyObject* saved_chunks;
+    uint32_t cycle_ms;
+    PyObject* fixed_time;
+    PyObject* ambient;
+    PyObject* gen_kind;
+    PyObject* gen_params;
 } Plane;
 
 static PyTypeObject PlaneType = {
@@ -37,6 +42,11 @@ static PyMemberDef Plane_members[] = {
     {"extra", T_OBJECT, offsetof(Plane, extra), 0, NULL},
     {"name", T_OBJECT, offsetof(Plane, name), 0, NULL},
     {"saved_chunks", T_OBJECT, offsetof(Plane, saved_chunks), 0, NULL},
+    {"cycle_ms", T_UINT, offsetof(Plane, cycle_ms), 0, NULL},
+    {"fixed_time", T_OBJECT, offsetof(Plane, fixed_time), 0, NULL},
+    {"ambient", T_OBJECT, offsetof(Plane, ambient), 0, NULL},
+    {"gen_kind", T_OBJECT, offsetof(Plane, gen_kind), 0, NULL},
+    {"gen_params", T_OBJECT, offsetof(Plane, gen_params), 0, NULL},
     {NULL}
 };
 
@@ -44,6 +54,10 @@ static void Plane_dealloc(Plane* self) {
     Py_XDECREF(self->extra);
     Py_XDECREF(self->name);
     Py_XDECREF(self->saved_chunks);
+    Py_XDECREF(self->fixed_time);
+    Py_XDECREF(self->ambient);
+    Py_XDECREF(self->gen_kind);
+    Py_XDECREF(self->gen_params);
 
     if (self->save != NULL) {
         Py_XDECREF(self->save->extra_raw);
@@ -62,11 +76,17 @@ static int Plane_init(Plane* self, PyObject* args, PyObject* kwds) {
         goto fail;
     }
 
+    self->gen_params = PyDict_New();
+    if (self->gen_params == NULL) {
+        goto fail;
+    }
+
     return 0;
 
 fail:
     SET_EXC();
     Py_XDECREF(self->saved_chunks);
+    Py_XDECREF(self->gen_params);
     return -1;
 }
 
@@ -124,6 +144,69 @@ Plane* plane_read(Reader* r, int version) {
         Py_DECREF(value);
     }
 
+    if (version >= 10) {
+        // Flags: 1 = ambient is set, 2 = fixed_time is set
+        struct {
+            uint32_t cycle_ms;
+            uint32_t flags;
+            uint32_t fixed_time;
+            uint32_t ambient;
+        } env;
+        READ(env);
+
+        p->cycle_ms = env.cycle_ms;
+        if (env.flags & 2) {
+            p->fixed_time = PyLong_FromUnsignedLong(env.fixed_time);
+        } else {
+            Py_INCREF(Py_None);
+            p->fixed_time = Py_None;
+        }
+        FAIL_IF(p->fixed_time == NULL);
+        if (env.flags & 1) {
+            p->ambient = PyLong_FromUnsignedLong(env.ambient);
+        } else {
+            Py_INCREF(Py_None);
+            p->ambient = Py_None;
+        }
+        FAIL_IF(p->ambient == NULL);
+    }
+
+    if (version >= 11) {
+        uint32_t kind_len;
+        READ(kind_len);
+        p->gen_kind = read_string(r, kind_len);
+        FAIL_IF(p->gen_kind == NULL);
+
+        uint32_t params_count;
+        READ(params_count);
+        for (uint32_t i = 0; i < params_count; ++i) {
+            uint32_t key_len;
+            READ(key_len);
+            PyObject* key = read_string(r, key_len);
+            FAIL_IF(key == NULL);
+
+            uint32_t value_len;
+            if (read_bytes(r, &value_len, sizeof(value_len)) < 0) {
+                Py_DECREF(key);
+                goto fail;
+            }
+            PyObject* value = read_string(r, value_len);
+            if (value == NULL) {
+                Py_DECREF(key);
+                goto fail;
+            }
+
+            if (PyDict_SetItem(p->gen_params, key, value)) {
+                Py_DECREF(key);
+                Py_DECREF(value);
+                goto fail;
+            }
+
+            Py_DECREF(key);
+            Py_DECREF(value);
+        }
+    }
+
 
     p->save->extra_raw = extra_read(r, version);
     FAIL_IF(p->save->extra_raw  == NULL);
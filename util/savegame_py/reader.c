@@ -30,6 +30,25 @@ fail:
     return -1;
 }
 
+int read_file_header(Reader* r, uint32_t* version_out) {
+    uint32_t version;
+    READ(version);
+    if (version >= 8) {
+        // Version 8 added the body length and checksum to the header.  The
+        // server already checks these when loading, so they're skipped here.
+        uint32_t len;
+        uint32_t checksum;
+        READ(len);
+        READ(checksum);
+    }
+    *version_out = version;
+    return 0;
+
+fail:
+    SET_EXC();
+    return -1;
+}
+
 PyObject* read_decode_item_name(Reader* r, uint16_t old_id, size_t name_len) {
     PyObject* key = PyLong_FromLong(old_id);
     PyObject* value = NULL;
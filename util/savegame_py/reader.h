@@ -29,6 +29,7 @@ static inline int read_bytes(Reader* r, void* buf, size_t len) {
 #define READ(x)     FAIL_IF(read_bytes(r, &x, sizeof(x)) < 0)
 
 int reader_init(Reader* r, PyObject* bytes);
+int read_file_header(Reader* r, uint32_t* version_out);
 PyObject* read_decode_item_name(Reader* r, uint16_t old_id, size_t name_len);
 PyObject* read_decode_template_name(Reader* r);
 PyObject* read_string(Reader* r, size_t len);
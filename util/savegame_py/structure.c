@@ -25,6 +25,7 @@ typedef struct _Structure {
     PyObject* offset;
     PyObject* template;
     uint32_t flags;
+    PyObject* crafting_jobs;
 
     PyObject* child_inventories;
 } Structure;
@@ -42,6 +43,7 @@ static PyMemberDef Structure_members[] = {
     {"offset", T_OBJECT, offsetof(Structure, offset), 0, NULL},
     {"template", T_OBJECT, offsetof(Structure, template), 0, NULL},
     {"flags", T_UINT, offsetof(Structure, flags), 0, NULL},
+    {"crafting_jobs", T_OBJECT, offsetof(Structure, crafting_jobs), 0, NULL},
     {"child_inventories", T_OBJECT, offsetof(Structure, child_inventories), 0, NULL},
     {NULL}
 };
@@ -50,6 +52,7 @@ static void Structure_dealloc(Structure* self) {
     Py_XDECREF(self->extra);
     Py_XDECREF(self->offset);
     Py_XDECREF(self->template);
+    Py_XDECREF(self->crafting_jobs);
     Py_XDECREF(self->child_inventories);
 
     if (self->save != NULL) {
@@ -64,6 +67,11 @@ static int Structure_init(Structure* self, PyObject* args, PyObject* kwds) {
         return -1;
     }
 
+    self->crafting_jobs = PyList_New(0);
+    if (self->crafting_jobs == NULL) {
+        goto fail;
+    }
+
     self->child_inventories = PyList_New(0);
     if (self->child_inventories == NULL) {
         goto fail;
@@ -73,6 +81,7 @@ static int Structure_init(Structure* self, PyObject* args, PyObject* kwds) {
 
 fail:
     SET_EXC();
+    Py_XDECREF(self->crafting_jobs);
     Py_XDECREF(self->child_inventories);
     return -1;
 }
@@ -114,6 +123,32 @@ Structure* structure_read(Reader* r, int version) {
         READ(s->flags);
     }
 
+    if (version >= 7) {
+        // Each job is a (recipe name, end time, count) tuple.
+        uint32_t job_count;
+        READ(job_count);
+        for (uint32_t i = 0; i < job_count; ++i) {
+            uint32_t name_len;
+            READ(name_len);
+            PyObject* name = read_string(r, name_len);
+            FAIL_IF(name == NULL);
+
+            int64_t end_time;
+            uint16_t count;
+            if (read_bytes(r, &end_time, sizeof(end_time)) < 0 ||
+                    read_bytes(r, &count, sizeof(count)) < 0) {
+                Py_DECREF(name);
+                goto fail;
+            }
+
+            PyObject* job = Py_BuildValue("NLH", name, (long long)end_time, count);
+            FAIL_IF(job == NULL);
+            int err = PyList_Append(s->crafting_jobs, job);
+            Py_DECREF(job);
+            FAIL_IF(err == -1);
+        }
+    }
+
 
     s->save->extra_raw = extra_read(r, version);
     FAIL_IF(s->save->extra_raw  == NULL);
@@ -36,6 +36,8 @@ def build_parser():
             help='port number for running the HTTP server (default: 8000)')
     p.add_argument('--origin', metavar='X,Y', type=int_pair, default=(0, 0),
             help='center point for map generation (default: 0,0)')
+    p.add_argument('--seed', metavar='INT', type=int, default=0,
+            help='world seed to use for terrain generation (default: 0)')
     return p
 
 
@@ -93,7 +95,7 @@ def mk_tile_handler(args):
     offset = V2(*args.origin) - V2(CENTER_TILE, CENTER_TILE)
 
     data = render_map.load_data(args.dist_dir)
-    worker = tg.Worker(args.dist_dir)
+    worker = tg.Worker(args.dist_dir, args.seed)
 
     wrap_chunk = mk_wrap(data)
     def get_chunk(cpos):